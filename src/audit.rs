@@ -0,0 +1,144 @@
+//! An audit trail of what was trashed, by whom and where it went, enabled with `audit_log` in the configuration file.
+//!
+//! Every file trashed makes an [`AuditRecord`], handed to an [`AuditSink`]: the system logger (see [`Syslog`]),
+//! or whatever tests swap it for. Records are logged as a single line of space-separated `key=value` fields,
+//! whose keys are, in order:
+//!
+//! * `original_path`: the absolute path the file was trashed from
+//! * `trashed_name`: its name in `$trash/files`
+//! * `trash_root`: the trash it was sent to
+//! * `uid`: the real user id of whoever trashed it
+//! * `method`: `rename`, `copy` (when it was on another filesystem than the trash), `forced-copy` or `compress`
+//! * `bytes`: how many bytes it took up, or `-` for a directory that wasn't measured
+//!
+//! Values are escaped as in `--print` (see [`escape_path`]), spaces included, so that a line splits back into
+//! fields on spaces. Logging can't fail as far as trashing is concerned.
+
+use std::{fmt, path::PathBuf};
+
+use crate::{ffi, fs::Transfer, report::escape_path};
+
+/// What's logged about a trashed file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub original_path: PathBuf,
+    pub trashed_name: PathBuf,
+    pub trash_root: PathBuf,
+    pub uid: u32,
+    pub transfer: Transfer,
+    pub bytes: Option<u64>,
+}
+
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let paths = [
+            ("original_path", &self.original_path),
+            ("trashed_name", &self.trashed_name),
+            ("trash_root", &self.trash_root),
+        ];
+        for (key, path) in paths {
+            write!(f, "{key}={} ", escape_path(path).replace(' ', "%20"))?;
+        }
+
+        write!(
+            f,
+            "uid={} method={} bytes={}",
+            self.uid,
+            self.transfer.name(),
+            self.bytes
+                .map_or_else(|| "-".to_owned(), |bytes| bytes.to_string())
+        )
+    }
+}
+
+/// Where audit records go
+pub trait AuditSink: fmt::Debug {
+    /// Logs `record`. Failing to is not a failure to trash, so there's nothing to report.
+    fn record(&self, record: &AuditRecord);
+}
+
+/// Logs records through syslog(3), where journald picks them up as well
+#[derive(Debug)]
+pub struct Syslog;
+
+impl AuditSink for Syslog {
+    fn record(&self, record: &AuditRecord) {
+        ffi::syslog(&format!("trashed {record}"));
+    }
+}
+
+/// Keeps records in memory, for tests to look at
+#[cfg(test)]
+#[derive(Debug, Default, Clone)]
+pub struct Recorder(pub std::rc::Rc<std::cell::RefCell<Vec<AuditRecord>>>);
+
+#[cfg(test)]
+impl AuditSink for Recorder {
+    fn record(&self, record: &AuditRecord) {
+        self.0.borrow_mut().push(record.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{AuditRecord, Recorder};
+    use crate::{context::TrashContext, error::Result, fs::Transfer, tests::FaultyFs, tests::Op};
+
+    #[test]
+    fn formats_records() {
+        let record = AuditRecord {
+            original_path: "/home/user/my notes\ttxt".into(),
+            trashed_name: "my notes\ttxt.1".into(),
+            trash_root: "/home/user/.local/share/Trash".into(),
+            uid: 1000,
+            transfer: Transfer::Copied,
+            bytes: Some(42),
+        };
+
+        assert_eq!(
+            record.to_string(),
+            "original_path=/home/user/my%20notes%09txt trashed_name=my%20notes%09txt.1 \
+             trash_root=/home/user/.local/share/Trash uid=1000 method=copy bytes=42"
+        );
+    }
+
+    #[test]
+    fn records_every_file_trashed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("trash");
+        for subdir in ["files", "info"] {
+            fs::create_dir_all(root.join(subdir))?;
+        }
+        fs::write(dir.path().join("a"), "dummy")?;
+        fs::write(dir.path().join("b"), "dummy!")?;
+
+        let recorder = Recorder::default();
+        // The second file is on "another filesystem"
+        let ctx = TrashContext::new(&root, vec![])?
+            .verifying_trashes(false)
+            .auditing(recorder.clone())
+            .with_fs(FaultyFs::failing(Op::Rename, 2, libc::EXDEV));
+
+        ctx.send_to_trash(ctx.home_trash(), &dir.path().join("a"))?;
+        ctx.send_to_trash(ctx.home_trash(), &dir.path().join("b"))?;
+        // Failures aren't recorded
+        assert!(ctx
+            .send_to_trash(ctx.home_trash(), &dir.path().join("missing"))
+            .is_err());
+
+        let records = recorder.0.borrow();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].original_path, dir.path().join("a"));
+        assert_eq!(records[0].trashed_name, Path::new("a"));
+        assert_eq!(records[0].trash_root, root);
+        assert_eq!(records[0].uid, crate::ffi::real_user_id());
+        assert_eq!(records[0].transfer, Transfer::Renamed);
+        assert_eq!(records[0].bytes, Some(5));
+        assert_eq!(records[1].transfer, Transfer::Copied);
+        assert_eq!(records[1].bytes, Some(6));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+//! Printing trashed files without restoring them, for `--cat`
+
+use std::{
+    ffi::OsStr,
+    io::{self, Write},
+};
+
+use crate::{
+    error::{Error, Result},
+    info_file::build_info_file_path,
+    trash::Trash,
+};
+
+/// Copies the contents of the entry of `trash` named `name` (exactly as in `$trash/files`) to `out`, byte for byte.
+///
+/// With `info` set, the contents of its info file are copied instead. Directories are refused.
+/// Nothing in the trash is changed.
+pub fn cat(trash: &Trash, name: &OsStr, info: bool, out: &mut impl Write) -> Result<()> {
+    let path = if info {
+        build_info_file_path(name, trash.info_path())
+    } else {
+        trash.files.as_path().join(name)
+    };
+
+    // Symlinks to directories are refused as well
+    if fs_err::metadata(&path)?.is_dir() {
+        return Err(Error::CatDirectory(path));
+    }
+
+    let mut file = fs_err::File::open(&path)?;
+    io::copy(&mut file, out)?;
+    out.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::cat;
+    use crate::{error::Result, fs::RealFs, trash::Trash, Error};
+
+    #[test]
+    fn prints_entries_byte_for_byte() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+
+        let contents = b"caf\xe9\r\n\x00\xff no newline";
+        let file_path = dir.path().join("notes");
+        fs::write(&file_path, contents)?;
+        trash.send_to_trash(&RealFs, &file_path)?;
+        let dir_path = dir.path().join("photos");
+        fs::create_dir(&dir_path)?;
+        trash.send_to_trash(&RealFs, &dir_path)?;
+
+        let listing = || -> Result<Vec<_>> {
+            let mut listing = Vec::new();
+            for entry in walkdir::WalkDir::new(trash.root.as_path()) {
+                let entry = entry.map_err(std::io::Error::from)?;
+                listing.push((
+                    entry.path().to_owned(),
+                    entry.metadata().map_err(std::io::Error::from)?.modified()?,
+                ));
+            }
+            Ok(listing)
+        };
+        let before = listing()?;
+
+        let mut out = Vec::new();
+        cat(&trash, "notes".as_ref(), false, &mut out)?;
+        assert_eq!(out, contents);
+
+        let mut out = Vec::new();
+        cat(&trash, "notes".as_ref(), true, &mut out)?;
+        assert_eq!(out, fs::read(trash.info_path().join("notes.trashinfo"))?);
+
+        let mut out = Vec::new();
+        assert!(matches!(
+            cat(&trash, "photos".as_ref(), false, &mut out),
+            Err(Error::CatDirectory(path)) if path == trash.files.as_path().join("photos")
+        ));
+        assert!(out.is_empty());
+
+        assert_eq!(listing()?, before);
+
+        Ok(())
+    }
+}
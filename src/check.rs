@@ -0,0 +1,572 @@
+//! Finds inconsistencies between `$trash/files`, `$trash/info` and `$trash/directorysizes`, such as
+//! the ones left behind when trashing a file is interrupted, and fixes them with `--fix`.
+
+use std::{
+    ffi::OsString,
+    fmt,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use fs_err as fs;
+
+use crate::{
+    checksum::{self, Verification},
+    directorysizes::{read_directory_sizes, remove_directory_size},
+    entries::purge,
+    error::{Error, Result},
+    fs::{is_temp_file_name, remove_recursively, FileSystem},
+    info_file::build_info_file_path,
+    recovery::is_leftover,
+    report::display_path,
+    resume,
+    trash::Trash,
+};
+
+/// The extension every info file must have
+const INFO_FILE_EXTENSION: &str = ".trashinfo";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Finding {
+    /// An info file whose file in `$trash/files` does not exist
+    OrphanedInfoFile(PathBuf),
+    /// A file in `$trash/files` which has no info file
+    MissingInfoFile(PathBuf),
+    /// A line of `$trash/directorysizes` for this path in `$trash/files`, which does not exist
+    StaleDirectorySize(PathBuf),
+    /// One of our temporary files, left behind by an interrupted run
+    LeftoverTemp(PathBuf),
+    /// A file in `$trash/files` that changed since it was trashed, according to its checksum
+    ChecksumMismatch(PathBuf),
+    /// The manifest of a copy into the trash that was interrupted, resumed by trashing its source again
+    /// (see [`crate::resume`])
+    InterruptedCopy(PathBuf),
+}
+
+impl Finding {
+    /// A short name for the kind of this finding
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Finding::OrphanedInfoFile(_) => "orphaned-info",
+            Finding::MissingInfoFile(_) => "missing-info",
+            Finding::StaleDirectorySize(_) => "stale-directorysize",
+            Finding::LeftoverTemp(_) => "temp-file",
+            Finding::ChecksumMismatch(_) => "checksum-mismatch",
+            Finding::InterruptedCopy(_) => "interrupted-copy",
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Finding::OrphanedInfoFile(path)
+            | Finding::MissingInfoFile(path)
+            | Finding::StaleDirectorySize(path)
+            | Finding::LeftoverTemp(path)
+            | Finding::ChecksumMismatch(path)
+            | Finding::InterruptedCopy(path) => path,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}", self.kind(), display_path(self.path()))
+    }
+}
+
+/// Every inconsistency found in `trash`: those [`orphans`] finds, along with our leftover temporary files and
+/// interrupted copies.
+pub fn check(trash: &Trash) -> Result<Vec<Finding>> {
+    let mut findings = orphans(trash)?;
+
+    findings.extend(temp_files(trash)?.into_iter().map(Finding::LeftoverTemp));
+    findings.extend(
+        resume::manifests(&trash.copies_path())?
+            .into_iter()
+            .map(Finding::InterruptedCopy),
+    );
+
+    findings.sort();
+
+    Ok(findings)
+}
+
+/// Cross-references `$trash/files`, `$trash/info` and `$trash/directorysizes`, returning the files without an info
+/// file, the info files without a file and the lines of `directorysizes` for directories that are gone, for
+/// `--orphans`. Nothing is changed.
+pub fn orphans(trash: &Trash) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    for entry in fs::read_dir(trash.files.as_path())? {
+        let entry = entry?;
+        if is_temp_file_name(&entry.file_name()) {
+            continue;
+        }
+
+        let info_file_path = build_info_file_path(&entry.file_name(), trash.info_path());
+
+        if info_file_path.symlink_metadata().is_err() {
+            findings.push(Finding::MissingInfoFile(entry.path()));
+        }
+    }
+
+    for entry in fs::read_dir(trash.info_path())? {
+        let entry = entry?;
+        let Some(file_name) = trashed_file_name(entry.file_name()) else {
+            // Not an info file, so not our business
+            continue;
+        };
+
+        if trash
+            .files
+            .as_path()
+            .join(file_name)
+            .symlink_metadata()
+            .is_err()
+        {
+            findings.push(Finding::OrphanedInfoFile(entry.path()));
+        }
+    }
+
+    for directory_size in read_directory_sizes(trash)? {
+        let path = trash.files.as_path().join(&directory_size.name);
+        if path.symlink_metadata().is_err() {
+            findings.push(Finding::StaleDirectorySize(path));
+        }
+    }
+
+    findings.sort();
+    findings.dedup();
+
+    Ok(findings)
+}
+
+/// Checks every entry of `trash` that has a checksum against it, returning the ones that changed since they were trashed.
+///
+/// Entries without a checksum, or whose info file can't be read, are skipped: [`check`] reports the latter.
+pub fn verify_checksums(trash: &Trash) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    for entry in fs::read_dir(trash.files.as_path())? {
+        let entry = entry?;
+        if is_temp_file_name(&entry.file_name()) {
+            continue;
+        }
+
+        let verification = match checksum::verify(trash, &entry.file_name()) {
+            Ok(verification) => verification,
+            Err(Error::Io(err)) if err.kind() == ErrorKind::NotFound => continue,
+            Err(Error::InvalidInfoFile(_)) => continue,
+            Err(err) => return Err(err),
+        };
+
+        if verification == Verification::Mismatch {
+            findings.push(Finding::ChecksumMismatch(entry.path()));
+        }
+    }
+
+    findings.sort();
+
+    Ok(findings)
+}
+
+/// Our temporary files in the root of `trash` and in `$trash/files`
+pub fn temp_files(trash: &Trash) -> Result<Vec<PathBuf>> {
+    let mut temp_files = Vec::new();
+
+    for dir in [trash.root.as_path(), trash.files.as_path()] {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            if is_temp_file_name(&entry.file_name()) {
+                temp_files.push(entry.path());
+            }
+        }
+    }
+
+    Ok(temp_files)
+}
+
+/// Removes what `finding` is about, if it's a leftover (see [`is_leftover`]), returning whether it was removed.
+///
+/// Files without an info file are removed along with their line in `$trash/directorysizes`, and so are the lines of
+/// directories that are gone.
+pub fn fix(
+    fs: &impl FileSystem,
+    trash: &Trash,
+    finding: &Finding,
+    min_age: Duration,
+    now: SystemTime,
+) -> Result<bool> {
+    if !is_leftover(trash, finding, min_age, now) {
+        return Ok(false);
+    }
+
+    match finding {
+        Finding::OrphanedInfoFile(path) => fs.remove_file(path)?,
+        Finding::MissingInfoFile(path) => match path.file_name() {
+            Some(name) => purge(fs, trash, name)?,
+            None => return Ok(false),
+        },
+        Finding::StaleDirectorySize(path) => match path.file_name() {
+            Some(name) => remove_directory_size(fs, trash, name)?,
+            None => return Ok(false),
+        },
+        Finding::LeftoverTemp(path) => remove_recursively(fs, path)?,
+        Finding::ChecksumMismatch(_) => return Ok(false),
+        Finding::InterruptedCopy(path) => resume::discard(fs, path)?,
+    }
+
+    Ok(true)
+}
+
+/// The name of the trashed file described by the info file named `info_file_name`.
+pub fn trashed_file_name(info_file_name: OsString) -> Option<OsString> {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let bytes = info_file_name.as_bytes();
+    let file_name = bytes.strip_suffix(INFO_FILE_EXTENSION.as_bytes())?;
+
+    Some(OsString::from_vec(file_name.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        io::Write,
+        path::{Path, PathBuf},
+        time::SystemTime,
+    };
+
+    use super::{check, fix, orphans, verify_checksums, Finding};
+    use crate::{
+        crash::{self, CrashPoint},
+        entries::entries,
+        error::Result,
+        fs::RealFs,
+        recovery::{marker_path, GRACE_PERIOD},
+        resume,
+        tests::{dummy_bytes, FaultyFs, Op},
+        trash::{Trash, TrashOptions},
+        Error,
+    };
+
+    fn dummy_trash(dir: &Path) -> Result<Trash> {
+        let trash = Trash::from_root(dir.join("trash"))?;
+        fs::create_dir(&trash.root)?;
+        fs::create_dir(&trash.files)?;
+        fs::create_dir(&trash.info)?;
+        File::create(&trash.directory_sizes)?;
+
+        Ok(trash)
+    }
+
+    /// Makes a directory with a file in it, returning its path
+    fn dummy_dir(dir: &Path) -> Result<std::path::PathBuf> {
+        let path = dir.join("dummy-dir");
+        fs::create_dir(&path)?;
+        File::create(path.join("inner"))?.write_all(&dummy_bytes())?;
+
+        Ok(path)
+    }
+
+    /// The PID of a process that already exited
+    fn dead_pid() -> Result<u32> {
+        let mut child = std::process::Command::new("true").spawn()?;
+        child.wait()?;
+
+        Ok(child.id())
+    }
+
+    #[test]
+    fn consistent_trash_has_no_findings() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+        trash.send_to_trash(&RealFs, &dummy_dir(dir.path())?)?;
+
+        assert!(check(&trash)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_files_without_info_and_info_without_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+
+        File::create(trash.files.as_path().join("lonely"))?;
+        File::create(trash.info_path().join("gone.trashinfo"))?;
+        // Not an info file, so ignored
+        File::create(trash.info_path().join("README"))?;
+
+        assert_eq!(
+            check(&trash)?,
+            [
+                Finding::OrphanedInfoFile(trash.info_path().join("gone.trashinfo")),
+                Finding::MissingInfoFile(trash.files.as_path().join("lonely")),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_lines_of_directorysizes_for_directories_that_are_gone() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+        trash.send_to_trash(&RealFs, &dummy_dir(dir.path())?)?;
+        fs::write(
+            &trash.directory_sizes,
+            format!(
+                "{}4096 1700000000 gone%20away\n",
+                fs::read_to_string(&trash.directory_sizes)?
+            ),
+        )?;
+        File::create(trash.files.as_path().join("lonely"))?;
+        // Leftovers are for --check, not --orphans
+        File::create(trash.files.as_path().join(".tt-tmp-1-x"))?;
+
+        let gone = trash.files.as_path().join("gone away");
+        assert_eq!(
+            orphans(&trash)?,
+            [
+                Finding::MissingInfoFile(trash.files.as_path().join("lonely")),
+                Finding::StaleDirectorySize(gone.clone()),
+            ]
+        );
+        assert_eq!(
+            Finding::StaleDirectorySize(gone.clone()).to_string(),
+            format!("stale-directorysize\t{}", gone.display())
+        );
+        assert!(check(&trash)?.contains(&Finding::StaleDirectorySize(gone.clone())));
+
+        let now = SystemTime::now();
+        assert!(fix(
+            &RealFs,
+            &trash,
+            &Finding::StaleDirectorySize(gone),
+            GRACE_PERIOD,
+            now
+        )?);
+        assert_eq!(
+            orphans(&trash)?,
+            [Finding::MissingInfoFile(
+                trash.files.as_path().join("lonely")
+            )]
+        );
+        assert_eq!(entries(&trash)?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_files_that_changed_since_trashed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+        let checksummed = TrashOptions {
+            checksum: true,
+            ..TrashOptions::default()
+        };
+
+        for name in ["intact", "rotten"] {
+            File::create(dir.path().join(name))?.write_all(&dummy_bytes())?;
+            trash.send_to_trash_with(&RealFs, &dir.path().join(name), &checksummed)?;
+        }
+        // Neither are the files without a checksum or without an info file reported
+        File::create(dir.path().join("unchecked"))?.write_all(&dummy_bytes())?;
+        trash.send_to_trash(&RealFs, &dir.path().join("unchecked"))?;
+        File::create(trash.files.as_path().join("lonely"))?;
+
+        let rotten = trash.files.as_path().join("rotten");
+        fs::write(&rotten, "rotten")?;
+        let finding = Finding::ChecksumMismatch(rotten.clone());
+        assert_eq!(verify_checksums(&trash)?, std::slice::from_ref(&finding));
+        assert_eq!(
+            finding.to_string(),
+            format!("checksum-mismatch\t{}", rotten.display())
+        );
+
+        // Such entries are not leftovers
+        assert!(!fix(
+            &RealFs,
+            &trash,
+            &finding,
+            GRACE_PERIOD,
+            SystemTime::now()
+        )?);
+        assert!(rotten.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_interrupted_copies() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+        let source = dummy_dir(dir.path())?;
+
+        // Copying it from another filesystem fails midway
+        let interrupted = FaultyFs::failing(Op::Rename, 1, libc::EIO);
+        let copy = resume::copy(
+            &interrupted,
+            &trash.copies_path(),
+            &source,
+            &trash.files.as_path().join("dummy-dir"),
+            true,
+        );
+        assert!(copy.is_err());
+        let manifest = resume::manifest_path(&trash.copies_path(), &source);
+        let finding = Finding::InterruptedCopy(manifest.clone());
+        assert_eq!(check(&trash)?, std::slice::from_ref(&finding));
+
+        // Left alone while it may still be resumed, however old
+        let later = SystemTime::now() + 2 * GRACE_PERIOD;
+        assert!(!fix(&RealFs, &trash, &finding, GRACE_PERIOD, later)?);
+
+        fs::remove_dir_all(&source)?;
+        assert!(fix(&RealFs, &trash, &finding, GRACE_PERIOD, later)?);
+        assert!(!manifest.exists());
+        assert!(!resume::staging_path(&manifest).exists());
+        assert!(check(&trash)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn classifies_and_fixes_leftovers() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+        let now = SystemTime::now();
+        let old = |path: &Path| -> Result<PathBuf> {
+            File::options()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?
+                .set_modified(now - 2 * GRACE_PERIOD)?;
+            Ok(path.to_owned())
+        };
+
+        // Temporary files of a dead process, of a live one and of an unknown one
+        let dead = trash
+            .root
+            .as_path()
+            .join(format!(".tt-tmp-{}-abc", dead_pid()?));
+        File::create(&dead)?;
+        let live = trash
+            .files
+            .as_path()
+            .join(format!(".tt-tmp-{}-abc", std::process::id()));
+        fs::create_dir(&live)?;
+        let unknown = old(&trash.files.as_path().join(".tt-tmp-abc"))?;
+        // Another tool's temporary file, which is an entry like any other as far as we can tell
+        let foreign = old(&trash.files.as_path().join(".tmpABC123"))?;
+        // Files without info files, and info files without files
+        let lonely = old(&trash.files.as_path().join("lonely"))?;
+        let recent = trash.files.as_path().join("recent");
+        File::create(&recent)?;
+        let gone = old(&trash.info_path().join("gone.trashinfo"))?;
+        let pending = old(&trash.info_path().join("pending.trashinfo"))?;
+        fs::write(
+            marker_path(&trash, "pending".as_ref()),
+            std::process::id().to_string(),
+        )?;
+
+        let names: Vec<_> = entries(&trash)?
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, [".tmpABC123", "lonely", "recent"]);
+
+        let findings = check(&trash)?;
+        assert_eq!(
+            findings,
+            [
+                Finding::OrphanedInfoFile(gone.clone()),
+                Finding::OrphanedInfoFile(pending.clone()),
+                Finding::MissingInfoFile(foreign.clone()),
+                Finding::MissingInfoFile(lonely.clone()),
+                Finding::MissingInfoFile(recent.clone()),
+                Finding::LeftoverTemp(dead.clone()),
+                Finding::LeftoverTemp(live.clone()),
+                Finding::LeftoverTemp(unknown.clone()),
+            ]
+        );
+
+        let mut removed = Vec::new();
+        for finding in &findings {
+            if fix(&RealFs, &trash, finding, GRACE_PERIOD, now)? {
+                removed.push(finding.path().to_owned());
+            }
+        }
+        assert_eq!(removed, [gone, foreign, lonely, dead, unknown]);
+        assert!(live.exists() && recent.exists() && pending.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn crash_after_info_write_leaves_original_intact() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+        let to_be_trashed = dummy_dir(dir.path())?;
+
+        let _crash = crash::arm(CrashPoint::AfterInfoWrite);
+        let err = trash.send_to_trash(&RealFs, &to_be_trashed).unwrap_err();
+        assert!(matches!(err, Error::SimulatedCrash("after_info_write")));
+
+        // Nothing was moved, and the dangling info file can be told apart
+        assert!(to_be_trashed.join("inner").exists());
+        assert_eq!(
+            check(&trash)?,
+            [Finding::OrphanedInfoFile(
+                trash.info_path().join("dummy-dir.trashinfo")
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn crash_after_move_leaves_file_fully_trashed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+        let to_be_trashed = dummy_dir(dir.path())?;
+
+        let _crash = crash::arm(CrashPoint::AfterMove);
+        let err = trash.send_to_trash(&RealFs, &to_be_trashed).unwrap_err();
+        assert!(matches!(err, Error::SimulatedCrash("after_move")));
+
+        assert!(!to_be_trashed.exists());
+        assert!(trash.files.as_path().join("dummy-dir/inner").exists());
+        assert!(check(&trash)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn crash_before_directorysizes_leaves_file_fully_trashed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+        let to_be_trashed = dummy_dir(dir.path())?;
+
+        let _crash = crash::arm(CrashPoint::BeforeDirectorySizes);
+        let err = trash.send_to_trash(&RealFs, &to_be_trashed).unwrap_err();
+        assert!(matches!(err, Error::SimulatedCrash("before_dirsizes")));
+
+        assert!(!to_be_trashed.exists());
+        assert!(trash.files.as_path().join("dummy-dir/inner").exists());
+        assert!(check(&trash)?.is_empty());
+        // The cache simply lacks this directory
+        assert!(fs::read_to_string(&trash.directory_sizes)?.is_empty());
+
+        Ok(())
+    }
+}
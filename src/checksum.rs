@@ -0,0 +1,107 @@
+//! Checksums of trashed files, recorded with `--checksum` in the non-standard `X-TT-Checksum` key of their info files
+//! and checked with `--verify`, so that files that rotted or were tampered with in the trash don't go unnoticed.
+//!
+//! Checksums are written as the name of their algorithm and the hash in hexadecimal, e.g. `sha256:9f86d0...`.
+//! Only regular files get one: directories and symlinks are trashed without.
+
+use std::{ffi::OsStr, io, path::Path};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::Result,
+    info_file::{build_info_file_path, read_info_file},
+    trash::Trash,
+};
+
+/// The algorithm checksums are computed with
+const ALGORITHM: &str = "sha256";
+
+/// What checking an entry against its checksum found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    /// The entry is as it was when trashed
+    Matches,
+    /// The entry changed since it was trashed
+    Mismatch,
+    /// No checksum was recorded for the entry, or not one we know how to check
+    Unrecorded,
+}
+
+/// The checksum of the file at `path`, read in full
+pub fn checksum(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs_err::File::open(path)?, &mut hasher)?;
+
+    Ok(format!("{ALGORITHM}:{:x}", hasher.finalize()))
+}
+
+/// Checks the entry of `trash` named `name` (exactly as in `$trash/files`) against the checksum in its info file.
+pub fn verify(trash: &Trash, name: &OsStr) -> Result<Verification> {
+    let info = read_info_file(&build_info_file_path(name, trash.info_path()))?;
+
+    let recorded = match info.checksum {
+        Some(recorded) if recorded.starts_with(&format!("{ALGORITHM}:")) => recorded,
+        _ => return Ok(Verification::Unrecorded),
+    };
+
+    let actual = checksum(&trash.files.as_path().join(name))?;
+
+    if actual.eq_ignore_ascii_case(&recorded) {
+        Ok(Verification::Matches)
+    } else {
+        Ok(Verification::Mismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{verify, Verification};
+    use crate::{
+        error::Result,
+        fs::RealFs,
+        trash::{Trash, TrashOptions},
+    };
+
+    #[test]
+    fn detects_changed_entries() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        fs::write(&trash.directory_sizes, "")?;
+        let checksummed = TrashOptions {
+            checksum: true,
+            ..TrashOptions::default()
+        };
+
+        fs::write(dir.path().join("kept"), "kept")?;
+        trash.send_to_trash_with(&RealFs, &dir.path().join("kept"), &checksummed)?;
+        fs::write(dir.path().join("rotten"), "rotten")?;
+        trash.send_to_trash_with(&RealFs, &dir.path().join("rotten"), &checksummed)?;
+        fs::write(dir.path().join("unchecked"), "unchecked")?;
+        trash.send_to_trash(&RealFs, &dir.path().join("unchecked"))?;
+        fs::create_dir(dir.path().join("directory"))?;
+        trash.send_to_trash_with(&RealFs, &dir.path().join("directory"), &checksummed)?;
+
+        let info = fs::read_to_string(trash.info_path().join("kept.trashinfo"))?;
+        assert!(info.contains(
+            "\nX-TT-Checksum=sha256:79f076abdd19a752db7267bfff2f9022161d120dea919fdaca2ffdfc24ca8c96\n"
+        ));
+
+        fs::write(trash.files.as_path().join("rotten"), "rotted")?;
+
+        for (name, expected) in [
+            ("kept", Verification::Matches),
+            ("rotten", Verification::Mismatch),
+            ("unchecked", Verification::Unrecorded),
+            ("directory", Verification::Unrecorded),
+        ] {
+            assert_eq!(verify(&trash, name.as_ref())?, expected, "{name}");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1758 @@
+//! Command-line argument handling
+
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::Write,
+    io::Read,
+    os::unix::ffi::OsStrExt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    date::{parse_age, parse_date_argument, parse_deletion_date, DateTime},
+    entries::EntryFilter,
+    error::{Error, Result},
+    listing::{ColorChoice, SortKey},
+    log::Level,
+    policy::Action,
+    restore::{Generations, RestoreOptions},
+};
+
+/// What `tt` was asked to do
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Send the operands to the trash
+    #[default]
+    Trash,
+    /// Report inconsistencies found in the home trash
+    Check,
+    /// Report the files, info files and lines of `directorysizes` of the home trash that don't go with one another
+    Orphans,
+    /// Restore the given trashed entry
+    Restore(OsString),
+    /// Restore the entry of the home trash with the given number, as shown by `--list`
+    RestoreId(u64),
+    /// Restore the entry trashed last, from any trash
+    RestoreLast,
+    /// Restore every entry trashed from the given path or from under it, directories before their contents
+    RestoreUnder(OsString),
+    /// Restore the entries picked with the given command
+    Pick(String),
+    /// Restore or delete entries of the home trash from a shell reading commands on stdin
+    InteractiveRestore,
+    /// List the entries of the home trash
+    List,
+    /// Permanently delete the entries of the home trash, or the ones named as operands
+    Empty,
+    /// Permanently delete the given entry of the home trash, given by its name or the start of it
+    Purge(OsString),
+    /// Browse the home trash interactively
+    #[cfg(feature = "ui")]
+    Ui,
+    /// Find the trashed versions of the given path
+    Which(OsString),
+    /// Print the contents of the given trashed entry
+    Cat(OsString),
+    /// Compare the given trashed entry with what's at its original path
+    Diff(OsString),
+    /// Move entries between the given trashes, given as `home` or as paths
+    Migrate { from: OsString, to: OsString },
+    /// Keep trashes within the limits of their policies, every so often until stopped
+    Daemon,
+    /// Replace identical files in the home trash with hard links to one of them
+    Dedupe,
+    /// Report how many entries every trash holds and how much space they take up
+    Size,
+    /// Print the completion script for the given shell
+    Completions(String),
+    /// Print the candidates for completing the given kind of value, used by the completion scripts
+    Complete(String),
+    /// Print what `tt` accepts
+    Help,
+    /// Print the version of `tt`
+    Version,
+}
+
+/// When to ask before trashing files, as with `rm`: the last of `-i`, `-I` and `-f` wins
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Interactive {
+    #[default]
+    Never,
+    /// Once, before trashing more than three files or any directory (`-I`)
+    Once,
+    /// Before trashing every file (`-i`)
+    Always,
+}
+
+/// How the value of an option is completed by the shell
+#[derive(Debug, Clone, Copy)]
+pub enum Completes {
+    /// Anything, there's nothing to suggest
+    Nothing,
+    /// Paths of files
+    Paths,
+    /// The names of the entries in the trash
+    TrashedEntries,
+    /// One of a fixed set of words
+    Words(&'static [&'static str]),
+}
+
+/// A long option accepted by `tt`
+#[derive(Debug)]
+pub struct Opt {
+    pub long: &'static str,
+    pub short: Option<char>,
+    /// The name of the value this option takes, if any
+    pub value: Option<(&'static str, Completes)>,
+    pub help: &'static str,
+    /// Hidden options are meant for scripts, so they're left out of completions
+    pub hidden: bool,
+}
+
+/// The shells `tt --completions` can generate scripts for
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+/// Every long option `tt` accepts
+pub const OPTIONS: &[Opt] = &[
+    Opt {
+        long: "print",
+        short: None,
+        value: None,
+        help: "Print the original and trashed path of every trashed file, separated by a tab",
+        hidden: false,
+    },
+    Opt {
+        long: "json",
+        short: None,
+        value: None,
+        help: "Print a JSON document describing what was done, failures included, instead of anything else on stdout",
+        hidden: false,
+    },
+    Opt {
+        long: "hook",
+        short: None,
+        value: Some(("CMD", Completes::Nothing)),
+        help: "Run CMD once files are trashed, giving it what --print would print on its stdin",
+        hidden: false,
+    },
+    Opt {
+        long: "override-protection",
+        short: None,
+        value: None,
+        help: "Trash files even if they are protected by the rules of the configuration file",
+        hidden: false,
+    },
+    Opt {
+        long: "policy",
+        short: None,
+        value: Some(("POLICY", Completes::Words(Action::NAMES))),
+        help: "Trash, delete or refuse every file, regardless of the policies of their mount points",
+        hidden: false,
+    },
+    Opt {
+        long: "checksum",
+        short: None,
+        value: None,
+        help: "Record a checksum of every trashed file, to be checked with --verify",
+        hidden: false,
+    },
+    Opt {
+        long: "compress",
+        short: None,
+        value: None,
+        help: "Compress trashed directories into a single archive, which is unpacked when restored",
+        hidden: false,
+    },
+    Opt {
+        long: "confirm-large",
+        short: None,
+        value: None,
+        help: "Ask before trashing directories over the confirm_large thresholds of the configuration file",
+        hidden: false,
+    },
+    Opt {
+        long: "one-file-system",
+        short: None,
+        value: None,
+        help: "Refuse files on another filesystem than the home trash instead of sending them to another trash",
+        hidden: false,
+    },
+    Opt {
+        long: "force-copy",
+        short: None,
+        value: None,
+        help: "Copy files into the trash then remove them, even when they could be renamed there",
+        hidden: false,
+    },
+    Opt {
+        long: "no-directorysizes",
+        short: None,
+        value: None,
+        help: "Neither measure trashed directories nor record their sizes in the directorysizes file of the trash",
+        hidden: false,
+    },
+    Opt {
+        long: "files-from",
+        short: None,
+        value: Some(("FILE", Completes::Paths)),
+        help: "Also trash the files listed in FILE, one per line, or in stdin if FILE is -",
+        hidden: false,
+    },
+    Opt {
+        long: "null",
+        short: Some('0'),
+        value: None,
+        help: "With --files-from, take the files as separated by NUL bytes rather than by newlines",
+        hidden: false,
+    },
+    Opt {
+        long: "interactive",
+        short: Some('i'),
+        value: None,
+        help: "Ask before trashing every file",
+        hidden: false,
+    },
+    Opt {
+        long: "interactive-once",
+        short: Some('I'),
+        value: None,
+        help: "Ask once before trashing more than three files or any directory",
+        hidden: false,
+    },
+    Opt {
+        long: "force",
+        short: Some('f'),
+        value: None,
+        help: "Ignore files that do not exist and never ask before trashing, emptying the trash or purging, undoing -i and -I",
+        hidden: false,
+    },
+    Opt {
+        long: "dir",
+        short: Some('d'),
+        value: None,
+        help: "Trash empty directories, as rm -d does",
+        hidden: false,
+    },
+    Opt {
+        long: "verbose",
+        short: Some('v'),
+        value: None,
+        help: "Tell where every trashed file went, under which name and how it got there, and with -vv, what --stats tells",
+        hidden: false,
+    },
+    Opt {
+        long: "stats",
+        short: None,
+        value: None,
+        help: "Tell how many files were renamed or copied and how long each phase of trashing took, once done",
+        hidden: false,
+    },
+    Opt {
+        long: "log-level",
+        short: None,
+        value: Some(("LEVEL", Completes::Words(Level::NAMES))),
+        help: "Log how things are gone about on stderr, down to LEVEL, overriding TT_LOG",
+        hidden: false,
+    },
+    Opt {
+        long: "quiet",
+        short: Some('q'),
+        value: None,
+        help: "Leave out warnings, such as the trash getting big or a file not being checksummed",
+        hidden: false,
+    },
+    Opt {
+        long: "deletion-date",
+        short: None,
+        value: Some(("DATE", Completes::Nothing)),
+        help: "Record trashed files as trashed at the given RFC 3339 date and time instead of now",
+        hidden: false,
+    },
+    Opt {
+        long: "no-preserve-root",
+        short: None,
+        value: None,
+        help: "Allow trashing / and the top directories of mounted filesystems, which are refused by default",
+        hidden: false,
+    },
+    Opt {
+        long: "preserve-root",
+        short: None,
+        value: None,
+        help: "Refuse to trash / and the top directories of mounted filesystems, undoing --no-preserve-root",
+        hidden: false,
+    },
+    Opt {
+        long: "no-preserve-context",
+        short: None,
+        value: None,
+        help: "Leave out SELinux contexts and other security attributes when copying files to another filesystem",
+        hidden: false,
+    },
+    Opt {
+        long: "check",
+        short: None,
+        value: None,
+        help: "Report inconsistencies found in the home trash",
+        hidden: false,
+    },
+    Opt {
+        long: "orphans",
+        short: None,
+        value: None,
+        help: "Report files without an info file, info files without a file and stale lines of directorysizes, changing nothing",
+        hidden: false,
+    },
+    Opt {
+        long: "fix",
+        short: None,
+        value: None,
+        help: "With --check, remove what interrupted runs left behind",
+        hidden: false,
+    },
+    Opt {
+        long: "verify",
+        short: None,
+        value: None,
+        help: "With --restore, refuse to restore files that changed since they were trashed. With --check, report them",
+        hidden: false,
+    },
+    Opt {
+        long: "restore",
+        short: None,
+        value: Some(("NAME", Completes::TrashedEntries)),
+        help: "Restore a trashed file to its original location",
+        hidden: false,
+    },
+    Opt {
+        long: "restore-id",
+        short: None,
+        value: Some(("N", Completes::Nothing)),
+        help: "Restore the trashed file numbered N by the last tt --list",
+        hidden: false,
+    },
+    Opt {
+        long: "restore-last",
+        short: None,
+        value: None,
+        help: "Restore the file trashed last, to undo the latest trashing",
+        hidden: false,
+    },
+    Opt {
+        long: "restore-under",
+        short: None,
+        value: Some(("PATH", Completes::Paths)),
+        help: "Restore every file trashed from PATH or from under it, directories before the files trashed from them",
+        hidden: false,
+    },
+    Opt {
+        long: "picker",
+        short: None,
+        value: Some(("COMMAND", Completes::Nothing)),
+        help: "Restore the trashed files picked with COMMAND, such as fzf --multi, from a list of them",
+        hidden: false,
+    },
+    Opt {
+        long: "by-path",
+        short: None,
+        value: None,
+        help: "Match the patterns given to --restore and --purge against where files were trashed from, not their names",
+        hidden: false,
+    },
+    Opt {
+        long: "restore-to",
+        short: None,
+        value: Some(("DIR", Completes::Paths)),
+        help: "Restore into DIR, keeping the name the file was trashed with, rather than to where it was trashed from",
+        hidden: false,
+    },
+    Opt {
+        long: "generation",
+        short: None,
+        value: Some(("N", Completes::Nothing)),
+        help: "When restoring a path trashed more than once, restore its Nth most recently trashed version",
+        hidden: false,
+    },
+    Opt {
+        long: "all",
+        short: None,
+        value: None,
+        help: "When restoring a path trashed more than once, restore all of its versions",
+        hidden: false,
+    },
+    Opt {
+        long: "dry-run",
+        short: None,
+        value: None,
+        help: "Only show what would be trashed and where to, or with --restore what would be restored",
+        hidden: false,
+    },
+    Opt {
+        long: "list",
+        short: None,
+        value: None,
+        help: "List the files in the trash",
+        hidden: false,
+    },
+    Opt {
+        long: "tree",
+        short: None,
+        value: None,
+        help: "With --list, show the files as a tree of the directories they were trashed from",
+        hidden: false,
+    },
+    Opt {
+        long: "sort",
+        short: None,
+        value: Some(("KEY", Completes::Words(SortKey::NAMES))),
+        help: "With --list, order the files by date (newest first, the default), size (largest first), name or path",
+        hidden: false,
+    },
+    Opt {
+        long: "reverse",
+        short: None,
+        value: None,
+        help: "With --list, show the files in the opposite order",
+        hidden: false,
+    },
+    Opt {
+        long: "no-truncate",
+        short: None,
+        value: None,
+        help: "With --list, show original paths whole even when they make lines wider than the terminal",
+        hidden: false,
+    },
+    Opt {
+        long: "color",
+        short: None,
+        value: Some(("WHEN", Completes::Words(ColorChoice::NAMES))),
+        help: "With --list, color names by the kind of file when auto, always or never, auto by default",
+        hidden: false,
+    },
+    Opt {
+        long: "empty",
+        short: None,
+        value: None,
+        help: "Permanently delete the files in the trash, or only the ones named as operands",
+        hidden: false,
+    },
+    Opt {
+        long: "purge",
+        short: None,
+        value: Some(("NAME", Completes::TrashedEntries)),
+        help: "Permanently delete the trashed file NAME, or the only one whose name starts with NAME",
+        hidden: false,
+    },
+    Opt {
+        long: "keep-last",
+        short: None,
+        value: Some(("N", Completes::Nothing)),
+        help: "With --empty, keep the N most recently trashed files",
+        hidden: false,
+    },
+    Opt {
+        long: "shred",
+        short: None,
+        value: None,
+        help: "With --empty, overwrite the contents of files with zeros before deleting them",
+        hidden: false,
+    },
+    Opt {
+        long: "interactive-restore",
+        short: None,
+        value: None,
+        help: "Restore, delete or inspect entries of the trash from a shell reading commands on stdin",
+        hidden: false,
+    },
+    #[cfg(feature = "ui")]
+    Opt {
+        long: "ui",
+        short: None,
+        value: None,
+        help: "Browse the trash interactively, to restore or delete files",
+        hidden: false,
+    },
+    Opt {
+        long: "yes",
+        short: Some('y'),
+        value: None,
+        help: "Answer every question with yes, such as the confirmation of --empty",
+        hidden: false,
+    },
+    Opt {
+        long: "since",
+        short: None,
+        value: Some(("DATE", Completes::Nothing)),
+        help: "Only list, empty or pick from files trashed at or after DATE",
+        hidden: false,
+    },
+    Opt {
+        long: "before",
+        short: None,
+        value: Some(("DATE", Completes::Nothing)),
+        help: "Only list, empty or pick from files trashed before DATE",
+        hidden: false,
+    },
+    Opt {
+        long: "after",
+        short: None,
+        value: Some(("DATE", Completes::Nothing)),
+        help: "Same as --since",
+        hidden: true,
+    },
+    Opt {
+        long: "older-than",
+        short: None,
+        value: Some(("DAYS", Completes::Nothing)),
+        help: "Only list, empty or pick from files trashed more than DAYS days ago, as with --before DAYSd",
+        hidden: false,
+    },
+    Opt {
+        long: "include-undated",
+        short: None,
+        value: None,
+        help: "Also list or empty files without a deletion date when filtering by date",
+        hidden: false,
+    },
+    Opt {
+        long: "include-invalid",
+        short: None,
+        value: None,
+        help: "Same as --include-undated",
+        hidden: true,
+    },
+    Opt {
+        long: "which",
+        short: None,
+        value: Some(("PATH", Completes::Paths)),
+        help: "Find the trashed versions of PATH",
+        hidden: false,
+    },
+    Opt {
+        long: "recursive",
+        short: Some('r'),
+        value: None,
+        help: "Trash directories along with what they hold, as rm -r (or -R) does, or with --which, also find files trashed from under PATH",
+        hidden: false,
+    },
+    Opt {
+        long: "cat",
+        short: None,
+        value: Some(("NAME", Completes::TrashedEntries)),
+        help: "Print the contents of the trashed file NAME (or trashed from the path NAME) without restoring it",
+        hidden: false,
+    },
+    Opt {
+        long: "info",
+        short: None,
+        value: None,
+        help: "With --cat, print the info file of the trashed file instead",
+        hidden: false,
+    },
+    Opt {
+        long: "diff",
+        short: None,
+        value: Some(("NAME", Completes::TrashedEntries)),
+        help: "Show how the trashed file NAME differs from what is now at its original path",
+        hidden: false,
+    },
+    Opt {
+        long: "tool",
+        short: None,
+        value: Some(("CMD", Completes::Nothing)),
+        help: "With --diff, compare with CMD, given the trashed and the original path",
+        hidden: false,
+    },
+    Opt {
+        long: "migrate",
+        short: None,
+        value: None,
+        help: "Move the entries given as operands (or every entry) from the trash given by --from to the one given by --to",
+        hidden: false,
+    },
+    Opt {
+        long: "from",
+        short: None,
+        value: Some(("TRASH", Completes::Paths)),
+        help: "With --migrate, the trash to move entries from, given as home or as a path",
+        hidden: false,
+    },
+    Opt {
+        long: "to",
+        short: None,
+        value: Some(("TRASH", Completes::Paths)),
+        help: "With --migrate, the trash to move entries to, given as home or as a path",
+        hidden: false,
+    },
+    Opt {
+        long: "dedupe",
+        short: None,
+        value: None,
+        help: "Replace identical files in the trash with hard links to one of them, to free space",
+        hidden: false,
+    },
+    Opt {
+        long: "size",
+        short: None,
+        value: None,
+        help: "Report how many entries each trash holds and how much space they take up",
+        hidden: false,
+    },
+    Opt {
+        long: "daemon",
+        short: None,
+        value: None,
+        help: "Stay in the foreground, purging what is past the retention or quota of their policy from the trashes every so often",
+        hidden: false,
+    },
+    Opt {
+        long: "interval",
+        short: None,
+        value: Some(("DURATION", Completes::Nothing)),
+        help: "With --daemon, how long to wait between purges, such as 30m or 6h, the default",
+        hidden: false,
+    },
+    Opt {
+        long: "once",
+        short: None,
+        value: None,
+        help: "With --daemon, purge once and exit",
+        hidden: false,
+    },
+    Opt {
+        long: "all-trashes",
+        short: None,
+        value: None,
+        help: "With --list, --empty, --size or --daemon, go over every trash of the current user instead of only the home trash",
+        hidden: false,
+    },
+    Opt {
+        long: "mount",
+        short: None,
+        value: Some(("PATH", Completes::Paths)),
+        help: "With --empty, only empty the trash of the mount point the given path is on",
+        hidden: false,
+    },
+    Opt {
+        long: "trash",
+        short: None,
+        value: Some(("TRASH", Completes::Paths)),
+        help: "Operate on the trash directory at the given path instead of finding the trashes of the current user",
+        hidden: false,
+    },
+    Opt {
+        long: "trash-dir",
+        short: None,
+        value: Some(("TRASH", Completes::Paths)),
+        help: "Same as --trash",
+        hidden: true,
+    },
+    Opt {
+        long: "no-verify-trash",
+        short: None,
+        value: None,
+        help: "Use trash directories even if other users could tamper with them",
+        hidden: false,
+    },
+    Opt {
+        long: "completions",
+        short: None,
+        value: Some(("SHELL", Completes::Words(SHELLS))),
+        help: "Print the completion script for the given shell",
+        hidden: false,
+    },
+    Opt {
+        long: "help",
+        short: Some('h'),
+        value: None,
+        help: "Print this help and exit",
+        hidden: false,
+    },
+    Opt {
+        long: "version",
+        short: Some('V'),
+        value: None,
+        help: "Print the version of tt and exit",
+        hidden: false,
+    },
+    Opt {
+        long: "__complete",
+        short: None,
+        value: Some(("KIND", Completes::Words(&["restore"]))),
+        help: "Print completion candidates for the given kind of value",
+        hidden: true,
+    },
+];
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub mode: Mode,
+    /// Whether trashed files are reported in a machine-readable way (see [`crate::report`])
+    pub print: bool,
+    /// Whether what was done is reported as a JSON document (see [`crate::json`])
+    pub json: bool,
+    /// The command run after trashing files, overriding the configuration file's
+    pub hook: Option<String>,
+    /// Whether files protected by the configuration file's rules may be trashed
+    pub override_protection: bool,
+    /// What to do with every file, overriding the per-mount policies of the configuration file
+    pub policy: Option<Action>,
+    /// Whether a checksum of trashed files is recorded in their info files
+    pub checksum: bool,
+    /// Whether trashed directories are compressed, regardless of their size
+    pub compress: bool,
+    /// Whether trashing directories over the configuration file's thresholds is confirmed first
+    pub confirm_large: bool,
+    /// Whether files on another filesystem than the trash are refused rather than sent to the trash of their own
+    pub one_file_system: bool,
+    /// Whether files are copied into the trash then removed, even when they could be renamed there
+    pub force_copy: bool,
+    /// Whether trashed directories are left out of `directorysizes`, and so left unmeasured
+    pub no_directorysizes: bool,
+    /// When trashed files are recorded as trashed, instead of now
+    pub deletion_date: Option<Duration>,
+    /// Whether empty directories may be trashed, as with `rm -d`
+    pub dir: bool,
+    /// Whether security attributes are left behind when files are copied to another filesystem
+    pub no_preserve_context: bool,
+    /// Whether `/` and the top directories of mount points may be trashed
+    pub no_preserve_root: bool,
+    /// Whether warnings, such as the trash getting big, are left out
+    pub quiet: bool,
+    /// Whether every trashed file is reported along with its path in the trash, and how it got there, and errors come
+    /// with more detail
+    pub verbose: bool,
+    /// Whether statistics about trashing are printed once done, with `--stats` or `-v` given twice
+    pub stats: bool,
+    /// Down to which level things are logged, overriding `TT_LOG`
+    pub log_level: Option<Level>,
+    /// When to ask before trashing files
+    pub interactive: Interactive,
+    /// Whether operands that don't exist are skipped without a word, as with `rm -f`
+    pub force: bool,
+    /// Whether patterns given to `--restore` and `--purge` match original paths rather than names in the trash
+    pub by_path: bool,
+    /// The directory `--restore` and `--restore-last` restore into, rather than to where entries were trashed from
+    pub restore_to: Option<OsString>,
+    /// Which versions `--restore` restores when given a path that was trashed more than once
+    pub generations: Generations,
+    /// Whether trashing and `--restore` should only check and report what they would do
+    pub dry_run: bool,
+    /// Whether `--check` should remove the leftovers it finds
+    pub fix: bool,
+    /// Whether `--restore` and `--check` check entries against their checksums
+    pub verify: bool,
+    /// Which entries `--list`, `--empty`, `--picker` and `--migrate` operate on
+    pub filter: EntryFilter,
+    /// Whether `--list` shows a tree of where entries were trashed from
+    pub tree: bool,
+    /// What `--list` orders entries by
+    pub sort: SortKey,
+    /// Whether `--list` shows entries in the opposite order
+    pub reverse: bool,
+    /// Whether `--list` shows original paths whole, however wide the terminal is
+    pub no_truncate: bool,
+    /// When `--list` colors names
+    pub color: ColorChoice,
+    /// How many of the most recently trashed entries `--empty` keeps
+    pub keep_last: Option<usize>,
+    /// Whether `--empty` overwrites the contents of files before deleting them
+    pub shred: bool,
+    /// Whether every question (see [`crate::prompt`]) is answered with yes beforehand
+    pub yes: bool,
+    /// Whether directories may be trashed along with what they hold, as with `rm -r`, and whether `--which` also
+    /// matches files trashed from under the given path
+    pub recursive: bool,
+    /// Whether `--cat` prints the info file rather than the trashed file
+    pub info: bool,
+    /// The command `--diff` compares with instead of showing its own diff
+    pub tool: Option<String>,
+    /// The trash `--migrate` moves entries from
+    pub from: Option<OsString>,
+    /// The trash `--migrate` moves entries to
+    pub to: Option<OsString>,
+    /// How long `--daemon` waits between passes, unless the default
+    pub interval: Option<Duration>,
+    /// Whether `--daemon` makes a single pass
+    pub once: bool,
+    /// Whether `--daemon` goes over every trash of the current user, rather than only the home trash
+    pub all_trashes: bool,
+    /// A path on the mount point whose trash `--empty` empties, rather than the home trash
+    pub mount: Option<OsString>,
+    /// The trash to operate on, instead of the ones of the current user
+    pub trash: Option<OsString>,
+    /// Whether trash directories are used without checking that they're safe to use
+    pub no_verify_trash: bool,
+    /// The files to operate on
+    pub operands: Vec<OsString>,
+    /// The file listing more files to trash, `-` being stdin
+    pub files_from: Option<OsString>,
+    /// Whether the files listed in `files_from` are separated by NUL bytes rather than by newlines
+    pub null: bool,
+}
+
+/// The option `arg` is, given as `--long`, `--long=VALUE` or `-s`, along with the value given inline, if any
+fn find_option(arg: &OsStr) -> Option<(&'static Opt, Option<OsString>)> {
+    let bytes = arg.as_bytes();
+
+    if let Some(long) = bytes.strip_prefix(b"--") {
+        let (long, value) = match long.iter().position(|&byte| byte == b'=') {
+            Some(equals) => (
+                &long[..equals],
+                Some(OsStr::from_bytes(&long[equals + 1..]).to_owned()),
+            ),
+            None => (long, None),
+        };
+        let option = OPTIONS
+            .iter()
+            .find(|option| option.long.as_bytes() == long)?;
+        return Some((option, value));
+    }
+
+    let mut short = std::str::from_utf8(bytes.strip_prefix(b"-")?).ok()?.chars();
+    let (short, None) = (short.next()?, short.next()) else {
+        return None;
+    };
+    // rm takes -R as well as -r
+    let short = if short == 'R' { 'r' } else { short };
+    let option = OPTIONS.iter().find(|option| option.short == Some(short))?;
+
+    Some((option, None))
+}
+
+/// The short options bundled in `arg`, as in `-rf`, each as an argument of its own, or `None` if `arg` isn't a bundle.
+///
+/// Fails with the first character that isn't a short option. As none of them takes a value, `-rfv` is always `-r -f
+/// -v`, as with `rm`.
+fn split_bundle(arg: &OsStr) -> Option<std::result::Result<Vec<OsString>, char>> {
+    let shorts = std::str::from_utf8(arg.as_bytes().strip_prefix(b"-")?).ok()?;
+    if shorts.starts_with('-') || shorts.chars().nth(1).is_none() {
+        return None;
+    }
+
+    let split = shorts
+        .chars()
+        .map(|short| {
+            let option = OsString::from(format!("-{short}"));
+            find_option(&option).map(|_| option).ok_or(short)
+        })
+        .collect();
+
+    Some(split)
+}
+
+/// Reads the files listed by `--files-from` from `list`, separated by `delimiter`. Their names are taken byte for
+/// byte, and empty ones (such as after a trailing delimiter) are left out.
+pub fn read_file_list(mut list: impl Read, delimiter: u8) -> Result<Vec<OsString>> {
+    let mut bytes = Vec::new();
+    list.read_to_end(&mut bytes)?;
+
+    Ok(bytes
+        .split(|&byte| byte == delimiter)
+        .filter(|name| !name.is_empty())
+        .map(|name| OsStr::from_bytes(name).to_owned())
+        .collect())
+}
+
+/// What `tt --help` prints: a synopsis, then every option along with what it does
+pub fn help() -> String {
+    let mut help = String::from(
+        "Usage: tt [OPTION]... [--] FILE...\n\
+         Sends files to the trash, or works with what it holds as told by the options.\n\n\
+         Options:\n",
+    );
+
+    let options: Vec<(String, &str)> = OPTIONS
+        .iter()
+        .filter(|option| !option.hidden)
+        .map(|option| {
+            let short = option
+                .short
+                .map_or_else(|| "    ".to_owned(), |short| format!("-{short}, "));
+            let value = option
+                .value
+                .map_or_else(String::new, |(name, _)| format!(" {name}"));
+            (format!("{short}--{}{value}", option.long), option.help)
+        })
+        .collect();
+    let width = options
+        .iter()
+        .map(|(usage, _)| usage.len())
+        .max()
+        .unwrap_or(0);
+    for (usage, text) in options {
+        let _ = writeln!(help, "  {usage:width$}  {text}");
+    }
+
+    help
+}
+
+/// The environment variable holding options given before the command line's (see [`default_options`])
+pub const OPTS_VAR: &str = "TT_OPTS";
+
+/// Splits `text`, the value of `TT_OPTS`, into words as `sh` would, which are parsed before the arguments given on
+/// the command line, so that these override them.
+///
+/// Only options and their values may be given this way: anything else would be taken as a file to trash, so it's
+/// refused with [`Error::Usage`]. So is `--files-from`, which names files to trash too, and `--null`, which only
+/// goes along with it.
+pub fn default_options(text: &OsStr) -> Result<Vec<OsString>> {
+    let text = text
+        .to_str()
+        .ok_or_else(|| Error::Usage(format!("{OPTS_VAR} is not valid UTF-8")))?;
+    let words: Vec<OsString> = split_words(text)?.into_iter().map(Into::into).collect();
+    let refuse_file_lists = |option: &Opt| match option.long {
+        "files-from" | "null" => Err(Error::Usage(format!(
+            "{OPTS_VAR} may only hold options that don't bring in files to trash, not --{}",
+            option.long
+        ))),
+        _ => Ok(()),
+    };
+
+    let mut rest = words.iter();
+    while let Some(word) = rest.next() {
+        if let Some(bundle) = split_bundle(word) {
+            let shorts = bundle.map_err(|unknown| {
+                Error::Usage(format!(
+                    "unknown option -{unknown} in {} in {OPTS_VAR}, see tt --help",
+                    word.to_string_lossy()
+                ))
+            })?;
+            for (option, _) in shorts.iter().filter_map(|short| find_option(short)) {
+                refuse_file_lists(option)?;
+            }
+            continue;
+        }
+        let Some((option, inline_value)) = find_option(word) else {
+            let word = word.to_string_lossy();
+            return Err(Error::Usage(
+                if word.starts_with('-') && word.len() > 1 && word != "--" {
+                    format!("unknown option {word} in {OPTS_VAR}, see tt --help")
+                } else {
+                    format!("{OPTS_VAR} may only hold options, not {word}")
+                },
+            ));
+        };
+        refuse_file_lists(option)?;
+
+        if option.value.is_some() && inline_value.is_none() && rest.next().is_none() {
+            return Err(Error::Usage(format!(
+                "--{} requires an argument, which {OPTS_VAR} lacks",
+                option.long
+            )));
+        }
+    }
+
+    Ok(words)
+}
+
+/// Splits `text` into words as `sh` would, without expanding anything: words are separated by whitespace,
+/// which single and double quotes or a backslash keep from doing so. In double quotes, a backslash only escapes
+/// `"`, `\`, `$` and `` ` ``.
+fn split_words(text: &str) -> Result<Vec<String>> {
+    let unterminated = || Error::Usage(format!("unterminated quote in {OPTS_VAR}"));
+
+    let mut words = Vec::new();
+    // `None` between words, so that quotes alone (`''`) make an empty word
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(unterminated)? {
+                            c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                let c = chars.next().ok_or_else(|| {
+                    Error::Usage(format!("{OPTS_VAR} ends with a lone backslash"))
+                })?;
+                word.get_or_insert_with(String::new).push(c);
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    Ok(words)
+}
+
+impl Args {
+    /// Parses the given arguments (not including the program name)
+    pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        // What's left of a bundle of short options, last first
+        let mut bundled = Vec::new();
+
+        while let Some(arg) = bundled.pop().or_else(|| args.next()) {
+            // Whatever comes after `--` is a file, even if it starts with `-`
+            if arg == "--" {
+                parsed.operands.extend(args.by_ref());
+                break;
+            }
+
+            if let Some(bundle) = split_bundle(&arg) {
+                let bundle = bundle.map_err(|unknown| {
+                    Error::Usage(format!(
+                        "unknown option -{unknown} in {}, see tt --help (files starting with - go after --)",
+                        arg.to_string_lossy()
+                    ))
+                })?;
+                bundled.extend(bundle.into_iter().rev());
+                continue;
+            }
+
+            let Some((option, inline_value)) = find_option(&arg) else {
+                if arg.as_bytes().starts_with(b"-") && arg != "-" {
+                    return Err(Error::Usage(format!(
+                        "unknown option {}, see tt --help (files starting with - go after --)",
+                        arg.to_string_lossy()
+                    )));
+                }
+                parsed.operands.push(arg);
+                continue;
+            };
+
+            let value = match (option.value, inline_value) {
+                (Some(_), Some(value)) => Some(value),
+                (Some((name, _)), None) => Some(args.next().ok_or_else(|| {
+                    Error::Usage(format!("--{} requires a {} argument", option.long, name))
+                })?),
+                (None, Some(_)) => {
+                    return Err(Error::Usage(format!(
+                        "--{} doesn't take an argument",
+                        option.long
+                    )))
+                }
+                (None, None) => None,
+            };
+
+            match (option.long, value) {
+                ("print", _) => parsed.print = true,
+                ("hook", Some(command)) => parsed.hook = Some(command.to_string_lossy().into()),
+                ("override-protection", _) => parsed.override_protection = true,
+                ("policy", Some(policy)) => parsed.policy = Some(policy.to_string_lossy().parse()?),
+                ("checksum", _) => parsed.checksum = true,
+                ("compress", _) => parsed.compress = true,
+                ("confirm-large", _) => parsed.confirm_large = true,
+                ("one-file-system", _) => parsed.one_file_system = true,
+                ("force-copy", _) => parsed.force_copy = true,
+                ("no-directorysizes", _) => parsed.no_directorysizes = true,
+                ("quiet", _) => parsed.quiet = true,
+                ("verbose", _) => {
+                    // Given twice, as in -vv
+                    parsed.stats |= parsed.verbose;
+                    parsed.verbose = true;
+                }
+                ("stats", _) => parsed.stats = true,
+                ("log-level", Some(level)) => {
+                    parsed.log_level = Some(level.to_string_lossy().parse()?)
+                }
+                ("files-from", Some(list)) => parsed.files_from = Some(list),
+                ("null", _) => parsed.null = true,
+                ("interactive", _) => {
+                    parsed.interactive = Interactive::Always;
+                    parsed.force = false;
+                }
+                ("interactive-once", _) => {
+                    parsed.interactive = Interactive::Once;
+                    parsed.force = false;
+                }
+                ("force", _) => {
+                    parsed.interactive = Interactive::Never;
+                    parsed.force = true;
+                }
+                ("json", _) => parsed.json = true,
+                ("deletion-date", Some(date)) => {
+                    parsed.deletion_date = Some(parse_deletion_date(&date.to_string_lossy())?)
+                }
+                ("dir", _) => parsed.dir = true,
+                ("no-preserve-context", _) => parsed.no_preserve_context = true,
+                ("no-preserve-root", _) => parsed.no_preserve_root = true,
+                ("preserve-root", _) => parsed.no_preserve_root = false,
+                ("check", _) => parsed.mode = Mode::Check,
+                ("orphans", _) => parsed.mode = Mode::Orphans,
+                ("fix", _) => parsed.fix = true,
+                ("verify", _) => parsed.verify = true,
+                ("restore", Some(name)) => parsed.mode = Mode::Restore(name),
+                ("restore-id", Some(id)) => {
+                    let id = id.to_str().and_then(|id| id.parse().ok()).ok_or_else(|| {
+                        Error::Usage(format!(
+                            "--restore-id expects the number of an entry, as shown by tt --list, got {}",
+                            id.to_string_lossy()
+                        ))
+                    })?;
+                    parsed.mode = Mode::RestoreId(id);
+                }
+                ("restore-last", _) => parsed.mode = Mode::RestoreLast,
+                ("restore-under", Some(path)) => parsed.mode = Mode::RestoreUnder(path),
+                ("picker", Some(command)) => {
+                    parsed.mode = Mode::Pick(command.to_string_lossy().into())
+                }
+                ("by-path", _) => parsed.by_path = true,
+                ("restore-to", Some(directory)) => parsed.restore_to = Some(directory),
+                ("generation", Some(n)) => {
+                    let version = n
+                        .to_str()
+                        .and_then(|n| n.parse().ok())
+                        .filter(|&version| version >= 1)
+                        .ok_or_else(|| {
+                            Error::Usage(format!(
+                                "--generation expects a positive number, got {}",
+                                n.to_string_lossy()
+                            ))
+                        })?;
+                    parsed.generations = Generations::Version(version);
+                }
+                ("all", _) => parsed.generations = Generations::All,
+                ("dry-run", _) => parsed.dry_run = true,
+                ("list", _) => parsed.mode = Mode::List,
+                ("empty", _) => parsed.mode = Mode::Empty,
+                ("purge", Some(name)) => parsed.mode = Mode::Purge(name),
+                ("tree", _) => parsed.tree = true,
+                ("sort", Some(key)) => parsed.sort = key.to_string_lossy().parse()?,
+                ("color", Some(when)) => parsed.color = when.to_string_lossy().parse()?,
+                ("reverse", _) => parsed.reverse = true,
+                ("no-truncate", _) => parsed.no_truncate = true,
+                ("keep-last", Some(n)) => {
+                    let count = n.to_str().and_then(|n| n.parse().ok()).ok_or_else(|| {
+                        Error::Usage(format!(
+                            "--keep-last expects a number, got {}",
+                            n.to_string_lossy()
+                        ))
+                    })?;
+                    parsed.keep_last = Some(count);
+                }
+                ("shred", _) => parsed.shred = true,
+                ("interactive-restore", _) => parsed.mode = Mode::InteractiveRestore,
+                #[cfg(feature = "ui")]
+                ("ui", _) => parsed.mode = Mode::Ui,
+                ("yes", _) => parsed.yes = true,
+                ("since" | "after", Some(date)) => {
+                    parsed.filter.since = Some(parse_date_argument(&date.to_string_lossy(), now)?)
+                }
+                ("before", Some(date)) => {
+                    parsed.filter.before = Some(parse_date_argument(&date.to_string_lossy(), now)?)
+                }
+                ("older-than", Some(days)) => {
+                    let before = days
+                        .to_str()
+                        .and_then(|days| days.trim().parse::<u64>().ok())
+                        .and_then(|days| days.checked_mul(24 * 60 * 60))
+                        .and_then(|age| now.checked_sub(Duration::from_secs(age)))
+                        .ok_or_else(|| {
+                            Error::Usage(format!(
+                                "--older-than expects a number of days, got {}",
+                                days.to_string_lossy()
+                            ))
+                        })?;
+                    parsed.filter.before = Some(DateTime::from_timestamp(before)?);
+                }
+                ("include-undated" | "include-invalid", _) => parsed.filter.include_undated = true,
+                ("which", Some(path)) => parsed.mode = Mode::Which(path),
+                ("recursive", _) => parsed.recursive = true,
+                ("cat", Some(name)) => parsed.mode = Mode::Cat(name),
+                ("info", _) => parsed.info = true,
+                ("diff", Some(name)) => parsed.mode = Mode::Diff(name),
+                ("tool", Some(command)) => parsed.tool = Some(command.to_string_lossy().into()),
+                ("migrate", _) => {
+                    parsed.mode = Mode::Migrate {
+                        from: OsString::new(),
+                        to: OsString::new(),
+                    }
+                }
+                ("from", Some(trash)) => parsed.from = Some(trash),
+                ("to", Some(trash)) => parsed.to = Some(trash),
+                ("dedupe", _) => parsed.mode = Mode::Dedupe,
+                ("size", _) => parsed.mode = Mode::Size,
+                ("daemon", _) => parsed.mode = Mode::Daemon,
+                ("interval", Some(interval)) => {
+                    let interval = interval
+                        .to_str()
+                        .and_then(|interval| parse_age(interval.trim()))
+                        .filter(|interval| !interval.is_zero())
+                        .ok_or_else(|| {
+                            Error::Usage(format!(
+                                "--interval expects a duration such as 30m or 6h, got {}",
+                                interval.to_string_lossy()
+                            ))
+                        })?;
+                    parsed.interval = Some(interval);
+                }
+                ("once", _) => parsed.once = true,
+                ("all-trashes", _) => parsed.all_trashes = true,
+                ("mount", Some(path)) => parsed.mount = Some(path),
+                ("trash" | "trash-dir", Some(trash)) => parsed.trash = Some(trash),
+                ("no-verify-trash", _) => parsed.no_verify_trash = true,
+                ("completions", Some(shell)) => {
+                    parsed.mode = Mode::Completions(shell.to_string_lossy().into())
+                }
+                ("__complete", Some(kind)) => {
+                    parsed.mode = Mode::Complete(kind.to_string_lossy().into())
+                }
+                ("help", _) => parsed.mode = Mode::Help,
+                ("version", _) => parsed.mode = Mode::Version,
+                _ => unreachable!("option --{} is not handled", option.long),
+            }
+        }
+
+        if parsed.files_from.is_some() && parsed.mode != Mode::Trash {
+            return Err(Error::Usage(
+                "--files-from only lists files to trash".into(),
+            ));
+        }
+        if parsed.all_trashes
+            && !matches!(
+                parsed.mode,
+                Mode::List | Mode::Empty | Mode::Size | Mode::Orphans | Mode::Daemon
+            )
+        {
+            return Err(Error::Usage(
+                "--all-trashes only applies to --list, --empty, --size, --orphans and --daemon"
+                    .into(),
+            ));
+        }
+        if parsed.all_trashes && parsed.trash.is_some() {
+            return Err(Error::Usage(
+                "--all-trashes and --trash can't be used together".into(),
+            ));
+        }
+        if parsed.mount.is_some() {
+            if parsed.mode != Mode::Empty {
+                return Err(Error::Usage("--mount only applies to --empty".into()));
+            }
+            if parsed.all_trashes || parsed.trash.is_some() {
+                return Err(Error::Usage(
+                    "--mount can't be used along with --all-trashes or --trash".into(),
+                ));
+            }
+        }
+        if parsed.by_path && !matches!(parsed.mode, Mode::Restore(_) | Mode::Purge(_)) {
+            return Err(Error::Usage(
+                "--by-path only applies to --restore and --purge".into(),
+            ));
+        }
+        if parsed.restore_to.is_some()
+            && !matches!(
+                parsed.mode,
+                Mode::Restore(_) | Mode::RestoreId(_) | Mode::RestoreLast
+            )
+        {
+            return Err(Error::Usage(
+                "--restore-to only applies to --restore, --restore-id and --restore-last".into(),
+            ));
+        }
+        if parsed.null && parsed.files_from.is_none() {
+            return Err(Error::Usage("-0 only applies to --files-from".into()));
+        }
+
+        if let Mode::Migrate { from, to } = &mut parsed.mode {
+            let missing = |option| Error::Usage(format!("--migrate requires {option}"));
+            *from = parsed.from.clone().ok_or_else(|| missing("--from"))?;
+            *to = parsed.to.clone().ok_or_else(|| missing("--to"))?;
+        }
+
+        if parsed.json {
+            let reported = matches!(
+                parsed.mode,
+                Mode::Trash
+                    | Mode::List
+                    | Mode::Empty
+                    | Mode::Restore(_)
+                    | Mode::RestoreId(_)
+                    | Mode::RestoreLast
+                    | Mode::RestoreUnder(_)
+                    | Mode::Pick(_)
+                    | Mode::Check
+            );
+            if !reported {
+                return Err(Error::Usage(
+                    "--json only applies to trashing, --list, --empty, --restore, --restore-id, --restore-last, --restore-under, --picker and --check"
+                        .into(),
+                ));
+            }
+            if parsed.print {
+                return Err(Error::Usage(
+                    "--json and --print can't be used together".into(),
+                ));
+            }
+            if parsed.tree {
+                return Err(Error::Usage(
+                    "--json and --tree can't be used together".into(),
+                ));
+            }
+            if parsed.all_trashes && parsed.mode == Mode::Empty {
+                return Err(Error::Usage(
+                    "--json only reports on emptying a single trash, not with --all-trashes".into(),
+                ));
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// How entries are restored, as told by `--dry-run`, `--verify` and `--no-preserve-context`
+    pub fn restore_options(&self) -> RestoreOptions {
+        RestoreOptions {
+            dry_run: self.dry_run,
+            verify: self.verify,
+            preserve_context: !self.no_preserve_context,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::{OsStr, OsString},
+        os::unix::ffi::OsStrExt,
+    };
+
+    use super::{default_options, help, read_file_list, Args, Interactive, Mode, OPTIONS};
+    use crate::{date::DateTime, error::Result, policy::Action, restore::Generations, Error};
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn parses_mode_and_operands() -> Result<()> {
+        let args = parse(&["a", "b"])?;
+        assert_eq!(args.mode, Mode::Trash);
+        assert_eq!(args.operands, ["a", "b"]);
+        assert!(!args.no_verify_trash);
+        assert!(parse(&["--no-verify-trash", "a"])?.no_verify_trash);
+        assert_eq!(args.trash, None);
+        assert_eq!(args.deletion_date, None);
+        assert_eq!(
+            parse(&["--deletion-date", "2004-08-31T22:32:08Z", "a"])?.deletion_date,
+            Some(std::time::Duration::from_secs(1_093_991_528))
+        );
+        assert!(matches!(
+            parse(&["--deletion-date", "1969-12-31T00:00:00Z", "a"]),
+            Err(Error::InvalidDeletionDate(_))
+        ));
+        assert_eq!(
+            parse(&["--list", "--trash", "/mnt/image/.Trash-1001"])?.trash,
+            Some("/mnt/image/.Trash-1001".into())
+        );
+        assert_eq!(
+            parse(&["--trash-dir", "/mnt/backup/.Trash-1000", "file.txt"])?.trash,
+            Some("/mnt/backup/.Trash-1000".into())
+        );
+
+        let args = parse(&["--print", "--hook", "notify-send trashed", "a"])?;
+        assert!(args.print);
+        assert_eq!(args.hook.as_deref(), Some("notify-send trashed"));
+        assert!(!args.override_protection);
+        assert!(parse(&["--override-protection", "a"])?.override_protection);
+        assert_eq!(args.policy, None);
+        assert_eq!(
+            parse(&["--policy", "delete", "a"])?.policy,
+            Some(Action::Delete)
+        );
+        assert!(matches!(
+            parse(&["--policy", "shred", "a"]),
+            Err(Error::Usage(_))
+        ));
+        assert_eq!(args.operands, ["a"]);
+
+        let args = parse(&["--check"])?;
+        assert_eq!(args.mode, Mode::Check);
+        assert!(args.operands.is_empty());
+        assert!(!args.fix);
+        assert!(parse(&["--check", "--fix"])?.fix);
+
+        let args = parse(&["--restore", "dummy"])?;
+        assert_eq!(args.mode, Mode::Restore("dummy".into()));
+        assert_eq!(parse(&["--restore-last"])?.mode, Mode::RestoreLast);
+        assert_eq!(parse(&["--restore-id", "12"])?.mode, Mode::RestoreId(12));
+        assert!(matches!(
+            parse(&["--restore-id", "twelve"]),
+            Err(Error::Usage(_))
+        ));
+        assert_eq!(
+            parse(&["--purge", "report.pdf"])?.mode,
+            Mode::Purge("report.pdf".into())
+        );
+        assert!(args.operands.is_empty());
+
+        let args = parse(&["--restore", "config.toml", "--generation", "2", "--dry-run"])?;
+        assert_eq!(args.generations, Generations::Version(2));
+        assert!(args.dry_run);
+        assert!(matches!(
+            parse(&["--restore", "config.toml", "--generation", "0"]),
+            Err(Error::Usage(_))
+        ));
+
+        assert!(parse(&["--purge", "*.odt", "--by-path"])?.by_path);
+        assert!(matches!(
+            parse(&["--list", "--by-path"]),
+            Err(Error::Usage(_))
+        ));
+        assert!(parse(&["--list", "--all-trashes"])?.all_trashes);
+        let args = parse(&["--orphans", "--all-trashes"])?;
+        assert!(args.mode == Mode::Orphans && args.all_trashes);
+        assert_eq!(
+            parse(&["--empty", "--mount", "/mnt/usb"])?.mount,
+            Some("/mnt/usb".into())
+        );
+        for args in [
+            &["--restore-last", "--all-trashes"][..],
+            &["--list", "--all-trashes", "--trash", "/mnt/usb/.Trash-1000"],
+            &["--empty", "--all-trashes", "--json"],
+            &["--list", "--mount", "/mnt/usb"],
+            &["--empty", "--mount", "/mnt/usb", "--all-trashes"],
+            &[
+                "--empty",
+                "--mount",
+                "/mnt/usb",
+                "--trash",
+                "/mnt/usb/.Trash-1000",
+            ],
+        ] {
+            assert!(matches!(parse(args), Err(Error::Usage(_))), "{args:?}");
+        }
+
+        let args = parse(&["--restore-last", "--restore-to", "/home/user/Desktop"])?;
+        assert_eq!(args.mode, Mode::RestoreLast);
+        assert_eq!(args.restore_to, Some("/home/user/Desktop".into()));
+        assert!(matches!(
+            parse(&["--restore-under", "/tmp", "--restore-to", "/home/user"]),
+            Err(Error::Usage(_))
+        ));
+
+        let args = parse(&["--__complete", "restore", "dum"])?;
+        assert_eq!(args.mode, Mode::Complete("restore".into()));
+        assert_eq!(args.operands, ["dum"]);
+
+        let args = parse(&["--which", "notes", "--recursive"])?;
+        assert_eq!(args.mode, Mode::Which("notes".into()));
+        assert!(args.recursive);
+
+        // rm's -R is -r
+        let args = parse(&["-R", "-d", "build"])?;
+        assert!(args.recursive && args.dir);
+        assert_eq!(args.operands, ["build"]);
+
+        // Short options can be bundled, as with rm
+        let args = parse(&["-rfv", "build/"])?;
+        assert!(args.recursive && args.force && args.verbose);
+        assert_eq!(args.interactive, Interactive::Never);
+        assert_eq!(args.operands, ["build/"]);
+        let args = parse(&["-Rdi", "-vv", "--", "-rf"])?;
+        assert!(args.recursive && args.dir && args.stats);
+        assert_eq!(args.interactive, Interactive::Always);
+        assert_eq!(args.operands, ["-rf"]);
+        assert_eq!(parse(&["-fI"])?.interactive, Interactive::Once);
+        assert!(matches!(
+            parse(&["-rfx", "build"]),
+            Err(Error::Usage(message)) if message.starts_with("unknown option -x in -rfx")
+        ));
+        assert!(matches!(parse(&["-notes.txt"]), Err(Error::Usage(_))));
+
+        let args = parse(&[
+            "--migrate",
+            "--from",
+            "/mnt/usb",
+            "--to",
+            "home",
+            "notes.txt",
+        ])?;
+        assert_eq!(
+            args.mode,
+            Mode::Migrate {
+                from: "/mnt/usb".into(),
+                to: "home".into()
+            }
+        );
+        assert_eq!(args.operands, ["notes.txt"]);
+        assert!(matches!(
+            parse(&["--migrate", "--from", "/mnt/usb"]),
+            Err(Error::Usage(_))
+        ));
+
+        assert!(matches!(parse(&["--restore"]), Err(Error::Usage(_))));
+
+        assert_eq!(args.interactive, Interactive::Never);
+        assert_eq!(parse(&["-i", "a"])?.interactive, Interactive::Always);
+        // The last one wins
+        assert_eq!(parse(&["-i", "-I", "a"])?.interactive, Interactive::Once);
+        assert_eq!(
+            parse(&["--interactive-once", "--interactive", "a"])?.interactive,
+            Interactive::Always
+        );
+        // As with rm, -f undoes -i and -I given before it, and they undo it
+        let args = parse(&["-i", "-f", "a"])?;
+        assert_eq!((args.interactive, args.force), (Interactive::Never, true));
+        let args = parse(&["--force", "-I", "a"])?;
+        assert_eq!((args.interactive, args.force), (Interactive::Once, false));
+
+        assert!(parse(&["--json", "a"])?.json);
+        assert!(parse(&["--check", "--json"])?.json);
+        for args in [&["--json", "--cat", "a"][..], &["--json", "--print", "a"]] {
+            assert!(matches!(parse(args), Err(Error::Usage(_))), "{args:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn takes_values_inline() -> Result<()> {
+        let args = parse(&[
+            "--policy=delete",
+            "--hook=",
+            "--trash=/mnt/a=b",
+            "--",
+            "--=x",
+        ])?;
+        assert_eq!(args.policy, Some(Action::Delete));
+        assert_eq!(args.hook.as_deref(), Some(""));
+        assert_eq!(args.trash, Some("/mnt/a=b".into()));
+        assert_eq!(args.operands, ["--=x"]);
+
+        assert!(matches!(parse(&["--print=yes"]), Err(Error::Usage(_))));
+        assert!(matches!(parse(&["--=x"]), Err(Error::Usage(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_unknown_options() -> Result<()> {
+        for args in [
+            &["--hepl"][..],
+            &["-x", "notes.txt"],
+            &["notes.txt", "--tras=/mnt"],
+        ] {
+            assert!(
+                matches!(parse(args), Err(Error::Usage(message)) if message.contains("unknown option")),
+                "{args:?}"
+            );
+        }
+
+        // Unless they come after `--`, which is left out, and `-` alone is a file as well
+        let args = parse(&["-q", "--", "-x", "--", "--help", "caf\u{e9}"])?;
+        assert!(args.quiet);
+        assert_eq!(args.mode, Mode::Trash);
+        assert_eq!(args.operands, ["-x", "--", "--help", "caf\u{e9}"]);
+        assert_eq!(parse(&["-"])?.operands, ["-"]);
+
+        assert_eq!(parse(&["--help"])?.mode, Mode::Help);
+        assert_eq!(parse(&["-V"])?.mode, Mode::Version);
+
+        Ok(())
+    }
+
+    #[test]
+    fn takes_everything_after_a_double_dash_as_is() -> Result<()> {
+        // Only the first `--` ends the options, so this trashes a file named `--`
+        assert_eq!(parse(&["--", "--"])?.operands, ["--"]);
+        assert_eq!(parse(&["--", "-f", "--list"])?.mode, Mode::Trash);
+
+        let latin1 = OsStr::from_bytes(b"-caf\xe9");
+        let args = Args::parse(
+            ["-v", "--", "-i"]
+                .map(OsString::from)
+                .into_iter()
+                .chain([latin1.to_owned()]),
+        )?;
+        assert!(args.verbose);
+        assert_eq!(args.interactive, Interactive::Never);
+        assert_eq!(args.operands, [OsStr::new("-i"), latin1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reads_lists_of_files() -> Result<()> {
+        let list = b"notes.txt\0with\nnewline\0caf\xe9 menu\0\0";
+        assert_eq!(
+            read_file_list(&list[..], b'\0')?,
+            [
+                OsStr::new("notes.txt"),
+                OsStr::new("with\nnewline"),
+                OsStr::from_bytes(b"caf\xe9 menu")
+            ]
+        );
+        assert_eq!(read_file_list(&b"a b\n-c\n\n"[..], b'\n')?, ["a b", "-c"]);
+
+        let args = parse(&["--files-from=-", "-0", "-f"])?;
+        assert_eq!(args.files_from, Some("-".into()));
+        assert!(args.null);
+        for args in [&["-0", "a"][..], &["--list", "--files-from", "list"]] {
+            assert!(matches!(parse(args), Err(Error::Usage(_))), "{args:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn lists_every_option_in_the_help() {
+        let help = help();
+
+        assert!(help.starts_with("Usage: tt [OPTION]... [--] FILE...\n"));
+        assert!(help.contains("\n  -i, --interactive "));
+        assert!(help.contains("\n      --trash TRASH "));
+        assert!(!help.contains("__complete"));
+        for option in OPTIONS.iter().filter(|option| !option.hidden) {
+            assert!(
+                help.contains(&format!("--{} ", option.long)),
+                "{}",
+                option.long
+            );
+        }
+    }
+
+    #[test]
+    fn splits_default_options_like_sh() -> Result<()> {
+        let split = |text: &str| default_options(text.as_ref());
+
+        assert_eq!(
+            split(r#"  --hook 'notify-send "tt: done"' --policy=del"ete"  -q "#)?,
+            [
+                "--hook",
+                r#"notify-send "tt: done""#,
+                "--policy=delete",
+                "-q"
+            ]
+        );
+        assert_eq!(
+            split(r#"--hook "echo \"\$TT_TRASH_COUNT\" \a" --trash my\ trash --hook ''"#)?,
+            [
+                "--hook",
+                r#"echo "$TT_TRASH_COUNT" \a"#,
+                "--trash",
+                "my trash",
+                "--hook",
+                ""
+            ]
+        );
+        assert!(split("")?.is_empty());
+
+        for text in ["--hook 'true", r#"--hook "true"#, r"-q \"] {
+            assert!(matches!(split(text), Err(Error::Usage(_))), "{text}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_operands_in_default_options() {
+        // Which would otherwise be trashed
+        for text in [
+            "-q notes.txt",
+            "--policy delete -- -q",
+            "--hook",
+            "--json x",
+            "--files-from=/tmp/list",
+            "--files-from /tmp/list",
+            "--null",
+            "-v0",
+        ] {
+            assert!(
+                matches!(default_options(text.as_ref()), Err(Error::Usage(_))),
+                "{text}"
+            );
+        }
+
+        // Mistyped options are told apart, naming the variable they're in
+        assert!(matches!(
+            default_options("-I --verbos".as_ref()),
+            Err(Error::Usage(message)) if message == "unknown option --verbos in TT_OPTS, see tt --help"
+        ));
+        assert!(default_options("-rI".as_ref()).is_ok());
+        assert!(matches!(
+            default_options("-rk".as_ref()),
+            Err(Error::Usage(message)) if message == "unknown option -k in -rk in TT_OPTS, see tt --help"
+        ));
+    }
+
+    #[test]
+    fn lets_the_command_line_override_default_options() -> Result<()> {
+        let defaults = default_options("--policy delete --hook 'notify-send trashed' -I".as_ref())?;
+        let args = Args::parse(
+            defaults
+                .into_iter()
+                .chain(["--policy", "trash", "-i", "notes.txt"].map(OsString::from)),
+        )?;
+
+        assert_eq!(args.policy, Some(Action::Trash));
+        assert_eq!(args.interactive, Interactive::Always);
+        assert_eq!(args.hook.as_deref(), Some("notify-send trashed"));
+        assert_eq!(args.operands, ["notes.txt"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parses_date_filters() -> Result<()> {
+        let args = parse(&["--list", "--since", "2024-01-01", "--include-undated"])?;
+        assert_eq!(args.mode, Mode::List);
+        assert_eq!(args.filter.since, DateTime::parse("2024-01-01T00:00:00"));
+        assert_eq!(args.filter.before, None);
+        assert!(args.filter.include_undated);
+
+        let args = parse(&["--before", "7d", "--empty", "-y"])?;
+        assert_eq!(args.mode, Mode::Empty);
+        assert!(args.yes);
+        assert!(args.filter.before.is_some());
+        assert!(!args.filter.include_undated);
+
+        assert!(matches!(
+            parse(&["--list", "--since", "last tuesday"]),
+            Err(Error::InvalidDate(_))
+        ));
+
+        // Under the names other tools give them
+        let args = parse(&[
+            "--list",
+            "--after",
+            "2024-05-01",
+            "--before",
+            "2024-05-08T12:30:00",
+            "--include-invalid",
+        ])?;
+        assert_eq!(args.filter.since, DateTime::parse("2024-05-01T00:00:00"));
+        assert_eq!(args.filter.before, DateTime::parse("2024-05-08T12:30:00"));
+        assert!(args.filter.include_undated);
+
+        // The same as --before 30d, as of a moment later
+        let before = |args: &[&str]| parse(args).map(|args| args.filter.before);
+        let earlier = before(&["--empty", "--before", "30d"])?;
+        let older_than = before(&["--empty", "--older-than", "30"])?;
+        let later = before(&["--empty", "--before", "30d"])?;
+        assert!(earlier <= older_than && older_than <= later);
+        for days in ["30d", "-1", "soon"] {
+            assert!(matches!(
+                parse(&["--empty", "--older-than", days]),
+                Err(Error::Usage(_))
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+//! Shell completion: the scripts printed by `tt --completions SHELL` and the
+//! candidates printed by `tt --__complete KIND PREFIX`, which those scripts call.
+
+use std::{ffi::OsStr, fmt::Write, io::ErrorKind, os::unix::ffi::OsStrExt};
+
+use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
+
+use crate::{
+    cli::{Completes, OPTIONS},
+    error::{Error, Result},
+    trash::Trash,
+};
+
+/// Bytes escaped in candidates that can't be printed as they are. Non-ASCII bytes are always escaped.
+const CANDIDATE_ENCODE_SET: &AsciiSet = &CONTROLS.add(b'%');
+
+/// Turns the name of a trashed entry into something a shell can insert.
+///
+/// Names that aren't valid UTF-8 or that contain control characters are percent-encoded,
+/// and are decoded back by `tt --restore`.
+pub fn candidate(name: &OsStr) -> String {
+    match name.to_str() {
+        Some(name) if !name.chars().any(char::is_control) => name.to_owned(),
+        _ => percent_encode(name.as_bytes(), CANDIDATE_ENCODE_SET).to_string(),
+    }
+}
+
+/// The completion candidates of `kind` starting with `prefix`, sorted.
+pub fn complete(trash: &Trash, kind: &str, prefix: &str) -> Result<Vec<String>> {
+    let mut candidates = match kind {
+        "restore" => trashed_entries(trash)?,
+        _ => return Err(Error::Usage(format!("can't complete {kind}"))),
+    };
+
+    candidates.retain(|candidate| candidate.starts_with(prefix));
+    candidates.sort();
+
+    Ok(candidates)
+}
+
+/// The names of the entries in `trash`, as completion candidates
+fn trashed_entries(trash: &Trash) -> Result<Vec<String>> {
+    let entries = match fs_err::read_dir(trash.files.as_path()) {
+        Ok(entries) => entries,
+        // Nothing was trashed yet
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    entries
+        .map(|entry| Ok(candidate(&entry?.file_name())))
+        .collect()
+}
+
+/// The completion script for `shell`
+pub fn script(shell: &str) -> Result<String> {
+    match shell {
+        "bash" => Ok(bash_script()),
+        "zsh" => Ok(zsh_script()),
+        "fish" => Ok(fish_script()),
+        _ => Err(Error::Usage(format!("unsupported shell: {shell}"))),
+    }
+}
+
+fn visible_options() -> impl Iterator<Item = &'static crate::cli::Opt> {
+    OPTIONS.iter().filter(|option| !option.hidden)
+}
+
+/// The ways `option` can be written on the command line
+fn spellings(option: &crate::cli::Opt) -> Vec<String> {
+    let long = format!("--{}", option.long);
+
+    match option.short {
+        Some(short) => vec![long, format!("-{short}")],
+        None => vec![long],
+    }
+}
+
+fn bash_script() -> String {
+    let mut script = String::from(
+        "_tt() {\n    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n    local IFS=$'\\n'\n\n    case \"$prev\" in\n",
+    );
+
+    for option in visible_options() {
+        let Some((_, completes)) = option.value else {
+            continue;
+        };
+        let candidates = match completes {
+            Completes::Nothing => "()".to_owned(),
+            Completes::Paths => "($(compgen -f -- \"$cur\"))".to_owned(),
+            Completes::TrashedEntries => "($(tt --__complete restore \"$cur\"))".to_owned(),
+            Completes::Words(words) => {
+                format!("($(compgen -W \"{}\" -- \"$cur\"))", words.join(" "))
+            }
+        };
+        let _ = writeln!(
+            script,
+            "        {})\n            COMPREPLY={candidates}\n            return ;;",
+            spellings(option).join("|")
+        );
+    }
+
+    let longs: Vec<_> = visible_options().flat_map(spellings).collect();
+    let _ = write!(
+        script,
+        "    esac\n\n    if [[ \"$cur\" == -* ]]; then\n        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n    else\n        COMPREPLY=($(compgen -f -- \"$cur\"))\n    fi\n}}\n\ncomplete -o filenames -F _tt tt\n",
+        longs.join(" ")
+    );
+
+    script
+}
+
+fn zsh_script() -> String {
+    let mut script = String::from(
+        "#compdef tt\n\n_tt_trashed_entries() {\n    local -a entries\n    entries=(\"${(@f)$(tt --__complete restore \"$PREFIX\")}\")\n    compadd -a entries\n}\n\n_arguments \\\n",
+    );
+
+    for option in visible_options() {
+        let value = match option.value {
+            None => String::new(),
+            Some((name, Completes::Nothing)) => format!(":{name}: "),
+            Some((name, Completes::Paths)) => format!(":{name}:_files"),
+            Some((name, Completes::TrashedEntries)) => format!(":{name}:_tt_trashed_entries"),
+            Some((name, Completes::Words(words))) => format!(":{name}:({})", words.join(" ")),
+        };
+        let spec = match option.short {
+            Some(short) => format!(
+                "'(-{short} --{long})'{{-{short},--{long}}}'",
+                long = option.long
+            ),
+            None => format!("'--{}", option.long),
+        };
+        let _ = writeln!(script, "    {spec}[{}]{value}' \\", option.help);
+    }
+
+    script.push_str("    '*:file:_files'\n");
+    script
+}
+
+fn fish_script() -> String {
+    let mut script = String::new();
+
+    for option in visible_options() {
+        let value = match option.value {
+            None => String::new(),
+            Some((_, Completes::Nothing)) => " -x".to_owned(),
+            Some((_, Completes::Paths)) => " -r -F".to_owned(),
+            Some((_, Completes::TrashedEntries)) => {
+                " -x -a '(tt --__complete restore (commandline -ct))'".to_owned()
+            }
+            Some((_, Completes::Words(words))) => format!(" -x -a '{}'", words.join(" ")),
+        };
+        let short = option
+            .short
+            .map(|short| format!(" -s {short}"))
+            .unwrap_or_default();
+        let _ = writeln!(
+            script,
+            "complete -c tt -l {}{short}{value} -d '{}'",
+            option.long, option.help
+        );
+    }
+
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, fs, os::unix::ffi::OsStrExt};
+
+    use super::{complete, script, visible_options};
+    use crate::{
+        error::Result,
+        fs::RealFs,
+        restore::{restore, RestoreOptions},
+        trash::Trash,
+    };
+
+    #[test]
+    fn completes_trashed_entry_names() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("trash"))?;
+
+        // The trash wasn't created yet
+        assert!(complete(&trash, "restore", "")?.is_empty());
+
+        fs::create_dir(&trash.root)?;
+        fs::create_dir(&trash.files)?;
+        fs::create_dir(&trash.info)?;
+
+        let names: [&[u8]; 4] = [b"foo.txt", b"foo\xff", b"foo\nbar", b"other"];
+        for name in names {
+            let path = dir.path().join(OsStr::from_bytes(name));
+            fs::write(&path, name)?;
+            trash.send_to_trash(&RealFs, &path)?;
+        }
+
+        assert_eq!(
+            complete(&trash, "restore", "foo")?,
+            ["foo%0Abar", "foo%FF", "foo.txt"]
+        );
+        assert_eq!(complete(&trash, "restore", "o")?, ["other"]);
+
+        // Encoded candidates can be restored
+        restore(
+            &RealFs,
+            &trash,
+            "foo%FF".as_ref(),
+            RestoreOptions::default(),
+        )?;
+        assert_eq!(
+            fs::read(dir.path().join(OsStr::from_bytes(b"foo\xff")))?,
+            b"foo\xff"
+        );
+
+        assert!(complete(&trash, "unknown", "").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn generates_scripts() -> Result<()> {
+        for shell in ["bash", "zsh", "fish"] {
+            let script = script(shell)?;
+            assert!(script.contains("tt --__complete restore"));
+            assert!(script.contains("restore"));
+            assert!(!script.contains("--__complete[") && !script.contains("-l __complete"));
+        }
+
+        // Every option the parser knows of is completed, as the scripts are made from the same table
+        let (bash, zsh, fish) = (script("bash")?, script("zsh")?, script("fish")?);
+        for option in visible_options() {
+            assert!(bash.contains(&format!("--{} ", option.long)));
+            assert!(zsh.contains(&format!("--{}", option.long)));
+            assert!(fish.contains(&format!("-l {} ", option.long)));
+        }
+
+        assert!(script("powershell").is_err());
+
+        Ok(())
+    }
+}
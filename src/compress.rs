@@ -0,0 +1,314 @@
+//! Compressing trashed directories into a single `NAME.tar.zst` archive in `$trash/files`, with `--compress`
+//! or for directories at least as big as the `compress_above` setting of the configuration file.
+//!
+//! The info file of an archive says so in the non-standard `X-TT-Compressed` key, so that restoring unpacks it
+//! back into a directory, and keeps the size of that directory in `X-TT-Original-Size`. Other implementations
+//! see archives as regular files, and restore them as such.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs::Permissions,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
+
+use walkdir::WalkDir;
+
+use crate::{
+    error::{Error, Result},
+    ffi::effective_user_id,
+    fs::{remove_recursively, temp_dir_in, temp_file_in, FileSystem, PRIVILEGE_BITS},
+};
+
+/// The format of archives, as written in `X-TT-Compressed`
+pub const FORMAT: &str = "tar.zst";
+
+/// The zstd compression level, which favors speed: these are files that are rarely restored
+const LEVEL: i32 = 3;
+
+/// The name of the archive of a directory named `name`
+pub fn archive_name(name: &OsStr) -> OsString {
+    let mut archive_name = name.to_owned();
+    archive_name.push(".");
+    archive_name.push(FORMAT);
+
+    archive_name
+}
+
+/// Archives the directory `dir` into a new file at `archive_path`, returning the size of the archive.
+///
+/// The archive is streamed into a temporary file next to `archive_path`, which is only renamed once complete,
+/// so that failing leaves nothing behind. Symlinks are archived as symlinks, and modes, owners and modification
+/// times are kept. `dir` itself is left untouched.
+pub fn compress(fs: &impl FileSystem, dir: &Path, archive_path: &Path) -> Result<u64> {
+    let parent = archive_path
+        .parent()
+        .ok_or_else(|| Error::FailedToObtainFileName(archive_path.to_owned()))?;
+    let temp = temp_file_in(parent)?;
+
+    let mut builder = tar::Builder::new(zstd::Encoder::new(temp.as_file(), LEVEL)?);
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", dir)?;
+    let archive = builder.into_inner()?.finish()?;
+    archive.sync_all()?;
+    let size = archive.metadata()?.len();
+
+    // Dropping `temp` past this point would remove whatever is at its path
+    let temp = temp.into_temp_path();
+    fs.rename(&temp, archive_path)?;
+    temp.keep().map_err(|err| err.error)?;
+
+    Ok(size)
+}
+
+/// Unpacks the archive at `archive_path` into a new directory at `destination`, whose parent must exist.
+///
+/// The archive is unpacked into a temporary directory next to `destination`, which is only renamed once complete,
+/// so that failing leaves nothing behind. Modes and modification times are restored, save for the setuid, setgid
+/// and sticky bits (see [`PRIVILEGE_BITS`]), and so are owners when running as root, if `keep_owners` is set: the
+/// archive should then be trusted as much as an info file whose owner is reapplied. The archive is left untouched.
+pub fn decompress(
+    fs: &impl FileSystem,
+    archive_path: &Path,
+    destination: &Path,
+    keep_owners: bool,
+) -> Result<()> {
+    let parent = destination
+        .parent()
+        .ok_or_else(|| Error::FailedToObtainFileName(destination.to_owned()))?;
+    let temp = temp_dir_in(parent)?;
+
+    let mut archive = tar::Archive::new(zstd::Decoder::new(fs_err::File::open(archive_path)?)?);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    archive.set_preserve_ownerships(keep_owners && effective_user_id() == 0);
+    archive.unpack(temp.path())?;
+    clear_privilege_bits(temp.path())?;
+
+    // Dropping `temp` past this point would remove whatever is at its path
+    let temp = temp.keep();
+    if let Err(err) = fs.rename(&temp, destination) {
+        let _ = remove_recursively(fs, &temp);
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// Clears the setuid, setgid and sticky bits of everything in `dir`, itself included, but for symlinks
+fn clear_privilege_bits(dir: &Path) -> Result<()> {
+    for entry in WalkDir::new(dir) {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let metadata = entry.path().symlink_metadata()?;
+        let mode = metadata.permissions().mode();
+        if !metadata.is_symlink() && mode & PRIVILEGE_BITS != 0 {
+            fs_err::set_permissions(entry.path(), Permissions::from_mode(mode & !PRIVILEGE_BITS))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File, Permissions},
+        os::unix::fs::{symlink, PermissionsExt},
+        path::{Path, PathBuf},
+    };
+
+    use walkdir::WalkDir;
+
+    use crate::{
+        directorysizes::read_directory_sizes,
+        error::Result,
+        fs::RealFs,
+        info_file::read_info_file,
+        restore::{restore, RestoreOptions},
+        tests::{dummy_bytes, FaultyFs, Op},
+        trash::{Trash, TrashOptions},
+    };
+
+    fn dummy_trash(root: &Path) -> Result<Trash> {
+        let trash = Trash::from_root(root)?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        File::create(&trash.directory_sizes)?;
+
+        Ok(trash)
+    }
+
+    /// The paths under `dir`, relative to it, along with their modes and contents (or targets, for symlinks)
+    fn snapshot(dir: &Path) -> Result<Vec<(PathBuf, u32, Vec<u8>)>> {
+        let mut snapshot = Vec::new();
+
+        for entry in WalkDir::new(dir).sort_by_file_name() {
+            let entry = entry.map_err(std::io::Error::from)?;
+            let metadata = entry.path().symlink_metadata()?;
+            let contents = if metadata.is_symlink() {
+                fs::read_link(entry.path())?
+                    .into_os_string()
+                    .into_encoded_bytes()
+            } else if metadata.is_file() {
+                fs::read(entry.path())?
+            } else {
+                vec![]
+            };
+            let relative = entry.path().strip_prefix(dir).unwrap().to_owned();
+            snapshot.push((relative, metadata.permissions().mode(), contents));
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Makes a directory named `name` in `dir` with a bit of everything in it, returning its path
+    fn dummy_tree(dir: &Path, name: &str) -> Result<PathBuf> {
+        let root = dir.join(name);
+        fs::create_dir_all(root.join("src/private"))?;
+        fs::create_dir(root.join("empty"))?;
+        fs::write(root.join("src/main.rs"), dummy_bytes())?;
+        fs::write(root.join("run.sh"), "#!/bin/sh\n")?;
+        fs::set_permissions(root.join("run.sh"), Permissions::from_mode(0o755))?;
+        fs::write(root.join("src/private/key"), "secret")?;
+        fs::set_permissions(root.join("src/private/key"), Permissions::from_mode(0o600))?;
+        fs::set_permissions(root.join("src/private"), Permissions::from_mode(0o700))?;
+        symlink("src/main.rs", root.join("link"))?;
+        fs::set_permissions(&root, Permissions::from_mode(0o750))?;
+
+        Ok(root)
+    }
+
+    #[test]
+    fn compresses_and_restores_directories() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(&dir.path().join("trash"))?;
+        let tree = dummy_tree(dir.path(), "build")?;
+        let before = snapshot(&tree)?;
+
+        let compressing = TrashOptions {
+            compress_above: Some(0),
+            ..TrashOptions::default()
+        };
+        let name = trash.send_to_trash_with(&RealFs, &tree, &compressing)?;
+        assert_eq!(name, Path::new("build.tar.zst"));
+        assert!(!tree.exists());
+
+        let archive = trash.files.as_path().join(&name);
+        assert!(archive.is_file());
+        let info = read_info_file(&trash.info_path().join("build.tar.zst.trashinfo"))?;
+        assert_eq!(info.path, tree);
+        assert_eq!(info.compressed.as_deref(), Some("tar.zst"));
+        assert!(info.original_size.is_some_and(|size| size > 0));
+        let sizes = read_directory_sizes(&trash)?;
+        assert_eq!(sizes.len(), 1);
+        assert_eq!(sizes[0].size, fs::metadata(&archive)?.len());
+
+        let plan = restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default())?;
+        assert_eq!(plan.destination, tree);
+        assert_eq!(snapshot(&tree)?, before);
+
+        // Nothing is left behind, not even temporary files
+        assert_eq!(fs::read_dir(&trash.files)?.count(), 0);
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 0);
+        assert!(read_directory_sizes(&trash)?.is_empty());
+        assert_eq!(fs::read_dir(dir.path())?.count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn restores_without_privileges() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(&dir.path().join("trash"))?;
+        let tree = dummy_tree(dir.path(), "build")?;
+        fs::set_permissions(tree.join("run.sh"), Permissions::from_mode(0o4755))?;
+        fs::set_permissions(tree.join("empty"), Permissions::from_mode(0o1777))?;
+
+        let compressing = TrashOptions {
+            compress_above: Some(0),
+            ..TrashOptions::default()
+        };
+        let name = trash.send_to_trash_with(&RealFs, &tree, &compressing)?;
+        restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default())?;
+
+        let mode = |path: &str| -> Result<u32> {
+            Ok(fs::metadata(tree.join(path))?.permissions().mode() & 0o7777)
+        };
+        assert_eq!(mode("run.sh")?, 0o755);
+        assert_eq!(mode("empty")?, 0o777);
+        assert_eq!(mode("src/private/key")?, 0o600);
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_compresses_directories_above_the_threshold() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(&dir.path().join("trash"))?;
+        let options = TrashOptions {
+            compress_above: Some(1024 * 1024),
+            ..TrashOptions::default()
+        };
+
+        let small = dummy_tree(dir.path(), "small")?;
+        assert_eq!(
+            trash.send_to_trash_with(&RealFs, &small, &options)?,
+            Path::new("small")
+        );
+
+        let big = dir.path().join("big");
+        fs::create_dir(&big)?;
+        fs::write(big.join("zeroes"), vec![0; 2 * 1024 * 1024])?;
+        assert_eq!(
+            trash.send_to_trash_with(&RealFs, &big, &options)?,
+            Path::new("big.tar.zst")
+        );
+        // Zeroes compress well
+        assert!(fs::metadata(trash.files.as_path().join("big.tar.zst"))?.len() < 1024 * 1024);
+
+        // Files are never compressed
+        fs::write(dir.path().join("file"), vec![0; 2 * 1024 * 1024])?;
+        assert_eq!(
+            trash.send_to_trash_with(&RealFs, &dir.path().join("file"), &options)?,
+            Path::new("file")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn cleans_up_when_failing() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(&dir.path().join("trash"))?;
+        let tree = dummy_tree(dir.path(), "build")?;
+        let before = snapshot(&tree)?;
+        let compressing = TrashOptions {
+            compress_above: Some(0),
+            ..TrashOptions::default()
+        };
+
+        // Renaming the complete archive into place fails
+        let faulty = FaultyFs::failing(Op::Rename, 1, libc::EIO);
+        assert!(trash
+            .send_to_trash_with(&faulty, &tree, &compressing)
+            .is_err());
+        assert_eq!(snapshot(&tree)?, before);
+        assert_eq!(fs::read_dir(&trash.files)?.count(), 0);
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 0);
+        // `files`, `info`, `directorysizes` and the lock file
+        assert_eq!(fs::read_dir(trash.root.as_path())?.count(), 4);
+
+        // And so does renaming the unpacked directory into place
+        let faulty = FaultyFs::failing(Op::Rename, 1, libc::EIO);
+        let name = trash.send_to_trash_with(&RealFs, &tree, &compressing)?;
+        assert!(restore(&faulty, &trash, name.as_os_str(), RestoreOptions::default()).is_err());
+        assert!(!tree.exists());
+        assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+        assert!(trash.files.as_path().join(&name).exists());
+
+        restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default())?;
+        assert_eq!(snapshot(&tree)?, before);
+
+        Ok(())
+    }
+}
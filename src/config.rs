@@ -0,0 +1,156 @@
+//! The configuration file, `$XDG_CONFIG_HOME/tt/config.toml`
+
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    error::{Error, Result},
+    home_dir::Env,
+    policy::MountPolicy,
+    trash::{LargeThresholds, NameAttempts},
+    usage::UsageLimits,
+};
+
+/// Overrides the location of the configuration file. Takes precedence over `XDG_CONFIG_HOME`.
+pub const CONFIG_VAR: &str = "TT_CONFIG";
+
+/// What can be set in the configuration file. Everything is optional.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// A shell command run after files are trashed (see [`crate::hook`]), overridden by `--hook`
+    pub hook: Option<String>,
+    /// Patterns of paths that must never be trashed (see [`crate::protect`]), overridden by `--override-protection`
+    pub protect: Vec<PathBuf>,
+    /// Policies keyed by mount point path or filesystem type (see [`crate::policy`]), overridden by `--policy`
+    pub mounts: BTreeMap<String, MountPolicy>,
+    /// The size, in bytes, from which trashed directories are compressed (see [`crate::compress`]), overridden by `--compress`
+    pub compress_above: Option<u64>,
+    /// When to warn that a trash got too big (see [`crate::usage`]), silenced by `--quiet`
+    pub usage_warning: UsageLimits,
+    /// How many names are tried when the name of a file is taken in the trash (see [`crate::trash::Trash::reserve_name`])
+    pub name_attempts: NameAttempts,
+    /// Whether every file trashed is logged to syslog (see [`crate::audit`])
+    pub audit_log: bool,
+    /// Past which trashed directories are confirmed first, when `--confirm-large` is given
+    pub confirm_large: LargeThresholds,
+}
+
+impl Config {
+    /// Parses the contents of a configuration file read from `path`
+    pub fn parse(text: &str, path: &Path) -> Result<Self> {
+        toml::from_str(text).map_err(|err| Error::InvalidConfig(path.to_owned(), err.to_string()))
+    }
+
+    /// Replaces a leading `~` in the paths of this configuration with `home_dir`
+    pub fn expand_tildes(mut self, home_dir: &Path) -> Self {
+        for pattern in &mut self.protect {
+            if let Ok(rest) = pattern.strip_prefix("~") {
+                *pattern = home_dir.join(rest);
+            }
+        }
+
+        self
+    }
+
+    /// Reads the configuration file found with the variables in `env`. A missing file means the defaults.
+    pub fn load(env: &Env, home_dir: &Path) -> Result<Self> {
+        let path = config_path_from(env, home_dir);
+
+        match fs_err::read_to_string(&path) {
+            Ok(text) => Ok(Self::parse(&text, &path)?.expand_tildes(home_dir)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// The configuration file is located at `$XDG_CONFIG_HOME/tt/config.toml`, falling back to
+/// `$HOME/.config/tt/config.toml`.
+///
+/// `TT_CONFIG`, when set in `env`, takes precedence over both.
+pub fn config_path_from(env: &Env, home_dir: &Path) -> PathBuf {
+    if let Some(path) = env.get(CONFIG_VAR) {
+        return path.into();
+    }
+
+    env.get("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir.join(".config"))
+        .join("tt/config.toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{config_path_from, Config};
+    use crate::{error::Result, home_dir::Env, Error};
+
+    #[test]
+    fn locates_the_config_file() {
+        let home = Path::new("/home/user");
+
+        let env = Env::default();
+        assert_eq!(
+            config_path_from(&env, home),
+            home.join(".config/tt/config.toml")
+        );
+
+        let env: Env = [("XDG_CONFIG_HOME", "/xdg")].into_iter().collect();
+        assert_eq!(
+            config_path_from(&env, home),
+            Path::new("/xdg/tt/config.toml")
+        );
+
+        let env: Env = [("XDG_CONFIG_HOME", "/xdg"), ("TT_CONFIG", "/etc/tt.toml")]
+            .into_iter()
+            .collect();
+        assert_eq!(config_path_from(&env, home), Path::new("/etc/tt.toml"));
+    }
+
+    #[test]
+    fn parses_config_files() -> Result<()> {
+        let path = Path::new("config.toml");
+
+        assert_eq!(Config::parse("", path)?, Config::default());
+        assert_eq!(
+            Config::parse("# After trashing\nhook = \"notify-send trashed\"\n", path)?.hook,
+            Some("notify-send trashed".into())
+        );
+
+        let config = Config::parse(
+            r#"protect = ["~", "~/.gnupg/**", "**/*.kdbx", "~user/x"]"#,
+            path,
+        )?
+        .expand_tildes("/home/user".as_ref());
+        assert_eq!(
+            config.protect,
+            [
+                Path::new("/home/user"),
+                Path::new("/home/user/.gnupg/**"),
+                Path::new("**/*.kdbx"),
+                Path::new("~user/x"),
+            ]
+        );
+
+        for invalid in [
+            "hook = 1",
+            "hook = ",
+            "hok = \"typo\"",
+            "protect = \"*.kdbx\"",
+        ] {
+            assert!(
+                matches!(Config::parse(invalid, path), Err(Error::InvalidConfig(..))),
+                "{invalid}"
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1421 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use unixstring::UnixString;
+
+use crate::{
+    audit::{AuditRecord, AuditSink},
+    error::{Error, Result},
+    ffi::{real_user_id, MountPoint},
+    fs::DirectoryStats,
+    fs::{FileSystem, RealFs},
+    home_dir::{home_dir_from, home_trash_path_from, Env},
+    log,
+    mount_table::MountTable,
+    policy::{self, Action, MountPolicy},
+    report::{display_message, display_path, warn},
+    trash::{LargeThresholds, NameAttempts, Sent, Trash, TrashOptions},
+};
+
+/// What was found at `$topdir/.Trash`, the trash directory an administrator may provide for every user
+#[derive(Debug)]
+enum AdminTrash {
+    Missing,
+    /// It exists but failed the given check, so it must not be used
+    Invalid(PathBuf, &'static str),
+    Valid(PathBuf),
+}
+
+/// Everything `tt` needs to know about its surroundings in order to decide
+/// where files should be sent to.
+///
+/// Built from the process' environment in `main`, but can also be built
+/// with explicit trash roots and mount tables, which is what tests use.
+#[derive(Debug)]
+pub struct TrashContext<F: FileSystem = RealFs> {
+    /// The trash located at `$XDG_DATA_HOME/Trash` (or its fallbacks)
+    home_trash: Trash,
+    /// The mounted filesystems, used to find the `$topdir` of files outside of the home trash's device
+    mount_table: MountTable,
+    /// Performs the filesystem operations needed to trash files
+    fs: F,
+    /// Whether trashes are checked to be safe to use before being used (see [`Trash::assert_safe`])
+    verify_trashes: bool,
+    /// The policies of the configuration file, keyed by mount point path or filesystem type
+    policies: BTreeMap<String, MountPolicy>,
+    /// What to do with every file, regardless of `policies`
+    action_override: Option<Action>,
+    /// How files are trashed
+    trash_options: TrashOptions,
+    /// The trash given with `--trash`, which takes the place of every other trash
+    explicit_trash: Option<Trash>,
+    /// Where trashed files are logged to, if anywhere (see [`crate::audit`])
+    audit: Option<Box<dyn AuditSink>>,
+    /// Whether files are refused rather than sent to a trash on another device than theirs
+    one_file_system: bool,
+    /// Whether `/` and the top directories of mount points are refused (see [`TrashContext::preserve_root`])
+    preserve_root: bool,
+}
+
+impl TrashContext {
+    /// Builds a context from a snapshot of environment variables and the given mount table.
+    pub fn from_env(env: &Env, mount_table: impl Into<MountTable>) -> Result<Self> {
+        let home_dir = home_dir_from(env).ok_or(Error::HomeDirNotFound)?;
+        let home_trash_path = home_trash_path_from(env, &home_dir)?;
+
+        Self::new(home_trash_path, mount_table)
+    }
+
+    /// Builds a context whose home trash is rooted at `home_trash_root`.
+    pub fn new(
+        home_trash_root: impl AsRef<Path>,
+        mount_table: impl Into<MountTable>,
+    ) -> Result<Self> {
+        Ok(Self {
+            home_trash: Trash::home(home_trash_root)?,
+            mount_table: mount_table.into(),
+            fs: RealFs,
+            verify_trashes: true,
+            policies: BTreeMap::new(),
+            action_override: None,
+            trash_options: TrashOptions::default(),
+            explicit_trash: None,
+            audit: None,
+            one_file_system: false,
+            preserve_root: true,
+        })
+    }
+}
+
+impl<F: FileSystem> TrashContext<F> {
+    /// Swaps out the filesystem operations performed by this context
+    #[cfg(test)]
+    pub fn with_fs<G: FileSystem>(self, fs: G) -> TrashContext<G> {
+        TrashContext {
+            home_trash: self.home_trash,
+            mount_table: self.mount_table,
+            fs,
+            verify_trashes: self.verify_trashes,
+            policies: self.policies,
+            action_override: self.action_override,
+            trash_options: self.trash_options,
+            explicit_trash: self.explicit_trash,
+            audit: self.audit,
+            one_file_system: self.one_file_system,
+            preserve_root: self.preserve_root,
+        }
+    }
+
+    /// Sets whether trashes are checked to be safe to use before being used, which they are by default
+    pub fn verifying_trashes(mut self, verify_trashes: bool) -> Self {
+        self.verify_trashes = verify_trashes;
+        self
+    }
+
+    /// Sets whether a checksum of trashed files is recorded (see [`crate::checksum`]), which it isn't by default
+    pub fn checksumming(mut self, checksum: bool) -> Self {
+        self.trash_options.checksum = checksum;
+        self
+    }
+
+    /// Sets the size from which trashed directories are compressed (see [`crate::compress`]). By default, none are.
+    pub fn compressing_above(mut self, threshold: Option<u64>) -> Self {
+        self.trash_options.compress_above = threshold;
+        self
+    }
+
+    /// Sets when trashed files are recorded as trashed, a [`Duration`] since UNIX_EPOCH. By default, that's now.
+    pub fn deleted_at(mut self, deletion_date: Option<Duration>) -> Self {
+        self.trash_options.deletion_date = deletion_date;
+        self
+    }
+
+    /// Sets how many names are tried when the name of a file is taken in the trash
+    pub fn attempting_names(mut self, attempts: NameAttempts) -> Self {
+        self.trash_options.name_attempts = attempts;
+        self
+    }
+
+    /// Logs every file trashed to `sink`
+    pub fn auditing(mut self, sink: impl AuditSink + 'static) -> Self {
+        self.audit = Some(Box::new(sink));
+        self
+    }
+
+    /// Sets whether security contexts are copied along when files are copied to another filesystem
+    pub fn preserving_context(mut self, preserve_context: bool) -> Self {
+        self.trash_options.preserve_context = preserve_context;
+        self
+    }
+
+    /// Sets whether files are copied into the trash, then removed, even when they could be renamed there, which they
+    /// aren't by default
+    pub fn forcing_copies(mut self, force_copy: bool) -> Self {
+        self.trash_options.force_copy = force_copy;
+        self
+    }
+
+    /// Sets whether the sizes of trashed directories are recorded in `directorysizes`, which they are by default
+    pub fn updating_directory_sizes(mut self, update_directory_sizes: bool) -> Self {
+        self.trash_options.update_directory_sizes = update_directory_sizes;
+        self
+    }
+
+    /// Sets whether files that aren't on the device of the home trash (or of the trash given with `--trash`) are
+    /// refused, rather than sent to the trash of their own mount point or copied over, which they aren't by default
+    pub fn keeping_to_one_filesystem(mut self, one_file_system: bool) -> Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Sets whether `/` and the top directories of mount points are refused, which they are by default
+    pub fn preserving_root(mut self, preserve_root: bool) -> Self {
+        self.preserve_root = preserve_root;
+        self
+    }
+
+    /// Makes the trash rooted at `root`, if any, the only one this context operates on: files are sent to it,
+    /// and it's the only trash that's read from.
+    ///
+    /// Fails if `root` doesn't exist or isn't the root of a trash or, unless told not to verify trashes (which must be
+    /// set beforehand), if it isn't safe to use.
+    pub fn targeting(mut self, root: Option<&Path>) -> Result<Self> {
+        let Some(root) = root else {
+            return Ok(self);
+        };
+
+        if root.symlink_metadata().is_err() {
+            return Err(Error::TrashDirDoesNotExist(root.to_owned()));
+        }
+        if !is_trash_root(root) {
+            return Err(Error::NotATrash(root.to_owned()));
+        }
+        let root = fs_err::canonicalize(root)?;
+        self.explicit_trash = Some(self.checked_trash(&root)?);
+
+        Ok(self)
+    }
+
+    /// Sets the per-mount policies, along with the action that overrides them all, if any
+    pub fn with_policies(
+        mut self,
+        policies: BTreeMap<String, MountPolicy>,
+        action_override: Option<Action>,
+    ) -> Self {
+        self.policies = policies;
+        self.action_override = action_override;
+        self
+    }
+
+    /// The policy for `path`, which must be canonical (see [`policy::resolve`]).
+    ///
+    /// Fails if the policy is to refuse trashing it, which must be checked before finding its trash,
+    /// since that may create one.
+    pub fn policy_for(&self, path: &Path) -> Result<MountPolicy> {
+        let mut policy = policy::resolve(&self.policies, &self.mount_table.mount_points(), path);
+        if let Some(action) = self.action_override {
+            policy.policy = action;
+        }
+
+        if policy.policy == Action::Refuse {
+            return Err(Error::RefusedByPolicy(path.to_owned()));
+        }
+
+        Ok(policy)
+    }
+
+    /// The limits on what `trash` holds, those of the policy of the mount point it's on.
+    ///
+    /// Unlike [`TrashContext::policy_for`], this never fails: a trash may be kept within limits even where trashing is refused.
+    pub fn limits_of(&self, trash: &Trash) -> MountPolicy {
+        policy::resolve(
+            &self.policies,
+            &self.mount_table.mount_points(),
+            trash.root.as_path(),
+        )
+    }
+
+    /// The mount points strictly inside `path`, which must be canonical.
+    ///
+    /// A directory holding one can't be renamed (`EBUSY`), and copying it to another filesystem instead would copy
+    /// the contents of the mounted filesystem, then delete them from it.
+    pub fn nested_mount_points(&self, path: &Path) -> Vec<PathBuf> {
+        self.mount_table
+            .mount_points()
+            .iter()
+            .map(|mount_point| &mount_point.fs_path_prefix)
+            .filter(|prefix| prefix.starts_with(path) && prefix.as_path() != path)
+            .cloned()
+            .collect()
+    }
+
+    /// Fails with [`Error::MountRoot`] if `path`, which must be canonical, is `/` or the top directory of a mount
+    /// point, unless told not to: trashing it would take along a whole filesystem, or try to.
+    ///
+    /// This must be checked before finding the trash of `path`, since that may create one.
+    pub fn preserve_root(&self, path: &Path) -> Result<()> {
+        if !self.preserve_root {
+            return Ok(());
+        }
+
+        let is_mount_root = path == Path::new("/")
+            || self
+                .mount_table
+                .mount_points()
+                .iter()
+                .any(|mount_point| mount_point.fs_path_prefix == path);
+        if is_mount_root {
+            return Err(Error::MountRoot(path.to_owned()));
+        }
+
+        Ok(())
+    }
+
+    /// Fails with [`Error::NestedMountPoints`] if `path` holds mount points (see [`TrashContext::nested_mount_points`])
+    fn refuse_nested_mount_points(&self, path: &Path) -> Result<()> {
+        let nested = self.nested_mount_points(path);
+        if !nested.is_empty() {
+            let nested: Vec<_> = nested.iter().map(|path| display_path(path)).collect();
+            return Err(Error::NestedMountPoints(path.to_owned(), nested.join(", ")));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `trash` is safe to use, unless told not to
+    pub fn verify(&self, trash: &Trash) -> Result<()> {
+        if self.verify_trashes {
+            trash.assert_safe()?;
+        }
+
+        Ok(())
+    }
+
+    /// Builds the trash rooted at `root`, checking that it exists and is safe to use (unless told not to)
+    fn checked_trash(&self, root: &Path) -> Result<Trash> {
+        if self.verify_trashes {
+            return Trash::from_root_checked(root);
+        }
+
+        let trash = Trash::from_root(root)?;
+        trash.assert_exists()?;
+
+        Ok(trash)
+    }
+
+    /// Sends `path` to `trash` through the filesystem operations of this context, logging it if auditing.
+    ///
+    /// Directories with filesystems mounted inside them are refused (see [`TrashContext::nested_mount_points`]), and
+    /// large ones are only trashed if `confirm` says so (see [`Trash::send`]).
+    pub fn send(
+        &self,
+        trash: &Trash,
+        path: &Path,
+        confirm: &mut dyn FnMut(&Path, DirectoryStats) -> Result<bool>,
+    ) -> Result<Sent> {
+        self.refuse_nested_mount_points(path)?;
+        let sent = trash.send(&self.fs, path, &self.trash_options, confirm)?;
+
+        if let Some(audit) = &self.audit {
+            audit.record(&AuditRecord {
+                original_path: path.to_owned(),
+                trashed_name: sent.file_name.clone(),
+                trash_root: trash.root.as_path().to_owned(),
+                uid: real_user_id(),
+                transfer: sent.transfer,
+                bytes: sent.size,
+            });
+        }
+
+        Ok(sent)
+    }
+
+    /// Finds out what [`TrashContext::send`] would do with `path`, without writing anything (see [`Trash::plan`])
+    pub fn plan(&self, trash: &Trash, path: &Path, planned: &[OsString]) -> Result<Sent> {
+        self.refuse_nested_mount_points(path)?;
+        trash.plan(path, &self.trash_options, planned)
+    }
+
+    /// Sets past which trashed directories are confirmed first (see [`Trash::send`]). By default, none are.
+    pub fn confirming_large(mut self, thresholds: Option<LargeThresholds>) -> Self {
+        self.trash_options.confirm_large = thresholds;
+        self
+    }
+
+    /// Like [`TrashContext::send`], only returning the name `path` got in `$trash/files`
+    #[cfg(test)]
+    pub fn send_to_trash(&self, trash: &Trash, path: &Path) -> Result<PathBuf> {
+        self.send(trash, path, &mut |_, _| Ok(true))
+            .map(|sent| sent.file_name)
+    }
+
+    /// The home trash of this context
+    pub fn home_trash(&self) -> &Trash {
+        &self.home_trash
+    }
+
+    /// The trash that operations on a single trash (such as `--list` or `--empty`) operate on: the one
+    /// given with `--trash` (see [`TrashContext::targeting`]), or else the home trash
+    pub fn target_trash(&self) -> &Trash {
+        self.explicit_trash.as_ref().unwrap_or(&self.home_trash)
+    }
+
+    /// Every trash that exists on this system and belongs to the current user, starting with the home trash.
+    ///
+    /// Besides the home trash, these are the `$topdir/.Trash/$uid` and `$topdir/.Trash-$uid` directories of the mount points.
+    /// When a trash was given with `--trash`, it's the only one.
+    pub fn known_trashes(&self) -> Vec<Trash> {
+        if let Some(trash) = &self.explicit_trash {
+            return vec![trash.clone()];
+        }
+
+        let uid = real_user_id();
+
+        let mount_points = self.mount_table.mount_points();
+        let topdir_trashes = mount_points
+            .iter()
+            .filter(|mount_point| !mount_point.is_pseudo())
+            .flat_map(|mount_point| {
+                let topdir = &mount_point.fs_path_prefix;
+                let admin_trash = match self.admin_trash(topdir) {
+                    AdminTrash::Valid(admin_trash) => Some(admin_trash.join(uid.to_string())),
+                    _ => None,
+                };
+
+                admin_trash
+                    .into_iter()
+                    .chain([topdir.join(format!(".Trash-{uid}"))])
+            })
+            .filter_map(|root| self.checked_trash(&root).ok());
+
+        let mut trashes = vec![self.home_trash.clone()];
+        for trash in topdir_trashes {
+            if !trashes.iter().any(|known| known.root == trash.root) {
+                trashes.push(trash);
+            }
+        }
+
+        trashes
+    }
+
+    /// Finds the mount point that contains `path` (see [`innermost_mount_point`]).
+    ///
+    /// The mount table is probed again when it has no mount point containing `path`, or when the one it has
+    /// is not on the device `path` is on, as happens when a filesystem was mounted since it was last probed.
+    /// Files in pseudo-filesystems or in read-only filesystems can't be trashed, so these are refused.
+    pub fn find_mount_point_of_file(&self, path: &Path) -> Result<MountPoint> {
+        let device_of = |path: &Path| {
+            let unx = UnixString::try_from(path.to_owned()).ok()?;
+            self.fs
+                .lstat(unx.as_c_str())
+                .ok()
+                .map(|lstat| lstat.device())
+        };
+        let device = device_of(path);
+        // Not being able to tell is no reason to probe again
+        let is_current = |mount_point: &MountPoint| {
+            device.is_none()
+                || device_of(&mount_point.fs_path_prefix).is_none_or(|d| Some(d) == device)
+        };
+
+        let mount_point = self
+            .mount_table
+            .find(path, is_current)
+            .ok_or(Error::FailedToObtainMountPoints)?;
+        log::debug(format_args!(
+            "{} is under the mount point {} ({})",
+            display_path(path),
+            display_path(&mount_point.fs_path_prefix),
+            mount_point.fs_type
+        ));
+
+        if mount_point.is_pseudo() {
+            return Err(Error::PseudoFilesystem(mount_point.fs_path_prefix.clone()));
+        }
+
+        if mount_point.read_only {
+            return Err(Error::ReadOnlyFilesystem(mount_point.fs_path_prefix));
+        }
+
+        Ok(mount_point)
+    }
+
+    /// Decides which trash `path` should be sent to.
+    ///
+    /// Files on the same device as the home trash (or as the symlink it was found through, see [`Trash::home`]) go to
+    /// the home trash. Any other file goes to
+    /// a trash in the top directory of its mount point, which is created if needed, unless that trash turns out to
+    /// be on another filesystem (see [`TrashContext::topdir_trash`]), in which case it goes to the home trash.
+    /// When a trash was given with `--trash`, every file goes there.
+    pub fn trash_for(&self, path: &Path) -> Result<Trash> {
+        let unx: UnixString = path.to_owned().try_into()?;
+        let device = self.fs.lstat(unx.as_c_str())?.device();
+        self.keep_to_one_filesystem(path, device)?;
+
+        if let Some(trash) = &self.explicit_trash {
+            return Ok(trash.clone());
+        }
+
+        if Some(device) == self.home_trash_device() || Some(device) == self.home_link_device() {
+            log::debug(format_args!(
+                "{} is on the filesystem of the home trash",
+                display_path(path)
+            ));
+            self.verify(&self.home_trash)?;
+            return Ok(self.home_trash.clone());
+        }
+
+        let mount_point = self.find_mount_point_of_file(path)?;
+        match self.topdir_trash(&mount_point.fs_path_prefix) {
+            Err(err @ Error::TrashOnAnotherFilesystem(..)) => {
+                warn(format_args!("{err}, using the home trash instead"));
+                self.verify(&self.home_trash)?;
+                Ok(self.home_trash.clone())
+            }
+            trash => trash,
+        }
+    }
+
+    /// Decides which trash `path` would be sent to, as [`TrashContext::trash_for`] does, but without creating anything.
+    ///
+    /// Along with the trash comes whether it doesn't exist yet, and so would be created first. A trash of a mount
+    /// point that can't be used is only found out about when it exists, so `$topdir/.Trash/$uid` is always taken to
+    /// be usable when it's missing.
+    pub fn planned_trash_for(&self, path: &Path) -> Result<(Trash, bool)> {
+        let unx: UnixString = path.to_owned().try_into()?;
+        let device = self.fs.lstat(unx.as_c_str())?.device();
+        self.keep_to_one_filesystem(path, device)?;
+
+        if let Some(trash) = &self.explicit_trash {
+            return Ok((trash.clone(), false));
+        }
+
+        if Some(device) == self.home_trash_device() || Some(device) == self.home_link_device() {
+            self.verify(&self.home_trash)?;
+            return Ok((self.home_trash.clone(), false));
+        }
+
+        let topdir = self.find_mount_point_of_file(path)?.fs_path_prefix;
+        let uid = real_user_id();
+        if let AdminTrash::Valid(admin_trash) = self.admin_trash(&topdir) {
+            let root = admin_trash.join(uid.to_string());
+            if !is_trash_root(&root) {
+                return Ok((Trash::from_root(&root)?, true));
+            }
+            if let Ok(trash) = self
+                .checked_trash(&root)
+                .and_then(|trash| self.on_filesystem_of(&topdir, trash))
+            {
+                return Ok((trash, false));
+            }
+        }
+
+        let root = topdir.join(format!(".Trash-{uid}"));
+        if !is_trash_root(&root) {
+            return Ok((Trash::from_root(&root)?, true));
+        }
+        match self
+            .checked_trash(&root)
+            .and_then(|trash| self.on_filesystem_of(&topdir, trash))
+        {
+            Err(Error::TrashOnAnotherFilesystem(..)) => {
+                self.verify(&self.home_trash)?;
+                Ok((self.home_trash.clone(), false))
+            }
+            trash => Ok((trash?, false)),
+        }
+    }
+
+    /// With `--one-file-system`, fails with [`Error::CrossesFilesystems`] unless `device`, the one `path` is on, is
+    /// the device of the trash given with `--trash`, or else of the home trash.
+    ///
+    /// Devices are compared rather than paths, so that files under a bind mount of the trash's filesystem count as
+    /// being on it, wherever it's mounted.
+    fn keep_to_one_filesystem(&self, path: &Path, device: u64) -> Result<()> {
+        if !self.one_file_system {
+            return Ok(());
+        }
+
+        let trash = self.target_trash();
+        let trash_device = match &self.explicit_trash {
+            Some(trash) => UnixString::try_from(trash.root.as_path().to_owned())
+                .ok()
+                .and_then(|root| self.fs.lstat(root.as_c_str()).ok())
+                .map(|lstat| lstat.device()),
+            None => self.home_trash_device(),
+        };
+        if trash_device != Some(device) {
+            return Err(Error::CrossesFilesystems(
+                path.to_owned(),
+                trash.root.as_path().to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The device the home trash is in.
+    ///
+    /// The home trash might not exist yet, in which case this is the device of its closest existing ancestor.
+    fn home_trash_device(&self) -> Option<u64> {
+        self.home_trash
+            .root
+            .as_path()
+            .ancestors()
+            .filter_map(|ancestor| UnixString::try_from(ancestor.to_owned()).ok())
+            .find_map(|ancestor| self.fs.lstat(ancestor.as_c_str()).ok())
+            .map(|lstat| lstat.device())
+    }
+
+    /// The device of the symlink the home trash was found through, if it was (see [`Trash::home`]).
+    ///
+    /// Files on that device go to the home trash as well, copied to where it leads: the trash of their own mount
+    /// point, often `/`, is rarely one their owner may create.
+    fn home_link_device(&self) -> Option<u64> {
+        let link = UnixString::try_from(self.home_trash.home_link()?.to_owned()).ok()?;
+
+        self.fs
+            .lstat(link.as_c_str())
+            .ok()
+            .map(|lstat| lstat.device())
+    }
+
+    /// Finds the trash `location` refers to, for `--from` and `--to`: `home` is the home trash, the root of
+    /// a trash (a directory with `files` and `info` in it) is that trash, and any other directory is taken as
+    /// the top directory of a mount point, whose trash is created if it's missing and `create` is set.
+    pub fn find_trash(&self, location: &OsStr, create: bool) -> Result<Trash> {
+        if location == "home" {
+            self.verify(&self.home_trash)?;
+            return Ok(self.home_trash.clone());
+        }
+
+        let location = fs_err::canonicalize(location)?;
+        if is_trash_root(&location) {
+            return self.checked_trash(&location);
+        }
+
+        if create {
+            return self.topdir_trash(&location);
+        }
+
+        let uid = real_user_id();
+        match self.admin_trash(&location) {
+            AdminTrash::Valid(admin_trash) => self
+                .checked_trash(&admin_trash.join(uid.to_string()))
+                .or_else(|_| self.checked_trash(&location.join(format!(".Trash-{uid}")))),
+            _ => self.checked_trash(&location.join(format!(".Trash-{uid}"))),
+        }
+    }
+
+    /// Finds the trash of the mount point `path` is on, as `--empty --mount` empties, or `None` if it has none.
+    ///
+    /// That's `$topdir/.Trash/$uid` if an administrator provided a `$topdir/.Trash` that passes the checks (see
+    /// [`TrashContext::admin_trash`]) and it holds one, and `$topdir/.Trash-$uid` otherwise. Neither is created.
+    pub fn mount_trash(&self, path: &Path) -> Result<Option<Trash>> {
+        let path = fs_err::canonicalize(path)?;
+        let topdir = self.find_mount_point_of_file(&path)?.fs_path_prefix;
+        let uid = real_user_id();
+
+        let mut roots = Vec::with_capacity(2);
+        if let AdminTrash::Valid(admin_trash) = self.admin_trash(&topdir) {
+            roots.push(admin_trash.join(uid.to_string()));
+        }
+        roots.push(topdir.join(format!(".Trash-{uid}")));
+
+        match roots.into_iter().find(|root| is_trash_root(root)) {
+            Some(root) => self.checked_trash(&root).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Finds (or creates) the trash of the mount point whose top directory is `topdir`.
+    ///
+    /// As per the spec, that's `$topdir/.Trash/$uid` if an administrator provided a `$topdir/.Trash` that passes
+    /// the checks (see [`TrashContext::admin_trash`]), and `$topdir/.Trash-$uid` otherwise.
+    ///
+    /// Either must be on the filesystem of `topdir`, so that files are renamed into it rather than copied: one that
+    /// isn't, such as a mount point or a symlink to another disk, fails with [`Error::TrashOnAnotherFilesystem`].
+    fn topdir_trash(&self, topdir: &Path) -> Result<Trash> {
+        // TODO: should we use the effective user ID here?
+        let uid = real_user_id();
+
+        match self.admin_trash(topdir) {
+            AdminTrash::Valid(admin_trash) => {
+                // If this directory is needed for a trashing operation but does not exist, the implementation must
+                // immediately create it. If it can't be used, we fall back to `$topdir/.Trash-$uid`.
+                let root = admin_trash.join(uid.to_string());
+                let trash = self
+                    .prepare_trash(&root)
+                    .and_then(|trash| self.on_filesystem_of(topdir, trash));
+                match trash {
+                    Ok(trash) => return Ok(trash),
+                    Err(err) => warn(format_args!(
+                        "can't use {}: {}",
+                        display_path(&root),
+                        display_message(&err)
+                    )),
+                }
+            }
+            // The spec wants the administrator to hear about this
+            AdminTrash::Invalid(admin_trash, failed_check) => warn(format_args!(
+                "not using {}: {}",
+                display_path(&admin_trash),
+                failed_check
+            )),
+            AdminTrash::Missing => {}
+        }
+
+        // If a $topdir/.Trash does not exist or has not passed the checks, check if `$topdir/.Trash-$uid` exists.
+        // If a $topdir/.Trash-$uid directory does not exist, the implementation must immediately create it, without any warnings or delays for the user.
+        let trash = self.prepare_trash(&topdir.join(format!(".Trash-{}", uid)))?;
+        self.on_filesystem_of(topdir, trash)
+    }
+
+    /// Gives `trash` back if its `files` directory is on the same device as `topdir`
+    fn on_filesystem_of(&self, topdir: &Path, trash: Trash) -> Result<Trash> {
+        let device_of = |path: &Path| -> Result<u64> {
+            let unx = UnixString::try_from(path.to_owned())?;
+            Ok(self.fs.lstat(unx.as_c_str())?.device())
+        };
+
+        if device_of(trash.files.as_path())? != device_of(topdir)? {
+            return Err(Error::TrashOnAnotherFilesystem(
+                trash.files.as_path().to_owned(),
+                topdir.to_owned(),
+            ));
+        }
+
+        Ok(trash)
+    }
+
+    /// Checks `$topdir/.Trash` as the spec wants: it must be an actual directory (not a symlink) with the sticky bit set.
+    fn admin_trash(&self, topdir: &Path) -> AdminTrash {
+        let path = topdir.join(".Trash");
+        let lstat = match UnixString::try_from(path.clone()) {
+            Ok(unx) => self.fs.lstat(unx.as_c_str()),
+            Err(err) => Err(err.into()),
+        };
+        let Ok(lstat) = lstat else {
+            return AdminTrash::Missing;
+        };
+
+        if lstat.is_symlink() {
+            AdminTrash::Invalid(path, "it is a symbolic link")
+        } else if !lstat.is_directory() {
+            AdminTrash::Invalid(path, "it is not a directory")
+        } else if lstat.mode() & libc::S_ISVTX == 0 {
+            AdminTrash::Invalid(path, "its sticky bit is not set")
+        } else {
+            AdminTrash::Valid(path)
+        }
+    }
+
+    /// Makes sure the trash rooted at `root` is ready to be used, creating whatever it's missing.
+    fn prepare_trash(&self, root: &Path) -> Result<Trash> {
+        let trash = Trash::from_root(root)?;
+        if trash.root.as_path().symlink_metadata().is_err() {
+            self.fs.create_dir(trash.root.as_path())?;
+        }
+
+        // Nothing is created in (or sent to) a trash another user could have prepared for us
+        self.verify(&trash)?;
+
+        for dir in [&trash.info, &trash.files] {
+            if dir.as_path().symlink_metadata().is_err() {
+                self.fs.create_dir(dir.as_path())?;
+            }
+        }
+        if trash.directory_sizes.as_path().symlink_metadata().is_err() {
+            self.fs.create_file(trash.directory_sizes.as_path())?;
+        }
+
+        Ok(trash)
+    }
+}
+
+/// Whether `path` looks like the root of a trash, that is, a directory with `files` and `info` in it
+fn is_trash_root(path: &Path) -> bool {
+    ["files", "info"].iter().all(|dir| path.join(dir).is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, fs::File, os::unix::fs::MetadataExt, path::Path, rc::Rc};
+
+    use super::TrashContext;
+    use crate::{
+        check::check,
+        config::Config,
+        entries::{entries, purge},
+        error::Result,
+        ffi::MountPoint,
+        fs::{RealFs, Transfer},
+        home_dir::Env,
+        info_file::read_info_file,
+        mount_table::MountTable,
+        policy::Action,
+        restore::{restore, RestoreOptions},
+        tests::FaultyFs,
+        which::which,
+        Error,
+    };
+
+    fn mount_point(prefix: &str, fs_type: &str, read_only: bool) -> MountPoint {
+        MountPoint {
+            fs_name: "dummy".into(),
+            fs_path_prefix: prefix.into(),
+            fs_type: fs_type.into(),
+            read_only,
+        }
+    }
+
+    #[test]
+    fn builds_home_trash_from_env() -> Result<()> {
+        let env: Env = [("TT_FAKE_HOME", "/tmp/fake-home")].into_iter().collect();
+        let ctx = TrashContext::from_env(&env, vec![])?;
+        assert_eq!(ctx.home_trash().root, "/tmp/fake-home/.local/share/Trash");
+
+        let env: Env = [
+            ("TT_FAKE_HOME", "/tmp/fake-home"),
+            ("TT_TRASH_DIR", "/tmp/trash"),
+        ]
+        .into_iter()
+        .collect();
+        let ctx = TrashContext::from_env(&env, vec![])?;
+        assert_eq!(ctx.home_trash().root, "/tmp/trash");
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_mount_point_of_file() -> Result<()> {
+        let mount_points = vec![
+            mount_point("/", "ext4", false),
+            mount_point("/mnt", "ext4", false),
+            mount_point("/mnt/data", "xfs", false),
+            mount_point("/mnt/data/nested", "btrfs", false),
+            mount_point("/mnt/cdrom", "iso9660", true),
+            mount_point("/proc", "proc", false),
+            mount_point("/sys", "sysfs", false),
+        ];
+        let ctx = TrashContext::new("/tmp/trash", mount_points)?;
+
+        enum Expected {
+            MountPoint(&'static str),
+            Pseudo,
+            ReadOnly,
+        }
+
+        let cases = [
+            ("/etc/fstab", Expected::MountPoint("/")),
+            ("/mnt/file", Expected::MountPoint("/mnt")),
+            ("/mnt/data/file", Expected::MountPoint("/mnt/data")),
+            (
+                "/mnt/data/nested/file",
+                Expected::MountPoint("/mnt/data/nested"),
+            ),
+            // A prefix that isn't a whole path component must not match
+            ("/mnt/database/file", Expected::MountPoint("/mnt")),
+            ("/mnt/cdrom/file", Expected::ReadOnly),
+            ("/proc/self/status", Expected::Pseudo),
+            ("/sys/kernel", Expected::Pseudo),
+        ];
+
+        for (path, expected) in cases {
+            let found = ctx.find_mount_point_of_file(path.as_ref());
+            match (found, expected) {
+                (Ok(mount_point), Expected::MountPoint(prefix)) => {
+                    assert_eq!(mount_point.fs_path_prefix, std::path::Path::new(prefix))
+                }
+                (Err(Error::PseudoFilesystem(_)), Expected::Pseudo) => {}
+                (Err(Error::ReadOnlyFilesystem(_)), Expected::ReadOnly) => {}
+                (found, _) => panic!("unexpected result for {path}: {found:?}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn probes_the_mount_table_again_when_it_looks_stale() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        File::create(&file)?;
+
+        // A table holding `initial`, counting how many times it's probed again, which gives `probed`
+        let counting = |initial: Vec<MountPoint>, probed: Vec<MountPoint>| {
+            let probes = Rc::new(Cell::new(0));
+            let counted = probes.clone();
+            let table = MountTable::new(initial, move || {
+                counted.set(counted.get() + 1);
+                Ok(probed.clone())
+            });
+            (table, probes)
+        };
+
+        // Mounted since the table was probed
+        let topdir = dir.path().to_string_lossy();
+        let (table, probes) = counting(vec![], vec![mount_point(&topdir, "ext4", false)]);
+        let ctx = TrashContext::new(dir.path().join("Trash"), table)?;
+        for _ in 0..2 {
+            assert_eq!(
+                ctx.find_mount_point_of_file(&file)?.fs_path_prefix,
+                dir.path()
+            );
+        }
+        assert_eq!(probes.get(), 1);
+
+        // The table has `/`, but `/proc` is on another device, so it can't be the right mount point
+        let (table, probes) = counting(
+            vec![mount_point("/", "ext4", false)],
+            vec![
+                mount_point("/", "ext4", false),
+                mount_point("/proc", "proc", false),
+            ],
+        );
+        let ctx = TrashContext::new(dir.path().join("Trash"), table)?;
+        assert!(matches!(
+            ctx.find_mount_point_of_file("/proc/self/status".as_ref()),
+            Err(Error::PseudoFilesystem(_))
+        ));
+        // Not again so soon after, whatever the device of the file looked up
+        ctx.find_mount_point_of_file(&file)?;
+        assert_eq!(probes.get(), 1);
+
+        // Lookups that keep missing don't keep probing
+        let (table, probes) = counting(vec![], vec![]);
+        let ctx = TrashContext::new(dir.path().join("Trash"), table)?;
+        for _ in 0..3 {
+            let found = ctx.find_mount_point_of_file("/proc/self/status".as_ref());
+            assert!(matches!(found, Err(Error::FailedToObtainMountPoints)));
+        }
+        assert_eq!(probes.get(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_directories_with_nested_mount_points() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let parent = dir.path().join("mnt-parent");
+        std::fs::create_dir_all(parent.join("sshfs"))?;
+        std::fs::create_dir_all(parent.join("iso/inner"))?;
+        std::fs::create_dir(dir.path().join("mnt-parent-2"))?;
+
+        let prefix = |path: &str| dir.path().join(path).to_string_lossy().into_owned();
+        let mount_points = vec![
+            mount_point("/", "ext4", false),
+            mount_point(&prefix("mnt-parent/sshfs"), "fuse.sshfs", false),
+            mount_point(&prefix("mnt-parent/iso/inner"), "iso9660", true),
+            // Not inside it, despite sharing a prefix
+            mount_point(&prefix("mnt-parent-2"), "ext4", false),
+        ];
+        let ctx = TrashContext::new(dir.path().join("Trash"), mount_points)?;
+        let trash = crate::trash::Trash::from_root(dir.path().join("Trash"))?;
+
+        assert_eq!(
+            ctx.nested_mount_points(&parent),
+            [parent.join("sshfs"), parent.join("iso/inner")]
+        );
+        match ctx.send_to_trash(&trash, &parent) {
+            Err(Error::NestedMountPoints(path, nested)) => {
+                assert_eq!(path, parent);
+                assert_eq!(
+                    nested,
+                    format!("{}/sshfs, {}/iso/inner", parent.display(), parent.display())
+                );
+            }
+            other => panic!("expected the directory to be refused, got {other:?}"),
+        }
+        assert!(parent.join("sshfs").is_dir());
+
+        // Mount points themselves, and what's inside them, are fine
+        assert!(ctx.nested_mount_points(&parent.join("sshfs")).is_empty());
+        assert!(ctx
+            .nested_mount_points(&dir.path().join("mnt-parent-2"))
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_known_trashes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let uid = crate::ffi::real_user_id();
+
+        for topdir in ["with-trash", "without-trash"] {
+            std::fs::create_dir(dir.path().join(topdir))?;
+        }
+        let topdir_trash = dir.path().join(format!("with-trash/.Trash-{uid}"));
+        std::fs::create_dir_all(topdir_trash.join("files"))?;
+        std::fs::create_dir_all(topdir_trash.join("info"))?;
+
+        let prefix = |topdir: &str| dir.path().join(topdir).to_string_lossy().into_owned();
+        let mount_points = vec![
+            mount_point(&prefix("with-trash"), "ext4", false),
+            mount_point(&prefix("without-trash"), "ext4", false),
+        ];
+        let ctx = TrashContext::new(dir.path().join("Trash"), mount_points)?;
+
+        let roots: Vec<_> = ctx
+            .known_trashes()
+            .into_iter()
+            .map(|trash| trash.root.as_path().to_owned())
+            .collect();
+        assert_eq!(roots, [dir.path().join("Trash"), topdir_trash]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn skips_unsafe_trashes_unless_told_not_to() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let uid = crate::ffi::real_user_id();
+
+        // Someone else's trash, which we'd be tricked into using
+        let elsewhere = dir.path().join("elsewhere");
+        std::fs::create_dir_all(elsewhere.join("files"))?;
+        std::fs::create_dir_all(elsewhere.join("info"))?;
+        std::fs::create_dir(dir.path().join("topdir"))?;
+        std::os::unix::fs::symlink(&elsewhere, dir.path().join(format!("topdir/.Trash-{uid}")))?;
+
+        let mount_points = vec![mount_point(
+            &dir.path().join("topdir").to_string_lossy(),
+            "ext4",
+            false,
+        )];
+        let ctx = TrashContext::new(dir.path().join("Trash"), mount_points)?;
+        assert_eq!(ctx.known_trashes().len(), 1);
+
+        let ctx = ctx.verifying_trashes(false);
+        assert_eq!(ctx.known_trashes().len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_topdir_trashes_on_another_filesystem() -> Result<()> {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = tempfile::tempdir()?;
+        let uid = crate::ffi::real_user_id();
+        let topdir = dir.path().join("topdir");
+        fs::create_dir(&topdir)?;
+        let admin_trash = topdir.join(".Trash");
+        fs::create_dir(&admin_trash)?;
+        fs::set_permissions(&admin_trash, fs::Permissions::from_mode(0o1777))?;
+        let home_trash = dir.path().join("Trash");
+        fs::create_dir(&home_trash)?;
+        let file = topdir.join("dummy");
+        File::create(&file)?;
+
+        let elsewhere = std::fs::metadata(&topdir)?.dev() + 1;
+        let admin_files = admin_trash.join(format!("{uid}/files"));
+        let user_files = topdir.join(format!(".Trash-{uid}/files"));
+        let mount_points = || vec![mount_point(&topdir.to_string_lossy(), "ext4", false)];
+
+        // Something is mounted over the admin-provided trash, so `.Trash-$uid` is used instead
+        let ctx = TrashContext::new(&home_trash, mount_points())?
+            .with_fs(FaultyFs::default().relocating(&admin_files, elsewhere));
+        let trash = ctx.topdir_trash(&topdir)?;
+        assert_eq!(trash.root, topdir.join(format!(".Trash-{uid}")).as_path());
+
+        // And when that one is on another filesystem as well, files go to the home trash
+        let fs = FaultyFs::default()
+            .relocating(&admin_files, elsewhere)
+            .relocating(&user_files, elsewhere)
+            .relocating(&home_trash, elsewhere + 1);
+        let ctx = TrashContext::new(&home_trash, mount_points())?.with_fs(fs);
+        assert!(matches!(
+            ctx.topdir_trash(&topdir),
+            Err(Error::TrashOnAnotherFilesystem(files, top)) if files == user_files && top == topdir
+        ));
+        assert_eq!(ctx.trash_for(&file)?.root, home_trash.as_path());
+
+        Ok(())
+    }
+
+    #[test]
+    fn plans_trashing_without_touching_anything() -> Result<()> {
+        use std::fs;
+
+        let dir = tempfile::tempdir()?;
+        let uid = crate::ffi::real_user_id();
+        let topdir = dir.path().join("topdir");
+        fs::create_dir(&topdir)?;
+        let home_trash = dir.path().join("Trash");
+        fs::create_dir(&home_trash)?;
+        let file = topdir.join("dummy");
+        File::create(&file)?;
+
+        // The home trash being elsewhere, the file goes to the trash of its mount point, which doesn't exist yet
+        let elsewhere = fs::metadata(&topdir)?.dev() + 1;
+        let mount_points = vec![mount_point(&topdir.to_string_lossy(), "ext4", false)];
+        let ctx = TrashContext::new(&home_trash, mount_points)?
+            .with_fs(FaultyFs::default().relocating(&home_trash, elsewhere));
+        let (trash, created) = ctx.planned_trash_for(&file)?;
+        assert_eq!(trash.root, topdir.join(format!(".Trash-{uid}")).as_path());
+        assert!(created);
+        assert_eq!(fs::read_dir(&topdir)?.count(), 1);
+
+        let plan = ctx.plan(&trash, &file, &[])?;
+        assert_eq!(plan.file_name, Path::new("dummy"));
+        assert_eq!(plan.transfer, Transfer::Renamed);
+
+        // Names taken in the trash, or by files planned before, are passed over
+        let trash = ctx.trash_for(&file)?;
+        let (planned, created) = ctx.planned_trash_for(&file)?;
+        assert_eq!(planned.root, trash.root);
+        assert!(!created);
+        fs::write(trash.files.as_path().join("dummy"), "")?;
+        fs::write(trash.info.as_path().join("dummy.1.trashinfo"), "")?;
+        let plan = ctx.plan(&trash, &file, &["dummy.2".into()])?;
+        assert_eq!(plan.file_name, Path::new("dummy.3"));
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 1);
+        assert!(file.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_to_one_filesystem_when_told_to() -> Result<()> {
+        use std::fs;
+
+        let dir = tempfile::tempdir()?;
+        let topdir = dir.path().join("topdir");
+        fs::create_dir(&topdir)?;
+        let home_trash = dir.path().join("Trash");
+        fs::create_dir(&home_trash)?;
+        let near = dir.path().join("near");
+        File::create(&near)?;
+        let far = topdir.join("far");
+        File::create(&far)?;
+
+        // The file in the top directory is on another device
+        let elsewhere = fs::metadata(&topdir)?.dev() + 1;
+        let mount_points = vec![mount_point(&topdir.to_string_lossy(), "ext4", false)];
+        let ctx = TrashContext::new(&home_trash, mount_points)?
+            .with_fs(FaultyFs::default().relocating(&far, elsewhere))
+            .keeping_to_one_filesystem(true);
+        assert_eq!(ctx.trash_for(&near)?.root, home_trash.as_path());
+        assert_eq!(ctx.planned_trash_for(&near)?.0.root, home_trash.as_path());
+        for result in [
+            ctx.trash_for(&far),
+            ctx.planned_trash_for(&far).map(|(trash, _)| trash),
+        ] {
+            assert!(matches!(
+                result,
+                Err(Error::CrossesFilesystems(path, root)) if path == far && root == home_trash
+            ));
+        }
+        // No trash was made for it
+        assert_eq!(fs::read_dir(&topdir)?.count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn preserves_root_unless_told_not_to() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let usb = dir.path().join("usb");
+        let mount_points = || {
+            vec![
+                mount_point("/", "ext4", false),
+                mount_point(&usb.to_string_lossy(), "vfat", false),
+            ]
+        };
+
+        let ctx = TrashContext::new(dir.path().join("Trash"), mount_points())?;
+        for root in [Path::new("/"), &usb] {
+            assert!(matches!(
+                ctx.preserve_root(root),
+                Err(Error::MountRoot(path)) if path == root
+            ));
+        }
+        // What's in them is fine
+        ctx.preserve_root(&usb.join("photos"))?;
+
+        let ctx =
+            TrashContext::new(dir.path().join("Trash"), mount_points())?.preserving_root(false);
+        ctx.preserve_root(Path::new("/"))?;
+        ctx.preserve_root(&usb)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn follows_a_symlinked_home_trash() -> Result<()> {
+        use std::{fs, os::unix::fs::symlink};
+
+        let dir = tempfile::tempdir()?;
+        let dir_path = dir.path().canonicalize()?;
+        // Named like the trash of a mount point, which it must not be taken for
+        let target = dir_path.join(format!("data/.Trash-{}", crate::ffi::real_user_id()));
+        for sub in ["files", "info"] {
+            fs::create_dir_all(target.join(sub))?;
+        }
+        fs::write(target.join("directorysizes"), "")?;
+        let data_dir = dir_path.join("home/.local/share");
+        fs::create_dir_all(&data_dir)?;
+        let link = data_dir.join("Trash");
+        symlink(&target, &link)?;
+
+        let in_home = dir_path.join("home/notes.txt");
+        fs::write(&in_home, "home")?;
+        let on_data = dir_path.join("data/big.iso");
+        fs::write(&on_data, "data")?;
+
+        // The trash and the file next to it are on the data disk, away from the home directory
+        let elsewhere = fs::metadata(&dir_path)?.dev() + 1;
+        let fs = FaultyFs::default()
+            .relocating(&target, elsewhere)
+            .relocating(&on_data, elsewhere);
+        let mount_points = vec![mount_point("/", "ext4", false)];
+        let ctx = TrashContext::new(&link, mount_points)?.with_fs(fs);
+        assert_eq!(ctx.home_trash().root, target.as_path());
+        assert_eq!(ctx.home_trash().home_link(), Some(link.as_path()));
+        // Checked where it leads, not refused for being a symlink
+        ctx.verify(ctx.home_trash())?;
+
+        for (path, contents) in [(&in_home, "home"), (&on_data, "data")] {
+            let trash = ctx.trash_for(path)?;
+            assert_eq!(trash.root, target.as_path());
+
+            let name = ctx.send_to_trash(&trash, path)?;
+            assert!(!path.exists());
+            // Absolute, as in any home trash
+            let info =
+                read_info_file(&target.join(format!("info/{}.trashinfo", name.to_string_lossy())))?;
+            assert_eq!(info.path, *path);
+
+            restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default())?;
+            assert_eq!(fs::read_to_string(path)?, contents);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn uses_admin_provided_trash_when_it_passes_the_checks() -> Result<()> {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = tempfile::tempdir()?;
+        let uid = crate::ffi::real_user_id();
+        let topdir = dir.path().join("topdir");
+        fs::create_dir(&topdir)?;
+        let admin_trash = topdir.join(".Trash");
+        fs::create_dir(&admin_trash)?;
+        fs::set_permissions(&admin_trash, fs::Permissions::from_mode(0o1777))?;
+
+        let mount_points = vec![mount_point(&topdir.to_string_lossy(), "ext4", false)];
+        let ctx = TrashContext::new(dir.path().join("Trash"), mount_points)?;
+
+        let trash = ctx.topdir_trash(&topdir)?;
+        assert_eq!(trash.root, admin_trash.join(uid.to_string()).as_path());
+
+        let dummy_path = topdir.join("dummy");
+        File::create(&dummy_path)?;
+        ctx.send_to_trash(&trash, &dummy_path)?;
+        assert!(admin_trash.join(format!("{uid}/files/dummy")).exists());
+        let info = read_info_file(&admin_trash.join(format!("{uid}/info/dummy.trashinfo")))?;
+        assert_eq!(info.path, dummy_path);
+
+        let listed = entries(&trash)?;
+        assert_eq!(listed[0].original_path.as_deref(), Some(&*dummy_path));
+        assert!(ctx
+            .known_trashes()
+            .iter()
+            .any(|known| known.root == trash.root));
+
+        // Without the sticky bit, `.Trash` must not be used
+        fs::set_permissions(&admin_trash, fs::Permissions::from_mode(0o777))?;
+        let trash = ctx.topdir_trash(&topdir)?;
+        assert_eq!(trash.root, topdir.join(format!(".Trash-{uid}")).as_path());
+
+        // Neither can it be a symlink
+        fs::remove_dir_all(&admin_trash)?;
+        let elsewhere = dir.path().join("elsewhere");
+        fs::create_dir(&elsewhere)?;
+        fs::set_permissions(&elsewhere, fs::Permissions::from_mode(0o1777))?;
+        std::os::unix::fs::symlink(&elsewhere, &admin_trash)?;
+        let trash = ctx.topdir_trash(&topdir)?;
+        assert_eq!(trash.root, topdir.join(format!(".Trash-{uid}")).as_path());
+        assert_eq!(fs::read_dir(&elsewhere)?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_the_trash_of_a_mount_point() -> Result<()> {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let dir = tempfile::tempdir()?;
+        let uid = crate::ffi::real_user_id();
+        let topdir = dir.path().join("topdir");
+        fs::create_dir_all(topdir.join("photos"))?;
+
+        let mount_points = vec![mount_point(&topdir.to_string_lossy(), "ext4", false)];
+        let ctx = TrashContext::new(dir.path().join("Trash"), mount_points)?;
+
+        // No trash there, and none is created
+        assert!(ctx.mount_trash(&topdir.join("photos"))?.is_none());
+        assert_eq!(fs::read_dir(&topdir)?.count(), 1);
+
+        let trash = ctx.topdir_trash(&topdir)?;
+        let found = ctx.mount_trash(&topdir.join("photos"))?.unwrap();
+        assert_eq!(found.root, trash.root);
+        assert_eq!(found.root, topdir.join(format!(".Trash-{uid}")).as_path());
+
+        // An admin-provided trash is only taken when it holds one for this user
+        let admin_trash = topdir.join(".Trash");
+        fs::create_dir(&admin_trash)?;
+        fs::set_permissions(&admin_trash, fs::Permissions::from_mode(0o1777))?;
+        let found = ctx.mount_trash(&topdir)?.unwrap();
+        assert_eq!(found.root, trash.root);
+        let trash = ctx.topdir_trash(&topdir)?;
+        let found = ctx.mount_trash(&topdir)?.unwrap();
+        assert_eq!(found.root, admin_trash.join(uid.to_string()).as_path());
+        assert_eq!(found.root, trash.root);
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_files_whose_mount_point_says_so() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let topdir = dir.path().canonicalize()?.join("topdir");
+        std::fs::create_dir(&topdir)?;
+        let dummy_path = topdir.join("dummy");
+        File::create(&dummy_path)?;
+
+        let config = Config::parse(
+            &format!(
+                "[mounts.{:?}]\npolicy = \"refuse\"",
+                topdir.to_string_lossy()
+            ),
+            "config.toml".as_ref(),
+        )?;
+        let mount_points = vec![
+            mount_point("/", "ext4", false),
+            mount_point(&topdir.to_string_lossy(), "ext4", false),
+        ];
+        let ctx = TrashContext::new(dir.path().join("Trash"), mount_points.clone())?
+            .with_policies(config.mounts.clone(), None);
+
+        let err = ctx.policy_for(&dummy_path).unwrap_err();
+        assert!(matches!(&err, Error::RefusedByPolicy(path) if *path == dummy_path));
+        assert!(err.to_string().contains("--policy"));
+        assert!(dummy_path.exists());
+        assert_eq!(std::fs::read_dir(&topdir)?.count(), 1);
+
+        // Other mount points are not concerned
+        assert_eq!(
+            ctx.policy_for(&dir.path().join("elsewhere"))?.policy,
+            Action::Trash
+        );
+
+        // `--policy` has the last word
+        let ctx = TrashContext::new(dir.path().join("Trash"), mount_points)?
+            .with_policies(config.mounts, Some(Action::Trash));
+        assert_eq!(ctx.policy_for(&dummy_path)?.policy, Action::Trash);
+
+        Ok(())
+    }
+
+    #[test]
+    fn files_on_home_trash_device_go_to_home_trash() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let home_trash_root = dir.path().join("Trash");
+
+        // Were the mount table to be used, this file would be refused
+        let mount_points = vec![mount_point("/", "ext4", true)];
+        let ctx = TrashContext::new(&home_trash_root, mount_points)?;
+
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?;
+
+        let trash = ctx.trash_for(&dummy_path)?;
+        assert_eq!(trash.root, home_trash_root.as_path());
+
+        Ok(())
+    }
+
+    #[test]
+    fn operates_on_an_explicit_trash() -> Result<()> {
+        use std::fs;
+
+        let dir = tempfile::tempdir()?;
+        let dir_path = dir.path().canonicalize()?;
+        // Someone else's trash on a disk image, which the current user would never find on their own
+        let root = dir_path.join("image/.Trash-1001");
+        fs::create_dir_all(root.join("files"))?;
+        fs::create_dir_all(root.join("info"))?;
+        fs::write(root.join("directorysizes"), "")?;
+        let home_trash_root = dir_path.join("Trash");
+        fs::create_dir_all(home_trash_root.join("files"))?;
+        fs::create_dir_all(home_trash_root.join("info"))?;
+
+        let mount_points = vec![mount_point("/", "ext4", false)];
+        let ctx = TrashContext::new(&home_trash_root, mount_points)?.targeting(Some(&root))?;
+        let trash = ctx.target_trash().clone();
+        assert_eq!(trash.root, root.as_path());
+        assert_eq!(ctx.home_trash().root, home_trash_root.as_path());
+        let known = ctx.known_trashes();
+        assert_eq!(known.len(), 1);
+        assert_eq!(known[0].root, trash.root);
+
+        // Trashing
+        let dummy_path = dir_path.join("dummy");
+        fs::write(&dummy_path, "dummy")?;
+        let name = ctx.send_to_trash(&ctx.trash_for(&dummy_path)?, &dummy_path)?;
+        assert!(root.join("files/dummy").exists());
+        assert_eq!(fs::read_dir(home_trash_root.join("files"))?.count(), 0);
+        // Even though it's named like the trash of a mount point
+        let info = fs::read_to_string(root.join("info/dummy.trashinfo"))?;
+        assert!(info.contains(&format!("\nPath={}\n", dummy_path.display())));
+
+        // Listing, finding and checking
+        let listed = entries(&trash)?;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].original_path.as_deref(), Some(&*dummy_path));
+        assert_eq!(which(&ctx.known_trashes(), &dummy_path, false)?.len(), 1);
+        assert!(check(&trash)?.is_empty());
+
+        // Restoring
+        restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default())?;
+        assert_eq!(fs::read_to_string(&dummy_path)?, "dummy");
+
+        // Emptying
+        ctx.send_to_trash(&trash, &dummy_path)?;
+        purge(&RealFs, &trash, name.as_os_str())?;
+        assert!(entries(&trash)?.is_empty());
+        assert_eq!(fs::read_dir(root.join("info"))?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_explicit_trashes_that_are_not_trashes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let not_a_trash = dir.path().join("not-a-trash");
+        std::fs::create_dir_all(not_a_trash.join("files"))?;
+
+        let ctx = || TrashContext::new(dir.path().join("Trash"), vec![]);
+        assert!(matches!(
+            ctx()?.targeting(Some(&not_a_trash)),
+            Err(Error::NotATrash(path)) if path == not_a_trash
+        ));
+
+        let missing = dir.path().join("missing");
+        assert!(matches!(
+            ctx()?.targeting(Some(&missing)),
+            Err(Error::TrashDirDoesNotExist(path)) if path == missing
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! Named points in the trashing process at which tests can simulate `tt` dying.
+//!
+//! Outside of tests, [`crash_point`] never does anything.
+
+#[cfg(test)]
+use std::cell::Cell;
+
+use crate::error::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashPoint {
+    /// The info file was written, but the file wasn't moved yet
+    AfterInfoWrite,
+    /// The file was moved into the trash
+    AfterMove,
+    /// Right before `$trash/directorysizes` is updated
+    BeforeDirectorySizes,
+}
+
+#[cfg(test)]
+impl CrashPoint {
+    pub const fn name(self) -> &'static str {
+        match self {
+            CrashPoint::AfterInfoWrite => "after_info_write",
+            CrashPoint::AfterMove => "after_move",
+            CrashPoint::BeforeDirectorySizes => "before_dirsizes",
+        }
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    static ARMED: Cell<Option<CrashPoint>> = const { Cell::new(None) };
+}
+
+/// Disarms its crash point once dropped
+#[cfg(test)]
+pub struct ArmedCrashPoint;
+
+#[cfg(test)]
+impl Drop for ArmedCrashPoint {
+    fn drop(&mut self) {
+        ARMED.with(|armed| armed.set(None));
+    }
+}
+
+/// Makes the current thread abort at `point` until the returned guard is dropped
+#[cfg(test)]
+pub fn arm(point: CrashPoint) -> ArmedCrashPoint {
+    ARMED.with(|armed| armed.set(Some(point)));
+    ArmedCrashPoint
+}
+
+/// Aborts the current operation, skipping any cleanup, if `point` was armed.
+///
+/// Must be used with `?` so that the abort propagates just like dying would.
+#[inline(always)]
+pub fn crash_point(point: CrashPoint) -> Result<()> {
+    #[cfg(test)]
+    if ARMED.with(Cell::get) == Some(point) {
+        return Err(crate::Error::SimulatedCrash(point.name()));
+    }
+
+    let _ = point;
+    Ok(())
+}
@@ -0,0 +1,209 @@
+//! Keeping trashes within the retention and quota of their policies on a schedule, for `--daemon`.
+//!
+//! Limits are otherwise only enforced right after trashing, so a trash nobody trashes to keeps what's past its
+//! retention forever. The daemon runs in the foreground (leaving supervision to systemd and the like), making a pass
+//! over the trashes every interval, give or take [`JITTER`] so that many daemons don't all wake up at once. Only one
+//! may run per user, which [`DaemonLock`] sees to.
+
+use std::{
+    collections::hash_map::RandomState,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    hash::{BuildHasher, Hasher},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    context::TrashContext,
+    error::{Error, Result},
+    ffi::{termination_requested, try_lock_exclusive},
+    fs::RealFs,
+    lock::TrashLock,
+    policy,
+    trash::Trash,
+};
+
+/// How long the daemon waits between passes, unless told otherwise with `--interval`
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// How far from the interval the wait between passes may stray, as a fraction of it
+pub const JITTER: f64 = 0.1;
+
+/// How often a wait between passes checks whether the daemon was asked to stop
+const STOP_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The name of the lock file, in the state directory (see [`crate::home_dir::state_dir_from`])
+const LOCK_FILE_NAME: &str = "daemon.lock";
+
+/// The lock held by the running daemon, released when dropped or when the process dies
+#[derive(Debug)]
+pub struct DaemonLock {
+    _file: File,
+}
+
+impl DaemonLock {
+    /// Takes the lock in `state_dir`, failing with [`Error::DaemonRunning`] if another daemon holds it
+    pub fn acquire(state_dir: &Path) -> Result<Self> {
+        fs::create_dir_all(state_dir)?;
+        let path = lock_path(state_dir);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        if !try_lock_exclusive(&file)? {
+            return Err(Error::DaemonRunning(path));
+        }
+
+        Ok(Self { _file: file })
+    }
+}
+
+/// Purges what's past the limits of their policies (see [`TrashContext::limits_of`]) from `trashes` as of `now`,
+/// returning the names purged from each, or why that failed. A trash failing doesn't keep the others from being done.
+///
+/// Each trash is locked exclusive while it's purged (see [`crate::lock`]), as purging mustn't get in the way of trashing.
+pub fn pass<'a>(
+    ctx: &TrashContext,
+    trashes: &'a [Trash],
+    now: SystemTime,
+) -> Vec<(&'a Trash, Result<Vec<OsString>>)> {
+    trashes
+        .iter()
+        .map(|trash| {
+            let limits = ctx.limits_of(trash);
+            let purged = TrashLock::exclusive(trash)
+                .and_then(|_lock| policy::enforce_limits(&RealFs, trash, &limits, None, now));
+
+            (trash, purged)
+        })
+        .collect()
+}
+
+/// `interval`, moved by up to [`JITTER`] of it either way, depending on `seed`
+pub fn jittered(interval: Duration, seed: u64) -> Duration {
+    // From -1 to 1
+    let offset = 2.0 * (seed as f64 / u64::MAX as f64) - 1.0;
+
+    interval.mul_f64(1.0 + JITTER * offset)
+}
+
+/// A seed for [`jittered`] that differs from one call to the next
+pub fn random_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Waits for `duration`, unless the daemon is asked to stop (see [`crate::ffi::catch_termination`]) in the meantime,
+/// returning whether it waited all the way.
+pub fn wait(duration: Duration) -> bool {
+    let mut left = duration;
+    while !left.is_zero() {
+        if termination_requested() {
+            return false;
+        }
+        let step = left.min(STOP_CHECK_INTERVAL);
+        thread::sleep(step);
+        left -= step;
+    }
+
+    !termination_requested()
+}
+
+/// Where the lock is held, given the state directory
+pub fn lock_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(LOCK_FILE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, time::Duration};
+
+    use super::{jittered, lock_path, pass, DaemonLock, DEFAULT_INTERVAL, JITTER};
+    use crate::{
+        context::TrashContext, error::Result, ffi::MountPoint, policy::MountPolicy, Error,
+    };
+
+    #[test]
+    fn purges_what_is_past_retention() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("Trash");
+        for subdir in ["files", "info"] {
+            fs::create_dir_all(root.join(subdir))?;
+        }
+        for (name, deletion_date) in [
+            ("old", "2001-01-01T00:00:00"),
+            ("new", "2999-01-01T00:00:00"),
+        ] {
+            fs::write(root.join("files").join(name), "dummy")?;
+            fs::write(
+                root.join("info").join(format!("{name}.trashinfo")),
+                format!("[Trash Info]\nPath=/tmp/{name}\nDeletionDate={deletion_date}\n"),
+            )?;
+        }
+
+        let mount_points = vec![MountPoint {
+            fs_name: "dummy".into(),
+            fs_path_prefix: dir.path().to_owned(),
+            fs_type: "ext4".into(),
+            read_only: false,
+        }];
+        let policies = BTreeMap::from([(
+            "ext4".to_owned(),
+            MountPolicy {
+                retention: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+                ..MountPolicy::default()
+            },
+        )]);
+        let ctx = TrashContext::new(&root, mount_points)?
+            .verifying_trashes(false)
+            .with_policies(policies, None);
+
+        let trashes = [ctx.home_trash().clone()];
+        let purged: Vec<_> = pass(&ctx, &trashes, std::time::SystemTime::now())
+            .into_iter()
+            .map(|(_, purged)| purged.unwrap())
+            .collect();
+        assert_eq!(purged, [["old"]]);
+        assert!(!root.join("files/old").exists());
+        assert!(root.join("files/new").exists());
+
+        // Nothing's left to purge on the next pass
+        let purged = pass(&ctx, &trashes, std::time::SystemTime::now());
+        assert!(purged[0].1.as_ref().unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_a_second_daemon() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let state_dir = dir.path().join("state/tt");
+
+        let lock = DaemonLock::acquire(&state_dir)?;
+        assert!(matches!(
+            DaemonLock::acquire(&state_dir),
+            Err(Error::DaemonRunning(path)) if path == lock_path(&state_dir)
+        ));
+
+        drop(lock);
+        DaemonLock::acquire(&state_dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn jitters_within_bounds() {
+        let lowest = DEFAULT_INTERVAL.mul_f64(1.0 - JITTER);
+        let highest = DEFAULT_INTERVAL.mul_f64(1.0 + JITTER);
+
+        assert_eq!(jittered(DEFAULT_INTERVAL, 0), lowest);
+        assert_eq!(jittered(DEFAULT_INTERVAL, u64::MAX), highest);
+        for seed in [1, u64::MAX / 3, u64::MAX / 2, u64::MAX - 1] {
+            let wait = jittered(DEFAULT_INTERVAL, seed);
+            assert!(lowest <= wait && wait <= highest, "{wait:?}");
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Dates and times, as stored in the `DeletionDate` key of info files and as given to `--since` and `--before`
+
+use std::{fmt, time::Duration};
+
+use crate::{
+    error::{Error, Result},
+    ffi,
+};
+
+/// A date and time in the local timezone, without a timezone offset, just like `DeletionDate`.
+///
+/// The fields are ordered from the most to the least significant, so the derived ordering is chronological.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+}
+
+impl DateTime {
+    /// Parses a date and time in the `YYYY-MM-DDThh:mm:ss` format
+    pub fn parse(text: &str) -> Option<Self> {
+        let (date, time) = text.split_once('T').or_else(|| text.split_once(' '))?;
+        let mut date_time = Self::parse_date(date)?;
+
+        let mut fields = time.split(':');
+        date_time.hour = parse_field(fields.next()?, 2).filter(|&hour| hour < 24)?;
+        date_time.minute = parse_field(fields.next()?, 2).filter(|&minute| minute < 60)?;
+        date_time.second = parse_field(fields.next()?, 2).filter(|&second| second < 60)?;
+
+        fields.next().is_none().then_some(date_time)
+    }
+
+    /// Parses a date in the `YYYY-MM-DD` format, at midnight
+    fn parse_date(text: &str) -> Option<Self> {
+        let mut fields = text.split('-');
+        let year = parse_field(fields.next()?, 4)?.into();
+        let month = parse_field(fields.next()?, 2).filter(|month| (1..=12).contains(month))?;
+        let day = parse_field(fields.next()?, 2)
+            .filter(|&day| day >= 1 && day <= days_in_month(year, month))?;
+
+        fields.next().is_none().then_some(Self {
+            year,
+            month,
+            day,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        })
+    }
+
+    /// The number of seconds since UNIX_EPOCH of this local date and time, if it can be told
+    pub fn timestamp(&self) -> Option<i64> {
+        ffi::local_timestamp(
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+        )
+    }
+
+    /// This date and time in the RFC 3339 format, along with the offset the local timezone had then, if it can be told
+    pub fn rfc3339(&self) -> Option<String> {
+        let offset = ffi::local_offset(self.timestamp()?)?;
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset = offset.unsigned_abs();
+
+        Some(format!(
+            "{self}{sign}{:02}:{:02}",
+            offset / 3600,
+            offset / 60 % 60
+        ))
+    }
+
+    /// The local date and time of a timestamp (represented as a [`Duration`] since UNIX_EPOCH)
+    pub fn from_timestamp(timestamp: Duration) -> Result<Self> {
+        let formatted = ffi::format_timestamp(timestamp)?;
+
+        Self::parse(&formatted).ok_or(Error::InvalidDate(formatted))
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Parses a field made of exactly `len` ASCII digits
+fn parse_field(field: &str, len: usize) -> Option<u32> {
+    let is_valid = field.len() == len && field.bytes().all(|byte| byte.is_ascii_digit());
+
+    is_valid.then(|| field.parse().ok()).flatten()
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+
+    match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// How many days `year-month-day` is after 1970-01-01, in the proleptic Gregorian calendar
+fn days_since_epoch(year: i64, month: u32, day: u32) -> i64 {
+    let (month, day) = (i64::from(month), i64::from(day));
+
+    // Years start in March so that leap days are at their end
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parses an RFC 3339 date and time with a timezone offset (e.g. `2024-01-01T10:00:00+02:00`),
+/// converting it to local time
+fn parse_rfc3339(text: &str) -> Option<Result<DateTime>> {
+    rfc3339_timestamp(text).map(DateTime::from_timestamp)
+}
+
+/// Parses an RFC 3339 date and time with a timezone offset into a timestamp (a [`Duration`] since UNIX_EPOCH).
+///
+/// Dates before UNIX_EPOCH can't be represented, so these are refused.
+fn rfc3339_timestamp(text: &str) -> Option<Duration> {
+    let (date_time, offset) = if let Some(date_time) = text.strip_suffix(['Z', 'z']) {
+        (date_time, 0)
+    } else {
+        let split = text.len().checked_sub(6)?;
+        let (date_time, offset) = (text.get(..split)?, text.get(split..)?);
+        let sign = match offset.as_bytes()[0] {
+            b'+' => 1,
+            b'-' => -1,
+            _ => return None,
+        };
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        let offset = parse_field(hours, 2)? * 3600 + parse_field(minutes, 2)? * 60;
+
+        (date_time, sign * i64::from(offset))
+    };
+
+    // Fractions of a second are allowed, but ignored
+    let date_time = match date_time.split_once('.') {
+        Some((date_time, fraction)) if fraction.bytes().all(|byte| byte.is_ascii_digit()) => {
+            date_time
+        }
+        Some(_) => return None,
+        None => date_time,
+    };
+
+    let utc = DateTime::parse(date_time)?;
+    let seconds = days_since_epoch(utc.year, utc.month, utc.day) * 86_400
+        + i64::from(utc.hour * 3600 + utc.minute * 60 + utc.second)
+        - offset;
+
+    Some(Duration::from_secs(u64::try_from(seconds).ok()?))
+}
+
+/// Parses a relative age such as `7d` or `3 weeks`, returning how long ago it refers to
+pub fn parse_age(text: &str) -> Option<Duration> {
+    let unit_start = text
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (amount, unit) = text.split_at(unit_start);
+
+    let amount: u64 = amount.parse().ok()?;
+    let unit_in_seconds = match unit.trim_start() {
+        "s" | "sec" | "secs" | "second" | "seconds" => 1,
+        "m" | "min" | "mins" | "minute" | "minutes" => 60,
+        "h" | "hour" | "hours" => 60 * 60,
+        "d" | "day" | "days" => 24 * 60 * 60,
+        "w" | "week" | "weeks" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    amount.checked_mul(unit_in_seconds).map(Duration::from_secs)
+}
+
+/// Parses the argument of `--since` or `--before`.
+///
+/// Accepted are:
+/// * dates (`YYYY-MM-DD`), meaning their midnight in local time;
+/// * dates and times (`YYYY-MM-DDThh:mm:ss`), in local time;
+/// * RFC 3339 dates and times with a timezone offset (`2024-01-01T10:00:00Z`, `2024-01-01T10:00:00-03:00`);
+/// * ages relative to `now` (a [`Duration`] since UNIX_EPOCH), such as `7d` or `3 weeks`.
+pub fn parse_date_argument(argument: &str, now: Duration) -> Result<DateTime> {
+    let invalid = || Error::InvalidDate(argument.to_owned());
+    let argument_trimmed = argument.trim();
+
+    if let Some(date) = DateTime::parse_date(argument_trimmed) {
+        return Ok(date);
+    }
+
+    if let Some(date_time) = DateTime::parse(argument_trimmed) {
+        return Ok(date_time);
+    }
+
+    if let Some(date_time) = parse_rfc3339(argument_trimmed) {
+        return date_time;
+    }
+
+    let age = parse_age(argument_trimmed).ok_or_else(invalid)?;
+    let timestamp = now.checked_sub(age).ok_or_else(invalid)?;
+
+    DateTime::from_timestamp(timestamp)
+}
+
+/// Parses the argument of `--deletion-date`, an RFC 3339 date and time with a timezone offset
+/// (e.g. `2019-05-04T18:30:00+02:00`), into a timestamp (a [`Duration`] since UNIX_EPOCH).
+pub fn parse_deletion_date(argument: &str) -> Result<Duration> {
+    rfc3339_timestamp(argument.trim())
+        .ok_or_else(|| Error::InvalidDeletionDate(argument.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{days_since_epoch, parse_date_argument, parse_deletion_date, DateTime};
+    use crate::Error;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn date_time(text: &str) -> DateTime {
+        DateTime::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parses_deletion_dates() {
+        let parsed = date_time("2004-08-31T22:32:08");
+        assert_eq!(parsed.to_string(), "2004-08-31T22:32:08");
+        assert!(parsed < date_time("2004-09-01T00:00:00"));
+
+        assert_eq!(DateTime::parse("2004-08-31 22:32:08"), Some(parsed));
+
+        for invalid in [
+            "",
+            "2004-08-31",
+            "2004-08-31T22:32",
+            "2004-8-31T22:32:08",
+            "2004-08-32T22:32:08",
+            "2003-02-29T00:00:00",
+            "2004-08-31T24:00:00",
+            "2004-08-31T22:32:08:00",
+        ] {
+            assert_eq!(DateTime::parse(invalid), None, "{invalid}");
+        }
+        assert!(DateTime::parse("2004-02-29T00:00:00").is_some());
+    }
+
+    #[test]
+    fn counts_days_since_epoch() {
+        assert_eq!(days_since_epoch(1970, 1, 1), 0);
+        assert_eq!(days_since_epoch(2000, 3, 1), 11_017);
+        assert_eq!(days_since_epoch(1969, 12, 31), -1);
+    }
+
+    #[test]
+    fn parses_absolute_date_arguments() {
+        let now = Duration::from_secs(0);
+
+        assert_eq!(
+            parse_date_argument("2024-01-01", now).unwrap(),
+            date_time("2024-01-01T00:00:00")
+        );
+        assert_eq!(
+            parse_date_argument("2024-01-01T10:20:30", now).unwrap(),
+            date_time("2024-01-01T10:20:30")
+        );
+
+        // 2024-01-01T10:00:00Z
+        let expected = DateTime::from_timestamp(Duration::from_secs(1_704_103_200)).unwrap();
+        for rfc3339 in [
+            "2024-01-01T10:00:00Z",
+            "2024-01-01T10:00:00.250Z",
+            "2024-01-01T12:00:00+02:00",
+            "2024-01-01T07:00:00-03:00",
+        ] {
+            assert_eq!(parse_date_argument(rfc3339, now).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn parses_relative_date_arguments() {
+        let now = Duration::from_secs(1_704_103_200);
+        let ago = |seconds| DateTime::from_timestamp(now - Duration::from_secs(seconds)).unwrap();
+
+        for (argument, seconds) in [
+            ("7d", 7 * DAY),
+            ("7 days", 7 * DAY),
+            ("1day", DAY),
+            ("3 weeks", 21 * DAY),
+            ("2w", 14 * DAY),
+            ("12h", 12 * 60 * 60),
+            ("30 min", 30 * 60),
+            ("45s", 45),
+        ] {
+            assert_eq!(parse_date_argument(argument, now).unwrap(), ago(seconds));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_date_arguments() {
+        let now = Duration::from_secs(1_704_103_200);
+
+        for invalid in [
+            "",
+            "yesterday",
+            "7",
+            "d",
+            "-7d",
+            "7 fortnights",
+            "2024-13-01",
+            "2024-01-01T10:00:00+2",
+            "2024-01-01T10:00:00.5x+02:00",
+            "100000w",
+        ] {
+            assert!(
+                matches!(
+                    parse_date_argument(invalid, now),
+                    Err(Error::InvalidDate(_))
+                ),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_deletion_date_arguments() {
+        for rfc3339 in [
+            "2024-01-01T10:00:00Z",
+            " 2024-01-01T10:00:00.250Z",
+            "2024-01-01T12:00:00+02:00",
+        ] {
+            assert_eq!(
+                parse_deletion_date(rfc3339).unwrap(),
+                Duration::from_secs(1_704_103_200)
+            );
+        }
+        assert_eq!(
+            parse_deletion_date("1970-01-01T00:00:00Z").unwrap(),
+            Duration::ZERO
+        );
+
+        for invalid in [
+            "",
+            "7d",
+            // Without a timezone, it can't be told apart
+            "2024-01-01T10:00:00",
+            "2024-01-01",
+            "2024-02-30T10:00:00Z",
+            "1969-12-31T23:59:59Z",
+            "1970-01-01T00:00:00+01:00",
+        ] {
+            assert!(
+                matches!(
+                    parse_deletion_date(invalid),
+                    Err(Error::InvalidDeletionDate(_))
+                ),
+                "{invalid}"
+            );
+        }
+    }
+}
@@ -0,0 +1,369 @@
+//! Replacing identical files in a trash with hard links to a single one, for `--dedupe`.
+//!
+//! Every regular file in `$trash/files` is a candidate, those inside trashed directories included. Candidates are
+//! grouped by size (along with their mode, owner and modification time, which linked files share), then by the
+//! SHA-256 of their contents,
+//! and all but one of the files of a group are replaced with hard links to that one. Every entry can still be restored,
+//! as each of its names still leads to the same contents.
+//!
+//! Entries may then share files, which [`linked_from_elsewhere`] tells apart: shredding an entry spares them (see
+//! [`crate::shred`]), and restoring one gives it copies of its own (see [`unshare`]), so that neither touches the
+//! entries that are left.
+//!
+//! Files with links outside of the trash are never replaced, as that wouldn't free anything: only whatever the trash
+//! holds the last links to. Nor are they linked to, lest changing them changed the entries too. Neither are files on another filesystem than `$trash/files`, which can't be linked to.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::{OsStr, OsString},
+    path::{Component, Path, PathBuf},
+};
+
+use unixstring::UnixString;
+use walkdir::WalkDir;
+
+use crate::{
+    checksum::checksum,
+    directorysizes::replace_directory_size,
+    error::Result,
+    ffi::{FileId, Lstat},
+    fs::{directory_size, is_temp_file_name, temp_path_next_to, FileSystem},
+    trash::Trash,
+};
+
+/// What deduplicating a trash did
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Deduplication {
+    /// How many files were replaced with hard links
+    pub linked: usize,
+    /// How many bytes that freed
+    pub saved: u64,
+}
+
+/// A file in `$trash/files`, under all of its names there
+#[derive(Debug)]
+struct Inode {
+    names: Vec<PathBuf>,
+    /// How many links it has, in the trash or not
+    nlink: u64,
+}
+
+impl Inode {
+    /// Whether every link to it is in the trash, so that replacing them all frees its space
+    fn is_replaceable(&self) -> bool {
+        self.nlink == self.names.len() as u64
+    }
+}
+
+/// What files must share to be linked together, their contents aside: size, mode, owner, group and modification
+/// time, in seconds and nanoseconds
+type Shape = (u64, u32, u32, u32, u64, i64);
+
+/// Replaces the identical files of `trash` with hard links to one of them, updating `$trash/directorysizes` for the
+/// directories that shrank.
+pub fn dedupe(fs: &impl FileSystem, trash: &Trash) -> Result<Deduplication> {
+    let files = trash.files.as_path();
+    let device = Lstat::lstat(UnixString::try_from(files.to_owned())?)?.device();
+
+    let mut inodes: BTreeMap<FileId, Inode> = BTreeMap::new();
+    let mut shapes: BTreeMap<Shape, BTreeSet<FileId>> = BTreeMap::new();
+    for entry in WalkDir::new(files).min_depth(1).sort_by_file_name() {
+        let entry = entry.map_err(std::io::Error::from)?;
+        if is_temp_file_name(entry.file_name()) {
+            continue;
+        }
+
+        let stat = Lstat::lstat(UnixString::try_from(entry.path().to_owned())?)?;
+        // Empty files take up no space to begin with
+        if !stat.is_regular_file() || stat.size() == 0 || stat.device() != device {
+            continue;
+        }
+
+        let id = stat.file_id();
+        inodes
+            .entry(id)
+            .or_insert_with(|| Inode {
+                names: Vec::new(),
+                nlink: stat.nlink(),
+            })
+            .names
+            .push(entry.into_path());
+        let shape = (
+            stat.size(),
+            stat.mode(),
+            stat.owner_user_id(),
+            stat.owner_group_id(),
+            stat.modified(),
+            stat.modified_nsec(),
+        );
+        shapes.entry(shape).or_default().insert(id);
+    }
+
+    let mut deduplication = Deduplication::default();
+    let mut shrunk: BTreeSet<OsString> = BTreeSet::new();
+    for ((size, ..), ids) in shapes {
+        if ids.len() < 2 {
+            continue;
+        }
+
+        let mut identical: BTreeMap<String, Vec<FileId>> = BTreeMap::new();
+        for id in ids {
+            let hash = checksum(&inodes[&id].names[0])?;
+            identical.entry(hash).or_default().push(id);
+        }
+
+        for ids in identical.into_values().filter(|ids| ids.len() > 1) {
+            // Never one with links outside of the trash, which could change what's in it
+            let Some(keeper) = ids.iter().find(|id| inodes[id].is_replaceable()) else {
+                continue;
+            };
+            let kept = &inodes[keeper].names[0];
+
+            for id in ids.iter().filter(|&id| id != keeper) {
+                let inode = &inodes[id];
+                if !inode.is_replaceable() {
+                    continue;
+                }
+
+                for name in &inode.names {
+                    link_over(fs, kept, name)?;
+                    shrunk.extend(top_level_directory(files, name));
+                }
+                deduplication.linked += inode.names.len();
+                deduplication.saved += size;
+            }
+        }
+    }
+
+    for name in shrunk {
+        let size = directory_size(UnixString::try_from(files.join(&name))?)?;
+        replace_directory_size(fs, trash, &name, size)?;
+    }
+
+    Ok(deduplication)
+}
+
+/// The regular files of the entry of `trash` named `name` that other entries of `trash` have hard links to, as
+/// [`dedupe`] leaves them
+pub fn linked_from_elsewhere(trash: &Trash, name: &OsStr) -> Result<BTreeSet<FileId>> {
+    let files = trash.files.as_path();
+
+    let mut linked = BTreeSet::new();
+    for entry in WalkDir::new(files.join(name)) {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let stat = Lstat::lstat(UnixString::try_from(entry.path().to_owned())?)?;
+        if stat.is_regular_file() && stat.nlink() > 1 {
+            linked.insert(stat.file_id());
+        }
+    }
+    // Which spares going over the whole trash most of the time
+    if linked.is_empty() {
+        return Ok(linked);
+    }
+
+    let mut elsewhere = BTreeSet::new();
+    let others = WalkDir::new(files)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| entry.depth() > 1 || entry.file_name() != name);
+    for entry in others {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let stat = Lstat::lstat(UnixString::try_from(entry.path().to_owned())?)?;
+        if linked.contains(&stat.file_id()) {
+            elsewhere.insert(stat.file_id());
+        }
+    }
+
+    Ok(elsewhere)
+}
+
+/// Replaces every regular file at or under `path` that's one of `shared` with a copy of its own, modified at the same
+/// time, so that changing it leaves the entries it was linked with alone
+pub fn unshare(fs: &impl FileSystem, path: &Path, shared: &BTreeSet<FileId>) -> Result<()> {
+    if shared.is_empty() {
+        return Ok(());
+    }
+
+    for entry in WalkDir::new(path) {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let stat = Lstat::lstat(UnixString::try_from(entry.path().to_owned())?)?;
+        if !stat.is_regular_file() || !shared.contains(&stat.file_id()) {
+            continue;
+        }
+
+        let modified = entry.metadata().map_err(std::io::Error::from)?.modified()?;
+        let temp = temp_path_next_to(entry.path());
+        fs.copy_file(entry.path(), &temp)?;
+        let copied = std::fs::File::options()
+            .write(true)
+            .open(&temp)
+            .and_then(|copy| copy.set_modified(modified))
+            .and_then(|()| fs.rename(&temp, entry.path()));
+        if let Err(err) = copied {
+            let _ = std::fs::remove_file(&temp);
+            return Err(err.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces `path` with a hard link to `target`, atomically
+fn link_over(fs: &impl FileSystem, target: &Path, path: &Path) -> Result<()> {
+    let temp = temp_path_next_to(path);
+    std::fs::hard_link(target, &temp)?;
+
+    if let Err(err) = fs.rename(&temp, path) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// The name of the trashed directory in `files` that `path` is inside of, if it's in one
+fn top_level_directory(files: &Path, path: &Path) -> Option<OsString> {
+    let mut components = path.strip_prefix(files).ok()?.components();
+    match (components.next()?, components.next()) {
+        (Component::Normal(name), Some(_)) => Some(name.to_owned()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        os::unix::fs::MetadataExt,
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{dedupe, Deduplication};
+    use crate::{
+        directorysizes::read_directory_sizes,
+        entries::{entries, shred_and_purge},
+        error::Result,
+        fs::RealFs,
+        restore::{restore, RestoreOptions},
+        trash::Trash,
+    };
+
+    fn inode(path: &Path) -> u64 {
+        fs::symlink_metadata(path).unwrap().ino()
+    }
+
+    /// Writes `contents` to a new file at `path`, modified at `modified`
+    fn write_at(path: &Path, contents: &[u8], modified: SystemTime) -> Result<()> {
+        fs::write(path, contents)?;
+        File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(modified)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn links_identical_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        fs::write(&trash.directory_sizes, "")?;
+
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let build = dir.path().join("build");
+        fs::create_dir(&build)?;
+        write_at(&build.join("app"), &[7; 1000], modified)?;
+        write_at(&build.join("app.bak"), &[7; 1000], modified)?;
+        fs::write(build.join("log"), "unique")?;
+        let app = dir.path().join("app");
+        write_at(&app, &[7; 1000], modified)?;
+        // Same size, other contents
+        let other = dir.path().join("other");
+        write_at(&other, &[8; 1000], modified)?;
+        // Same contents, modified at another time, which linking would lose
+        let newer = dir.path().join("newer");
+        write_at(&newer, &[7; 1000], modified + Duration::from_secs(1))?;
+        // One with a link outside of the trash, which isn't worth replacing, nor linking to
+        let kept_outside = dir.path().join("kept");
+        write_at(&kept_outside, &[7; 1000], modified)?;
+        for path in [&build, &app, &other, &newer, &kept_outside] {
+            trash.send_to_trash(&RealFs, path)?;
+        }
+        let files = trash.files.as_path();
+        fs::hard_link(files.join("kept"), &kept_outside)?;
+
+        let before = read_directory_sizes(&trash)?[0].size;
+
+        assert_eq!(
+            dedupe(&RealFs, &trash)?,
+            Deduplication {
+                linked: 2,
+                saved: 2000
+            }
+        );
+
+        // Identical files in the trash alone are linked together
+        let linked = inode(&files.join("app"));
+        for name in ["build/app", "build/app.bak"] {
+            assert_eq!(inode(&files.join(name)), linked, "{name}");
+            assert_eq!(fs::read(files.join(name))?, vec![7; 1000]);
+        }
+        for name in ["other", "newer", "kept"] {
+            assert_ne!(inode(&files.join(name)), linked, "{name}");
+        }
+
+        // The trashed directory holds its two copies once
+        let after = read_directory_sizes(&trash)?;
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].name, "build");
+        assert_eq!(after[0].size, before - 1000);
+
+        // Nothing's left to do the second time around
+        assert_eq!(dedupe(&RealFs, &trash)?, Deduplication::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_the_entries_left_alone_when_one_is_shredded_or_restored() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        fs::write(&trash.directory_sizes, "")?;
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for name in ["a", "b", "c"] {
+            let path = dir.path().join(name);
+            write_at(&path, b"the same contents", modified)?;
+            trash.send_to_trash(&RealFs, &path)?;
+        }
+        let files = trash.files.as_path();
+        assert_eq!(dedupe(&RealFs, &trash)?.linked, 2);
+
+        // b still holds the contents of a
+        shred_and_purge(&RealFs, &trash, "a".as_ref())?;
+        assert_eq!(fs::read(files.join("b"))?, b"the same contents");
+
+        // Changing a restored file leaves the one in the trash as it was
+        let options = RestoreOptions {
+            dry_run: false,
+            verify: false,
+            preserve_context: true,
+        };
+        restore(&RealFs, &trash, "b".as_ref(), options)?;
+        let restored = dir.path().join("b");
+        assert_ne!(inode(&restored), inode(&files.join("c")));
+        assert_eq!(fs::metadata(&restored)?.modified()?, modified);
+        fs::write(&restored, "changed")?;
+        assert_eq!(fs::read(files.join("c"))?, b"the same contents");
+
+        // The last one left has nothing to spare
+        shred_and_purge(&RealFs, &trash, "c".as_ref())?;
+        assert!(entries(&trash)?.is_empty());
+
+        Ok(())
+    }
+}
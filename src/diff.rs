@@ -0,0 +1,533 @@
+//! Comparing a trashed entry with what's now at its original path, for `--diff`.
+//!
+//! Text files get a unified diff, binary files (those with a NUL byte in their first 8 KiB, as `diff`
+//! decides it) are only reported as differing, with their sizes and SHA-256 hashes. Directories get
+//! a summary: which files are only on one side and which ones changed. A missing original path is
+//! compared as if it were empty, like `diff -N` does.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::{error::Result, report::display_path};
+
+/// Lines of context around the changes of a unified diff
+const CONTEXT: usize = 3;
+
+/// How many bytes are looked at to tell binary files apart
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+/// Past this many differing lines, the shortest diff isn't searched for anymore, which would take
+/// quadratic memory: the differing region is shown as entirely removed and then entirely added.
+const MAX_EDIT_COST: usize = 2000;
+
+/// What there is at a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Missing,
+    File,
+    Directory,
+    Symlink,
+}
+
+impl Kind {
+    fn of(path: &Path) -> io::Result<Self> {
+        match path.symlink_metadata() {
+            Ok(metadata) if metadata.is_dir() => Ok(Self::Directory),
+            Ok(metadata) if metadata.is_symlink() => Ok(Self::Symlink),
+            Ok(_) => Ok(Self::File),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::Missing),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Kind::Missing => "missing",
+            Kind::File => "a file",
+            Kind::Directory => "a directory",
+            Kind::Symlink => "a symbolic link",
+        }
+    }
+}
+
+/// Writes to `out` how the trashed entry at `trashed` differs from `original`, returning whether it does.
+pub fn diff(trashed: &Path, original: &Path, out: &mut impl Write) -> Result<bool> {
+    match (Kind::of(trashed)?, Kind::of(original)?) {
+        (Kind::Directory, Kind::Directory | Kind::Missing) => {
+            diff_directories(trashed, original, out)
+        }
+        (Kind::File, Kind::File | Kind::Missing) => diff_files(trashed, original, out),
+        (Kind::Symlink, Kind::Symlink) => {
+            let (from, to) = (fs_err::read_link(trashed)?, fs_err::read_link(original)?);
+            if from != to {
+                writeln!(
+                    out,
+                    "Symbolic links differ: {} -> {} and {} -> {}",
+                    display_path(trashed),
+                    display_path(&from),
+                    display_path(original),
+                    display_path(&to)
+                )?;
+            }
+            Ok(from != to)
+        }
+        (trashed_kind, original_kind) => {
+            writeln!(
+                out,
+                "{} is {} while {} is {}",
+                display_path(trashed),
+                trashed_kind.describe(),
+                display_path(original),
+                original_kind.describe()
+            )?;
+            Ok(true)
+        }
+    }
+}
+
+/// Runs `tool` through `sh -c` on the trashed entry and its original path, returning its exit status.
+///
+/// Both paths are given as arguments, e.g. `meld` runs `meld TRASHED ORIGINAL`.
+pub fn run_tool(tool: &str, trashed: &Path, original: &Path) -> Result<i32> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{tool} \"$1\" \"$2\""))
+        .arg("sh")
+        .arg(trashed)
+        .arg(original)
+        .status()?;
+
+    // Killed by a signal
+    Ok(status.code().unwrap_or(2))
+}
+
+/// The contents of the file at `path`, which are empty if it doesn't exist
+fn read_or_empty(path: &Path) -> Result<Vec<u8>> {
+    match fs_err::read(path) {
+        Ok(contents) => Ok(contents),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn is_binary(contents: &[u8]) -> bool {
+    contents[..contents.len().min(BINARY_SNIFF_LEN)].contains(&0)
+}
+
+fn diff_files(trashed: &Path, original: &Path, out: &mut impl Write) -> Result<bool> {
+    let (old, new) = (read_or_empty(trashed)?, read_or_empty(original)?);
+    if old == new {
+        return Ok(false);
+    }
+
+    if is_binary(&old) || is_binary(&new) {
+        writeln!(
+            out,
+            "Binary files {} and {} differ",
+            display_path(trashed),
+            display_path(original)
+        )?;
+        for (path, contents) in [(trashed, &old), (original, &new)] {
+            writeln!(
+                out,
+                "  {}: {} bytes, sha256 {:x}",
+                display_path(path),
+                contents.len(),
+                Sha256::digest(contents)
+            )?;
+        }
+        return Ok(true);
+    }
+
+    writeln!(out, "--- {}", display_path(trashed))?;
+    writeln!(out, "+++ {}", display_path(original))?;
+    write_unified_diff(&lines(&old), &lines(&new), out)?;
+
+    Ok(true)
+}
+
+/// Summarizes how the directories differ, file by file
+fn diff_directories(trashed: &Path, original: &Path, out: &mut impl Write) -> Result<bool> {
+    // Which side each relative path is on
+    let mut paths: BTreeMap<PathBuf, (bool, bool)> = BTreeMap::new();
+    for (root, is_trashed) in [(trashed, true), (original, false)] {
+        if Kind::of(root)? == Kind::Missing {
+            continue;
+        }
+
+        for entry in WalkDir::new(root).min_depth(1) {
+            let entry = entry.map_err(io::Error::from)?;
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            let sides = paths.entry(relative.to_owned()).or_default();
+            if is_trashed {
+                sides.0 = true;
+            } else {
+                sides.1 = true;
+            }
+        }
+    }
+
+    let mut differs = false;
+    for (relative, sides) in paths {
+        let (from, to) = (trashed.join(&relative), original.join(&relative));
+
+        let line = match sides {
+            (true, false) => format!("Only in the trash: {}", display_path(&relative)),
+            (false, _) => format!("Only at the original path: {}", display_path(&relative)),
+            (true, true) => match (Kind::of(&from)?, Kind::of(&to)?) {
+                (Kind::Directory, Kind::Directory) => continue,
+                (Kind::File, Kind::File) => {
+                    let (old, new) = (fs_err::read(&from)?, fs_err::read(&to)?);
+                    if old == new {
+                        continue;
+                    }
+                    if is_binary(&old) || is_binary(&new) {
+                        format!("Binary files differ: {}", display_path(&relative))
+                    } else {
+                        let (removed, added) = count_changes(&lines(&old), &lines(&new));
+                        format!("Changed: {} (+{added} -{removed})", display_path(&relative))
+                    }
+                }
+                (Kind::Symlink, Kind::Symlink) => {
+                    if fs_err::read_link(&from)? == fs_err::read_link(&to)? {
+                        continue;
+                    }
+                    format!("Changed: {} (symbolic link)", display_path(&relative))
+                }
+                (from_kind, to_kind) => format!(
+                    "Changed: {} ({} in the trash, {} at the original path)",
+                    display_path(&relative),
+                    from_kind.describe(),
+                    to_kind.describe()
+                ),
+            },
+        };
+
+        writeln!(out, "{line}")?;
+        differs = true;
+    }
+
+    Ok(differs)
+}
+
+/// The lines of `contents`, each with its line terminator, except maybe the last one
+fn lines(contents: &[u8]) -> Vec<&[u8]> {
+    contents.split_inclusive(|&byte| byte == b'\n').collect()
+}
+
+/// A step of turning a sequence of lines into another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    /// The `.0`th old line is the `.1`th new line
+    Keep(usize, usize),
+    /// The `n`th old line was removed
+    Remove(usize),
+    /// The `n`th new line was added
+    Add(usize),
+}
+
+/// How many lines were removed and added to get from `old` to `new`
+fn count_changes(old: &[&[u8]], new: &[&[u8]]) -> (usize, usize) {
+    edit_script(old, new)
+        .iter()
+        .fold((0, 0), |(removed, added), edit| match edit {
+            Edit::Keep(..) => (removed, added),
+            Edit::Remove(_) => (removed + 1, added),
+            Edit::Add(_) => (removed, added + 1),
+        })
+}
+
+/// A shortest edit script from `old` to `new`, found with Myers' algorithm after setting aside
+/// their common prefix and suffix.
+fn edit_script(old: &[&[u8]], new: &[&[u8]]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let (old_middle, new_middle) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut edits: Vec<_> = (0..prefix).map(|i| Edit::Keep(i, i)).collect();
+    let middle = myers(old_middle, new_middle).unwrap_or_else(|| {
+        (0..old_middle.len())
+            .map(Edit::Remove)
+            .chain((0..new_middle.len()).map(Edit::Add))
+            .collect()
+    });
+    edits.extend(middle.into_iter().map(|edit| match edit {
+        Edit::Keep(i, j) => Edit::Keep(prefix + i, prefix + j),
+        Edit::Remove(i) => Edit::Remove(prefix + i),
+        Edit::Add(j) => Edit::Add(prefix + j),
+    }));
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    edits.extend((0..suffix).map(|i| Edit::Keep(old_end + i, new_end + i)));
+
+    edits
+}
+
+/// Myers' O((N+M)D) algorithm, giving up (with `None`) past [`MAX_EDIT_COST`] edits.
+fn myers(old: &[&[u8]], new: &[&[u8]]) -> Option<Vec<Edit>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize;
+    // The furthest `x` reached on each diagonal `k = x - y`, indexed by `k + offset`
+    let mut furthest = vec![0isize; 2 * max + 2];
+    // The state of `furthest` before each step, kept for backtracking
+    let mut trace = Vec::new();
+
+    let at = |k: isize| (k + offset) as usize;
+
+    'search: {
+        for d in 0..=max.min(MAX_EDIT_COST) as isize {
+            trace.push(furthest.clone());
+            for k in (-d..=d).step_by(2) {
+                let goes_down = k == -d || (k != d && furthest[at(k - 1)] < furthest[at(k + 1)]);
+                let mut x = if goes_down {
+                    furthest[at(k + 1)]
+                } else {
+                    furthest[at(k - 1)] + 1
+                };
+                let mut y = x - k;
+                while x < n && y < m && old[x as usize] == new[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                furthest[at(k)] = x;
+
+                if x >= n && y >= m {
+                    break 'search;
+                }
+            }
+        }
+        return None;
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, furthest) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let came_down = k == -d || (k != d && furthest[at(k - 1)] < furthest[at(k + 1)]);
+        let previous_k = if came_down { k + 1 } else { k - 1 };
+        let previous_x = furthest[at(previous_k)];
+        let previous_y = previous_x - previous_k;
+
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Keep(x as usize, y as usize));
+        }
+
+        if d > 0 {
+            if came_down {
+                edits.push(Edit::Add(previous_y as usize));
+            } else {
+                edits.push(Edit::Remove(previous_x as usize));
+            }
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    edits.reverse();
+
+    Some(edits)
+}
+
+/// Writes the hunks of a unified diff from `old` to `new`
+fn write_unified_diff(old: &[&[u8]], new: &[&[u8]], out: &mut impl Write) -> io::Result<()> {
+    let edits = edit_script(old, new);
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Keep(..)))
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut start = 0;
+    while start < changes.len() {
+        // Changes close enough to share their context go in the same hunk
+        let mut end = start;
+        while end + 1 < changes.len() && changes[end + 1] - changes[end] <= 2 * CONTEXT + 1 {
+            end += 1;
+        }
+
+        let first = changes[start].saturating_sub(CONTEXT);
+        let last = (changes[end] + CONTEXT).min(edits.len() - 1);
+        write_hunk(&edits[first..=last], old, new, out)?;
+
+        start = end + 1;
+    }
+
+    Ok(())
+}
+
+fn write_hunk(
+    edits: &[Edit],
+    old: &[&[u8]],
+    new: &[&[u8]],
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let old_lines: Vec<usize> = edits
+        .iter()
+        .filter_map(|edit| match *edit {
+            Edit::Keep(i, _) | Edit::Remove(i) => Some(i),
+            Edit::Add(_) => None,
+        })
+        .collect();
+    let new_lines: Vec<usize> = edits
+        .iter()
+        .filter_map(|edit| match *edit {
+            Edit::Keep(_, j) | Edit::Add(j) => Some(j),
+            Edit::Remove(_) => None,
+        })
+        .collect();
+
+    // Hunks always include the context around their changes, so a side without lines is an empty file
+    let range = |lines: &[usize]| match lines.first() {
+        Some(first) => format!("{},{}", first + 1, lines.len()),
+        None => "0,0".to_owned(),
+    };
+    writeln!(out, "@@ -{} +{} @@", range(&old_lines), range(&new_lines))?;
+
+    for edit in edits {
+        let (prefix, line) = match *edit {
+            Edit::Keep(i, _) => (b' ', old[i]),
+            Edit::Remove(i) => (b'-', old[i]),
+            Edit::Add(j) => (b'+', new[j]),
+        };
+        out.write_all(&[prefix])?;
+        out.write_all(line)?;
+        if !line.ends_with(b"\n") {
+            out.write_all(b"\n\\ No newline at end of file\n")?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::symlink};
+
+    use super::{diff, edit_script, lines, Edit};
+    use crate::error::Result;
+
+    /// Runs [`diff`], returning whether the paths differ and what was written
+    fn run(trashed: &std::path::Path, original: &std::path::Path) -> Result<(bool, String)> {
+        let mut out = Vec::new();
+        let differs = diff(trashed, original, &mut out)?;
+
+        Ok((differs, String::from_utf8(out).unwrap()))
+    }
+
+    #[test]
+    fn finds_shortest_edit_scripts() {
+        let old = lines(b"a\nb\nc\nd\n");
+        let new = lines(b"a\nc\nd\ne\n");
+        assert_eq!(
+            edit_script(&old, &new),
+            [
+                Edit::Keep(0, 0),
+                Edit::Remove(1),
+                Edit::Keep(2, 1),
+                Edit::Keep(3, 2),
+                Edit::Add(3),
+            ]
+        );
+
+        assert_eq!(edit_script(&[], &lines(b"a\n")), [Edit::Add(0)]);
+        assert_eq!(edit_script(&lines(b"a\n"), &[]), [Edit::Remove(0)]);
+        assert!(edit_script(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn compares_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (trashed, original) = (dir.path().join("trashed"), dir.path().join("original"));
+
+        // The same
+        let contents: String = (1..=20).map(|n| format!("line {n}\n")).collect();
+        fs::write(&trashed, &contents)?;
+        fs::write(&original, &contents)?;
+        assert_eq!(run(&trashed, &original)?, (false, String::new()));
+
+        // Changed, in two places far enough apart to make two hunks
+        let changed = contents
+            .replace("line 2\n", "line two\n")
+            .replace("line 18\n", "");
+        fs::write(&original, changed)?;
+        let (differs, output) = run(&trashed, &original)?;
+        assert!(differs);
+        assert_eq!(
+            output,
+            format!(
+                "--- {}\n+++ {}\n\
+                @@ -1,5 +1,5 @@\n line 1\n-line 2\n+line two\n line 3\n line 4\n line 5\n\
+                @@ -15,6 +15,5 @@\n line 15\n line 16\n line 17\n-line 18\n line 19\n line 20\n",
+                trashed.display(),
+                original.display()
+            )
+        );
+
+        // Missing, which is like being empty
+        fs::write(&trashed, "only line")?;
+        fs::remove_file(&original)?;
+        let (differs, output) = run(&trashed, &original)?;
+        assert!(differs);
+        assert!(output.ends_with("@@ -1,1 +0,0 @@\n-only line\n\\ No newline at end of file\n"));
+
+        // Binary
+        fs::write(&trashed, b"\x00\x01\x02")?;
+        fs::write(&original, b"\x00\x01")?;
+        let (differs, output) = run(&trashed, &original)?;
+        assert!(differs);
+        assert!(output.starts_with("Binary files"));
+        assert!(output.contains(": 3 bytes, sha256 "));
+        assert!(output.contains(": 2 bytes, sha256 "));
+
+        Ok(())
+    }
+
+    #[test]
+    fn summarizes_directories() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (trashed, original) = (dir.path().join("trashed"), dir.path().join("original"));
+
+        for root in [&trashed, &original] {
+            fs::create_dir_all(root.join("sub"))?;
+            fs::write(root.join("same"), "same\n")?;
+            symlink("same", root.join("link"))?;
+        }
+        fs::write(trashed.join("sub/config"), "a\nb\n")?;
+        fs::write(original.join("sub/config"), "a\nc\nd\n")?;
+        fs::write(trashed.join("gone"), "")?;
+        fs::write(original.join("new"), "")?;
+        assert!(!run(&trashed.join("same"), &original.join("same"))?.0);
+
+        let (differs, output) = run(&trashed, &original)?;
+        assert!(differs);
+        assert_eq!(
+            output,
+            "Only in the trash: gone\nOnly at the original path: new\nChanged: sub/config (+2 -1)\n"
+        );
+
+        fs::remove_dir_all(&original)?;
+        let (differs, output) = run(&trashed, &original)?;
+        assert!(differs);
+        assert!(output.contains("Only in the trash: sub/config\n"));
+
+        Ok(())
+    }
+}
@@ -1,19 +1,65 @@
-use std::ffi::OsStr;
-use std::io::Write;
-use std::os::unix::prelude::OsStrExt;
+use std::ffi::{OsStr, OsString};
+use std::io::{ErrorKind, Write};
+use std::os::unix::prelude::{OsStrExt, OsStringExt};
 use std::time::Duration;
 
-use fs_err as fs;
-use percent_encoding::{percent_encode, NON_ALPHANUMERIC};
-
-use crate::fs::copy_directorysizes;
+use crate::fs::{copy_directorysizes, temp_file_in, FileSystem};
 use crate::trash::Trash;
+use percent_encoding::{percent_decode, percent_encode, NON_ALPHANUMERIC};
+
+/// A line of `$trash/directorysizes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectorySize {
+    /// The size of the directory, in bytes
+    pub size: u64,
+    /// The modification time of the directory's info file, as a UNIX timestamp
+    pub mtime: u64,
+    /// The name of the directory in `$trash/files`, already percent-decoded
+    pub name: OsString,
+}
+
+/// Parses a line of `$trash/directorysizes`, returning `None` if it's malformed.
+pub fn parse_directory_size(line: &[u8]) -> Option<DirectorySize> {
+    let mut fields = line.split(|&byte| byte == b' ');
+
+    let mut parse_number = || std::str::from_utf8(fields.next()?).ok()?.parse().ok();
+    let size = parse_number()?;
+    let mtime = parse_number()?;
+
+    let name: Vec<u8> = percent_decode(fields.next()?).collect();
+
+    if name.is_empty() || fields.next().is_some() {
+        return None;
+    }
+
+    Some(DirectorySize {
+        size,
+        mtime,
+        name: OsString::from_vec(name),
+    })
+}
+
+/// Reads every valid line of `$trash/directorysizes`, which might not exist.
+pub fn read_directory_sizes(trash: &Trash) -> crate::Result<Vec<DirectorySize>> {
+    let contents = match fs_err::read(trash.directory_sizes.as_path()) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(contents
+        .split(|&byte| byte == b'\n')
+        .filter_map(parse_directory_size)
+        .collect())
+}
 
 /// Updates the $trash/directorysizes file with the information
 /// of a directory being trashed.
 // TODO: receive the that this directory will have in the trash?
 // TODO: add test
 pub fn update_directory_sizes(
+    // Where the update takes place
+    fs: &impl FileSystem,
     // The trash that this directory was sent to
     trash: &Trash,
     // The total size of the directory and its contents, in bytes
@@ -37,17 +83,74 @@ pub fn update_directory_sizes(
 
     // Even though we already have a handle to this file (right above),
     // we'll reopen it in order to be able to append to it, instead of overwriting its contents
-    let mut temp = fs::OpenOptions::new().append(true).open(_temp.path())?;
+    let mut temp = fs_err::OpenOptions::new().append(true).open(_temp.path())?;
 
     // Append to temp file
     writeln!(temp, "{directory_size} {deletion_time} {percent_encoded}")?;
 
     // Atomic rename to actual directorysizes file
-    fs::rename(temp.path(), trash.directory_sizes.as_path())?;
+    fs.rename(temp.path(), trash.directory_sizes.as_path())?;
+
+    Ok(())
+}
+
+/// Removes the line of the directory named `file_name_in_trash` from `$trash/directorysizes`, if there's one.
+///
+/// Like [`update_directory_sizes`], the file is rewritten through a temporary file and an atomic rename.
+pub fn remove_directory_size(
+    fs: &impl FileSystem,
+    trash: &Trash,
+    file_name_in_trash: &OsStr,
+) -> crate::Result<()> {
+    let contents = match fs_err::read(trash.directory_sizes.as_path()) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut temp = temp_file_in(trash.root.as_path())?;
+
+    for line in contents.split(|&byte| byte == b'\n') {
+        let is_removed = parse_directory_size(line)
+            .map(|directory_size| directory_size.name == file_name_in_trash)
+            .unwrap_or(false);
+
+        if !line.is_empty() && !is_removed {
+            temp.write_all(line)?;
+            temp.write_all(b"\n")?;
+        }
+    }
+
+    fs.rename(temp.path(), trash.directory_sizes.as_path())?;
 
     Ok(())
 }
 
+/// Sets the size of the directory named `file_name_in_trash` in `$trash/directorysizes` to `directory_size`, keeping
+/// the rest of its line. Directories without a line are left without one.
+pub fn replace_directory_size(
+    fs: &impl FileSystem,
+    trash: &Trash,
+    file_name_in_trash: &OsStr,
+    directory_size: u64,
+) -> crate::Result<()> {
+    let Some(previous) = read_directory_sizes(trash)?
+        .into_iter()
+        .find(|directory_size| directory_size.name == file_name_in_trash)
+    else {
+        return Ok(());
+    };
+
+    remove_directory_size(fs, trash, file_name_in_trash)?;
+    update_directory_sizes(
+        fs,
+        trash,
+        directory_size,
+        file_name_in_trash,
+        Duration::from_secs(previous.mtime),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -59,7 +162,12 @@ mod tests {
     use percent_encoding::{percent_encode, NON_ALPHANUMERIC};
     use tempfile::TempDir;
 
-    use crate::{fs::directory_size, tests::dummy_bytes, trash::Trash};
+    use super::{parse_directory_size, remove_directory_size, DirectorySize};
+    use crate::{
+        fs::{directory_size, RealFs},
+        tests::dummy_bytes,
+        trash::Trash,
+    };
 
     fn dummy_dir() -> crate::Result<(TempDir, Vec<File>)> {
         let dir = tempfile::tempdir()?;
@@ -93,7 +201,7 @@ mod tests {
             writeln!(directorysizes, "{FIRST_LINE}")?;
         }
 
-        let trashed_file_name = trash.send_to_trash(dir_to_trash.path())?;
+        let trashed_file_name = trash.send_to_trash(&RealFs, dir_to_trash.path())?;
         let percent_encoded =
             percent_encode(trashed_file_name.as_os_str().as_bytes(), NON_ALPHANUMERIC);
 
@@ -122,4 +230,42 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn parses_directorysizes_lines() {
+        assert_eq!(
+            parse_directory_size(b"16384 15803468 Documents%20and%20more"),
+            Some(DirectorySize {
+                size: 16384,
+                mtime: 15803468,
+                name: "Documents and more".into()
+            })
+        );
+
+        assert_eq!(parse_directory_size(b""), None);
+        assert_eq!(parse_directory_size(b"16384 Documents"), None);
+        assert_eq!(parse_directory_size(b"16384 15803468 a b"), None);
+        assert_eq!(parse_directory_size(b"-1 15803468 Documents"), None);
+    }
+
+    #[test]
+    fn removes_directorysizes_line() -> crate::Result<()> {
+        let temp_trash = tempfile::tempdir()?;
+        let trash = Trash::from_root(temp_trash.path())?;
+        fs::create_dir(&trash.files)?;
+
+        fs::write(
+            &trash.directory_sizes,
+            "16384 15803468 Documents\n4096 15803469 Music\nthis line is malformed\n",
+        )?;
+
+        remove_directory_size(&RealFs, &trash, "Documents".as_ref())?;
+
+        assert_eq!(
+            fs::read_to_string(&trash.directory_sizes)?,
+            "4096 15803469 Music\nthis line is malformed\n"
+        );
+
+        Ok(())
+    }
 }
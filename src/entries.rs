@@ -0,0 +1,710 @@
+//! Enumerating, filtering and permanently deleting the entries of a trash
+
+use std::{
+    ffi::{OsStr, OsString},
+    fmt,
+    io::ErrorKind,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use unixstring::UnixString;
+
+use crate::{
+    date::DateTime,
+    dedupe::linked_from_elsewhere,
+    directorysizes::{read_directory_sizes, remove_directory_size, DirectorySize},
+    error::{Error, Result},
+    ffi::{FileId, Lstat},
+    fs::{directory_size, explain_denial, is_temp_file_name, remove_recursively, FileSystem},
+    glob::{matches_name, matches_path},
+    info_file::{build_info_file_path, read_info_file},
+    report::display_path,
+    restore::find_entry,
+    shred::shred,
+    trash::Trash,
+    usage::{grouped, human_size},
+};
+
+/// A file or directory in `$trash/files`, along with what its info file says about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashEntry {
+    /// The name of this entry in `$trash/files`
+    pub name: OsString,
+    /// The `Path` value of this entry's info file, percent-decoded, if its info file could be read.
+    ///
+    /// Depending on the tool that trashed the entry, this might be absolute or relative to the trash's [base directory](Trash::base_dir).
+    pub raw_path: Option<PathBuf>,
+    /// The absolute path this entry was trashed from, if its info file could be read and its `Path` is valid
+    pub original_path: Option<PathBuf>,
+    /// When this entry was trashed, if its info file has a valid `DeletionDate`
+    pub deletion_date: Option<DateTime>,
+    /// The size of the directory this entry is an archive of (see [`crate::compress`]), if it's one
+    pub original_size: Option<u64>,
+}
+
+impl TrashEntry {
+    /// Reads the info file of the entry of `trash` named `name`.
+    ///
+    /// An entry whose info file is missing or invalid is still an entry, just one we know nothing about.
+    fn read(trash: &Trash, name: OsString) -> Self {
+        let info = read_info_file(&build_info_file_path(&name, trash.info_path())).ok();
+
+        let raw_path = info.as_ref().map(|info| info.path.clone());
+        let original_path = raw_path
+            .as_ref()
+            .and_then(|raw_path| trash.resolve_original_path(raw_path).ok());
+        let original_size = info.as_ref().and_then(|info| info.original_size);
+        let deletion_date = info
+            .and_then(|info| info.deletion_date)
+            .and_then(|deletion_date| DateTime::parse(&deletion_date));
+
+        Self {
+            name,
+            raw_path,
+            original_path,
+            deletion_date,
+            original_size,
+        }
+    }
+}
+
+/// The entries of a trash, whose info files are only read as they're iterated over (see [`lazy_entries`])
+#[derive(Debug)]
+pub struct Entries<'a> {
+    trash: &'a Trash,
+    names: std::vec::IntoIter<OsString>,
+}
+
+impl Iterator for Entries<'_> {
+    type Item = TrashEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let name = self.names.next()?;
+
+        Some(TrashEntry::read(self.trash, name))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.names.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Entries<'_> {}
+
+/// The entries of `trash`, sorted by name, with their info files read one at a time as they're needed,
+/// which matters for trashes holding tens of thousands of entries. Our temporary files are not entries.
+pub fn lazy_entries(trash: &Trash) -> Result<Entries<'_>> {
+    let files = match fs_err::read_dir(trash.files.as_path()) {
+        Ok(files) => files,
+        // Nothing was trashed yet
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Ok(Entries {
+                trash,
+                names: Vec::new().into_iter(),
+            })
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut names = Vec::new();
+    for file in files {
+        let name = file?.file_name();
+        // Leftovers of interrupted runs, not trashed files
+        if !is_temp_file_name(&name) {
+            names.push(name);
+        }
+    }
+    names.sort();
+
+    Ok(Entries {
+        trash,
+        names: names.into_iter(),
+    })
+}
+
+/// The entries of `trash`, sorted by name. Our temporary files are not entries.
+pub fn entries(trash: &Trash) -> Result<Vec<TrashEntry>> {
+    Ok(lazy_entries(trash)?.collect())
+}
+
+/// How many bytes the entry of `trash` named `name` takes up.
+///
+/// The sizes of directories are taken from `directory_sizes` (the contents of `$trash/directorysizes`)
+/// when they're there, and computed otherwise.
+pub fn entry_size(trash: &Trash, name: &OsStr, directory_sizes: &[DirectorySize]) -> Result<u64> {
+    if let Some(cached) = directory_sizes
+        .iter()
+        .find(|directory_size| directory_size.name == name)
+    {
+        return Ok(cached.size);
+    }
+
+    directory_size(trash.files.as_path().join(name).try_into()?)
+}
+
+/// How many hard links the entry of `trash` named `name` has, or `None` if it's a directory, whose link count
+/// says nothing about how much space deleting it frees
+pub fn link_count(trash: &Trash, name: &OsStr) -> Result<Option<u64>> {
+    let lstat = Lstat::lstat(UnixString::try_from(trash.files.as_path().join(name))?)?;
+
+    Ok((!lstat.is_directory()).then(|| lstat.nlink()))
+}
+
+/// An overview of a set of entries, shown before permanently deleting them
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Summary {
+    pub count: usize,
+    /// The total size of the entries, in bytes. Entries whose size can't be determined are not counted.
+    pub total_size: u64,
+    /// The same, taking archives of directories (see [`crate::compress`]) at the size of the directory they're of
+    pub original_size: u64,
+    /// When the oldest entry (with a valid deletion date) was trashed
+    pub oldest: Option<DateTime>,
+}
+
+impl Summary {
+    /// Summarizes `entries`, which are in `trash`
+    pub fn of(trash: &Trash, entries: &[TrashEntry]) -> Result<Self> {
+        let directory_sizes = read_directory_sizes(trash)?;
+
+        let mut total_size = 0;
+        let mut original_size = 0;
+        for entry in entries {
+            let size = entry_size(trash, &entry.name, &directory_sizes).ok();
+            total_size += size.unwrap_or(0);
+            original_size += entry.original_size.or(size).unwrap_or(0);
+        }
+
+        Ok(Self {
+            count: entries.len(),
+            total_size,
+            original_size,
+            oldest: entries.iter().filter_map(|entry| entry.deletion_date).min(),
+        })
+    }
+
+    /// How many entries there are and how much space they take up, as `--size` prints it, along with how much they
+    /// would once their archives are unpacked
+    pub fn describe_size(&self) -> String {
+        let mut description = format!(
+            "{} entr{}, {}",
+            grouped(self.count as u64),
+            if self.count == 1 { "y" } else { "ies" },
+            human_size(self.total_size)
+        );
+        if self.original_size != self.total_size {
+            description.push_str(&format!(
+                " ({} uncompressed)",
+                human_size(self.original_size)
+            ));
+        }
+
+        description
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} entr{} ({})",
+            grouped(self.count as u64),
+            if self.count == 1 { "y" } else { "ies" },
+            human_size(self.total_size)
+        )?;
+
+        match self.oldest {
+            Some(oldest) => write!(f, ", the oldest trashed at {oldest}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Splits `entries` into the `count` most recently trashed ones and the others, both newest first, for `--keep-last`.
+///
+/// Entries trashed at the same time (dates have a resolution of one second) are ordered by name, the greatest being
+/// considered the newest, as for [`crate::restore::generations`]. Entries without a deletion date are the oldest.
+pub fn keep_newest(
+    mut entries: Vec<TrashEntry>,
+    count: usize,
+) -> (Vec<TrashEntry>, Vec<TrashEntry>) {
+    entries.sort_by(|entry, other| {
+        (other.deletion_date, &other.name).cmp(&(entry.deletion_date, &entry.name))
+    });
+    let rest = entries.split_off(count.min(entries.len()));
+
+    (entries, rest)
+}
+
+/// Selects which entries `--list` and `--empty` operate on
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EntryFilter {
+    /// Only entries trashed at or after this date
+    pub since: Option<DateTime>,
+    /// Only entries trashed strictly before this date
+    pub before: Option<DateTime>,
+    /// Whether entries without a valid deletion date are kept when filtering by date
+    pub include_undated: bool,
+}
+
+impl EntryFilter {
+    pub fn matches(&self, entry: &TrashEntry) -> bool {
+        match entry.deletion_date {
+            Some(date) => {
+                self.since.is_none_or(|since| date >= since)
+                    && self.before.is_none_or(|before| date < before)
+            }
+            None => !self.skips_undated(),
+        }
+    }
+
+    /// Whether entries without a valid deletion date are left out
+    pub fn skips_undated(&self) -> bool {
+        let filters_by_date = self.since.is_some() || self.before.is_some();
+
+        filters_by_date && !self.include_undated
+    }
+}
+
+/// The entry of `trash` named `name`, or else the only one whose name starts with `name`, for `--purge`.
+///
+/// Fails with [`Error::EntryNotFound`] if there's no such entry, and with [`Error::AmbiguousEntry`] if several names
+/// start with `name`, none of which is `name` itself.
+pub fn find_by_prefix(trash: &Trash, name: &OsStr) -> Result<TrashEntry> {
+    if let Ok(name) = find_entry(trash, name) {
+        return Ok(TrashEntry::read(trash, name));
+    }
+
+    let mut matching: Vec<_> = lazy_entries(trash)?
+        .names
+        .filter(|candidate| candidate.as_bytes().starts_with(name.as_bytes()))
+        .collect();
+    match matching.len() {
+        0 => Err(Error::EntryNotFound(name.into())),
+        1 => Ok(TrashEntry::read(trash, matching.remove(0))),
+        _ => {
+            let candidates: Vec<_> = matching
+                .iter()
+                .map(|candidate| display_path(candidate.as_ref()))
+                .collect();
+            Err(Error::AmbiguousEntry(name.into(), candidates.join(", ")))
+        }
+    }
+}
+
+/// The entries of `trash` whose name matches the glob `pattern` (see [`crate::glob`]) or, with `by_path`, whose
+/// original path does, for `--restore` and `--purge`. Entries whose original path is unknown never match by path.
+pub fn find_matching(trash: &Trash, pattern: &OsStr, by_path: bool) -> Result<Vec<TrashEntry>> {
+    Ok(entries(trash)?
+        .into_iter()
+        .filter(|entry| match &entry.original_path {
+            _ if !by_path => matches_name(pattern, &entry.name),
+            Some(original_path) => matches_path(pattern.as_ref(), original_path),
+            None => false,
+        })
+        .collect())
+}
+
+/// What tells an entry apart from another one trashed under the same name after it was restored or deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    deletion_date: Option<DateTime>,
+    file_id: Option<FileId>,
+}
+
+impl Stamp {
+    /// The stamp of `entry`, one of the entries of `trash`
+    pub fn of(trash: &Trash, entry: &TrashEntry) -> Self {
+        let path = trash.files.as_path().join(&entry.name);
+        let file_id = UnixString::try_from(path)
+            .ok()
+            .and_then(|unx| Lstat::lstat(unx).ok())
+            .map(|lstat| lstat.file_id());
+
+        Self {
+            deletion_date: entry.deletion_date,
+            file_id,
+        }
+    }
+}
+
+/// Fails with [`Error::EntryChanged`] unless the entry of `trash` named like `entry` is still the one `stamp` was
+/// taken of: while deleting entries was being confirmed, it may have been restored and another file trashed in its stead.
+pub fn assert_unchanged(trash: &Trash, entry: &TrashEntry, stamp: Stamp) -> Result<()> {
+    let current = TrashEntry::read(trash, entry.name.clone());
+    if Stamp::of(trash, &current) != stamp {
+        return Err(Error::EntryChanged(entry.name.clone().into()));
+    }
+
+    Ok(())
+}
+
+/// Permanently deletes the entry of `trash` named `name`, along with its info file and its
+/// line in `$trash/directorysizes`.
+pub fn purge(fs: &impl FileSystem, trash: &Trash, name: &OsStr) -> Result<()> {
+    let path = trash.files.as_path().join(name);
+    let unx: UnixString = path.clone().try_into()?;
+    let is_directory = fs.lstat(unx.as_c_str())?.is_directory();
+    let info_file_path = build_info_file_path(name, trash.info_path());
+    // Archives have their size in `$trash/directorysizes` too
+    let is_archive = read_info_file(&info_file_path).is_ok_and(|info| info.compressed.is_some());
+
+    remove_recursively(fs, &path).map_err(|err| explain_denial(fs, &path, err))?;
+
+    remove_if_exists(fs, &info_file_path)?;
+
+    if is_directory || is_archive {
+        remove_directory_size(fs, trash, name)?;
+    }
+
+    Ok(())
+}
+
+/// Like [`purge`], overwriting the contents of the entry first (see [`shred`]), so that they're gone for good. Files
+/// that other entries were linked to by `--dedupe` are only deleted, as these entries still need them.
+pub fn shred_and_purge(fs: &impl FileSystem, trash: &Trash, name: &OsStr) -> Result<()> {
+    shred(
+        &trash.files.as_path().join(name),
+        &linked_from_elsewhere(trash, name)?,
+    )?;
+
+    purge(fs, trash, name)
+}
+
+fn remove_if_exists(fs: &impl FileSystem, path: &Path) -> Result<()> {
+    match fs.remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{
+        assert_unchanged, entries, find_by_prefix, find_matching, keep_newest, purge,
+        shred_and_purge, EntryFilter, Stamp, Summary, TrashEntry,
+    };
+    use crate::{
+        date::DateTime,
+        error::{Error, Result},
+        fs::RealFs,
+        trash::Trash,
+    };
+
+    /// Puts the info file `tests/fixtures/trashinfo/{fixture}.trashinfo` in `trash`, along with the entry it describes
+    fn add_fixture(trash: &Trash, fixture: &str) -> Result<()> {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/trashinfo");
+        let info_file_name = format!("{fixture}.trashinfo");
+
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        fs::copy(
+            fixtures.join(&info_file_name),
+            trash.info_path().join(&info_file_name),
+        )?;
+        fs::write(trash.files.as_path().join(fixture), fixture)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_absolute_and_relative_paths() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let home_trash = Trash::from_root(dir.path().join("home/.local/share/Trash"))?;
+        let topdir_trash = Trash::from_root(dir.path().join("mnt/.Trash-1000"))?;
+
+        for fixture in ["gio-home", "trash-cli-home"] {
+            add_fixture(&home_trash, fixture)?;
+        }
+        for fixture in ["gio-topdir", "trash-cli-topdir", "parent-dir"] {
+            add_fixture(&topdir_trash, fixture)?;
+        }
+
+        let paths = |trash| -> Result<Vec<_>> {
+            Ok(entries(trash)?
+                .into_iter()
+                .map(|entry| (entry.raw_path, entry.original_path))
+                .collect())
+        };
+        let some = |path: &str| Some(Path::new(path).to_owned());
+
+        assert_eq!(
+            paths(&home_trash)?,
+            [
+                (
+                    some("/home/user/Pictures/holiday 2023/beach.jpg"),
+                    some("/home/user/Pictures/holiday 2023/beach.jpg")
+                ),
+                (
+                    some("/home/user/notes/todo.txt"),
+                    some("/home/user/notes/todo.txt")
+                ),
+            ]
+        );
+
+        assert_eq!(
+            paths(&topdir_trash)?,
+            [
+                (
+                    some("Documents/quarterly report.pdf"),
+                    Some(dir.path().join("mnt/Documents/quarterly report.pdf"))
+                ),
+                // `..` is not allowed
+                (some("../escaped.txt"), None),
+                (
+                    some("projects/old build"),
+                    Some(dir.path().join("mnt/projects/old build"))
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+
+    fn entry(deletion_date: Option<&str>) -> TrashEntry {
+        TrashEntry {
+            name: "dummy".into(),
+            raw_path: None,
+            original_path: None,
+            deletion_date: deletion_date.map(|date| DateTime::parse(date).unwrap()),
+            original_size: None,
+        }
+    }
+
+    fn date(text: &str) -> Option<DateTime> {
+        DateTime::parse(text)
+    }
+
+    #[test]
+    fn filters_entries_by_date() {
+        let old = entry(Some("2023-06-01T12:00:00"));
+        let new = entry(Some("2024-06-01T12:00:00"));
+        let undated = entry(None);
+
+        let everything = EntryFilter::default();
+        assert!([&old, &new, &undated]
+            .iter()
+            .all(|entry| everything.matches(entry)));
+
+        let since = EntryFilter {
+            since: date("2024-01-01T00:00:00"),
+            ..Default::default()
+        };
+        assert!(!since.matches(&old));
+        assert!(since.matches(&new));
+        assert!(!since.matches(&undated));
+
+        let before = EntryFilter {
+            before: date("2024-01-01T00:00:00"),
+            include_undated: true,
+            ..Default::default()
+        };
+        assert!(before.matches(&old));
+        assert!(!before.matches(&new));
+        assert!(before.matches(&undated));
+
+        let between = EntryFilter {
+            since: date("2023-06-01T12:00:00"),
+            before: date("2024-06-01T12:00:00"),
+            ..Default::default()
+        };
+        assert!(between.matches(&old));
+        assert!(!between.matches(&new));
+    }
+
+    #[test]
+    fn lists_and_purges_entries() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("trash"))?;
+
+        assert!(entries(&trash)?.is_empty());
+
+        fs::create_dir(&trash.root)?;
+        fs::create_dir(&trash.files)?;
+        fs::create_dir(&trash.info)?;
+        fs::write(&trash.directory_sizes, "")?;
+
+        let dummy_path = dir.path().join("dummy");
+        fs::write(&dummy_path, "dummy")?;
+        let dummy_dir = dir.path().join("dummy-dir");
+        fs::create_dir(&dummy_dir)?;
+        fs::write(dummy_dir.join("inner"), "inner")?;
+
+        trash.send_to_trash(&RealFs, &dummy_path)?;
+        trash.send_to_trash(&RealFs, &dummy_dir)?;
+
+        // An entry without an info file
+        fs::write(trash.files.as_path().join("orphan"), "orphan")?;
+
+        let listed = entries(&trash)?;
+        let names: Vec<_> = listed.iter().map(|entry| entry.name.clone()).collect();
+        assert_eq!(names, ["dummy", "dummy-dir", "orphan"]);
+        assert_eq!(listed[0].original_path.as_deref(), Some(&*dummy_path));
+        assert!(listed[0].deletion_date.is_some());
+        assert_eq!(listed[2].original_path, None);
+        assert_eq!(listed[2].deletion_date, None);
+
+        let summary = Summary::of(&trash, &listed)?;
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.total_size, 16);
+        assert_eq!(summary.original_size, 16);
+        assert_eq!(summary.describe_size(), "3 entries, 16 bytes");
+        let oldest = summary.oldest.expect("dummy has a deletion date");
+        assert_eq!(
+            summary.to_string(),
+            format!("3 entries (16 bytes), the oldest trashed at {oldest}")
+        );
+        // Archives of directories are also taken at the size of the directory they're of
+        let compressed = [
+            TrashEntry {
+                original_size: Some(4096),
+                ..listed[0].clone()
+            },
+            listed[2].clone(),
+        ];
+        let compressed = Summary::of(&trash, &compressed)?;
+        assert_eq!(
+            (compressed.total_size, compressed.original_size),
+            (11, 4102)
+        );
+        assert_eq!(
+            compressed.describe_size(),
+            "2 entries, 11 bytes (4.0 KiB uncompressed)"
+        );
+        // Directories are taken at their size in directorysizes, whose broken lines are skipped
+        let directory_sizes = fs::read(&trash.directory_sizes)?;
+        fs::write(&trash.directory_sizes, "garbage\n\n1000 1 dummy-dir\n")?;
+        assert_eq!(Summary::of(&trash, &listed)?.total_size, 1011);
+        fs::write(&trash.directory_sizes, directory_sizes)?;
+        assert_eq!(
+            summary.oldest,
+            listed.iter().filter_map(|entry| entry.deletion_date).min()
+        );
+
+        // A whole name wins over the longer ones it's the start of
+        let found = |name: &str| find_by_prefix(&trash, name.as_ref()).map(|entry| entry.name);
+        assert_eq!(found("dummy")?, "dummy");
+        assert_eq!(found("dummy-")?, "dummy-dir");
+        assert_eq!(found("orph")?, "orphan");
+        assert!(matches!(
+            found("dum"),
+            Err(Error::AmbiguousEntry(_, candidates)) if candidates == "dummy, dummy-dir"
+        ));
+        assert!(matches!(found("missing"), Err(Error::EntryNotFound(_))));
+
+        // Patterns match names, or original paths with `by_path`, which orphans don't have
+        let matching = |pattern: &str, by_path| -> Result<Vec<_>> {
+            Ok(find_matching(&trash, pattern.as_ref(), by_path)?
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect())
+        };
+        assert_eq!(matching("dummy*", false)?, ["dummy", "dummy-dir"]);
+        assert_eq!(matching("*an", false)?, ["orphan"]);
+        assert_eq!(matching("*-dir", true)?, ["dummy-dir"]);
+        assert!(matching("orph*", true)?.is_empty());
+
+        for entry in listed {
+            purge(&RealFs, &trash, &entry.name)?;
+        }
+
+        assert!(entries(&trash)?.is_empty());
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 0);
+        assert!(fs::read_to_string(&trash.directory_sizes)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn tells_entries_from_those_trashed_in_their_stead() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        let dummy_path = dir.path().join("dummy");
+        fs::write(&dummy_path, "dummy")?;
+        trash.send_to_trash(&RealFs, &dummy_path)?;
+
+        let entry = find_by_prefix(&trash, "dummy".as_ref())?;
+        let stamp = Stamp::of(&trash, &entry);
+        assert_unchanged(&trash, &entry, stamp)?;
+
+        // Another file under the same name, trashed within the same second
+        let trashed = trash.files.as_path().join("dummy");
+        let replacement = dir.path().join("replacement");
+        fs::write(&replacement, "another dummy")?;
+        fs::rename(&replacement, &trashed)?;
+        assert!(matches!(
+            assert_unchanged(&trash, &entry, stamp),
+            Err(Error::EntryChanged(_))
+        ));
+
+        fs::remove_file(&trashed)?;
+        assert!(assert_unchanged(&trash, &entry, stamp).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn shredding_leaves_nothing_to_read() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        fs::write(&trash.directory_sizes, "")?;
+        let secrets = dir.path().join("secrets");
+        fs::create_dir(&secrets)?;
+        fs::write(secrets.join("key"), "hunter2")?;
+        trash.send_to_trash(&RealFs, &secrets)?;
+
+        // Another name for the same data, which outlives the entry
+        let copy = dir.path().join("copy");
+        fs::hard_link(trash.files.as_path().join("secrets/key"), &copy)?;
+
+        shred_and_purge(&RealFs, &trash, "secrets".as_ref())?;
+
+        assert!(entries(&trash)?.is_empty());
+        assert_eq!(fs::read(&copy)?, [0; 7]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_the_newest_entries() {
+        let entry = |name: &str, deletion_date: &str| TrashEntry {
+            name: name.into(),
+            raw_path: None,
+            original_path: None,
+            deletion_date: DateTime::parse(deletion_date),
+            original_size: None,
+        };
+        let entries = vec![
+            entry("old", "2024-01-01T00:00:00"),
+            entry("undated", "someday"),
+            entry("tie-a", "2024-03-01T10:00:00"),
+            entry("newest", "2024-06-01T00:00:00"),
+            entry("tie-b", "2024-03-01T10:00:00"),
+        ];
+        let names = |entries: &[TrashEntry]| -> Vec<String> {
+            entries
+                .iter()
+                .map(|entry| entry.name.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        let (kept, rest) = keep_newest(entries.clone(), 2);
+        assert_eq!(names(&kept), ["newest", "tie-b"]);
+        assert_eq!(names(&rest), ["tie-a", "old", "undated"]);
+
+        let (kept, rest) = keep_newest(entries.clone(), 0);
+        assert!(kept.is_empty());
+        assert_eq!(rest.len(), 5);
+
+        let (kept, rest) = keep_newest(entries, 20);
+        assert_eq!(kept.len(), 5);
+        assert!(rest.is_empty());
+    }
+}
@@ -6,10 +6,16 @@ pub enum Error {
     InteriorNulByte(#[from] unixstring::Error),
     #[error("Path {0} does not contain a working trash directory")]
     TrashDirDoesNotExist(PathBuf),
+    #[error("{0} is not a trash directory, which must hold a files and an info directory")]
+    NotATrash(PathBuf),
     #[error("IO: {0}")]
     Io(#[from] std::io::Error),
     #[error("Failed to parse mount points")]
     FailedToObtainMountPoints,
+    #[error("Failed to trash {0}: {1}")]
+    TrashFailed(PathBuf, std::io::Error),
+    #[error("Failed to move {0} into the trash: {2}")]
+    MoveFailed(PathBuf, PathBuf, std::io::Error),
     #[error("Clock went backwards: {0}")]
     SystemTime(#[from] std::time::SystemTimeError),
     #[error("Failed to obtain filename of path {0}")]
@@ -18,6 +24,205 @@ pub enum Error {
     StringFromBytes,
     #[error("Invalid UTF-8: {0}")]
     Utf8(#[from] std::str::Utf8Error),
+    #[error("Failed to obtain the user's home directory")]
+    HomeDirNotFound,
+    #[error("{0} is a pseudo-filesystem, files in it can't be trashed")]
+    PseudoFilesystem(PathBuf),
+    #[error("{0} is mounted read-only, files in it can't be trashed")]
+    ReadOnlyFilesystem(PathBuf),
+    #[error(
+        "Refusing to use {0} as a trash directory: {1} (pass --no-verify-trash to use it anyway)"
+    )]
+    UnsafeTrash(PathBuf, String),
+    #[error("Refusing to lock the trash through {0}: {1}")]
+    UnsafeLockFile(PathBuf, String),
+    #[error("Invalid configuration file {0}: {1}")]
+    InvalidConfig(PathBuf, String),
+    #[error("{0}")]
+    HookFailed(String),
+    #[error("{0} is protected by rule {1} ({2}), pass --override-protection to trash it anyway")]
+    Protected(PathBuf, usize, PathBuf),
+    #[error("Cannot trash {0}: is a directory (pass -r to trash it along with what it holds, or -d if it's empty)")]
+    IsADirectory(PathBuf),
+    #[error(
+        "Cannot trash {0}: directory not empty (pass -r to trash it along with what it holds)"
+    )]
+    DirectoryNotEmpty(PathBuf),
+    #[error("Refusing to trash {0}, the top directory of a mounted filesystem (pass --no-preserve-root to trash it anyway)")]
+    MountRoot(PathBuf),
+    #[error("Refusing to trash {0}, as rm refuses to remove . and ..")]
+    DotOperand(PathBuf),
+    #[error("Refusing to trash {0}, as per the policy of its mount point (pass --policy to override it)")]
+    RefusedByPolicy(PathBuf),
+    #[error(
+        "{0} changed since it was trashed, according to its checksum, so it was left in the trash"
+    )]
+    ChecksumMismatch(PathBuf),
+    #[error("{0} is compressed as {1}, which this version of tt can't unpack")]
+    UnsupportedCompression(PathBuf, String),
+    #[error("Invalid info file: {0}")]
+    InvalidInfoFile(PathBuf),
+    #[error("Invalid original path in info file: {0}")]
+    InvalidOriginalPath(PathBuf),
+    #[error("Cannot trash {0}: no such file or directory")]
+    OperandNotFound(PathBuf),
+    #[error("No trashed file named {0}")]
+    EntryNotFound(PathBuf),
+    #[error("No entry of the trash is numbered {0}")]
+    IdNotFound(u64),
+    #[error("The trash changed since it was last listed, so {0} may not be the number of the entry it was: list it again with tt --list, or restore the entry by name")]
+    ListingOutdated(u64),
+    #[error("{0} changed since it was listed, so it was left alone")]
+    EntryChanged(PathBuf),
+    #[error("No trashed file matches {0}")]
+    NoMatchingEntry(PathBuf),
+    #[error("{0} has no trashed version {1}")]
+    VersionNotFound(PathBuf, usize),
+    #[error("{0} is a directory, see what it holds with --list or get it back with --restore")]
+    CatDirectory(PathBuf),
+    #[error("Can't write to {0}")]
+    NotWritable(PathBuf),
+    #[error("Can't restore to {0}: file already exists")]
+    RestoreDestinationExists(PathBuf),
+    #[error("Can't restore into {0}: no such directory")]
+    RestoreDirectoryNotFound(PathBuf),
+    #[error("{0} was left in the trash, as {1}, which was trashed separately, couldn't be restored first")]
+    ParentNotRestored(PathBuf, PathBuf),
+    #[error("{0} was replaced while it was being trashed, so it was left where it is")]
+    SourceChanged(PathBuf),
+    #[error("{0} is marked immutable (chattr +i), run 'chattr -i' on it first")]
+    Immutable(PathBuf),
+    #[error("{0} is marked append-only (chattr +a), run 'chattr -a' on it first")]
+    AppendOnly(PathBuf),
+    #[error("No free name for {0} in the trash after trying {1} names, the last being {2}: {3}")]
+    NamesExhausted(PathBuf, u32, PathBuf, std::io::Error),
+    #[error("Can't reserve a name in {0}, which must be writable: {1}")]
+    CannotReserveName(PathBuf, std::io::Error),
+    #[error("{0}")]
+    Usage(String),
+    #[error("{0} is on another host, only local file:// URIs can be trashed")]
+    NonLocalUri(String),
+    #[error("{0} has an encoded / or NUL byte in a file name, which can't be in a path")]
+    InvalidUri(String),
+    #[error(
+        "Refusing to trash {0}, as filesystems are mounted inside it: {1} (unmount them first)"
+    )]
+    NestedMountPoints(PathBuf, String),
+    #[error("{0} is the start of the names of several entries: {1}")]
+    AmbiguousEntry(PathBuf, String),
+    #[error("Did not trash {0}, as it was not confirmed")]
+    NotConfirmed(PathBuf),
+    #[error("{0} is already being copied into this trash by another process")]
+    CopyInProgress(PathBuf),
+    #[error(
+        "{0} is on another filesystem than {1}, so files would be copied there rather than moved"
+    )]
+    TrashOnAnotherFilesystem(PathBuf, PathBuf),
+    #[error("Copied {0}, but its path is too long to be renamed into place")]
+    PathTooLong(PathBuf),
+    #[error("Refusing to go ahead without confirmation since there's no terminal to ask on, pass --yes to confirm")]
+    ConfirmationRequired,
+    #[error("Another tt --daemon is running, as it holds the lock {0}")]
+    DaemonRunning(PathBuf),
+    #[error(
+        "The trash at {} is busy{}, try again once it's done",
+        .0.display(),
+        .1.map_or_else(String::new, |pid| format!(" (held by pid {pid})"))
+    )]
+    TrashBusy(PathBuf, Option<u32>),
+    #[error("Nothing in the trash has a deletion date, so there's nothing to restore")]
+    NothingToRestore,
+    #[error(
+        "{0} is on another filesystem than the trash at {1}, which --one-file-system keeps to"
+    )]
+    CrossesFilesystems(PathBuf, PathBuf),
+    #[error("The root of a trash must be an absolute path, not {0}")]
+    RelativeTrashRoot(PathBuf),
+    #[error("--ui needs a terminal, but stdout is not one")]
+    NotATerminal,
+    #[error("The picker printed {0:?}, which is not one of the lines it was given")]
+    InvalidPickerOutput(String),
+    #[error("Invalid date {0:?}: expected YYYY-MM-DD, an RFC 3339 date or an age such as 7d or \"3 weeks\"")]
+    InvalidDate(String),
+    #[error("Invalid deletion date {0:?}: expected an RFC 3339 date and time with a timezone, such as 2024-01-01T10:00:00Z, no earlier than 1970")]
+    InvalidDeletionDate(String),
+    #[cfg(test)]
+    #[error("Simulated crash at {0}")]
+    SimulatedCrash(&'static str),
+}
+
+impl Error {
+    /// A short name for the kind of this error, which unlike its message doesn't change between versions
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Error::InteriorNulByte(..) => "interior-nul-byte",
+            Error::TrashDirDoesNotExist(..) => "trash-dir-does-not-exist",
+            Error::NotATrash(..) => "not-a-trash",
+            Error::Io(..) => "io",
+            Error::TrashFailed(..) => "trash-failed",
+            Error::MoveFailed(..) => "move-failed",
+            Error::FailedToObtainMountPoints => "failed-to-obtain-mount-points",
+            Error::SystemTime(..) => "system-time",
+            Error::FailedToObtainFileName(..) => "failed-to-obtain-file-name",
+            Error::StringFromBytes => "string-from-bytes",
+            Error::Utf8(..) => "utf8",
+            Error::HomeDirNotFound => "home-dir-not-found",
+            Error::PseudoFilesystem(..) => "pseudo-filesystem",
+            Error::ReadOnlyFilesystem(..) => "read-only-filesystem",
+            Error::UnsafeTrash(..) => "unsafe-trash",
+            Error::UnsafeLockFile(..) => "unsafe-lock-file",
+            Error::InvalidConfig(..) => "invalid-config",
+            Error::HookFailed(..) => "hook-failed",
+            Error::Protected(..) => "protected",
+            Error::RefusedByPolicy(..) => "refused-by-policy",
+            Error::MountRoot(..) => "mount-root",
+            Error::DotOperand(..) => "dot-operand",
+            Error::IsADirectory(..) => "is-a-directory",
+            Error::DirectoryNotEmpty(..) => "directory-not-empty",
+            Error::ChecksumMismatch(..) => "checksum-mismatch",
+            Error::UnsupportedCompression(..) => "unsupported-compression",
+            Error::InvalidInfoFile(..) => "invalid-info-file",
+            Error::InvalidOriginalPath(..) => "invalid-original-path",
+            Error::OperandNotFound(..) => "operand-not-found",
+            Error::EntryNotFound(..) => "entry-not-found",
+            Error::EntryChanged(..) => "entry-changed",
+            Error::NoMatchingEntry(..) => "no-matching-entry",
+            Error::IdNotFound(..) => "id-not-found",
+            Error::ListingOutdated(..) => "listing-outdated",
+            Error::VersionNotFound(..) => "version-not-found",
+            Error::CatDirectory(..) => "cat-directory",
+            Error::NotWritable(..) => "not-writable",
+            Error::RestoreDestinationExists(..) => "restore-destination-exists",
+            Error::RestoreDirectoryNotFound(..) => "restore-directory-not-found",
+            Error::ParentNotRestored(..) => "parent-not-restored",
+            Error::SourceChanged(..) => "source-changed",
+            Error::Immutable(..) => "immutable",
+            Error::AppendOnly(..) => "append-only",
+            Error::NamesExhausted(..) => "names-exhausted",
+            Error::CannotReserveName(..) => "cannot-reserve-name",
+            Error::Usage(..) => "usage",
+            Error::NonLocalUri(..) => "non-local-uri",
+            Error::InvalidUri(..) => "invalid-uri",
+            Error::NestedMountPoints(..) => "nested-mount-points",
+            Error::AmbiguousEntry(..) => "ambiguous-entry",
+            Error::NotConfirmed(..) => "not-confirmed",
+            Error::CopyInProgress(..) => "copy-in-progress",
+            Error::TrashOnAnotherFilesystem(..) => "trash-on-another-filesystem",
+            Error::PathTooLong(..) => "path-too-long",
+            Error::ConfirmationRequired => "confirmation-required",
+            Error::DaemonRunning(..) => "daemon-running",
+            Error::TrashBusy(..) => "trash-busy",
+            Error::CrossesFilesystems(..) => "crosses-filesystems",
+            Error::RelativeTrashRoot(..) => "relative-trash-root",
+            Error::NothingToRestore => "nothing-to-restore",
+            Error::NotATerminal => "not-a-terminal",
+            Error::InvalidPickerOutput(..) => "invalid-picker-output",
+            Error::InvalidDate(..) => "invalid-date",
+            Error::InvalidDeletionDate(..) => "invalid-deletion-date",
+            #[cfg(test)]
+            Error::SimulatedCrash(..) => "simulated-crash",
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -1,7 +1,18 @@
+mod access;
+mod chown;
+mod dir_fd;
+mod flock;
 mod getpwuid;
+mod inode_flags;
 mod lstat;
 mod mount_point;
+mod process;
+mod signal;
+mod statvfs;
+mod syslog;
+mod terminal;
 mod time;
+mod xattr;
 
 pub fn effective_user_id() -> u32 {
     // Safety: the POSIX Programmer's Manual states that
@@ -15,7 +26,40 @@ pub fn real_user_id() -> u32 {
     unsafe { libc::getuid() }
 }
 
+/// Whether the current process is in the group `gid`, either as its real, effective or a supplementary group.
+pub fn is_group_member(gid: u32) -> bool {
+    // Safety: the POSIX Programmer's Manual states that
+    // getgid and getegid will always be successful.
+    if gid == unsafe { libc::getgid() } || gid == unsafe { libc::getegid() } {
+        return true;
+    }
+
+    // Safety: given a size of 0, getgroups only returns how many supplementary groups there are
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    let Ok(len) = usize::try_from(count) else {
+        return false;
+    };
+    let mut groups = vec![0; len];
+    // Safety: `groups` has room for `count` group IDs
+    let count = unsafe { libc::getgroups(count, groups.as_mut_ptr()) };
+
+    usize::try_from(count).is_ok_and(|len| groups[..len].contains(&gid))
+}
+
+pub use access::is_writable_directory;
+pub use chown::lchown;
+pub use dir_fd::DirFd;
+pub use flock::{try_lock_exclusive, try_lock_shared};
 pub use getpwuid::get_home_dir;
-pub use lstat::Lstat;
-pub use mount_point::{probe_mount_points, probe_mount_points_in, MountPoint};
-pub use time::format_timestamp;
+pub use inode_flags::InodeFlags;
+pub use lstat::{FileId, Lstat};
+#[cfg(test)]
+pub use mount_point::probe_mount_points_in;
+pub use mount_point::{innermost_mount_point, probe_mount_points, MountPoint};
+pub use process::process_exists;
+pub use signal::{catch_termination, termination_requested};
+pub use statvfs::filesystem_capacity;
+pub use syslog::syslog;
+pub use terminal::terminal_width;
+pub use time::{format_timestamp, local_offset, local_timestamp};
+pub use xattr::{get_xattr, list_xattrs, set_xattr, XattrFile};
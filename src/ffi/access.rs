@@ -0,0 +1,16 @@
+use std::ffi::CStr;
+
+/// Whether files can be created in and removed from the directory `path` by the effective user.
+pub fn is_writable_directory(path: impl AsRef<CStr>) -> bool {
+    let mode = libc::W_OK | libc::X_OK;
+
+    // Safety: faccessat is memory-safe given a valid C string
+    0 == unsafe {
+        libc::faccessat(
+            libc::AT_FDCWD,
+            path.as_ref().as_ptr(),
+            mode,
+            libc::AT_EACCESS,
+        )
+    }
+}
@@ -0,0 +1,21 @@
+use std::ffi::CStr;
+
+use libc::{gid_t, uid_t};
+
+use crate::error::{Error, Result};
+
+/// Changes the owner and/or group of the file given by `path`, without following symlinks.
+///
+/// `None` leaves the respective ID unchanged.
+pub fn lchown(path: impl AsRef<CStr>, owner: Option<u32>, group: Option<u32>) -> Result<()> {
+    // chown(2) leaves an ID unchanged when given -1
+    let owner = owner.unwrap_or(uid_t::MAX);
+    let group = group.unwrap_or(gid_t::MAX);
+
+    if -1 == unsafe { libc::lchown(path.as_ref().as_ptr(), owner, group) } {
+        let io_err = std::io::Error::last_os_error();
+        Err(Error::Io(io_err))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    ffi::{CStr, CString, OsStr},
+    fs::File,
+    io,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+};
+
+use unixstring::UnixString;
+
+use crate::{error::Result, report::display_path};
+
+/// An open directory, which files in it can be operated on relative to.
+///
+/// Operating on a file through its full path resolves that path again every time, so swapping one of its
+/// components for a symlink in between redirects every later step elsewhere. Operating relative to the open
+/// directory only ever resolves the last component, which is never followed if it's a symlink.
+///
+/// It's also how trees deeper than `PATH_MAX` are walked, since their paths can't be given to the kernel whole:
+/// directories opened with [`DirFd::open_at`] are never resolved through their full path.
+#[derive(Debug)]
+pub struct DirFd {
+    fd: OwnedFd,
+    path: PathBuf,
+}
+
+impl DirFd {
+    /// Opens the directory at `path`, failing if it's a symlink (`O_NOFOLLOW`) or isn't a directory (`O_DIRECTORY`)
+    pub fn open(path: &Path) -> Result<Self> {
+        let unx: UnixString = path.to_owned().try_into()?;
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+
+        // Safety: open is memory-safe, and the descriptor it returns is owned by nothing else
+        let fd = unsafe { libc::open(unx.as_ptr(), flags) };
+        if fd == -1 {
+            let err = io::Error::last_os_error();
+            return Err(io::Error::new(
+                err.kind(),
+                format!("failed to open {}: {err}", display_path(path)),
+            )
+            .into());
+        }
+
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            path: path.to_owned(),
+        })
+    }
+
+    /// Opens the directory named `name` in this directory, failing if it's a symlink or isn't a directory
+    pub fn open_at(&self, name: &CStr) -> Result<Self> {
+        let path = self.path.join(OsStr::from_bytes(name.to_bytes()));
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+
+        // Safety: openat is memory-safe, and the descriptor it returns is owned by nothing else
+        let fd = unsafe { libc::openat(self.as_raw_fd(), name.as_ptr(), flags) };
+        if fd == -1 {
+            let err = io::Error::last_os_error();
+            return Err(io::Error::new(
+                err.kind(),
+                format!("failed to open {}: {err}", display_path(&path)),
+            )
+            .into());
+        }
+
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            path,
+        })
+    }
+
+    /// The path this directory was opened at, which might not lead to it anymore.
+    ///
+    /// Only meant for messages: it may be too long to be resolved.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The names of the files in this directory, `.` and `..` aside, sorted by their bytes
+    pub fn entries(&self) -> io::Result<Vec<CString>> {
+        // The stream owns the descriptor it's given, so it gets its own
+        // Safety: dup and fdopendir are memory-safe
+        let fd = unsafe { libc::fcntl(self.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let stream = unsafe { libc::fdopendir(fd) };
+        if stream.is_null() {
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+        // A descriptor shared with this one shares its offset too, which another listing may have moved
+        unsafe { libc::rewinddir(stream) };
+
+        let mut names = Vec::new();
+        let read = loop {
+            // readdir only tells its errors apart from the end of the stream through errno
+            unsafe { *libc::__errno_location() = 0 };
+            let entry = unsafe { libc::readdir(stream) };
+            if entry.is_null() {
+                let errno = io::Error::last_os_error();
+                break if errno.raw_os_error() == Some(0) {
+                    Ok(())
+                } else {
+                    Err(errno)
+                };
+            }
+
+            // Safety: d_name is NUL-terminated, and valid until the next call to readdir
+            let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+            if name.to_bytes() != b"." && name.to_bytes() != b".." {
+                names.push(name.to_owned());
+            }
+        };
+        // Safety: the stream is closed once, closing its descriptor along with it
+        unsafe { libc::closedir(stream) };
+        read?;
+
+        names.sort();
+        Ok(names)
+    }
+
+    /// Creates the directory named `name` in this directory, with the permission bits `mode` (minus the umask)
+    pub fn create_dir_at(&self, name: &CStr, mode: u32) -> io::Result<()> {
+        // Safety: mkdirat is memory-safe
+        if -1 == unsafe { libc::mkdirat(self.as_raw_fd(), name.as_ptr(), mode as libc::mode_t) } {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Opens the file named `name` in this directory with the `open(2)` flags `flags`, symlinks not being followed.
+    /// Files it creates get the permission bits `mode` (minus the umask).
+    pub fn open_file_at(&self, name: &CStr, flags: i32, mode: u32) -> io::Result<File> {
+        let flags = flags | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+
+        // Safety: openat is memory-safe, and the descriptor it returns is owned by nothing else
+        let fd =
+            unsafe { libc::openat(self.as_raw_fd(), name.as_ptr(), flags, mode as libc::c_uint) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// The target of the symlink named `name` in this directory
+    pub fn read_link_at(&self, name: &CStr) -> io::Result<CString> {
+        let mut buf = vec![0; libc::PATH_MAX as usize];
+        loop {
+            // Safety: readlinkat writes at most `buf.len()` bytes into `buf`
+            let len = unsafe {
+                libc::readlinkat(
+                    self.as_raw_fd(),
+                    name.as_ptr(),
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                )
+            };
+            if len == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            // Filling the buffer means it may have been cut short
+            let len = len as usize;
+            if len < buf.len() {
+                buf.truncate(len);
+                return CString::new(buf)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+            buf.resize(buf.len() * 2, 0);
+        }
+    }
+
+    /// Creates a symlink named `name` in this directory, pointing to `target`
+    pub fn symlink_at(&self, target: &CStr, name: &CStr) -> io::Result<()> {
+        // Safety: symlinkat is memory-safe
+        if -1 == unsafe { libc::symlinkat(target.as_ptr(), self.as_raw_fd(), name.as_ptr()) } {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Sets the permission bits of this directory to `mode`
+    pub fn set_mode(&self, mode: u32) -> io::Result<()> {
+        // Safety: fchmod is memory-safe
+        if -1 == unsafe { libc::fchmod(self.as_raw_fd(), mode as libc::mode_t) } {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Renames the file named `name` in this directory to `to`, as `renameat(2)` does
+    pub fn rename_at(&self, name: &CStr, to: &CStr) -> io::Result<()> {
+        // Safety: renameat is memory-safe
+        let renamed =
+            unsafe { libc::renameat(self.as_raw_fd(), name.as_ptr(), libc::AT_FDCWD, to.as_ptr()) };
+        if renamed == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Removes the file named `name` in this directory, which must not be a directory, as `unlinkat(2)` does
+    pub fn unlink_at(&self, name: &CStr) -> io::Result<()> {
+        // Safety: unlinkat is memory-safe
+        if -1 == unsafe { libc::unlinkat(self.as_raw_fd(), name.as_ptr(), 0) } {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+impl AsFd for DirFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for DirFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::symlink};
+
+    use cstr::cstr;
+    use unixstring::UnixString;
+
+    use super::DirFd;
+    use crate::{error::Result, ffi::Lstat};
+
+    #[test]
+    fn operates_relative_to_the_directory() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let parent = dir.path().join("parent");
+        fs::create_dir(&parent)?;
+        fs::write(parent.join("file"), "file")?;
+
+        let opened = DirFd::open(&parent)?;
+        let stat = Lstat::lstat_at(&opened, cstr!("file"))?;
+        assert!(stat.is_regular_file());
+        assert_eq!(
+            stat.file_id(),
+            Lstat::lstat(UnixString::try_from(parent.join("file"))?)?.file_id()
+        );
+
+        // Moving the directory away doesn't matter, the same one is still operated on
+        fs::rename(&parent, dir.path().join("moved"))?;
+        fs::create_dir(&parent)?;
+        let to = UnixString::try_from(dir.path().join("renamed"))?;
+        opened.rename_at(cstr!("file"), to.as_c_str())?;
+        assert_eq!(fs::read_to_string(dir.path().join("renamed"))?, "file");
+
+        fs::write(dir.path().join("moved/other"), "other")?;
+        opened.unlink_at(cstr!("other"))?;
+        assert_eq!(fs::read_dir(dir.path().join("moved"))?.count(), 0);
+
+        // Symlinks are not followed, not even to open the directory
+        symlink(dir.path().join("moved"), dir.path().join("link"))?;
+        assert!(DirFd::open(&dir.path().join("link")).is_err());
+        symlink("/", dir.path().join("moved/root"))?;
+        assert!(Lstat::lstat_at(&opened, cstr!("root"))?.is_symlink());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+use std::{fs::File, io, os::unix::io::AsRawFd};
+
+/// Takes an exclusive lock on `file` without waiting for it, returning whether it was taken.
+///
+/// The lock is released when every descriptor of `file` is closed, including when the process dies.
+pub fn try_lock_exclusive(file: &File) -> io::Result<bool> {
+    try_lock(file, libc::LOCK_EX)
+}
+
+/// Takes a shared lock on `file` without waiting for it, returning whether it was taken.
+///
+/// Any number of shared locks may be held at once, but none while an exclusive lock is held, and the other way around.
+pub fn try_lock_shared(file: &File) -> io::Result<bool> {
+    try_lock(file, libc::LOCK_SH)
+}
+
+fn try_lock(file: &File, operation: libc::c_int) -> io::Result<bool> {
+    // Safety: flock is memory-safe, and the descriptor is valid as long as `file` is
+    if 0 == unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } {
+        return Ok(true);
+    }
+
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(err),
+    }
+}
@@ -0,0 +1,82 @@
+use std::{io, os::fd::AsRawFd, path::Path};
+
+use fs_err::os::unix::fs::OpenOptionsExt;
+
+use crate::error::Result;
+
+/// `FS_IMMUTABLE_FL`, from `linux/fs.h`: the file can't be modified, renamed or removed, not even by root
+const IMMUTABLE: u32 = 0x0000_0010;
+/// `FS_APPEND_FL`, from `linux/fs.h`: the file can only be appended to, and can't be renamed or removed
+const APPEND_ONLY: u32 = 0x0000_0020;
+
+/// The attributes of a file, as set by `chattr` and shown by `lsattr`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InodeFlags(pub u32);
+
+impl InodeFlags {
+    #[cfg(test)]
+    pub const IMMUTABLE: Self = Self(IMMUTABLE);
+    #[cfg(test)]
+    pub const APPEND_ONLY: Self = Self(APPEND_ONLY);
+
+    /// Reads the attributes of the file at `path` through the `FS_IOC_GETFLAGS` ioctl.
+    ///
+    /// Symlinks are not followed: since they can't be opened, this fails for them. So it does on filesystems
+    /// without these attributes.
+    pub fn of(path: &Path) -> Result<Self> {
+        let file = fs_err::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+            .open(path)?;
+
+        // The kernel writes an int, whatever the definition of the ioctl says
+        let mut flags: libc::c_int = 0;
+        // Safety: FS_IOC_GETFLAGS only writes to `flags`
+        if -1 == unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) } {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(Self(flags as u32))
+    }
+
+    /// Whether the file can't be modified, renamed or removed (`chattr +i`)
+    pub const fn is_immutable(self) -> bool {
+        self.0 & IMMUTABLE != 0
+    }
+
+    /// Whether the file can only be appended to, and not renamed or removed (`chattr +a`)
+    pub const fn is_append_only(self) -> bool {
+        self.0 & APPEND_ONLY != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::symlink};
+
+    use super::InodeFlags;
+    use crate::error::Result;
+
+    #[test]
+    fn reads_inode_flags() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        fs::write(&file, "file")?;
+
+        // Filesystems without attributes (such as tmpfs, on some kernels) make this fail, which is fine
+        if let Ok(flags) = InodeFlags::of(&file) {
+            assert!(!flags.is_immutable());
+            assert!(!flags.is_append_only());
+        }
+
+        symlink(&file, dir.path().join("link"))?;
+        assert!(InodeFlags::of(&dir.path().join("link")).is_err());
+        assert!(InodeFlags::of(&dir.path().join("missing")).is_err());
+
+        assert!(InodeFlags::IMMUTABLE.is_immutable());
+        assert!(!InodeFlags::IMMUTABLE.is_append_only());
+        assert!(InodeFlags::APPEND_ONLY.is_append_only());
+
+        Ok(())
+    }
+}
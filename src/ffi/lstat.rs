@@ -1,16 +1,29 @@
 use std::mem;
-use std::os::unix::fs::PermissionsExt;
+use std::os::{fd::AsRawFd, unix::fs::PermissionsExt};
 use std::{ffi::CStr, fs::Permissions};
 
 use libc::lstat;
 
+use super::DirFd;
+
 use crate::error::{Error, Result};
 
 pub struct Lstat {
     inner: libc::stat,
 }
 
+/// What identifies a file for as long as it exists: its device and inode numbers.
+///
+/// Paths can be made to point to another file at any time, so this is what tells whether a path
+/// still names the file it named before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileId {
+    pub device: u64,
+    pub inode: u64,
+}
+
 #[allow(dead_code)]
+#[allow(clippy::self_named_constructors)]
 impl Lstat {
     pub fn lstat(path: impl AsRef<CStr>) -> Result<Self> {
         Ok(Self {
@@ -18,15 +31,56 @@ impl Lstat {
         })
     }
 
+    /// Like [`Lstat::lstat`], for the file named `name` in the directory `dir`, which is not resolved again
+    pub fn lstat_at(dir: &DirFd, name: impl AsRef<CStr>) -> Result<Self> {
+        // Safety: The all-zero byte-pattern is a valid `struct stat`
+        let mut stat_buf = unsafe { mem::zeroed() };
+
+        let flags = libc::AT_SYMLINK_NOFOLLOW;
+        if -1
+            == unsafe {
+                libc::fstatat(
+                    dir.as_raw_fd(),
+                    name.as_ref().as_ptr(),
+                    &mut stat_buf,
+                    flags,
+                )
+            }
+        {
+            return Err(Error::Io(std::io::Error::last_os_error()));
+        }
+
+        Ok(Self { inner: stat_buf })
+    }
+
     pub const fn mode(&self) -> u32 {
         self.inner.st_mode
     }
 
+    /// Whether this is a directory. Symlinks to directories are not considered directories.
+    pub const fn is_directory(&self) -> bool {
+        self.mode() & libc::S_IFMT == libc::S_IFDIR
+    }
+
+    pub const fn is_regular_file(&self) -> bool {
+        self.mode() & libc::S_IFMT == libc::S_IFREG
+    }
+
+    pub const fn is_symlink(&self) -> bool {
+        self.mode() & libc::S_IFMT == libc::S_IFLNK
+    }
+
     /// Total size, in bytes
     pub const fn size(&self) -> u64 {
         self.inner.st_size as u64
     }
 
+    /// How many hard links this file has, this name included
+    #[allow(clippy::unnecessary_cast)] // nlink_t is 32 bits wide on some architectures
+    pub const fn nlink(&self) -> u64 {
+        self.inner.st_nlink as u64
+    }
+
     pub const fn block_size(&self) -> i64 {
         self.inner.st_blksize
     }
@@ -47,6 +101,30 @@ impl Lstat {
         self.inner.st_mtime as u64
     }
 
+    /// The nanoseconds part of the modification time
+    pub const fn modified_nsec(&self) -> i64 {
+        self.inner.st_mtime_nsec
+    }
+
+    /// The ID of the device containing this file
+    pub const fn device(&self) -> u64 {
+        self.inner.st_dev
+    }
+
+    /// The same attributes, as if the file were on the device `device`
+    #[cfg(test)]
+    pub fn on_device(mut self, device: u64) -> Self {
+        self.inner.st_dev = device;
+        self
+    }
+
+    pub const fn file_id(&self) -> FileId {
+        FileId {
+            device: self.inner.st_dev,
+            inode: self.inner.st_ino,
+        }
+    }
+
     pub const fn owner_user_id(&self) -> u32 {
         self.inner.st_uid
     }
@@ -5,18 +5,55 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::error::{Error, Result};
+use crate::{
+    error::{Error, Result},
+    log,
+    report::display_path,
+};
 use cstr::cstr;
 use libc::{getmntent, setmntent};
 use unixstring::UnixString;
 
-#[derive(Debug, PartialEq, Eq)]
+/// Filesystems that don't hold user files, so they never get trash directories
+const PSEUDO_FILESYSTEMS: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "pstore",
+    "rpc_pipefs",
+    "securityfs",
+    "sysfs",
+    "tracefs",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MountPoint {
     pub fs_name: String,
     pub fs_path_prefix: PathBuf,
+    /// The type of the filesystem, e.g. `ext4` or `proc`
+    pub fs_type: String,
+    /// Whether this filesystem was mounted read-only
+    pub read_only: bool,
 }
 
 impl MountPoint {
+    /// Whether this is a pseudo-filesystem, such as `proc` or `sysfs`
+    pub fn is_pseudo(&self) -> bool {
+        PSEUDO_FILESYSTEMS.contains(&self.fs_type.as_str())
+    }
+
     pub fn is_root(&self) -> bool {
         self.fs_path_prefix == Path::new("/")
     }
@@ -30,6 +67,19 @@ impl MountPoint {
     }
 }
 
+/// The mount point of `mount_points` that contains `path`.
+///
+/// Since mount points may be nested, this is the one with the longest prefix.
+pub fn innermost_mount_point<'a>(
+    mount_points: &'a [MountPoint],
+    path: &Path,
+) -> Option<&'a MountPoint> {
+    mount_points
+        .iter()
+        .filter(|mount_point| mount_point.contains(path))
+        .max_by_key(|mount_point| mount_point.fs_path_prefix.as_os_str().len())
+}
+
 #[cfg(test)]
 mod mount_point_fns {
 
@@ -39,6 +89,8 @@ mod mount_point_fns {
         MountPoint {
             fs_name: "/dev/sda2".into(),
             fs_path_prefix: "/".into(),
+            fs_type: "ext4".into(),
+            read_only: false,
         }
     }
 
@@ -46,6 +98,8 @@ mod mount_point_fns {
         MountPoint {
             fs_name: "/dev/sda2".into(),
             fs_path_prefix: "/home".into(),
+            fs_type: "ext4".into(),
+            read_only: false,
         }
     }
 
@@ -60,6 +114,19 @@ mod mount_point_fns {
         assert!(!root().is_home());
         assert!(home().is_home());
     }
+
+    #[test]
+    fn is_pseudo() {
+        let proc = MountPoint {
+            fs_name: "proc".into(),
+            fs_path_prefix: "/proc".into(),
+            fs_type: "proc".into(),
+            read_only: false,
+        };
+
+        assert!(proc.is_pseudo());
+        assert!(!root().is_pseudo());
+    }
 }
 
 impl PartialOrd for MountPoint {
@@ -104,15 +171,34 @@ pub fn probe_mount_points_in(path: &CStr) -> Result<Vec<MountPoint>> {
         // so this deref must be safe (I guess?)
         let fs_name = unsafe { (*entry).mnt_fsname };
         let fs_dir = unsafe { (*entry).mnt_dir };
+        let fs_type = unsafe { (*entry).mnt_type };
+        let fs_opts = unsafe { (*entry).mnt_opts };
 
         let fs_name = unsafe { UnixString::from_ptr(fs_name) };
 
         let fs_dir = unsafe { UnixString::from_ptr(fs_dir) };
 
+        let fs_type = unsafe { UnixString::from_ptr(fs_type) };
+
+        let fs_opts = unsafe { UnixString::from_ptr(fs_opts) }.into_string_lossy();
+
         let mount_point = MountPoint {
             fs_name: fs_name.into_string_lossy(),
             fs_path_prefix: fs_dir.into(),
+            fs_type: fs_type.into_string_lossy(),
+            read_only: fs_opts.split(',').any(|opt| opt == "ro"),
         };
+        log::trace(format_args!(
+            "found the mount point {} of {} ({}{})",
+            display_path(&mount_point.fs_path_prefix),
+            mount_point.fs_name,
+            mount_point.fs_type,
+            if mount_point.read_only {
+                ", read-only"
+            } else {
+                ""
+            }
+        ));
         mount_points.push(Reverse(mount_point));
     }
 
@@ -127,9 +213,7 @@ pub fn probe_mount_points_in(path: &CStr) -> Result<Vec<MountPoint>> {
 mod mount_point_probing_tests {
     use tempfile::NamedTempFile;
 
-    use std::{
-        collections::BTreeSet, ffi::CString, io::Write, os::unix::prelude::OsStrExt, time::Duration,
-    };
+    use std::{ffi::CString, io::Write, os::unix::prelude::OsStrExt, time::Duration};
 
     use crate::ffi::{probe_mount_points_in, MountPoint};
 
@@ -143,6 +227,7 @@ mod mount_point_probing_tests {
     securityfs /sys/kernel/security securityfs rw,nosuid,nodev,noexec,relatime 0 0
     tmpfs /dev/shm tmpfs rw,nosuid,nodev,inode64 0 0
     devpts /dev/pts devpts rw,nosuid,noexec,relatime,gid=5,mode=620,ptmxmode=000 0 0
+    /dev/sdb1 /mnt/ro ext4 ro,noatime 0 0
 "#;
 
     #[test]
@@ -160,48 +245,76 @@ mod mount_point_probing_tests {
 
         let mount_points = probe_mount_points_in(&temp_path_cstr).unwrap();
 
-        let mount_points: BTreeSet<_> = mount_points.into_iter().collect();
+        // `MountPoint`'s ordering only considers the length of the prefixes, so
+        // we sort by the prefixes themselves in order to compare both lists
+        let mut mount_points = mount_points;
+        mount_points.sort_by(|a, b| a.fs_path_prefix.cmp(&b.fs_path_prefix));
 
         let expected = vec![
             MountPoint {
                 fs_name: "efivarfs".into(),
                 fs_path_prefix: "/sys/firmware/efi/efivars".into(),
+                fs_type: "efivarfs".into(),
+                read_only: false,
             },
             MountPoint {
                 fs_name: "securityfs".into(),
                 fs_path_prefix: "/sys/kernel/security".into(),
+                fs_type: "securityfs".into(),
+                read_only: false,
             },
             MountPoint {
                 fs_name: "devpts".into(),
                 fs_path_prefix: "/dev/pts".into(),
+                fs_type: "devpts".into(),
+                read_only: false,
             },
             MountPoint {
                 fs_name: "tmpfs".into(),
                 fs_path_prefix: "/dev/shm".into(),
+                fs_type: "tmpfs".into(),
+                read_only: false,
             },
             MountPoint {
                 fs_name: "proc".into(),
                 fs_path_prefix: "/proc".into(),
+                fs_type: "proc".into(),
+                read_only: false,
             },
             MountPoint {
                 fs_name: "run".into(),
                 fs_path_prefix: "/run".into(),
+                fs_type: "tmpfs".into(),
+                read_only: false,
             },
             MountPoint {
                 fs_name: "dev".into(),
                 fs_path_prefix: "/dev".into(),
+                fs_type: "devtmpfs".into(),
+                read_only: false,
             },
             MountPoint {
                 fs_name: "sys".into(),
                 fs_path_prefix: "/sys".into(),
+                fs_type: "sysfs".into(),
+                read_only: false,
             },
             MountPoint {
                 fs_name: "/dev/sda2".into(),
                 fs_path_prefix: "/".into(),
+                fs_type: "ext4".into(),
+                read_only: false,
+            },
+            MountPoint {
+                fs_name: "/dev/sdb1".into(),
+                fs_path_prefix: "/mnt/ro".into(),
+                fs_type: "ext4".into(),
+                read_only: true,
             },
         ];
 
-        let expected: BTreeSet<_> = expected.into_iter().collect();
+        let mut expected = expected;
+        expected.sort_by(|a, b| a.fs_path_prefix.cmp(&b.fs_path_prefix));
 
         assert_eq!(mount_points, expected);
     }
@@ -218,11 +331,15 @@ mod mount_point_ordering_tests {
         let first = MountPoint {
             fs_name: "portal".into(),
             fs_path_prefix: "/run/user/1000".into(),
+            fs_type: "fuse.portal".into(),
+            read_only: false,
         };
 
         let second = MountPoint {
             fs_name: "portal".into(),
             fs_path_prefix: "/run/user/1001/doc".into(),
+            fs_type: "fuse.portal".into(),
+            read_only: false,
         };
 
         assert!(first < second);
@@ -236,11 +353,15 @@ mod mount_point_ordering_tests {
         let first = MountPoint {
             fs_name: "portal".into(),
             fs_path_prefix: "/run/user/1000/doc".into(),
+            fs_type: "fuse.portal".into(),
+            read_only: false,
         };
 
         let second = MountPoint {
             fs_name: "portal".into(),
             fs_path_prefix: "/run/user/1001/doc".into(),
+            fs_type: "fuse.portal".into(),
+            read_only: false,
         };
 
         assert!(first != second);
@@ -249,11 +370,15 @@ mod mount_point_ordering_tests {
         let first = MountPoint {
             fs_name: "portal2".into(),
             fs_path_prefix: "/run/user/1000/doc".into(),
+            fs_type: "fuse.portal".into(),
+            read_only: false,
         };
 
         let second = MountPoint {
             fs_name: "portal".into(),
             fs_path_prefix: "/run/user/1000/doc".into(),
+            fs_type: "fuse.portal".into(),
+            read_only: false,
         };
 
         assert!(first != second);
@@ -262,11 +387,15 @@ mod mount_point_ordering_tests {
         let first = MountPoint {
             fs_name: "portal2".into(),
             fs_path_prefix: "/run/user/1000/doc".into(),
+            fs_type: "fuse.portal".into(),
+            read_only: false,
         };
 
         let second = MountPoint {
             fs_name: "portal".into(),
             fs_path_prefix: "/run/user/1001/doc".into(),
+            fs_type: "fuse.portal".into(),
+            read_only: false,
         };
 
         assert!(first != second);
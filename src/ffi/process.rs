@@ -0,0 +1,9 @@
+/// Whether a process with the given PID is currently running.
+///
+/// Processes of other users count as running, even though we can't signal them.
+pub fn process_exists(pid: i32) -> bool {
+    // kill(2) with signal 0 only checks whether the process could be signaled
+    // Safety: kill is memory-safe
+    0 == unsafe { libc::kill(pid, 0) }
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
@@ -0,0 +1,36 @@
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Set by [`on_termination`], once SIGTERM or SIGINT is received
+static TERMINATION_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_termination(_signal: libc::c_int) {
+    // Only async-signal-safe things may be done here, which storing to an atomic is
+    TERMINATION_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Catches SIGTERM and SIGINT, which then only make [`termination_requested`] return true
+/// instead of killing the process, so that it can stop when it's done with what it's doing.
+pub fn catch_termination() -> io::Result<()> {
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        // Safety: the handler only stores to an atomic, which is async-signal-safe
+        let previous = unsafe {
+            libc::signal(
+                signal,
+                on_termination as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            )
+        };
+        if previous == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether SIGTERM or SIGINT was received since [`catch_termination`] was called
+pub fn termination_requested() -> bool {
+    TERMINATION_REQUESTED.load(Ordering::SeqCst)
+}
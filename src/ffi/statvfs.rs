@@ -0,0 +1,17 @@
+use std::{ffi::CStr, mem};
+
+use crate::error::{Error, Result};
+
+/// The capacity, in bytes, of the filesystem containing the file given by `path`
+pub fn filesystem_capacity(path: impl AsRef<CStr>) -> Result<u64> {
+    // Safety: The all-zero byte-pattern is a valid `struct statvfs`
+    let mut statvfs_buf: libc::statvfs = unsafe { mem::zeroed() };
+
+    if -1 == unsafe { libc::statvfs(path.as_ref().as_ptr(), &mut statvfs_buf) } {
+        let io_err = std::io::Error::last_os_error();
+        Err(Error::Io(io_err))
+    } else {
+        // Sizes are counted in fragments
+        Ok(statvfs_buf.f_blocks as u64 * statvfs_buf.f_frsize as u64)
+    }
+}
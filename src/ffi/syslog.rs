@@ -0,0 +1,17 @@
+use std::ffi::CString;
+
+use cstr::cstr;
+
+/// Sends `message` to the system logger, which journald also collects, as an informational message from `tt`.
+///
+/// syslog(3) doesn't report failures, so neither does this. Interior NUL bytes are dropped.
+pub fn syslog(message: &str) {
+    let message = CString::new(message.replace('\0', "")).expect("NUL bytes were removed");
+
+    // Safety: the identity is a static string, as openlog keeps a pointer to it, and the message
+    // is passed as an argument to a constant format string rather than as the format itself
+    unsafe {
+        libc::openlog(cstr!("tt").as_ptr(), libc::LOG_PID, libc::LOG_USER);
+        libc::syslog(libc::LOG_INFO, cstr!("%s").as_ptr(), message.as_ptr());
+    }
+}
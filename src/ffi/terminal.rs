@@ -0,0 +1,15 @@
+use std::mem;
+
+/// How many columns wide the terminal stdout goes to is, if it goes to one that tells
+pub fn terminal_width() -> Option<usize> {
+    // Safety: The all-zero byte-pattern is a valid `struct winsize`
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+
+    if -1 == unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) }
+        || size.ws_col == 0
+    {
+        None
+    } else {
+        Some(size.ws_col.into())
+    }
+}
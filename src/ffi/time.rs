@@ -1,10 +1,10 @@
 use std::{mem, time::Duration};
 
 use cstr::cstr;
-use libc::{c_char, localtime_r, size_t, time, tm};
+use libc::{c_char, c_int, localtime_r, mktime, size_t, time_t, tm};
 use unixstring::UnixString;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 
 // crate libc doesn't have bindings to those yet
 extern "C" {
@@ -20,21 +20,19 @@ extern "C" {
 
 const BUF_SIZ: usize = 64;
 
-/// Formats a timestamp (represented as a [`Duration`] since UNIX_EPOCH) into a YYYY-MM-DDThh:mm:ss format
-pub fn format_timestamp(now: Duration) -> Result<String> {
-    let mut timestamp = now.as_secs();
+/// Formats a timestamp (represented as a [`Duration`] since UNIX_EPOCH) into a YYYY-MM-DDThh:mm:ss format, in local time
+pub fn format_timestamp(timestamp: Duration) -> Result<String> {
+    let ltime = time_t::try_from(timestamp.as_secs()).map_err(|_| {
+        Error::InvalidDate(format!("{} seconds after the epoch", timestamp.as_secs()))
+    })?;
 
     // Safety: the all-zero byte-pattern is valid struct tm
     let mut new_time: tm = unsafe { mem::zeroed() };
 
-    // Safety: time is memory-safe
-    // TODO: it'd be better to call `time(NULL)` here
-    let ltime = unsafe { time(&mut timestamp as *mut _ as *mut _) };
-
     unsafe { tzset() };
 
     // Safety: localtime_r is memory safe, threadsafe.
-    unsafe { localtime_r(&ltime as *const i64, &mut new_time as *mut tm) };
+    unsafe { localtime_r(&ltime as *const time_t, &mut new_time as *mut tm) };
 
     let mut char_buf: [c_char; BUF_SIZ] = [0; BUF_SIZ];
 
@@ -55,13 +53,59 @@ pub fn format_timestamp(now: Duration) -> Result<String> {
     Ok(unx.to_string_lossy().into())
 }
 
+/// The timestamp (in seconds since UNIX_EPOCH) of a date and time in local time, `None` if it's out of range.
+///
+/// Whether daylight saving time is in effect is left to the system, so a time that happens twice when clocks go back
+/// is either of them.
+pub fn local_timestamp(
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+) -> Option<time_t> {
+    let field = |value: i64| c_int::try_from(value).ok();
+
+    // Safety: the all-zero byte-pattern is valid struct tm
+    let mut time: tm = unsafe { mem::zeroed() };
+    time.tm_year = field(year - 1900)?;
+    time.tm_mon = field(i64::from(month) - 1)?;
+    time.tm_mday = field(day.into())?;
+    time.tm_hour = field(hour.into())?;
+    time.tm_min = field(minute.into())?;
+    time.tm_sec = field(second.into())?;
+    time.tm_isdst = -1;
+
+    unsafe { tzset() };
+
+    // Safety: mktime only reads and normalizes the struct it's given
+    let timestamp = unsafe { mktime(&mut time as *mut tm) };
+
+    // -1 is also one second before the epoch, which mktime tells apart by normalizing the struct
+    (timestamp != -1 || time.tm_year == 69).then_some(timestamp)
+}
+
+/// How many seconds ahead of UTC the local timezone was at `timestamp`, in seconds since UNIX_EPOCH
+pub fn local_offset(timestamp: time_t) -> Option<i64> {
+    // Safety: the all-zero byte-pattern is valid struct tm
+    let mut time: tm = unsafe { mem::zeroed() };
+
+    unsafe { tzset() };
+
+    // Safety: localtime_r is memory safe, threadsafe.
+    let converted = unsafe { localtime_r(&timestamp as *const time_t, &mut time as *mut tm) };
+
+    (!converted.is_null()).then_some(time.tm_gmtoff)
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::{SystemTime, UNIX_EPOCH};
 
-    use chrono::Local;
+    use chrono::{Local, TimeZone};
 
-    use crate::ffi::time::format_timestamp;
+    use crate::ffi::time::{format_timestamp, local_offset, local_timestamp};
 
     #[test]
     fn formats_timestamp_into_valid_rfc3339() {
@@ -76,4 +120,57 @@ mod tests {
 
         assert_eq!(&rfc3339, &format_timestamp(now).unwrap());
     }
+
+    #[test]
+    fn finds_timestamps_of_local_times() {
+        for seconds in [0, 1_093_991_528, 1_704_103_200] {
+            let local = Local.timestamp_opt(seconds, 0).unwrap().naive_local();
+            let fields = local.format("%Y %m %d %H %M %S").to_string();
+            let fields: Vec<u32> = fields
+                .split(' ')
+                .map(|field| field.parse().unwrap())
+                .collect();
+
+            assert_eq!(
+                local_timestamp(
+                    fields[0].into(),
+                    fields[1],
+                    fields[2],
+                    fields[3],
+                    fields[4],
+                    fields[5]
+                ),
+                Some(seconds)
+            );
+        }
+    }
+
+    #[test]
+    fn finds_offsets_of_the_local_timezone() {
+        for seconds in [0, 1_093_991_528, 1_704_103_200] {
+            let offset = Local
+                .timestamp_opt(seconds, 0)
+                .unwrap()
+                .offset()
+                .local_minus_utc();
+
+            assert_eq!(local_offset(seconds), Some(offset.into()));
+        }
+    }
+
+    #[test]
+    fn formats_timestamps_other_than_now() {
+        for seconds in [0, 1_093_991_528, 1_704_103_200] {
+            let expected = Local
+                .timestamp_opt(seconds, 0)
+                .unwrap()
+                .format("%Y-%m-%dT%T")
+                .to_string();
+
+            assert_eq!(
+                format_timestamp(std::time::Duration::from_secs(seconds as u64)).unwrap(),
+                expected
+            );
+        }
+    }
 }
@@ -0,0 +1,148 @@
+use std::{
+    ffi::{CStr, CString},
+    io,
+    os::fd::{AsRawFd, BorrowedFd},
+};
+
+/// A file whose extended attributes are operated on
+#[derive(Debug, Clone, Copy)]
+pub enum XattrFile<'a> {
+    /// The file at a path, symlinks not being followed
+    Path(&'a CStr),
+    /// An open file, which is how files whose paths are too long to be resolved are reached
+    Fd(BorrowedFd<'a>),
+}
+
+/// Calls `get` with a buffer it fills, growing it for as long as it's too small
+fn read_into_buffer(get: impl Fn(&mut [u8]) -> libc::ssize_t) -> io::Result<Vec<u8>> {
+    loop {
+        // Asking with an empty buffer gives the size needed
+        let size = get(&mut []);
+        if size == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buf = vec![0; size as usize];
+        match get(&mut buf) {
+            -1 if io::Error::last_os_error().raw_os_error() == Some(libc::ERANGE) => {
+                // It grew in between
+                continue;
+            }
+            -1 => return Err(io::Error::last_os_error()),
+            size => {
+                buf.truncate(size as usize);
+                return Ok(buf);
+            }
+        }
+    }
+}
+
+/// The names of the extended attributes of `file`
+pub fn list_xattrs(file: XattrFile) -> io::Result<Vec<CString>> {
+    // Safety: llistxattr and flistxattr write at most `buf.len()` bytes into `buf`
+    let names = read_into_buffer(|buf| unsafe {
+        match file {
+            XattrFile::Path(path) => {
+                libc::llistxattr(path.as_ptr(), buf.as_mut_ptr().cast(), buf.len())
+            }
+            XattrFile::Fd(fd) => {
+                libc::flistxattr(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len())
+            }
+        }
+    })?;
+
+    // The names are NUL-terminated, one after the other
+    Ok(names
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| CString::new(name).ok())
+        .collect())
+}
+
+/// The value of the extended attribute `name` of `file`
+pub fn get_xattr(file: XattrFile, name: &CStr) -> io::Result<Vec<u8>> {
+    // Safety: lgetxattr and fgetxattr write at most `buf.len()` bytes into `buf`
+    read_into_buffer(|buf| unsafe {
+        match file {
+            XattrFile::Path(path) => libc::lgetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+            ),
+            XattrFile::Fd(fd) => libc::fgetxattr(
+                fd.as_raw_fd(),
+                name.as_ptr(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+            ),
+        }
+    })
+}
+
+/// Sets the extended attribute `name` of `file` to `value`
+pub fn set_xattr(file: XattrFile, name: &CStr, value: &[u8]) -> io::Result<()> {
+    // Safety: lsetxattr and fsetxattr only read `value.len()` bytes from `value`
+    let set = unsafe {
+        match file {
+            XattrFile::Path(path) => libc::lsetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            ),
+            XattrFile::Fd(fd) => libc::fsetxattr(
+                fd.as_raw_fd(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            ),
+        }
+    };
+    if set == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use cstr::cstr;
+    use unixstring::UnixString;
+
+    use super::{get_xattr, list_xattrs, set_xattr, XattrFile};
+    use crate::error::Result;
+
+    #[test]
+    fn reads_and_writes_xattrs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = UnixString::try_from(dir.path().join("file"))?;
+        fs::write(path.as_path(), "file")?;
+        let path = XattrFile::Path(path.as_c_str());
+
+        let value = vec![b'x'; 1000];
+        if let Err(err) = set_xattr(path, cstr!("user.tt.test"), &value) {
+            // Not every filesystem has them
+            assert_eq!(err.raw_os_error(), Some(libc::EOPNOTSUPP));
+            return Ok(());
+        }
+
+        assert!(list_xattrs(path)?
+            .iter()
+            .any(|name| name.as_c_str() == cstr!("user.tt.test")));
+        assert_eq!(get_xattr(path, cstr!("user.tt.test"))?, value);
+        assert_eq!(
+            get_xattr(path, cstr!("user.tt.missing"))
+                .unwrap_err()
+                .raw_os_error(),
+            Some(libc::ENODATA)
+        );
+
+        Ok(())
+    }
+}
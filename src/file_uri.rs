@@ -0,0 +1,122 @@
+//! `file://` URIs, as desktop environments and clipboard managers hand files around, taken as operands.
+//!
+//! Only arguments starting with `file://` are URIs: plain paths are taken as they are, `%` and all.
+
+use std::{
+    ffi::{OsStr, OsString},
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::PathBuf,
+};
+
+use percent_encoding::percent_decode;
+
+use crate::error::{Error, Result};
+
+const SCHEME: &[u8] = b"file://";
+
+/// The path `argument` stands for: the path a `file://` URI points to, or else `argument` itself.
+///
+/// URIs may have an empty authority (`file:///home/user/notes.txt`) or `localhost`: other hosts are refused with
+/// [`Error::NonLocalUri`]. Their path is percent-decoded into bytes, which needn't be UTF-8, one segment at a time:
+/// a segment decoding to `/` (`%2F`) or to a NUL byte is refused with [`Error::InvalidUri`], as it would otherwise
+/// make another path out of a file name.
+pub fn operand_path(argument: &OsStr) -> Result<PathBuf> {
+    let Some(rest) = argument.as_bytes().strip_prefix(SCHEME) else {
+        return Ok(argument.into());
+    };
+
+    let path_start = rest.iter().position(|&byte| byte == b'/');
+    let authority = &rest[..path_start.unwrap_or(rest.len())];
+    let is_local = authority.is_empty() || authority.eq_ignore_ascii_case(b"localhost");
+    let (true, Some(path_start)) = (is_local, path_start) else {
+        return Err(Error::NonLocalUri(argument.to_string_lossy().into_owned()));
+    };
+
+    let mut path = Vec::new();
+    for (index, segment) in rest[path_start..].split(|&byte| byte == b'/').enumerate() {
+        if index > 0 {
+            path.push(b'/');
+        }
+        let segment: Vec<u8> = percent_decode(segment).collect();
+        if segment.contains(&b'/') || segment.contains(&0) {
+            return Err(Error::InvalidUri(argument.to_string_lossy().into_owned()));
+        }
+        path.extend(segment);
+    }
+
+    Ok(OsString::from_vec(path).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+    use super::operand_path;
+    use crate::Error;
+
+    fn path(argument: &str) -> crate::Result<std::path::PathBuf> {
+        operand_path(argument.as_ref())
+    }
+
+    #[test]
+    fn decodes_file_uris() -> crate::Result<()> {
+        assert_eq!(
+            path("file:///home/me/some%20file.txt")?,
+            Path::new("/home/me/some file.txt")
+        );
+        assert_eq!(
+            path("file:///home/me/caf%C3%A9")?,
+            Path::new("/home/me/café")
+        );
+        assert_eq!(path("file://localhost/etc/fstab")?, Path::new("/etc/fstab"));
+        assert_eq!(path("file://LOCALHOST/etc/fstab")?, Path::new("/etc/fstab"));
+        assert_eq!(
+            path("file:///tmp/latin1-%E9")?,
+            Path::new(OsStr::from_bytes(b"/tmp/latin1-\xe9"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_plain_paths_alone() -> crate::Result<()> {
+        for plain in [
+            "some%20file.txt",
+            "/tmp/100%",
+            "file:/tmp/notes",
+            "./file://x",
+        ] {
+            assert_eq!(path(plain)?, Path::new(plain));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_file_names_that_decode_to_paths() {
+        for uri in [
+            "file:///home/me/caf%C3%A9%2Fnot-a-dir",
+            "file:///home/me/%2f",
+            "file:///home/me/nul%00byte",
+        ] {
+            assert!(
+                matches!(path(uri), Err(Error::InvalidUri(invalid)) if invalid == uri),
+                "{uri}"
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_other_hosts() {
+        for remote in [
+            "file://otherhost/home/me/notes.txt",
+            "file://otherhost",
+            "file://",
+        ] {
+            assert!(
+                matches!(path(remote), Err(Error::NonLocalUri(uri)) if uri == remote),
+                "{remote}"
+            );
+        }
+    }
+}
@@ -1,20 +1,179 @@
 use std::{
-    ffi::OsString,
-    fs::{self},
-    path::Path,
+    collections::HashSet,
+    ffi::{CStr, OsStr, OsString},
+    fs::{self, Permissions},
+    io,
+    os::{
+        fd::AsFd,
+        unix::{ffi::OsStrExt, fs::PermissionsExt},
+    },
+    path::{Component, Path, PathBuf},
 };
 
-use tempfile::NamedTempFile;
+use cstr::cstr;
+use tempfile::{NamedTempFile, TempDir};
 use unixstring::UnixString;
 use uuid::Uuid;
+use walkdir::WalkDir;
 
 use crate::{
-    error::Result,
-    ffi::Lstat,
-    light_fs::{path_is_directory, path_is_regular_file},
+    error::{Error, Result},
+    ffi::{self, DirFd, FileId, InodeFlags, Lstat, XattrFile},
+    log,
+    report::{display_path, warn},
+    resume, stats,
     trash::Trash,
 };
 
+/// The filesystem operations that trashing files is made of.
+///
+/// [`RealFs`] performs them on the actual filesystem, but this can be swapped out
+/// (through [`TrashContext`](crate::context::TrashContext)) in order to simulate failures.
+pub trait FileSystem {
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Copies the contents and permission bits of the regular file `from` into `to`
+    fn copy_file(&self, from: &Path, to: &Path) -> io::Result<u64>;
+
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Creates (or truncates) the regular file at `path`, opening it for writing
+    fn create_file(&self, path: &Path) -> io::Result<fs_err::File>;
+
+    /// Creates the regular file at `path`, opening it for writing, failing with
+    /// [`AlreadyExists`](io::ErrorKind::AlreadyExists) if anything is already there
+    fn create_new_file(&self, path: &Path) -> io::Result<fs_err::File>;
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    fn lstat(&self, path: &CStr) -> Result<Lstat>;
+
+    /// Like [`FileSystem::lstat`], for the file named `name` in the directory `dir`
+    fn lstat_at(&self, dir: &DirFd, name: &CStr) -> Result<Lstat>;
+
+    /// Like [`FileSystem::rename`], for the file named `name` in the directory `dir`
+    fn rename_at(&self, dir: &DirFd, name: &CStr, to: &Path) -> io::Result<()>;
+
+    /// Like [`FileSystem::remove_file`], for the file named `name` in the directory `dir`
+    fn remove_file_at(&self, dir: &DirFd, name: &CStr) -> io::Result<()>;
+
+    /// Like [`FileSystem::create_dir`], for the directory named `name` in the directory `dir`
+    fn create_dir_at(&self, dir: &DirFd, name: &CStr) -> io::Result<()>;
+
+    /// Like [`FileSystem::copy_file`], from the file named `from_name` in the directory `from_dir` into the file
+    /// named `to_name` in the directory `to_dir`. Symlinks are not followed.
+    fn copy_file_at(
+        &self,
+        from_dir: &DirFd,
+        from_name: &CStr,
+        to_dir: &DirFd,
+        to_name: &CStr,
+    ) -> io::Result<u64>;
+
+    /// Like [`FileSystem::symlink`], for the symlink named `name` in the directory `dir`
+    fn symlink_at(&self, target: &CStr, dir: &DirFd, name: &CStr) -> io::Result<()>;
+
+    /// The attributes of the file at `path` (see [`InodeFlags::of`])
+    fn inode_flags(&self, path: &Path) -> Result<InodeFlags>;
+}
+
+/// Operates on the actual filesystem
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealFs;
+
+impl FileSystem for RealFs {
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs_err::rename(from, to)
+    }
+
+    fn copy_file(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        fs_err::copy(from, to)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs_err::create_dir(path)
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<fs_err::File> {
+        fs_err::File::create(path)
+    }
+
+    fn create_new_file(&self, path: &Path) -> io::Result<fs_err::File> {
+        fs_err::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        fs_err::os::unix::fs::symlink(target, link)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs_err::remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs_err::remove_dir_all(path)
+    }
+
+    fn lstat(&self, path: &CStr) -> Result<Lstat> {
+        Lstat::lstat(path)
+    }
+
+    fn lstat_at(&self, dir: &DirFd, name: &CStr) -> Result<Lstat> {
+        Lstat::lstat_at(dir, name)
+    }
+
+    fn rename_at(&self, dir: &DirFd, name: &CStr, to: &Path) -> io::Result<()> {
+        let to = UnixString::try_from(to.to_owned())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+        dir.rename_at(name, to.as_c_str())
+    }
+
+    fn remove_file_at(&self, dir: &DirFd, name: &CStr) -> io::Result<()> {
+        dir.unlink_at(name)
+    }
+
+    fn create_dir_at(&self, dir: &DirFd, name: &CStr) -> io::Result<()> {
+        dir.create_dir_at(name, 0o777)
+    }
+
+    fn copy_file_at(
+        &self,
+        from_dir: &DirFd,
+        from_name: &CStr,
+        to_dir: &DirFd,
+        to_name: &CStr,
+    ) -> io::Result<u64> {
+        let mut from = from_dir.open_file_at(from_name, libc::O_RDONLY, 0)?;
+        let permissions = from.metadata()?.permissions();
+        let mut to = to_dir.open_file_at(
+            to_name,
+            libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+            0o600,
+        )?;
+
+        let copied = io::copy(&mut from, &mut to)?;
+        to.set_permissions(permissions)?;
+
+        Ok(copied)
+    }
+
+    fn symlink_at(&self, target: &CStr, dir: &DirFd, name: &CStr) -> io::Result<()> {
+        dir.symlink_at(target, name)
+    }
+
+    fn inode_flags(&self, path: &Path) -> Result<InodeFlags> {
+        InodeFlags::of(path)
+    }
+}
+
 /// Assuming that a file with path `path` exists in the directory `dir`,
 /// this function appends to `path` an UUID in order to make its path unique.
 ///
@@ -28,38 +187,487 @@ pub fn build_unique_file_name(path: impl AsRef<Path>, _dir: impl AsRef<Path>) ->
     new_file_name
 }
 
+/// `file_name` followed by `.n`, e.g. `notes.txt.2`, the first variations tried when `file_name` is taken in a trash
+pub fn numbered_file_name(file_name: &OsStr, n: u32) -> OsString {
+    let mut new_file_name = file_name.to_owned();
+    new_file_name.push(format!(".{n}"));
+    new_file_name
+}
+
+/// The setuid, setgid and sticky bits of a mode, which restoring never gives files back: whoever could write to
+/// the trash could otherwise have a privileged program made of what's in it.
+pub const PRIVILEGE_BITS: u32 = libc::S_ISUID | libc::S_ISGID | libc::S_ISVTX;
+
+/// The prefix of the names of every temporary file `tt` makes, inside or outside of trashes.
+///
+/// Recognizing these is what lets leftovers of interrupted runs be removed without ever
+/// touching another tool's files.
+pub const TEMP_FILE_PREFIX: &str = ".tt-tmp-";
+
+/// Whether `file_name` is the name of one of our temporary files
+pub fn is_temp_file_name(file_name: &OsStr) -> bool {
+    file_name
+        .as_bytes()
+        .starts_with(TEMP_FILE_PREFIX.as_bytes())
+}
+
+/// The PID of the process that made the temporary file named `file_name`, if it's one of ours.
+///
+/// Temporary files are named `.tt-tmp-$pid-$random`.
+pub fn temp_file_owner(file_name: &OsStr) -> Option<i32> {
+    let rest = file_name
+        .as_bytes()
+        .strip_prefix(TEMP_FILE_PREFIX.as_bytes())?;
+    let pid = rest.split(|&byte| byte == b'-').next()?;
+
+    std::str::from_utf8(pid).ok()?.parse().ok()
+}
+
+/// The prefix of the temporary files made by this process
+fn temp_file_prefix() -> String {
+    format!("{}{}-", TEMP_FILE_PREFIX, std::process::id())
+}
+
+/// Makes a temporary file in the directory `dir`, removed once dropped.
+pub fn temp_file_in(dir: &Path) -> io::Result<NamedTempFile> {
+    tempfile::Builder::new()
+        .prefix(&temp_file_prefix())
+        .tempfile_in(dir)
+}
+
+/// Makes a temporary directory in the directory `dir`, removed along with its contents once dropped.
+pub fn temp_dir_in(dir: &Path) -> io::Result<TempDir> {
+    tempfile::Builder::new()
+        .prefix(&temp_file_prefix())
+        .tempdir_in(dir)
+}
+
+/// A path for a temporary file next to `path`, which doesn't exist yet
+pub fn temp_path_next_to(path: &Path) -> PathBuf {
+    path.with_file_name(temp_file_prefix() + &Uuid::new_v4().to_string())
+}
+
+/// How a file got to where it was sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    /// It was renamed, within a filesystem
+    Renamed,
+    /// It was copied to another filesystem, then removed
+    Copied,
+    /// It was archived (see [`crate::compress`]), then removed
+    Compressed,
+    /// It was copied, then removed, even if it could have been renamed (see [`copy_and_remove_at`])
+    ForcedCopy,
+}
+
+impl Transfer {
+    pub fn name(self) -> &'static str {
+        match self {
+            Transfer::Renamed => "rename",
+            Transfer::Copied => "copy",
+            Transfer::Compressed => "compress",
+            Transfer::ForcedCopy => "forced-copy",
+        }
+    }
+
+    /// How it's told to people, as by `-v`
+    pub fn description(self) -> &'static str {
+        match self {
+            Transfer::Renamed => "renamed",
+            Transfer::Copied => "copied then removed",
+            Transfer::Compressed => "compressed then removed",
+            Transfer::ForcedCopy => "force-copied then removed",
+        }
+    }
+}
+
 /// Tries to rename a file from `from` to `to`.
 ///
-/// If renaming fails, copies the contents of the file to the new path and removes the original source.
-pub fn move_file(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
-    // TODO: add rename to light-fs and switch these arguments to impl AsRef<CStr>
-    if fs::rename(&from, &to).is_err() {
-        // rename(2) failed, likely because the files are in different mount points
+/// If the paths are on different filesystems, copies the contents of the file to the new path and removes the original source.
+/// Security contexts are only copied along when `preserve_context` is set (see [`copy_xattrs`]).
+pub fn move_file(
+    fs: &impl FileSystem,
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    preserve_context: bool,
+) -> Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+
+    match fs.rename(from, to) {
+        Ok(()) => Ok(()),
+        // rename(2) fails with EXDEV when the files are in different mount points
         // or are on separate filesystems.
-        copy_and_remove(from, to)?;
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            log_copy_fallback(from, to);
+            copy_and_remove(fs, from, to, preserve_context)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Like [`move_file`], for the file named `name` in the directory `dir`, which must still be the file `expected` identifies.
+///
+/// Renaming moves whatever `name` is at that point without following it, so a file swapped for a symlink is
+/// never a problem. Copying to another filesystem goes through paths, though: the original is only removed if
+/// it's still `expected` once copied, otherwise the copy is removed instead and this fails with
+/// [`Error::SourceChanged`].
+///
+/// Directories are copied through the copies directory `copies`, if given, so that an interrupted copy can be resumed
+/// (see [`crate::resume`]).
+///
+/// Returns whether the file was renamed or copied.
+pub fn move_file_at(
+    fs: &impl FileSystem,
+    dir: &DirFd,
+    name: &CStr,
+    expected: FileId,
+    to: &Path,
+    preserve_context: bool,
+    copies: Option<&Path>,
+) -> Result<Transfer> {
+    match fs.rename_at(dir, name, to) {
+        Ok(()) => Ok(Transfer::Renamed),
+        // rename(2) fails with EXDEV when the files are in different mount points
+        // or are on separate filesystems.
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            log_copy_fallback(&dir.path().join(OsStr::from_bytes(name.to_bytes())), to);
+            copy_and_remove_at(fs, dir, name, expected, to, preserve_context, copies)?;
+            Ok(Transfer::Copied)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Logs that `from` is copied to `to` since it can't be renamed there
+fn log_copy_fallback(from: &Path, to: &Path) {
+    log::debug(format_args!(
+        "{} can't be renamed to {}, which is on another filesystem, so it's copied there instead",
+        display_path(from),
+        display_path(to)
+    ));
+}
+
+/// What [`move_file_at`] does when the file can't be renamed: copies the file named `name` in the directory `dir`
+/// to `to`, then removes it if it's still the file `expected` identifies. Calling it directly never renames the file,
+/// so that the copy in the trash doesn't share its inode with other links to it (see `--force-copy`).
+///
+/// Once the copy is whole, failing to remove the original is only warned about: the file is in the trash by then,
+/// and its info file must stay for it to be restored.
+pub fn copy_and_remove_at(
+    fs: &impl FileSystem,
+    dir: &DirFd,
+    name: &CStr,
+    expected: FileId,
+    to: &Path,
+    preserve_context: bool,
+    copies: Option<&Path>,
+) -> Result<()> {
+    let from = dir.path().join(OsStr::from_bytes(name.to_bytes()));
+    let manifest = match copies {
+        Some(copies) if fs.lstat_at(dir, name)?.is_directory() => {
+            Some(resume::copy(fs, copies, &from, to, preserve_context)?)
+        }
+        _ => {
+            copy_into(fs, &from, to, preserve_context)?;
+            None
+        }
+    };
+
+    // Checked both through `dir` and through the path, since that's how directories are removed
+    let from_unx: UnixString = from.clone().try_into()?;
+    let current = fs
+        .lstat_at(dir, name)
+        .and_then(|at| Ok((at, fs.lstat(from_unx.as_c_str())?)));
+    let is_directory = match current {
+        Ok((at, by_path)) if at.file_id() == expected && by_path.file_id() == expected => {
+            by_path.is_directory()
+        }
+        current => {
+            // What was copied is not what was meant to be moved
+            remove_recursively(fs, to)?;
+            if let Some(manifest) = &manifest {
+                resume::discard(fs, manifest)?;
+            }
+            return Err(current.err().unwrap_or(Error::SourceChanged(from)));
+        }
+    };
+
+    let removed = if is_directory {
+        fs.remove_dir_all(&from)
+    } else {
+        fs.remove_file_at(dir, name)
+    };
+    if let Err(err) = removed {
+        warn(format_args!(
+            "failed to remove {} after copying it to {}: {}",
+            display_path(&from),
+            display_path(to),
+            err
+        ));
+    }
+    // The file was moved either way, and `--check` reports a manifest left behind
+    if let Some(manifest) = &manifest {
+        let _ = fs.remove_file(manifest);
     }
 
     Ok(())
 }
 
-/// Will copy the contents of `from` into `to`.
+/// Will copy the contents of `from` into `to`, recursively if `from` is a directory, then delete `from`.
+fn copy_and_remove(
+    fs: &impl FileSystem,
+    from: &Path,
+    to: &Path,
+    preserve_context: bool,
+) -> Result<()> {
+    copy_into(fs, from, to, preserve_context)?;
+
+    remove_recursively(fs, from)
+}
+
+/// Will copy the contents of `from` into `to`, recursively if `from` is a directory.
 ///
-/// The file in `from` is then deleted.
-fn copy_and_remove(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
-    let (from, to) = (from.as_ref(), to.as_ref());
-    fs::copy(from, to)?;
-    if from.is_dir() {
-        fs::remove_dir_all(from)?;
+/// The copy is made under a temporary name next to `to` and only renamed to `to` once complete,
+/// so a copy interrupted by `tt` dying is recognizable as a leftover.
+/// If copying fails midway, whatever was already copied is removed and `from` is left untouched.
+fn copy_into(fs: &impl FileSystem, from: &Path, to: &Path, preserve_context: bool) -> Result<()> {
+    let temp = temp_path_next_to(to);
+
+    if let Err(err) = copy_recursively(fs, from, &temp, preserve_context)
+        .and_then(|()| rename_copy(fs, from, &temp, to))
+    {
+        // Don't leave a partial copy behind
+        if temp.symlink_metadata().is_ok() {
+            let _ = remove_recursively(fs, &temp);
+        }
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// Renames `temp`, the finished copy of `from`, to `to`.
+///
+/// What's inside a directory is copied through descriptors (see [`copy_contents_at`]), but renaming it into place
+/// goes through paths: should `to` be too long to be resolved, this fails with [`Error::PathTooLong`] rather than
+/// with a bare `ENAMETOOLONG`.
+pub fn rename_copy(fs: &impl FileSystem, from: &Path, temp: &Path, to: &Path) -> Result<()> {
+    match fs.rename(temp, to) {
+        Err(err) if err.raw_os_error() == Some(libc::ENAMETOOLONG) => {
+            Err(Error::PathTooLong(from.to_owned()))
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Copies `from` into `to`. Directories are copied recursively and symlinks are copied as symlinks.
+/// Extended attributes are copied along (see [`copy_xattrs`]).
+fn copy_recursively(
+    fs: &impl FileSystem,
+    from: &Path,
+    to: &Path,
+    preserve_context: bool,
+) -> Result<()> {
+    let metadata = fs_err::symlink_metadata(from)?;
+    let file_type = metadata.file_type();
+
+    if file_type.is_symlink() {
+        let target = fs_err::read_link(from)?;
+        fs.symlink(&target, to)?;
+        copy_xattrs(from, to, preserve_context)?;
+    } else if file_type.is_dir() {
+        fs.create_dir(to)?;
+        copy_contents_at(fs, &DirFd::open(from)?, &DirFd::open(to)?, preserve_context)?;
+        copy_xattrs(from, to, preserve_context)?;
+        // Done last in case the directory isn't writable
+        fs::set_permissions(to, metadata.permissions())?;
     } else {
-        fs::remove_file(from)?;
+        stats::copied(fs.copy_file(from, to)?);
+        copy_xattrs(from, to, preserve_context)?;
+    }
+
+    Ok(())
+}
+
+/// Copies what the directory `from` holds into the directory `to`, like [`copy_recursively`].
+///
+/// Every file is reached relative to the directory it's in, so trees deeper than `PATH_MAX` are copied just like
+/// any other, as long as there are descriptors enough to hold two per level open.
+fn copy_contents_at(
+    fs: &impl FileSystem,
+    from: &DirFd,
+    to: &DirFd,
+    preserve_context: bool,
+) -> Result<()> {
+    for name in from.entries()? {
+        let stat = Lstat::lstat_at(from, &name)?;
+
+        if stat.is_directory() {
+            fs.create_dir_at(to, &name)?;
+            let copy = to.open_at(&name)?;
+            copy_contents_at(fs, &from.open_at(&name)?, &copy, preserve_context)?;
+            copy_xattrs_at(from, to, &name, preserve_context)?;
+            // Done last in case the directory isn't writable
+            copy.set_mode(stat.mode())?;
+        } else if stat.is_symlink() {
+            fs.symlink_at(&from.read_link_at(&name)?, to, &name)?;
+            copy_xattrs_at(from, to, &name, preserve_context)?;
+        } else {
+            stats::copied(fs.copy_file_at(from, &name, to, &name)?);
+            copy_xattrs_at(from, to, &name, preserve_context)?;
+        }
     }
 
     Ok(())
 }
 
-/// Makes a temporary copy of `$trash/directorysizes`.
+/// The security attributes worth trying to copy even when they aren't listed, since unprivileged
+/// processes may not be allowed to list them
+const SECURITY_CONTEXTS: [&CStr; 2] = [cstr!("security.selinux"), cstr!("security.capability")];
+
+/// Copies the extended attributes of `from` onto `to`, so that ACLs, SELinux labels, file capabilities
+/// and `user.*` attributes survive being copied to another filesystem.
+///
+/// `security.*` attributes, such as SELinux contexts, are only copied when `preserve_context` is set.
+/// Those, just like attributes the destination filesystem doesn't support, often can't be copied without
+/// privileges: such failures are silently ignored.
+pub fn copy_xattrs(from: &Path, to: &Path, preserve_context: bool) -> Result<()> {
+    let from = UnixString::try_from(from.to_owned())?;
+    let to = UnixString::try_from(to.to_owned())?;
+
+    copy_xattrs_of(
+        XattrFile::Path(from.as_c_str()),
+        XattrFile::Path(to.as_c_str()),
+        preserve_context,
+    )
+}
+
+/// Like [`copy_xattrs`], for the file named `name` in the directory `from` and its copy in the directory `to`.
+///
+/// Both are opened to be reached, whatever the length of their paths. Symlinks can't be, so theirs are reached through
+/// their paths, and skipped should those be too long to be resolved: nothing is lost but a security context, the only
+/// kind of attribute symlinks can have.
+pub fn copy_xattrs_at(from: &DirFd, to: &DirFd, name: &CStr, preserve_context: bool) -> Result<()> {
+    // Without blocking on FIFOs
+    let flags = libc::O_RDONLY | libc::O_NONBLOCK;
+    let source = match from.open_file_at(name, flags, 0) {
+        Ok(source) => source,
+        Err(err) if err.raw_os_error() == Some(libc::ELOOP) => {
+            let name = OsStr::from_bytes(name.to_bytes());
+            return match copy_xattrs(
+                &from.path().join(name),
+                &to.path().join(name),
+                preserve_context,
+            ) {
+                Err(Error::Io(err)) if err.raw_os_error() == Some(libc::ENAMETOOLONG) => Ok(()),
+                result => result,
+            };
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let copy = to.open_file_at(name, flags, 0)?;
+
+    copy_xattrs_of(
+        XattrFile::Fd(source.as_fd()),
+        XattrFile::Fd(copy.as_fd()),
+        preserve_context,
+    )
+}
+
+/// Does what [`copy_xattrs`] does, for files however they're reached
+fn copy_xattrs_of(from: XattrFile, to: XattrFile, preserve_context: bool) -> Result<()> {
+    /// The errors meaning an attribute can't be copied, rather than that copying went wrong
+    fn is_unsupported(err: &io::Error) -> bool {
+        matches!(
+            err.raw_os_error(),
+            Some(libc::EPERM | libc::EACCES | libc::EOPNOTSUPP | libc::ENODATA)
+        )
+    }
+
+    let mut names = match ffi::list_xattrs(from) {
+        Ok(names) => names,
+        Err(err) if is_unsupported(&err) => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+    if preserve_context {
+        for name in SECURITY_CONTEXTS {
+            if !names.iter().any(|listed| listed.as_c_str() == name) {
+                names.push(name.to_owned());
+            }
+        }
+    } else {
+        names.retain(|name| !name.to_bytes().starts_with(b"security."));
+    }
+
+    for name in names {
+        let value = match ffi::get_xattr(from, &name) {
+            Ok(value) => value,
+            Err(err) if is_unsupported(&err) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        match ffi::set_xattr(to, &name, &value) {
+            Err(err) if !is_unsupported(&err) => return Err(err.into()),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Explains why renaming or removing `path` failed with `err` when its attributes (or those of its parent directory)
+/// are to blame, since immutable and append-only files can't be renamed or removed, not even by root, in a way that's
+/// easily mistaken for a lack of permissions. Any other error is given back as is, and so is `err` when the attributes
+/// can't be read.
+pub fn explain_denial(fs: &impl FileSystem, path: &Path, err: Error) -> Error {
+    match &err {
+        Error::Io(io_err) if io_err.kind() == io::ErrorKind::PermissionDenied => {}
+        _ => return err,
+    }
+
+    for candidate in [path].into_iter().chain(path.parent()) {
+        match fs.inode_flags(candidate) {
+            Ok(flags) if flags.is_immutable() => return Error::Immutable(candidate.to_owned()),
+            Ok(flags) if flags.is_append_only() => return Error::AppendOnly(candidate.to_owned()),
+            _ => {}
+        }
+    }
+
+    err
+}
+
+/// Fails as `rm` does if `path` is a directory (symlinks are not followed) that may not be trashed: with `empty`, as
+/// with `-d`, empty directories may be, and with `recursive`, as with `-r`, any may be.
+///
+/// Only the first entry of the directory is read to tell whether it's empty, however big it is.
+pub fn check_directory_operand(path: &Path, empty: bool, recursive: bool) -> Result<()> {
+    if recursive || !path.symlink_metadata()?.is_dir() {
+        return Ok(());
+    }
+    if !empty {
+        return Err(Error::IsADirectory(path.to_owned()));
+    }
+    if fs::read_dir(path)?.next().is_some() {
+        return Err(Error::DirectoryNotEmpty(path.to_owned()));
+    }
+
+    Ok(())
+}
+
+/// Removes the file given by `path`, recursively if it's a directory (symlinks are not followed).
+pub fn remove_recursively(fs: &impl FileSystem, path: &Path) -> Result<()> {
+    if path.symlink_metadata()?.is_dir() {
+        fs.remove_dir_all(path)?;
+    } else {
+        fs.remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Makes a temporary copy of `$trash/directorysizes`, in the root of the trash so that it's never
+/// mistaken for a trashed file.
 pub fn copy_directorysizes(path: &Trash) -> Result<NamedTempFile> {
-    let temp = NamedTempFile::new_in(path.files.as_path())?;
+    let temp = temp_file_in(path.root.as_path())?;
 
     // Copy the directorysizes to our new path
     fs::copy(path.directory_sizes.as_path(), temp.path())?;
@@ -67,28 +675,179 @@ pub fn copy_directorysizes(path: &Trash) -> Result<NamedTempFile> {
     Ok(temp)
 }
 
-/// Scans a directory recursively adding up the total of bytes it contains.
+/// Gives the group `gid` ownership of `path` (and of everything under it, if it's a directory),
+/// also making it readable and writable by that group.
 ///
-/// Symlinks found are not followed.
-pub fn directory_size(path: UnixString) -> Result<u64> {
-    let mut size = 0;
+/// Used when trashing into a shared (setgid) trash directory, so that the other members
+/// of its group are able to restore or remove the entries we create.
+pub fn share_with_group(path: &Path, gid: u32) -> Result<()> {
+    // WalkDir does not follow symlinks by default
+    for entry in WalkDir::new(path) {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let unx: UnixString = entry.path().to_owned().try_into()?;
+
+        // We may not be a member of the trash's group (e.g. a sticky, world-writable trash).
+        // The group-writable permissions still apply then, so this isn't fatal.
+        let _ = ffi::lchown(&unx, None, Some(gid));
+
+        // The permission bits of symlinks are meaningless
+        if entry.path_is_symlink() {
+            continue;
+        }
+
+        let mode = Lstat::lstat(&unx)?.mode() & 0o7777;
+        // Directories must also be searchable by the group
+        let group_bits = if entry.file_type().is_dir() {
+            0o070
+        } else {
+            0o060
+        };
+        fs::set_permissions(entry.path(), Permissions::from_mode(mode | group_bits))?;
+    }
+
+    Ok(())
+}
+
+/// Resolves the `.` and `..` components of `path` without looking at the filesystem, dropping trailing slashes along
+/// the way. `..` at the root stays at the root.
+pub fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// The absolute path of the file the operand `path` names, which is what gets trashed: the directory it's in is
+/// canonicalized, but not its last component, so that a symlink is trashed itself rather than what it points to, as
+/// `rm` removes the link.
+///
+/// Operands whose last component is `.` or `..` are refused, as `rm` refuses them, and those that don't exist fail with
+/// [`Error::OperandNotFound`] (a dangling symlink exists, as it can be trashed).
+pub fn resolve_operand(path: &Path) -> Result<PathBuf> {
+    let last = path
+        .as_os_str()
+        .as_bytes()
+        .split(|&byte| byte == b'/')
+        .rev()
+        .find(|component| !component.is_empty());
+    if matches!(last, Some(b"." | b"..")) {
+        return Err(Error::DotOperand(path.to_owned()));
+    }
+    if let Err(err) = path.symlink_metadata() {
+        if err.kind() == io::ErrorKind::NotFound {
+            return Err(Error::OperandNotFound(path.to_owned()));
+        }
+    }
 
-    let lstat_size = |path: &UnixString| -> crate::Result<u64> { Ok(Lstat::lstat(path)?.size()) };
+    let absolute = if path.is_absolute() {
+        path.to_owned()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+
+    match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => Ok(fs::canonicalize(parent)?.join(name)),
+        // The root directory
+        _ => Ok(fs::canonicalize(&absolute)?),
+    }
+}
+
+/// Makes `path` absolute and normalized, so that it can be compared to the paths in info files.
+///
+/// Symlinks in the longest existing ancestor of `path` are resolved (as they were when the file was trashed),
+/// while `.` and `..` components are resolved lexically, since the rest of the path might not exist.
+pub fn normalize_path(path: &Path) -> Result<PathBuf> {
+    let absolute = if path.is_absolute() {
+        path.to_owned()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+    let normalized = normalize_lexically(&absolute);
+
+    let mut missing = Vec::new();
+    let mut existing = normalized.as_path();
+
+    loop {
+        if let Ok(mut canonical) = existing.canonicalize() {
+            canonical.extend(missing.iter().rev());
+            return Ok(canonical);
+        }
 
-    if path_is_directory(&path) {
-        for entry in fs::read_dir(&path)? {
-            let entry: UnixString = entry?.path().try_into()?;
-            if path_is_regular_file(&entry) {
-                size += lstat_size(&entry)?;
-            } else if path_is_directory(&entry) {
-                size += directory_size(entry)?;
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(file_name)) => {
+                missing.push(file_name);
+                existing = parent;
             }
+            _ => return Ok(normalized),
         }
+    }
+}
+
+/// What a directory holds, as found by [`directory_stats`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DirectoryStats {
+    /// The total of bytes of its files
+    pub size: u64,
+    /// How many files, directories and symlinks it holds, at any depth
+    pub entries: u64,
+}
+
+/// Scans a directory recursively adding up the total of bytes it contains.
+///
+/// Symlinks found are not followed, and files hard linked more than once in the directory are only counted once.
+pub fn directory_size(path: UnixString) -> Result<u64> {
+    directory_stats(path).map(|stats| stats.size)
+}
+
+/// Does what [`directory_size`] does, counting what the directory holds along the way
+///
+/// The directory is walked through descriptors, so trees deeper than `PATH_MAX` are measured too.
+pub fn directory_stats(path: UnixString) -> Result<DirectoryStats> {
+    let mut stats = DirectoryStats::default();
+    let mut seen = HashSet::new();
+
+    let lstat = Lstat::lstat(&path)?;
+    if lstat.is_directory() {
+        add_stats_at(&DirFd::open(path.as_path())?, &mut seen, &mut stats)?;
     } else {
-        size = lstat_size(&path)?;
+        stats.size += size_once(&lstat, &mut seen);
+    }
+
+    Ok(stats)
+}
+
+/// Adds what the directory `dir` holds to `stats`, skipping the files in `seen`, to which the files with several links
+/// are added
+fn add_stats_at(dir: &DirFd, seen: &mut HashSet<FileId>, stats: &mut DirectoryStats) -> Result<()> {
+    for name in dir.entries()? {
+        let lstat = Lstat::lstat_at(dir, &name)?;
+        stats.entries += 1;
+        if lstat.is_regular_file() {
+            stats.size += size_once(&lstat, seen);
+        } else if lstat.is_directory() {
+            add_stats_at(&dir.open_at(&name)?, seen, stats)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The size of the file `lstat` describes, unless it's another link to a file in `seen`
+fn size_once(lstat: &Lstat, seen: &mut HashSet<FileId>) -> u64 {
+    // Another link to a file that was counted already takes up no more space
+    if lstat.nlink() > 1 && !seen.insert(lstat.file_id()) {
+        return 0;
     }
 
-    Ok(size)
+    lstat.size()
 }
 
 #[cfg(test)]
@@ -99,9 +858,16 @@ mod tests {
 
     use unixstring::UnixString;
 
-    use crate::ffi::Lstat;
-    use crate::fs::{copy_and_remove, move_file};
-    use crate::tests::dummy_bytes;
+    use cstr::cstr;
+
+    use crate::ffi::{get_xattr, set_xattr, DirFd, Lstat, XattrFile};
+    use crate::fs::{
+        check_directory_operand, copy_and_remove, directory_size, directory_stats, move_file,
+        normalize_path, resolve_operand, DirectoryStats, RealFs,
+    };
+    use crate::resume;
+    use crate::tests::{dummy_bytes, FaultyFs, Op};
+    use crate::Error;
 
     #[test]
     fn test_clone_and_delete() {
@@ -122,7 +888,7 @@ mod tests {
         let new_path: UnixString = dir_path.join("moved_dummy").try_into().unwrap();
         // There shouldn't be anything here yet
         assert!(!new_path.as_path().exists());
-        copy_and_remove(file_path.as_path(), new_path.as_path()).unwrap();
+        copy_and_remove(&RealFs, file_path.as_path(), new_path.as_path(), true).unwrap();
 
         // This file shouldn't exist anymore!
         assert!(!file_path.as_path().exists());
@@ -160,7 +926,7 @@ mod tests {
         let new_path: UnixString = dir_path.join("moved_dummy").try_into().unwrap();
         // There shouldn't be anything here yet
         assert!(!new_path.as_path().exists());
-        move_file(&file_path, &new_path).unwrap();
+        move_file(&RealFs, &file_path, &new_path, true).unwrap();
 
         // This file shouldn't exist anymore!
         assert!(!file_path.as_path().exists());
@@ -178,4 +944,293 @@ mod tests {
 
         assert_eq!(prev_stat.accessed(), new_stat.accessed());
     }
+
+    #[test]
+    fn copies_directories_across_filesystems() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        std::fs::create_dir_all(source.join("nested")).unwrap();
+        File::create(source.join("nested/dummy"))
+            .unwrap()
+            .write_all(&dummy_bytes())
+            .unwrap();
+        std::os::unix::fs::symlink("nested/dummy", source.join("link")).unwrap();
+
+        let destination = dir.path().join("destination");
+        // Pretend that the paths are on different filesystems
+        let fs = FaultyFs::failing(Op::Rename, 1, libc::EXDEV);
+        move_file(&fs, &source, &destination, true).unwrap();
+
+        assert!(!source.exists());
+        assert!(destination.join("nested/dummy").is_file());
+        assert_eq!(
+            std::fs::read_link(destination.join("link")).unwrap(),
+            std::path::Path::new("nested/dummy")
+        );
+    }
+
+    #[test]
+    fn copies_extended_attributes_across_filesystems() -> crate::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("source");
+        std::fs::create_dir(&source)?;
+        let file = UnixString::try_from(source.join("dummy"))?;
+        std::fs::write(file.as_path(), "dummy")?;
+        let source_unx = UnixString::try_from(source.clone())?;
+
+        for path in [&source_unx, &file] {
+            if let Err(err) = set_xattr(
+                XattrFile::Path(path.as_c_str()),
+                cstr!("user.tt.test"),
+                b"dummy",
+            ) {
+                // Not every filesystem has them
+                assert_eq!(err.raw_os_error(), Some(libc::EOPNOTSUPP));
+                return Ok(());
+            }
+        }
+
+        // With or without security contexts, which are out of reach here unless privileged or on SELinux
+        for preserve_context in [true, false] {
+            let destination = dir.path().join(format!("destination-{preserve_context}"));
+            // Pretend that the paths are on different filesystems
+            let fs = FaultyFs::failing(Op::Rename, 1, libc::EXDEV);
+            move_file(&fs, &source, &destination, preserve_context)?;
+
+            for path in [destination.clone(), destination.join("dummy")] {
+                let unx = UnixString::try_from(path)?;
+                assert_eq!(
+                    get_xattr(XattrFile::Path(unx.as_c_str()), cstr!("user.tt.test"))?,
+                    b"dummy"
+                );
+            }
+
+            move_file(&RealFs, &destination, &source, true)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_copy_cleans_up_partial_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        std::fs::create_dir(&source).unwrap();
+        for name in ["first", "second", "third"] {
+            File::create(source.join(name))
+                .unwrap()
+                .write_all(&dummy_bytes())
+                .unwrap();
+        }
+
+        let destination = dir.path().join("destination");
+        let fs = FaultyFs::failing(Op::Rename, 1, libc::EXDEV).and(Op::Copy, 2, libc::ENOSPC);
+
+        let err = move_file(&fs, &source, &destination, true).unwrap_err();
+        assert!(matches!(err, crate::Error::Io(err) if err.raw_os_error() == Some(libc::ENOSPC)));
+
+        // The partial copy was removed and the source is intact
+        assert!(!destination.exists());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        assert_eq!(std::fs::read_dir(&source).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn only_falls_back_to_copying_across_filesystems() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        File::create(&source).unwrap();
+        let destination = dir.path().join("destination");
+
+        let fs = FaultyFs::failing(Op::Rename, 1, libc::EACCES);
+        assert!(move_file(&fs, &source, &destination, true).is_err());
+
+        assert!(source.exists());
+        assert!(!destination.exists());
+        assert_eq!(fs.calls(Op::Copy), 0);
+    }
+
+    #[test]
+    fn counts_hard_links_once() -> crate::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trashed = dir.path().join("trashed");
+        std::fs::create_dir_all(trashed.join("nested"))?;
+        std::fs::write(trashed.join("large"), vec![0; 1 << 20])?;
+        std::fs::hard_link(trashed.join("large"), trashed.join("nested/large"))?;
+        // Links from outside don't matter, the file is counted once either way
+        std::fs::hard_link(trashed.join("large"), dir.path().join("large"))?;
+        std::fs::write(trashed.join("small"), "dummy")?;
+
+        let large = UnixString::try_from(trashed.join("large"))?;
+        assert_eq!(Lstat::lstat(&large)?.nlink(), 3);
+
+        assert_eq!(directory_size(trashed.clone().try_into()?)?, (1 << 20) + 5);
+        assert_eq!(directory_size(large)?, 1 << 20);
+        // Every name counts as an entry, though
+        assert_eq!(
+            directory_stats(trashed.try_into()?)?,
+            DirectoryStats {
+                size: (1 << 20) + 5,
+                entries: 4
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn normalizes_paths() -> crate::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let canonical_dir = dir.path().canonicalize()?;
+        std::fs::create_dir(dir.path().join("existing"))?;
+        std::os::unix::fs::symlink(dir.path().join("existing"), dir.path().join("link"))?;
+
+        assert_eq!(
+            normalize_path(&dir.path().join("existing/./missing/../file"))?,
+            canonical_dir.join("existing/file")
+        );
+        assert_eq!(
+            normalize_path(&dir.path().join("link/missing/file"))?,
+            canonical_dir.join("existing/missing/file")
+        );
+
+        let relative = normalize_path("some-relative-file".as_ref())?;
+        assert!(relative.is_absolute());
+        assert!(relative.ends_with("some-relative-file"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_operands_to_symlinks_rather_than_their_targets() -> crate::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let canonical_dir = dir.path().canonicalize()?;
+        let file = dir.path().join("file");
+        File::create(&file)?;
+        let project = dir.path().join("project");
+        std::fs::create_dir(&project)?;
+        File::create(project.join("file"))?;
+        std::os::unix::fs::symlink(&file, dir.path().join("file-link"))?;
+        std::os::unix::fs::symlink(&project, dir.path().join("project-link"))?;
+        std::os::unix::fs::symlink(dir.path().join("gone"), dir.path().join("dangling"))?;
+
+        for link in ["file-link", "project-link", "dangling"] {
+            let resolved = resolve_operand(&dir.path().join(link))?;
+            assert_eq!(resolved, canonical_dir.join(link));
+            assert!(resolved.symlink_metadata()?.is_symlink());
+            // Without -r, as rm removes a link to a directory
+            check_directory_operand(&resolved, false, false)?;
+        }
+        // Directories on the way are resolved all the same
+        assert_eq!(
+            resolve_operand(&dir.path().join("project-link/file"))?,
+            canonical_dir.join("project/file")
+        );
+        assert_eq!(
+            resolve_operand(&dir.path().join("project-link/"))?,
+            canonical_dir.join("project-link")
+        );
+
+        for operand in [".", "..", "project/..", "project/./", "../."] {
+            assert!(
+                matches!(resolve_operand(operand.as_ref()), Err(Error::DotOperand(_))),
+                "{operand}"
+            );
+        }
+        let missing = dir.path().join("missing/file");
+        assert!(matches!(
+            resolve_operand(&missing),
+            Err(Error::OperandNotFound(path)) if path == missing
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_directories_as_rm_does() -> crate::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        File::create(&file)?;
+        let empty = dir.path().join("empty");
+        std::fs::create_dir(&empty)?;
+        let full = dir.path().join("full");
+        std::fs::create_dir(&full)?;
+        File::create(full.join("file"))?;
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&full, &link)?;
+
+        // Files, and symlinks to directories, are always fine
+        for path in [&file, &link] {
+            check_directory_operand(path, false, false)?;
+        }
+        for path in [&empty, &full] {
+            assert!(matches!(
+                check_directory_operand(path, false, false),
+                Err(Error::IsADirectory(refused)) if refused == *path
+            ));
+            check_directory_operand(path, false, true)?;
+        }
+        check_directory_operand(&empty, true, false)?;
+        assert!(matches!(
+            check_directory_operand(&full, true, false),
+            Err(Error::DirectoryNotEmpty(refused)) if refused == full
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn handles_trees_deeper_than_path_max() -> crate::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let name = std::ffi::CString::new(vec![b'd'; 250]).unwrap();
+        // 20 levels of 250 bytes each, deeper than PATH_MAX
+        let depth = 20;
+        assert!(depth * 251 > libc::PATH_MAX as usize);
+
+        // Built level by level, since the full path can't be resolved
+        let source = dir.path().join("node_modules");
+        std::fs::create_dir(&source)?;
+        let mut level = DirFd::open(&source)?;
+        for _ in 0..depth {
+            level.create_dir_at(&name, 0o755)?;
+            level = level.open_at(&name)?;
+        }
+        level
+            .open_file_at(cstr!("index.js"), libc::O_WRONLY | libc::O_CREAT, 0o644)?
+            .write_all(b"dummy")?;
+
+        let stats = DirectoryStats {
+            size: 5,
+            entries: depth as u64 + 1,
+        };
+        assert_eq!(directory_stats(source.clone().try_into()?)?, stats);
+
+        // Copied rather than renamed, as across filesystems
+        let destination = dir.path().join("moved");
+        move_file(
+            &FaultyFs::failing(Op::Rename, 1, libc::EXDEV),
+            &source,
+            &destination,
+            true,
+        )?;
+        assert!(!source.exists());
+        assert_eq!(directory_stats(destination.clone().try_into()?)?, stats);
+
+        // And so is it when the copy can be resumed
+        let copies = dir.path().join("copies");
+        let back = dir.path().join("back");
+        resume::copy(&RealFs, &copies, &destination, &back, true)?;
+        let mut level = DirFd::open(&back)?;
+        for _ in 0..depth {
+            level = level.open_at(&name)?;
+        }
+        let mut contents = String::new();
+        std::io::Read::read_to_string(
+            &mut level.open_file_at(cstr!("index.js"), libc::O_RDONLY, 0)?,
+            &mut contents,
+        )?;
+        assert_eq!(contents, "dummy");
+
+        Ok(())
+    }
 }
@@ -0,0 +1,104 @@
+//! Glob patterns, matched against bytes so that names that aren't valid UTF-8 match like any other.
+//!
+//! `*` matches any sequence of bytes within a component, and `?` matches a single byte. Against paths, patterns are
+//! matched component by component: a `**` component matches any number of components, including none, and patterns
+//! that don't start with `/` can match at any depth, as if they started with `**/`.
+
+use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+/// Whether `text` holds wildcards, so that it's taken as a pattern rather than as a name
+pub fn is_pattern(text: &OsStr) -> bool {
+    text.as_bytes().iter().any(|byte| b"*?".contains(byte))
+}
+
+/// Whether `name`, a single component, matches `pattern`
+pub fn matches_name(pattern: &OsStr, name: &OsStr) -> bool {
+    matches_component(pattern.as_bytes(), name.as_bytes())
+}
+
+/// Whether `path` matches `pattern`, relative patterns matching at any depth
+pub fn matches_path(pattern: &Path, path: &Path) -> bool {
+    let pattern = components(pattern);
+    let path = components(path);
+
+    if pattern.first() == Some(&&b""[..]) {
+        matches(&pattern, &path)
+    } else {
+        let anywhere: Vec<&[u8]> = [&b"**"[..]].into_iter().chain(pattern).collect();
+        matches(&anywhere, &path)
+    }
+}
+
+/// The components of `path`, as separated by `/`. Absolute paths start with an empty component.
+pub fn components(path: &Path) -> Vec<&[u8]> {
+    let bytes = path.as_os_str().as_bytes();
+    let bytes = bytes
+        .strip_suffix(b"/")
+        .filter(|_| bytes.len() > 1)
+        .unwrap_or(bytes);
+
+    if bytes == b"/" {
+        return vec![b""];
+    }
+
+    bytes.split(|&byte| byte == b'/').collect()
+}
+
+/// Whether the components of `path` match the components of `pattern`
+pub fn matches(pattern: &[&[u8]], path: &[&[u8]]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&b"**", rest)) => (0..=path.len()).any(|skipped| matches(rest, &path[skipped..])),
+        Some((glob, rest)) => path.split_first().is_some_and(|(component, path)| {
+            matches_component(glob, component) && matches(rest, path)
+        }),
+    }
+}
+
+/// Whether `name` matches `glob`, in which `*` matches any sequence of bytes and `?` any single byte
+fn matches_component(glob: &[u8], name: &[u8]) -> bool {
+    match glob.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => {
+            (0..=name.len()).any(|skipped| matches_component(rest, &name[skipped..]))
+        }
+        Some((b'?', rest)) => !name.is_empty() && matches_component(rest, &name[1..]),
+        Some((byte, rest)) => name.first() == Some(byte) && matches_component(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+    use super::{is_pattern, matches_name, matches_path};
+
+    #[test]
+    fn matches_names_and_paths() {
+        assert!(is_pattern("target*".as_ref()));
+        assert!(is_pattern("file?.txt".as_ref()));
+        assert!(!is_pattern("notes.txt".as_ref()));
+
+        let name = |bytes: &[u8]| OsStr::from_bytes(bytes).to_owned();
+        assert!(matches_name("target*".as_ref(), "target".as_ref()));
+        assert!(matches_name("target*".as_ref(), "target.2".as_ref()));
+        assert!(!matches_name("target*".as_ref(), "my-target".as_ref()));
+        assert!(matches_name("*".as_ref(), &name(b"caf\xe9")));
+        assert!(matches_name("caf?".as_ref(), &name(b"caf\xe9")));
+
+        for (pattern, path, expected) in [
+            ("*.odt", "/home/user/report.odt", true),
+            ("*.odt", "/home/user/report.odt.bak", false),
+            ("docs/*.odt", "/home/user/docs/report.odt", true),
+            ("/home/*/report.odt", "/home/user/report.odt", true),
+            ("/home/*.odt", "/home/user/report.odt", false),
+            ("/home/**", "/home/user/report.odt", true),
+        ] {
+            assert_eq!(
+                matches_path(Path::new(pattern), Path::new(path)),
+                expected,
+                "{pattern} against {path}"
+            );
+        }
+    }
+}
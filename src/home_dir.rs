@@ -1,15 +1,59 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
 
 use unixstring::UnixString;
 
 use crate::error::Result;
 use crate::ffi;
 
-// Attemps to find the calling user's home directory.
-/// Will check for the HOME env. variable first, falling back to
-/// checking passwd if HOME isn't set.
-pub fn home_dir() -> Option<UnixString> {
-    match std::env::var_os("HOME").map(UnixString::from_os_string) {
+/// Overrides the user's home directory. Takes precedence over `HOME`.
+pub const FAKE_HOME_VAR: &str = "TT_FAKE_HOME";
+/// Overrides the location of the home trash. Takes precedence over `XDG_DATA_HOME`.
+pub const TRASH_DIR_VAR: &str = "TT_TRASH_DIR";
+
+/// A snapshot of environment variables.
+///
+/// Allows the home directory and home trash to be located without looking at
+/// the process' actual environment, which is what tests need.
+#[derive(Debug, Default, Clone)]
+pub struct Env {
+    vars: HashMap<OsString, OsString>,
+}
+
+impl Env {
+    /// Snapshots the environment of the current process
+    pub fn current() -> Self {
+        std::env::vars_os().collect()
+    }
+
+    /// Gets the value of the variable `key`, if set
+    pub fn get(&self, key: impl AsRef<OsStr>) -> Option<&OsStr> {
+        self.vars.get(key.as_ref()).map(OsString::as_os_str)
+    }
+}
+
+impl<K: Into<OsString>, V: Into<OsString>> FromIterator<(K, V)> for Env {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let vars = iter
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+
+        Self { vars }
+    }
+}
+
+/// Attempts to find the calling user's home directory with the variables in `env`.
+///
+/// Will check for the TT_FAKE_HOME and HOME env. variables first, falling back to
+/// checking passwd if neither is set.
+pub fn home_dir_from(env: &Env) -> Option<UnixString> {
+    let home = env.get(FAKE_HOME_VAR).or_else(|| env.get("HOME"));
+
+    match home.map(|home| UnixString::from_os_string(home.to_owned())) {
         Some(Ok(unx)) => Some(unx),
         None => ffi::get_home_dir(),
         Some(Err(_)) => panic!("HOME has an interior nul byte"),
@@ -18,10 +62,85 @@ pub fn home_dir() -> Option<UnixString> {
 
 /// XDG claims that the trash directory is located at $XDG_DATA_HOME/Trash.
 /// Since XDG_DATA_HOME is often undefined by distros, we fallback to $HOME/.local/share/Trash
-pub fn home_trash_path(home_dir: impl AsRef<Path>) -> Result<UnixString> {
-    Ok(std::env::var_os("XDG_DATA_HOME")
+///
+/// `TT_TRASH_DIR`, when set in `env`, takes precedence over both.
+pub fn home_trash_path_from(env: &Env, home_dir: impl AsRef<Path>) -> Result<UnixString> {
+    if let Some(trash_dir) = env.get(TRASH_DIR_VAR) {
+        return Ok(PathBuf::from(trash_dir).try_into()?);
+    }
+
+    Ok(env
+        .get("XDG_DATA_HOME")
         .map(PathBuf::from)
+        // Relative ones are invalid, as far as the XDG spec goes
+        .filter(|data_home| data_home.is_absolute())
         .map(|home| home.join("Trash"))
         .unwrap_or_else(|| home_dir.as_ref().join(".local/share/Trash"))
         .try_into()?)
 }
+
+/// Where `tt` keeps its state: `$XDG_STATE_HOME/tt`, falling back to `$HOME/.local/state/tt`.
+pub fn state_dir_from(env: &Env, home_dir: impl AsRef<Path>) -> PathBuf {
+    env.get("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir.as_ref().join(".local/state"))
+        .join("tt")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{home_dir_from, home_trash_path_from, state_dir_from, Env};
+
+    #[test]
+    fn fake_home_overrides_home() {
+        let env: Env = [("HOME", "/home/dummy"), ("TT_FAKE_HOME", "/tmp/fake")]
+            .into_iter()
+            .collect();
+        assert_eq!(home_dir_from(&env).unwrap(), "/tmp/fake");
+
+        let env: Env = [("HOME", "/home/dummy")].into_iter().collect();
+        assert_eq!(home_dir_from(&env).unwrap(), "/home/dummy");
+    }
+
+    #[test]
+    fn home_trash_path_resolution_order() {
+        let home = "/home/dummy";
+
+        let env = Env::default();
+        assert_eq!(
+            home_trash_path_from(&env, home).unwrap(),
+            "/home/dummy/.local/share/Trash"
+        );
+
+        let env: Env = [("XDG_DATA_HOME", "/data")].into_iter().collect();
+        assert_eq!(home_trash_path_from(&env, home).unwrap(), "/data/Trash");
+
+        let env: Env = [("XDG_DATA_HOME", "data")].into_iter().collect();
+        assert_eq!(
+            home_trash_path_from(&env, home).unwrap(),
+            "/home/dummy/.local/share/Trash"
+        );
+
+        let env: Env = [("XDG_DATA_HOME", "/data"), ("TT_TRASH_DIR", "/tmp/trash")]
+            .into_iter()
+            .collect();
+        assert_eq!(home_trash_path_from(&env, home).unwrap(), "/tmp/trash");
+    }
+
+    #[test]
+    fn state_dir_resolution_order() {
+        let home = "/home/dummy";
+
+        let env = Env::default();
+        assert_eq!(
+            state_dir_from(&env, home),
+            std::path::Path::new("/home/dummy/.local/state/tt")
+        );
+
+        let env: Env = [("XDG_STATE_HOME", "/state")].into_iter().collect();
+        assert_eq!(
+            state_dir_from(&env, home),
+            std::path::Path::new("/state/tt")
+        );
+    }
+}
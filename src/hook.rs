@@ -0,0 +1,106 @@
+//! The hook: a shell command run once after files are trashed, set with `hook` in the
+//! configuration file or with `--hook`.
+//!
+//! The hook gets the [report](crate::report) of what was trashed on its stdin, along with
+//! a summary in environment variables.
+
+use std::{
+    io::{ErrorKind, Write},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    error::{Error, Result},
+    report::Trashed,
+};
+
+/// How many files were trashed
+pub const COUNT_VAR: &str = "TT_TRASH_COUNT";
+/// How many bytes the trashed files take up, in total
+pub const BYTES_VAR: &str = "TT_TRASH_BYTES";
+
+/// Runs `command` through `sh -c`, telling it about `trashed`, with its stdout going to `stdout`.
+///
+/// Fails if the command can't be run or doesn't exit successfully.
+pub fn run_hook(command: &str, trashed: &[Trashed], stdout: Stdio) -> Result<()> {
+    let total_size: u64 = trashed.iter().filter_map(|trashed| trashed.size).sum();
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(stdout)
+        .env(COUNT_VAR, trashed.len().to_string())
+        .env(BYTES_VAR, total_size.to_string())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().expect("the hook's stdin is piped");
+    let written = trashed
+        .iter()
+        .try_for_each(|trashed| writeln!(stdin, "{trashed}"));
+    // Lets the hook see the end of its input
+    drop(stdin);
+
+    let status = child.wait()?;
+
+    match written {
+        // Hooks don't have to read their input
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => return Err(err.into()),
+        _ => {}
+    }
+
+    if !status.success() {
+        return Err(Error::HookFailed(status.to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process::Stdio};
+
+    use super::run_hook;
+    use crate::{error::Result, report::Trashed, Error};
+
+    #[test]
+    fn runs_the_hook_with_the_report() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("output");
+
+        let trashed = [
+            Trashed {
+                original_path: "/home/user/a".into(),
+                trashed_path: "/home/user/.local/share/Trash/files/a".into(),
+                size: Some(3),
+            },
+            Trashed {
+                original_path: "/home/user/tab\tb".into(),
+                trashed_path: "/home/user/.local/share/Trash/files/tab\tb".into(),
+                size: Some(4),
+            },
+        ];
+
+        let command = format!(
+            "cat > '{0}'; echo \"$TT_TRASH_COUNT $TT_TRASH_BYTES\" >> '{0}'",
+            output.display()
+        );
+        run_hook(&command, &trashed, Stdio::inherit())?;
+
+        assert_eq!(
+            fs::read_to_string(&output)?,
+            "/home/user/a\t/home/user/.local/share/Trash/files/a\n\
+             /home/user/tab%09b\t/home/user/.local/share/Trash/files/tab%09b\n\
+             2 7\n"
+        );
+
+        // Not reading the report is fine, failing is not
+        run_hook("true", &trashed, Stdio::inherit())?;
+        assert!(matches!(
+            run_hook("exit 3", &trashed, Stdio::inherit()),
+            Err(Error::HookFailed(status)) if status.contains('3')
+        ));
+
+        Ok(())
+    }
+}
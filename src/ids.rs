@@ -0,0 +1,235 @@
+//! The numbers `--list` shows for the entries of a trash, which `--restore-id` takes in place of their names.
+//!
+//! Entries are numbered from 1 in the order they were trashed, oldest first (those without a deletion date coming
+//! before any other, and entries trashed in the same second being ordered by name), so that trashing more files
+//! leaves the numbers of those already there as they were. Removing entries shifts them, though, so `--list` records
+//! how many entries each trash it listed held and when the newest of them was trashed, in
+//! `$XDG_STATE_HOME/tt/last-listing`, and numbers are only taken as long as the trash still matches that.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    date::DateTime,
+    entries::TrashEntry,
+    error::{Error, Result},
+    report::escape_path,
+    trash::Trash,
+};
+
+/// The name of the file in the state directory the last listing is recorded in
+const LISTING_FILE_NAME: &str = "last-listing";
+
+/// The file the last listing is recorded in, given the state directory (see [`crate::home_dir::state_dir_from`])
+pub fn listing_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(LISTING_FILE_NAME)
+}
+
+/// The number of every entry of `entries`, all the entries of a trash, by name
+pub fn numbered(entries: &[TrashEntry]) -> BTreeMap<&OsStr, u64> {
+    let mut ordered: Vec<_> = entries.iter().collect();
+    ordered.sort_by(|entry, other| {
+        (entry.deletion_date, &entry.name).cmp(&(other.deletion_date, &other.name))
+    });
+
+    ordered
+        .into_iter()
+        .zip(1..)
+        .map(|(entry, id)| (entry.name.as_os_str(), id))
+        .collect()
+}
+
+/// What the numbers of the entries of a trash depend on, which tells whether they changed since it was listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub count: usize,
+    /// When the most recently trashed entry was trashed, if any has a deletion date
+    pub newest: Option<DateTime>,
+}
+
+impl Fingerprint {
+    /// The fingerprint of a trash holding `entries`
+    pub fn of(entries: &[TrashEntry]) -> Self {
+        Self {
+            count: entries.len(),
+            newest: entries.iter().filter_map(|entry| entry.deletion_date).max(),
+        }
+    }
+}
+
+/// The fingerprints recorded in the file at `path`, keyed by the escaped root of their trash. A missing or unreadable
+/// file holds none, and so do lines that can't be made sense of.
+fn read_listing(path: &Path) -> BTreeMap<String, Fingerprint> {
+    let text = fs_err::read_to_string(path).unwrap_or_default();
+
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let count = fields.next()?.parse().ok()?;
+            let newest = match fields.next()? {
+                "-" => None,
+                newest => Some(DateTime::parse(newest)?),
+            };
+            let root = fields.next()?;
+            Some((root.to_owned(), Fingerprint { count, newest }))
+        })
+        .collect()
+}
+
+/// The fingerprint of `trash` recorded in the file at `path`, if it was listed
+pub fn recorded(path: &Path, trash: &Trash) -> Option<Fingerprint> {
+    read_listing(path).remove(&escape_path(trash.root.as_path()))
+}
+
+/// Records that `trash` was listed while its fingerprint was `fingerprint` in the file at `path`, along with the
+/// other trashes recorded there, creating its directory if needed
+pub fn record(path: &Path, trash: &Trash, fingerprint: Fingerprint) -> Result<()> {
+    let mut listing = read_listing(path);
+    listing.insert(escape_path(trash.root.as_path()), fingerprint);
+
+    let text: String = listing
+        .iter()
+        .map(|(root, fingerprint)| {
+            let newest = fingerprint
+                .newest
+                .map_or_else(|| "-".to_owned(), |newest| newest.to_string());
+            format!("{}\t{newest}\t{root}\n", fingerprint.count)
+        })
+        .collect();
+    match fs_err::write(path, &text) {
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            if let Some(dir) = path.parent() {
+                fs_err::create_dir_all(dir)?;
+            }
+            fs_err::write(path, &text)?;
+        }
+        result => result?,
+    }
+
+    Ok(())
+}
+
+/// The entry of `entries`, all the entries of `trash`, numbered `id`.
+///
+/// Fails if the trash changed since it was last listed, as recorded in the file at `path`, since the number may
+/// then have been shown for another entry, or if no entry has that number.
+pub fn find<'a>(
+    path: &Path,
+    trash: &Trash,
+    entries: &'a [TrashEntry],
+    id: u64,
+) -> Result<&'a TrashEntry> {
+    if recorded(path, trash) != Some(Fingerprint::of(entries)) {
+        return Err(Error::ListingOutdated(id));
+    }
+
+    let name = numbered(entries)
+        .into_iter()
+        .find_map(|(name, numbered)| (numbered == id).then_some(name))
+        .ok_or(Error::IdNotFound(id))?;
+
+    Ok(entries
+        .iter()
+        .find(|entry| entry.name == name)
+        .expect("numbered entries are among them"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, fs};
+
+    use super::{find, listing_path, numbered, record, recorded, Fingerprint};
+    use crate::{
+        date::DateTime,
+        entries::{entries, TrashEntry},
+        error::{Error, Result},
+        trash::Trash,
+    };
+
+    fn entry(name: &str, deletion_date: Option<&str>) -> TrashEntry {
+        TrashEntry {
+            name: name.into(),
+            raw_path: None,
+            original_path: None,
+            deletion_date: deletion_date.and_then(DateTime::parse),
+            original_size: None,
+        }
+    }
+
+    #[test]
+    fn numbers_entries_oldest_first() {
+        let entries = [
+            entry("b", Some("2024-03-02T09:00:00")),
+            entry("undated", None),
+            entry("c", Some("2024-03-01T10:00:00")),
+            entry("a", Some("2024-03-02T09:00:00")),
+        ];
+        let numbers = numbered(&entries);
+        let ids: Vec<_> = ["undated", "c", "a", "b"]
+            .into_iter()
+            .map(|name| numbers[OsStr::new(name)])
+            .collect();
+        assert_eq!(ids, [1, 2, 3, 4]);
+
+        // Trashing more leaves the others numbered as they were
+        let more = [&entries[..], &[entry("d", Some("2024-03-03T08:00:00"))]].concat();
+        let renumbered = numbered(&more);
+        assert!(numbers.iter().all(|(name, id)| renumbered[name] == *id));
+        assert_eq!(renumbered[OsStr::new("d")], 5);
+    }
+
+    #[test]
+    fn only_takes_numbers_of_an_unchanged_trash() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        let other = Trash::from_root(dir.path().join("other"))?;
+        let path = listing_path(&dir.path().join("state/tt"));
+
+        for (name, date) in [
+            ("old", "2024-01-01T00:00:00"),
+            ("new", "2024-02-01T00:00:00"),
+        ] {
+            fs::write(trash.files.as_path().join(name), name)?;
+            fs::write(
+                trash.info.as_path().join(format!("{name}.trashinfo")),
+                format!("[Trash Info]\nPath=/home/user/{name}\nDeletionDate={date}\n"),
+            )?;
+        }
+        let listed = entries(&trash)?;
+
+        // Never listed
+        assert!(matches!(
+            find(&path, &trash, &listed, 1),
+            Err(Error::ListingOutdated(1))
+        ));
+
+        record(&path, &trash, Fingerprint::of(&listed))?;
+        let none = Fingerprint {
+            count: 0,
+            newest: None,
+        };
+        record(&path, &other, none)?;
+        assert_eq!(recorded(&path, &other), Some(none));
+        assert_eq!(find(&path, &trash, &listed, 1)?.name, "old");
+        assert_eq!(find(&path, &trash, &listed, 2)?.name, "new");
+        assert!(matches!(
+            find(&path, &trash, &listed, 3),
+            Err(Error::IdNotFound(3))
+        ));
+
+        // Once an entry is gone, the numbers may mean something else
+        let rest = &listed[1..];
+        assert!(matches!(
+            find(&path, &trash, rest, 1),
+            Err(Error::ListingOutdated(1))
+        ));
+
+        Ok(())
+    }
+}
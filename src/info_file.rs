@@ -8,16 +8,78 @@
 //!        - The value type for this key is “string”; it SHOULD store the file name as the sequence of bytes produced by the file system, with characters escaped as in URLs (as defined by RFC 2396, section 2).
 //!    * The key “DeletionDate” contains the date and time when the file/directory was trashed. The date and time are to be in the YYYY-MM-DDThh:mm:ss format (see RFC 3339). The time zone should be the user's (or filesystem's) local time. The value type for this key is “string”.
 
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::io::Write;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
 
-use crate::error::Result;
-use crate::ffi;
+use percent_encoding::{percent_decode, percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::error::{Error, Result};
+use crate::ffi::{self, Lstat};
+use crate::fs::FileSystem;
 use crate::trash::Trash;
-use fs_err::File;
 use std::time::Duration;
 
+/// The bytes escaped in the `Path` key: everything but the unreserved characters of RFC 2396 and `/`.
+const PATH_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+/// The contents of an info file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashInfo {
+    /// The original location of the trashed file, already percent-decoded.
+    pub path: PathBuf,
+    /// When the file was trashed, in the YYYY-MM-DDThh:mm:ss format
+    pub deletion_date: Option<String>,
+    /// The permission bits of the trashed file, kept in the non-standard `X-TT-Mode` key
+    pub mode: Option<u32>,
+    /// The user and group IDs of the owner of the trashed file, kept in the non-standard `X-TT-Owner` key
+    pub owner: Option<(u32, u32)>,
+    /// The checksum of the trashed file (see [`crate::checksum`]), kept in the non-standard `X-TT-Checksum` key
+    pub checksum: Option<String>,
+    /// The format the trashed directory was compressed into (see [`crate::compress`]), kept in the non-standard `X-TT-Compressed` key
+    pub compressed: Option<String>,
+    /// The size of the trashed directory before it was compressed, kept in the non-standard `X-TT-Original-Size` key
+    pub original_size: Option<u64>,
+}
+
+impl TrashInfo {
+    /// The info of a file trashed from `original_path` at `deletion_date`, a [`Duration`] starting in UNIX_EPOCH
+    pub fn new(original_path: &Path, deletion_date: Duration) -> Result<Self> {
+        Ok(Self {
+            path: original_path.to_owned(),
+            // The date and time are to be in the YYYY-MM-DDThh:mm:ss format.
+            // The time zone should be the user's (or filesystem's) local time.
+            deletion_date: Some(ffi::format_timestamp(deletion_date)?),
+            mode: None,
+            owner: None,
+            checksum: None,
+            compressed: None,
+            original_size: None,
+        })
+    }
+
+    /// Records the owner and mode of the file being trashed, as given by `stat`, so that restoring can put them back
+    /// even if they're lost on the way (e.g. when copying across filesystems). Symlinks have no mode worth keeping.
+    pub fn with_metadata(self, stat: &Lstat) -> Self {
+        Self {
+            mode: (!stat.is_symlink()).then_some(stat.mode() & 0o7777),
+            owner: Some((stat.owner_user_id(), stat.owner_group_id())),
+            ..self
+        }
+    }
+}
+
 /// Builds the name of the info file for a file being trashed.
 pub fn build_info_file_path(file_name: &OsStr, trash_info_path: &Path) -> PathBuf {
     let mut file_name = file_name.to_owned();
@@ -36,41 +98,163 @@ pub fn build_info_file_path(file_name: &OsStr, trash_info_path: &Path) -> PathBu
 ///        - The value type for this key is “string”; it SHOULD store the file name as the sequence of bytes produced by the file system, with characters escaped as in URLs (as defined by RFC 2396, section 2).
 ///    * The key “DeletionDate” contains the date and time when the file/directory was trashed. The date and time are to be in the YYYY-MM-DDThh:mm:ss format (see RFC 3339). The time zone should be the user's (or filesystem's) local time. The value type for this key is “string”.
 ///
-/// This function writes the info file for the file given by `file_name`, which was originally in `original_path` (before getting trashed).
-///
-/// The trash used is given by `trash`.
+/// This function writes `info` as the info file of the file of `trash` named `file_name`, through `fs`,
+/// failing if it already exists.
 ///
-/// The deletion timestamp is given by `deletion_date`, a [`Duration`] starting in UNIX_EPOCH.
+/// `info.path` is written as is, so it must already be relative to the trash's [base directory](Trash::base_dir)
+/// if it's meant to be. A missing `DeletionDate` is left out. What else is known, such as the owner and mode, goes in `X-TT-` keys after
+/// the standard ones, which other implementations ignore.
 ///
 /// Returns the path of the created info file, if successful.
 pub fn write_info_file(
-    original_path: &Path,
+    fs: &impl FileSystem,
     file_name: &OsStr,
     trash: &Trash,
-    deletion_date: Duration,
+    info: &TrashInfo,
 ) -> Result<PathBuf> {
-    // The date and time are to be in the YYYY-MM-DDThh:mm:ss format.
-    // The time zone should be the user's (or filesystem's) local time.
-    let rfc3339 = ffi::format_timestamp(deletion_date)?;
-
     // The info file is to be built in $trash/info
     let info_path = trash.info_path();
 
     // This file MUST have exactly the same name as the file or directory in $trash/files, plus the extension “.trashinfo”.
     let info_file_path = build_info_file_path(file_name, info_path);
 
-    let mut info_file = File::create(&info_file_path)?;
+    // Never clobber an info file, be it another trashing in progress or one we don't know about
+    let mut info_file = fs.create_new_file(&info_file_path)?;
+
+    // The original path is stored as the bytes produced by the filesystem, escaped as in URLs
+    let encoded_path = percent_encode(info.path.as_os_str().as_bytes(), PATH_ENCODE_SET);
 
     writeln!(info_file, "[Trash Info]")?;
-    // TODO: is this correct when `original_path` isn't valid UTF-8?
-    writeln!(info_file, "Path={}", original_path.display())?;
-    writeln!(info_file, "DeletionDate={}", &rfc3339)?;
+    writeln!(info_file, "Path={}", encoded_path)?;
+    if let Some(deletion_date) = &info.deletion_date {
+        writeln!(info_file, "DeletionDate={}", deletion_date)?;
+    }
+    if let Some(mode) = info.mode {
+        writeln!(info_file, "X-TT-Mode={:04o}", mode)?;
+    }
+    if let Some((uid, gid)) = info.owner {
+        writeln!(info_file, "X-TT-Owner={}:{}", uid, gid)?;
+    }
+    if let Some(checksum) = &info.checksum {
+        writeln!(info_file, "X-TT-Checksum={}", checksum)?;
+    }
+    if let Some(compressed) = &info.compressed {
+        writeln!(info_file, "X-TT-Compressed={}", compressed)?;
+    }
+    if let Some(original_size) = info.original_size {
+        writeln!(info_file, "X-TT-Original-Size={}", original_size)?;
+    }
 
     info_file.sync_all()?;
 
     Ok(info_file_path)
 }
 
+/// Reads the info file at `info_file_path`.
+pub fn read_info_file(info_file_path: &Path) -> Result<TrashInfo> {
+    let contents = fs_err::read(info_file_path)?;
+
+    parse_info_file(&contents).ok_or_else(|| Error::InvalidInfoFile(info_file_path.into()))
+}
+
+/// Parses the contents of an info file.
+///
+/// Returns `None` if the `[Trash Info]` group or its `Path` key are missing. Unknown keys are ignored,
+/// and so are `X-TT-` keys with invalid values.
+pub fn parse_info_file(contents: &[u8]) -> Option<TrashInfo> {
+    let mut lines = contents
+        .split(|&byte| byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        // Blank lines and comments are allowed in desktop entry files
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"));
+
+    if lines.next()? != b"[Trash Info]" {
+        return None;
+    }
+
+    let mut path = None;
+    let mut deletion_date = None;
+    let mut mode = None;
+    let mut owner = None;
+    let mut checksum = None;
+    let mut compressed = None;
+    let mut original_size = None;
+
+    for line in lines {
+        if line.starts_with(b"[") {
+            // The start of another group
+            break;
+        }
+
+        let Some(separator) = line.iter().position(|&byte| byte == b'=') else {
+            continue;
+        };
+        // Whitespace around the `=` is ignored, but trailing whitespace might be part of the value
+        let (key, value) = (
+            line[..separator].trim_ascii(),
+            line[separator + 1..].trim_ascii_start(),
+        );
+
+        match key {
+            b"Path" => {
+                // Implementations disagree on what to escape, so both escaped and raw bytes are accepted.
+                // Malformed escapes are taken literally.
+                let decoded: Vec<u8> = percent_decode(value).collect();
+                path = Some(PathBuf::from(OsString::from_vec(decoded)));
+            }
+            b"DeletionDate" => {
+                deletion_date = Some(String::from_utf8_lossy(value.trim_ascii_end()).into_owned());
+            }
+            b"X-TT-Mode" => {
+                mode = std::str::from_utf8(value.trim_ascii_end())
+                    .ok()
+                    .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+                    .filter(|&mode| mode <= 0o7777);
+            }
+            b"X-TT-Checksum" => {
+                checksum = std::str::from_utf8(value.trim_ascii_end())
+                    .ok()
+                    .filter(|checksum| !checksum.is_empty())
+                    .map(str::to_owned);
+            }
+            b"X-TT-Compressed" => {
+                compressed = std::str::from_utf8(value.trim_ascii_end())
+                    .ok()
+                    .filter(|format| !format.is_empty())
+                    .map(str::to_owned);
+            }
+            b"X-TT-Original-Size" => {
+                original_size = std::str::from_utf8(value.trim_ascii_end())
+                    .ok()
+                    .and_then(|size| size.parse().ok());
+            }
+            b"X-TT-Owner" => {
+                let parse_id = |id: &[u8]| std::str::from_utf8(id).ok()?.parse::<u32>().ok();
+                owner = value
+                    .trim_ascii_end()
+                    .split(|&byte| byte == b':')
+                    .map(parse_id)
+                    .collect::<Option<Vec<_>>>()
+                    .and_then(|ids| match ids[..] {
+                        [uid, gid] => Some((uid, gid)),
+                        _ => None,
+                    });
+            }
+            _ => {}
+        }
+    }
+
+    Some(TrashInfo {
+        path: path?,
+        deletion_date,
+        mode,
+        owner,
+        checksum,
+        compressed,
+        original_size,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -78,13 +262,15 @@ mod tests {
         fs::{self, File},
         io::Write,
         path::Path,
-        time::{SystemTime, UNIX_EPOCH},
+        time::{Duration, SystemTime, UNIX_EPOCH},
     };
 
     use crate::{
         ffi,
-        home_dir::home_dir,
-        info_file::{build_info_file_path, write_info_file},
+        fs::RealFs,
+        info_file::{
+            build_info_file_path, parse_info_file, read_info_file, write_info_file, TrashInfo,
+        },
         tests::dummy_bytes,
         trash::Trash,
     };
@@ -102,8 +288,7 @@ mod tests {
 
     #[test]
     fn builds_and_writes_info_file_correctly() {
-        let home_dir = home_dir().unwrap();
-        let dir = tempfile::tempdir_in(&home_dir).unwrap();
+        let dir = tempfile::tempdir().unwrap();
         let dir_path = dir.path();
         let trash = Trash::from_root(dir_path).unwrap();
 
@@ -117,7 +302,8 @@ mod tests {
 
         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
 
-        write_info_file(&dummy_file_path, &file_name, &trash, now).unwrap();
+        let info = TrashInfo::new(&dummy_file_path, now).unwrap();
+        write_info_file(&RealFs, &file_name, &trash, &info).unwrap();
 
         let info_file_path = trash.info_path().join("dummy.trashinfo");
         let info_file = fs::read_to_string(&info_file_path).unwrap();
@@ -132,4 +318,156 @@ mod tests {
 
         assert_eq!(info_file, info_file_should_be)
     }
+
+    #[test]
+    fn writes_owner_and_mode_after_the_standard_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let trash = Trash::from_root(dir.path()).unwrap();
+        fs::create_dir(trash.info_path()).unwrap();
+
+        let info = TrashInfo {
+            mode: Some(0o4755),
+            owner: Some((1000, 100)),
+            checksum: Some("sha256:2c26b46b".into()),
+            compressed: Some("tar.zst".into()),
+            original_size: Some(4096),
+            ..TrashInfo::new(Path::new("/tmp/dummy"), Duration::ZERO).unwrap()
+        };
+        let info_file_path = write_info_file(&RealFs, OsStr::new("dummy"), &trash, &info).unwrap();
+
+        let contents = fs::read_to_string(&info_file_path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines[0..2], ["[Trash Info]", "Path=/tmp/dummy"]);
+        assert!(lines[2].starts_with("DeletionDate="));
+        assert_eq!(
+            lines[3..],
+            [
+                "X-TT-Mode=4755",
+                "X-TT-Owner=1000:100",
+                "X-TT-Checksum=sha256:2c26b46b",
+                "X-TT-Compressed=tar.zst",
+                "X-TT-Original-Size=4096"
+            ]
+        );
+
+        assert_eq!(read_info_file(&info_file_path).unwrap(), info);
+    }
+
+    #[test]
+    fn encodes_and_decodes_path() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let trash = Trash::from_root(dir.path()).unwrap();
+        fs::create_dir(trash.info_path()).unwrap();
+
+        let original_path = Path::new(OsStr::from_bytes(b"/home/dummy/50% of a file\xff\n.txt"));
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+        let info_file_path = write_info_file(
+            &RealFs,
+            OsStr::new("dummy"),
+            &trash,
+            &TrashInfo::new(original_path, now).unwrap(),
+        )
+        .unwrap();
+
+        let contents = fs::read_to_string(&info_file_path).unwrap();
+        assert!(contents.contains("Path=/home/dummy/50%25%20of%20a%20file%FF%0A.txt\n"));
+
+        let info = read_info_file(&info_file_path).unwrap();
+        assert_eq!(info.path, original_path);
+        assert_eq!(
+            info.deletion_date.unwrap(),
+            ffi::format_timestamp(now).unwrap()
+        );
+    }
+
+    #[test]
+    fn parses_info_files() {
+        let info = parse_info_file(
+            b"[Trash Info]\nPath=foo/bar\nX-Unknown=1\nDeletionDate=2004-08-31T22:32:08\n",
+        )
+        .unwrap();
+        assert_eq!(info.path, Path::new("foo/bar"));
+        assert_eq!(info.deletion_date.as_deref(), Some("2004-08-31T22:32:08"));
+
+        // Keys from other groups are ignored
+        let info = parse_info_file(b"[Trash Info]\nPath=/a\n[Other]\nPath=/b\n").unwrap();
+        assert_eq!(info.path, Path::new("/a"));
+
+        // Our own extended keys, and invalid values for them
+        let info = parse_info_file(
+            b"[Trash Info]\nPath=/a\nDeletionDate=2004-08-31T22:32:08\nX-TT-Mode=0640\nX-TT-Owner=1000:100\n",
+        )
+        .unwrap();
+        assert_eq!((info.mode, info.owner), (Some(0o640), Some((1000, 100))));
+        for invalid in [
+            "X-TT-Mode=rw-r--r--\nX-TT-Owner=user:users",
+            "X-TT-Mode=0999\nX-TT-Owner=1000",
+            "X-TT-Mode=77777\nX-TT-Owner=1000:100:1",
+        ] {
+            let contents = format!("[Trash Info]\nPath=/a\n{invalid}\n");
+            let info = parse_info_file(contents.as_bytes()).unwrap();
+            assert_eq!((info.mode, info.owner), (None, None), "{invalid}");
+        }
+
+        assert!(parse_info_file(b"Path=/a\n").is_none());
+        assert!(parse_info_file(b"[Trash Info]\nDeletionDate=2004-08-31T22:32:08\n").is_none());
+    }
+
+    /// The info files in `tests/fixtures/trashinfo` written by other implementations, and the paths they decode to
+    const FIXTURES: &[(&str, &[u8])] = &[
+        ("gio-utf8", "/home/user/Música/café olé.mp3".as_bytes()),
+        ("kde-raw-utf8", "/home/user/Música/café olé.mp3".as_bytes()),
+        ("lowercase-hex", "/home/user/música/café.mp3".as_bytes()),
+        ("raw-latin1", b"/home/user/caf\xe9.txt"),
+        ("encoded-latin1", b"/home/user/caf\xe9.txt"),
+        ("malformed-escapes", b"/home/user/50%off%2x%4"),
+        ("crlf-unencoded-space", b"/home/user/my file.txt"),
+        ("trailing-space", b"/home/user/trailing "),
+        ("plus-sign", b"/home/user/a+b+c.txt"),
+        ("trash-cli-home", b"/home/user/notes/todo.txt"),
+        ("gio-topdir", b"Documents/quarterly report.pdf"),
+    ];
+
+    #[test]
+    fn decodes_info_files_of_other_implementations() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/trashinfo");
+
+        for (fixture, expected) in FIXTURES {
+            let info = read_info_file(&fixtures.join(format!("{fixture}.trashinfo")))
+                .unwrap_or_else(|err| panic!("{fixture}: {err}"));
+
+            assert_eq!(info.path.as_os_str().as_bytes(), *expected, "{fixture}");
+            assert!(info.deletion_date.is_some(), "{fixture}");
+        }
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let trash = Trash::from_root(dir.path()).unwrap();
+        fs::create_dir(trash.info_path()).unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+        for (_, path) in FIXTURES {
+            let original_path = Path::new(OsStr::from_bytes(path));
+            let info_file_path = write_info_file(
+                &RealFs,
+                OsStr::new("dummy"),
+                &trash,
+                &TrashInfo::new(original_path, now).unwrap(),
+            )
+            .unwrap();
+
+            assert_eq!(read_info_file(&info_file_path).unwrap().path, original_path);
+            // Info files are never overwritten
+            fs::remove_file(info_file_path).unwrap();
+        }
+    }
 }
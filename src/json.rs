@@ -0,0 +1,517 @@
+//! The documents printed by `--json`: a single JSON object per run, in place of anything else `tt` would print
+//! to stdout. Warnings and prompts still go to stderr.
+//!
+//! A document's `operation` tells what it reports on: `trash`, `list`, `empty`, `restore` or `check`.
+//! Failures are reported in the document as a `kind`, which is one of the names given by [`Error::kind`] and
+//! what scripts should look at, along with a `message` meant for people. A run failing before there's
+//! anything to report prints `{"error": ...}` alone.
+//!
+//! Paths and names are escaped as in `--print` (see [`escape_path`]), so that they're valid UTF-8 and any
+//! two of them can be told apart. Dates are as in `--list`. Unknown values are `null`.
+//!
+//! Fields may be added to these documents, but never renamed or removed: the tests below lock the format in.
+
+use std::{ffi::OsStr, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    check::Finding,
+    entries::TrashEntry,
+    error::Error,
+    listing::{EntryFile, EntryKind},
+    report::escape_path,
+    restore::RestorePlan,
+    trash::{Sent, Trash},
+};
+
+/// Prints `document` on a line of its own
+pub fn print(document: &impl Serialize) {
+    println!(
+        "{}",
+        serde_json::to_string(document).expect("documents serialize to JSON")
+    );
+}
+
+/// What went wrong
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    kind: &'static str,
+    message: String,
+}
+
+impl From<&Error> for Failure {
+    fn from(err: &Error) -> Self {
+        Self {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// The document of a run that failed before there was anything else to report
+#[derive(Debug, Serialize)]
+pub struct Fatal {
+    error: Failure,
+}
+
+impl From<&Error> for Fatal {
+    fn from(err: &Error) -> Self {
+        Self { error: err.into() }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "operation", rename_all = "kebab-case")]
+pub enum Document {
+    Trash {
+        dry_run: bool,
+        /// What became of each operand, in order, or what would have when dry running
+        results: Vec<TrashOutcome>,
+    },
+    List {
+        trash: String,
+        entries: Vec<ListedEntry>,
+    },
+    Empty {
+        trash: String,
+        /// Whether deleting was confirmed, nothing being deleted otherwise
+        confirmed: bool,
+        /// The names of the entries deleted
+        deleted: Vec<String>,
+        /// The names of the entries kept by `--keep-last`
+        kept: Vec<String>,
+        failed: Vec<EntryFailure>,
+    },
+    Restore {
+        dry_run: bool,
+        /// The entries restored, or that would be when dry running
+        restored: Vec<Restored>,
+        failed: Vec<EntryFailure>,
+    },
+    Check {
+        trash: String,
+        findings: Vec<CheckedFinding>,
+    },
+}
+
+impl Document {
+    /// Whether anything reported in this document failed
+    pub fn has_failures(&self) -> bool {
+        match self {
+            Document::Trash { results, .. } => results
+                .iter()
+                .any(|outcome| matches!(outcome, TrashOutcome::Failed { .. })),
+            Document::Empty { failed, .. } | Document::Restore { failed, .. } => !failed.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+/// What became of a file given to be trashed
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum TrashOutcome {
+    Trashed {
+        /// The operand, as given
+        argument: String,
+        original_path: String,
+        /// The root of the trash it was sent to
+        trash: String,
+        /// Its name in `$trash/files`
+        trashed_name: String,
+        /// `rename`, `copy`, `forced-copy` or `compress`, as in the audit log (see [`crate::audit`])
+        method: &'static str,
+        /// `None` for directories that weren't measured, with `--no-directorysizes`
+        bytes: Option<u64>,
+        directory: bool,
+    },
+    /// Left alone, as answered when asked about it (see [`crate::prompt`])
+    Skipped {
+        argument: String,
+    },
+    /// Permanently deleted instead, as per the policy of its mount point
+    Deleted {
+        argument: String,
+        original_path: String,
+    },
+    Failed {
+        argument: String,
+        error: Failure,
+    },
+}
+
+impl TrashOutcome {
+    pub fn trashed(argument: &OsStr, original_path: &Path, trash: &Trash, sent: &Sent) -> Self {
+        Self::Trashed {
+            argument: escape_path(argument.as_ref()),
+            original_path: escape_path(original_path),
+            trash: escape_path(trash.root.as_path()),
+            trashed_name: escape_path(&sent.file_name),
+            method: sent.transfer.name(),
+            bytes: sent.size,
+            directory: sent.is_directory,
+        }
+    }
+
+    pub fn deleted(argument: &OsStr, original_path: &Path) -> Self {
+        Self::Deleted {
+            argument: escape_path(argument.as_ref()),
+            original_path: escape_path(original_path),
+        }
+    }
+
+    pub fn skipped(argument: &OsStr) -> Self {
+        Self::Skipped {
+            argument: escape_path(argument.as_ref()),
+        }
+    }
+
+    pub fn failed(argument: &OsStr, err: &Error) -> Self {
+        Self::Failed {
+            argument: escape_path(argument.as_ref()),
+            error: err.into(),
+        }
+    }
+}
+
+/// An entry of the trash, as `--list` shows it
+#[derive(Debug, Serialize)]
+pub struct ListedEntry {
+    name: String,
+    original_path: Option<String>,
+    /// In RFC 3339, taking the local time of the info file, which doesn't say which timezone that was, as in the
+    /// current timezone
+    deletion_date: Option<String>,
+    /// The same, in seconds since the epoch
+    deletion_timestamp: Option<i64>,
+    /// How many hard links it has, `null` for directories (see [`crate::entries::link_count`])
+    links: Option<u64>,
+    /// `file`, `directory`, `symlink` or `other`
+    kind: Option<&'static str>,
+    /// In bytes
+    size: Option<u64>,
+    /// For archives of directories (see [`crate::compress`]), the size of the directory it's of, in bytes
+    original_size: Option<u64>,
+    /// What it points to, if it's a symlink
+    target: Option<String>,
+    /// The root of the trash it's in, which tells entries apart with `--all-trashes`
+    trash: String,
+    /// The number `--restore-id` takes for it (see [`crate::ids`])
+    id: u64,
+}
+
+impl ListedEntry {
+    pub fn new(
+        trash: &Trash,
+        id: u64,
+        entry: &TrashEntry,
+        links: Option<u64>,
+        file: &EntryFile,
+    ) -> Self {
+        Self {
+            name: escape_path(entry.name.as_ref()),
+            original_path: entry.original_path.as_deref().map(escape_path),
+            deletion_date: entry.deletion_date.and_then(|date| date.rfc3339()),
+            deletion_timestamp: entry.deletion_date.and_then(|date| date.timestamp()),
+            links,
+            kind: file.kind.map(EntryKind::name),
+            size: file.size.bytes(),
+            original_size: entry.original_size,
+            target: file.target.as_deref().map(escape_path),
+            trash: escape_path(trash.root.as_path()),
+            id,
+        }
+    }
+}
+
+/// An entry that couldn't be deleted or restored
+#[derive(Debug, Serialize)]
+pub struct EntryFailure {
+    name: String,
+    error: Failure,
+}
+
+impl EntryFailure {
+    pub fn new(name: &OsStr, err: &Error) -> Self {
+        Self {
+            name: escape_path(name.as_ref()),
+            error: err.into(),
+        }
+    }
+}
+
+/// An entry restored, following its [`RestorePlan`]
+#[derive(Debug, Serialize)]
+pub struct Restored {
+    /// Where it was, in `$trash/files`
+    source: String,
+    destination: String,
+    /// The missing ancestors of `destination` that were created, outermost first
+    created_directories: Vec<String>,
+    /// `rename`, `copy` (when it was on another filesystem than `destination`) or `unpack`
+    method: &'static str,
+}
+
+impl From<&RestorePlan> for Restored {
+    fn from(plan: &RestorePlan) -> Self {
+        let method = if plan.unpacks {
+            "unpack"
+        } else if plan.crosses_devices {
+            "copy"
+        } else {
+            "rename"
+        };
+
+        Self {
+            source: escape_path(&plan.source),
+            destination: escape_path(&plan.destination),
+            created_directories: plan
+                .missing_parents
+                .iter()
+                .map(|parent| escape_path(parent))
+                .collect(),
+            method,
+        }
+    }
+}
+
+/// An inconsistency found by `--check`
+#[derive(Debug, Serialize)]
+pub struct CheckedFinding {
+    /// As given by [`Finding::kind`]
+    kind: &'static str,
+    path: String,
+    /// Whether `--fix` removed it
+    removed: bool,
+}
+
+impl CheckedFinding {
+    pub fn new(finding: &Finding, removed: bool) -> Self {
+        Self {
+            kind: finding.kind(),
+            path: escape_path(finding.path()),
+            removed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::PathBuf};
+
+    use chrono::TimeZone;
+
+    use super::{
+        CheckedFinding, Document, EntryFailure, Fatal, ListedEntry, Restored, TrashOutcome,
+    };
+    use crate::{
+        check::Finding,
+        date::DateTime,
+        entries::TrashEntry,
+        error::Result,
+        fs::Transfer,
+        listing::{EntryFile, EntryKind, EntrySize},
+        restore::RestorePlan,
+        trash::{Sent, Trash},
+        Error,
+    };
+
+    fn to_json(document: &Document) -> String {
+        serde_json::to_string(document).unwrap()
+    }
+
+    #[test]
+    fn serializes_trash_outcomes() -> Result<()> {
+        let trash = Trash::from_root("/home/user/.local/share/Trash")?;
+        let sent = Sent {
+            file_name: "notes.txt.1".into(),
+            transfer: Transfer::Copied,
+            size: Some(42),
+            is_directory: false,
+        };
+        let document = Document::Trash {
+            dry_run: false,
+            results: vec![
+                TrashOutcome::trashed(
+                    "notes.txt".as_ref(),
+                    "/mnt/usb/notes.txt".as_ref(),
+                    &trash,
+                    &sent,
+                ),
+                TrashOutcome::deleted("cache".as_ref(), "/tmp/cache".as_ref()),
+                TrashOutcome::skipped("keep".as_ref()),
+                TrashOutcome::failed(
+                    OsStr::from_bytes(b"tab\there\xff"),
+                    &Error::Protected("/home/user/tab\there".into(), 1, "/home/user".into()),
+                ),
+            ],
+        };
+
+        assert!(document.has_failures());
+        assert_eq!(
+            to_json(&document),
+            r#"{"operation":"trash","dry_run":false,"results":["#.to_owned()
+                + r#"{"status":"trashed","argument":"notes.txt","original_path":"/mnt/usb/notes.txt","#
+                + r#""trash":"/home/user/.local/share/Trash","trashed_name":"notes.txt.1","method":"copy","bytes":42,"directory":false},"#
+                + r#"{"status":"deleted","argument":"cache","original_path":"/tmp/cache"},"#
+                + r#"{"status":"skipped","argument":"keep"},"#
+                + r#"{"status":"failed","argument":"tab%09here%FF","error":{"kind":"protected","#
+                + r#""message":"/home/user/tab\there is protected by rule 1 (/home/user), pass --override-protection to trash it anyway"}}]}"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn serializes_listings() {
+        let trash = Trash::from_root("/home/user/.local/share/Trash").unwrap();
+        let entries = [
+            TrashEntry {
+                name: "notes.txt".into(),
+                raw_path: Some("/home/user/notes.txt".into()),
+                original_path: Some("/home/user/notes.txt".into()),
+                deletion_date: DateTime::parse("2024-03-01T10:00:00"),
+                original_size: Some(4096),
+            },
+            TrashEntry {
+                name: "100%".into(),
+                raw_path: None,
+                original_path: None,
+                deletion_date: None,
+                original_size: None,
+            },
+        ];
+        let document = Document::List {
+            trash: "/home/user/.local/share/Trash".into(),
+            entries: vec![
+                ListedEntry::new(
+                    &trash,
+                    2,
+                    &entries[0],
+                    Some(2),
+                    &EntryFile {
+                        kind: Some(EntryKind::File),
+                        size: EntrySize::Known(5),
+                        target: None,
+                    },
+                ),
+                ListedEntry::new(
+                    &trash,
+                    1,
+                    &entries[1],
+                    None,
+                    &EntryFile {
+                        kind: None,
+                        size: EntrySize::Unknown,
+                        target: None,
+                    },
+                ),
+            ],
+        };
+
+        // Which depends on the timezone the test runs in
+        let date = chrono::Local
+            .with_ymd_and_hms(2024, 3, 1, 10, 0, 0)
+            .unwrap();
+        let (rfc3339, timestamp) = (date.to_rfc3339(), date.timestamp());
+        assert!(!document.has_failures());
+        assert_eq!(
+            to_json(&document),
+            r#"{"operation":"list","trash":"/home/user/.local/share/Trash","entries":["#.to_owned()
+                + r#"{"name":"notes.txt","original_path":"/home/user/notes.txt","#
+                + &format!(
+                    r#""deletion_date":"{rfc3339}","deletion_timestamp":{timestamp},"links":2,"kind":"file","size":5,"original_size":4096,"target":null,"#
+                )
+                + r#""trash":"/home/user/.local/share/Trash","id":2},"#
+                + r#"{"name":"100%25","original_path":null,"deletion_date":null,"deletion_timestamp":null,"#
+                + r#""links":null,"kind":null,"size":null,"original_size":null,"target":null,"#
+                + r#""trash":"/home/user/.local/share/Trash","id":1}]}"#
+        );
+    }
+
+    #[test]
+    fn serializes_deletions() {
+        let document = Document::Empty {
+            trash: "/home/user/.local/share/Trash".into(),
+            confirmed: true,
+            deleted: vec!["a".into()],
+            kept: vec!["c".into()],
+            failed: vec![EntryFailure::new(
+                "b".as_ref(),
+                &Error::Io(std::io::Error::from_raw_os_error(libc::EACCES)),
+            )],
+        };
+
+        assert!(document.has_failures());
+        assert_eq!(
+            to_json(&document),
+            r#"{"operation":"empty","trash":"/home/user/.local/share/Trash","confirmed":true,"deleted":["a"],"kept":["c"],"#
+                .to_owned()
+                + r#""failed":[{"name":"b","error":{"kind":"io","message":"IO: Permission denied (os error 13)"}}]}"#
+        );
+    }
+
+    #[test]
+    fn serializes_restores() {
+        let plans = [
+            RestorePlan {
+                source: "/home/user/.local/share/Trash/files/notes.txt".into(),
+                destination: "/home/user/docs/notes.txt".into(),
+                missing_parents: vec![PathBuf::from("/home/user/docs")],
+                crosses_devices: false,
+                unpacks: false,
+            },
+            RestorePlan {
+                source: "/mnt/usb/.Trash-1000/files/photos.tar.zst".into(),
+                destination: "/home/user/photos".into(),
+                missing_parents: vec![],
+                crosses_devices: true,
+                unpacks: true,
+            },
+        ];
+        let document = Document::Restore {
+            dry_run: true,
+            restored: plans.iter().map(Restored::from).collect(),
+            failed: vec![EntryFailure::new(
+                "report.pdf".as_ref(),
+                &Error::RestoreDestinationExists("/home/user/report.pdf".into()),
+            )],
+        };
+
+        assert_eq!(
+            to_json(&document),
+            r#"{"operation":"restore","dry_run":true,"restored":["#.to_owned()
+                + r#"{"source":"/home/user/.local/share/Trash/files/notes.txt","destination":"/home/user/docs/notes.txt","#
+                + r#""created_directories":["/home/user/docs"],"method":"rename"},"#
+                + r#"{"source":"/mnt/usb/.Trash-1000/files/photos.tar.zst","destination":"/home/user/photos","#
+                + r#""created_directories":[],"method":"unpack"}],"#
+                + r#""failed":[{"name":"report.pdf","error":{"kind":"restore-destination-exists","#
+                + r#""message":"Can't restore to /home/user/report.pdf: file already exists"}}]}"#
+        );
+    }
+
+    #[test]
+    fn serializes_checks_and_fatal_errors() {
+        let document = Document::Check {
+            trash: "/home/user/.local/share/Trash".into(),
+            findings: vec![
+                CheckedFinding::new(&Finding::MissingInfoFile("/t/files/a".into()), false),
+                CheckedFinding::new(&Finding::LeftoverTemp("/t/files/.tt-tmp-1".into()), true),
+            ],
+        };
+
+        assert_eq!(
+            to_json(&document),
+            r#"{"operation":"check","trash":"/home/user/.local/share/Trash","findings":["#
+                .to_owned()
+                + r#"{"kind":"missing-info","path":"/t/files/a","removed":false},"#
+                + r#"{"kind":"temp-file","path":"/t/files/.tt-tmp-1","removed":true}]}"#
+        );
+
+        assert_eq!(
+            serde_json::to_string(&Fatal::from(&Error::EntryNotFound("notes".into()))).unwrap(),
+            r#"{"error":{"kind":"entry-not-found","message":"No trashed file named notes"}}"#
+        );
+    }
+}
@@ -0,0 +1,583 @@
+//! Rendering the entries of a trash as the rows of `--list`.
+//!
+//! Every row holds the number of an entry (see [`crate::ids`]), then its deletion date, name, original path and link
+//! count, followed by what its file in
+//! `$trash/files` is: its kind, its size and, for symlinks, their target. Sizes of directories come from
+//! `$trash/directorysizes`, and are marked with a `~` when they had to be computed afresh; a `~` alone means the size
+//! couldn't be told. Archives of directories (see [`crate::compress`]) show the size of the directory they're of next to
+//! theirs. Entries whose info file is missing or invalid are still listed, with [`NO_INFO`] for their
+//! original path. Anything else unknown is shown as `-`.
+//!
+//! Rows are aligned into columns on a terminal, and tab-separated otherwise, for other tools to split them. Original
+//! paths too long for the table to fit in the terminal are shortened in their middle, unless told not to. They're
+//! ordered as told by [`SortKey`], newest first unless told otherwise. Names are colored by kind, as `ls` colors
+//! them, when told so by [`ColorChoice`].
+//!
+//! ```text
+//! 3  2024-03-02T09:05:00  hosts      /home/user/hosts      1  symlink    10 bytes  /etc/hosts
+//! 2  2024-03-02T09:00:00  build      /home/user/build      -  directory  ~3.8 MiB  -
+//! 1  2024-03-01T10:00:00  notes.txt  /home/user/notes.txt  1  file       5 bytes   -
+//! ```
+
+use std::{cmp::Reverse, ffi::OsStr, path::PathBuf, str::FromStr};
+
+use unixstring::UnixString;
+
+use crate::{
+    directorysizes::DirectorySize,
+    entries::TrashEntry,
+    error::{Error, Result},
+    ffi::Lstat,
+    fs::directory_size,
+    home_dir::Env,
+    report::display_path,
+    trash::Trash,
+    usage::human_size,
+};
+
+/// What's shown for the original path of an entry whose info file is missing or invalid
+pub const NO_INFO: &str = "<no valid info file>";
+
+/// The column of rows holding the original path of entries, which is shortened when the table is too wide
+const PATH_COLUMN: usize = 3;
+
+/// How narrow original paths may be shortened to, below which they're left for the terminal to wrap
+const MIN_PATH_WIDTH: usize = 16;
+
+/// What kind of file an entry is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    /// A FIFO, socket or device
+    Other,
+}
+
+impl EntryKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            EntryKind::File => "file",
+            EntryKind::Directory => "directory",
+            EntryKind::Symlink => "symlink",
+            EntryKind::Other => "other",
+        }
+    }
+
+    /// The SGR parameters names of this kind are colored with, as by the default `LS_COLORS`
+    fn color(self) -> Option<&'static str> {
+        match self {
+            EntryKind::File => None,
+            EntryKind::Directory => Some("01;34"),
+            EntryKind::Symlink => Some("01;36"),
+            EntryKind::Other => Some("33"),
+        }
+    }
+}
+
+/// How big an entry is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntrySize {
+    /// As given by `lstat`, or by `$trash/directorysizes`
+    Known(u64),
+    /// Computed afresh, since `$trash/directorysizes` doesn't have it
+    Computed(u64),
+    Unknown,
+}
+
+impl EntrySize {
+    pub fn bytes(self) -> Option<u64> {
+        match self {
+            EntrySize::Known(size) | EntrySize::Computed(size) => Some(size),
+            EntrySize::Unknown => None,
+        }
+    }
+}
+
+/// What the file of an entry in `$trash/files` is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryFile {
+    /// `None` if the file couldn't be looked at
+    pub kind: Option<EntryKind>,
+    pub size: EntrySize,
+    /// What it points to, if it's a symlink
+    pub target: Option<PathBuf>,
+}
+
+impl EntryFile {
+    /// Looks at the file of the entry of `trash` named `name`, `directory_sizes` being the contents of
+    /// `$trash/directorysizes`
+    pub fn of(trash: &Trash, name: &OsStr, directory_sizes: &[DirectorySize]) -> Self {
+        let path = trash.files.as_path().join(name);
+        let Some(lstat) = UnixString::try_from(path.clone())
+            .ok()
+            .and_then(|unx| Lstat::lstat(unx).ok())
+        else {
+            return Self {
+                kind: None,
+                size: EntrySize::Unknown,
+                target: None,
+            };
+        };
+
+        let kind = match lstat.mode() & libc::S_IFMT {
+            libc::S_IFREG => EntryKind::File,
+            libc::S_IFDIR => EntryKind::Directory,
+            libc::S_IFLNK => EntryKind::Symlink,
+            _ => EntryKind::Other,
+        };
+        let size = if kind == EntryKind::Directory {
+            match directory_sizes.iter().find(|cached| cached.name == name) {
+                Some(cached) => EntrySize::Known(cached.size),
+                None => UnixString::try_from(path.clone())
+                    .ok()
+                    .and_then(|unx| directory_size(unx).ok())
+                    .map_or(EntrySize::Unknown, EntrySize::Computed),
+            }
+        } else {
+            EntrySize::Known(lstat.size())
+        };
+        let target = (kind == EntryKind::Symlink)
+            .then(|| std::fs::read_link(&path).ok())
+            .flatten();
+
+        Self {
+            kind: Some(kind),
+            size,
+            target,
+        }
+    }
+}
+
+/// What `--list` orders entries by
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// When they were trashed, newest first, those with an unknown date last
+    #[default]
+    Date,
+    /// How much space they take up, largest first, those with an unknown size last
+    Size,
+    /// Their names in `$trash/files`
+    Name,
+    /// Where they were trashed from, those trashed from an unknown path last
+    Path,
+}
+
+impl SortKey {
+    /// The values `--sort` accepts
+    pub const NAMES: &'static [&'static str] = &["date", "size", "name", "path"];
+}
+
+impl FromStr for SortKey {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "date" => Ok(Self::Date),
+            "size" => Ok(Self::Size),
+            "name" => Ok(Self::Name),
+            "path" => Ok(Self::Path),
+            _ => Err(Error::Usage(format!(
+                "--sort expects one of {}, got {text}",
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+/// When `--list` colors names, as set with `--color`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// When writing to a terminal, unless `NO_COLOR` is set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// The values `--color` accepts
+    pub const NAMES: &'static [&'static str] = &["auto", "always", "never"];
+
+    /// Whether to color output, going to a terminal if `is_terminal`, in the environment `env`
+    pub fn enabled(self, is_terminal: bool, env: &Env) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // See https://no-color.org
+            ColorChoice::Auto => {
+                is_terminal && env.get("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(Error::Usage(format!(
+                "--color expects one of {}, got {text}",
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+/// Orders `listed`, entries along with their files, by `key`, in the opposite order if `reverse` is set. Entries that
+/// compare equal keep their order, which is by name as [`crate::entries::entries`] gives them.
+pub fn sort<T>(listed: &mut [(TrashEntry, T, EntryFile)], key: SortKey, reverse: bool) {
+    match key {
+        SortKey::Date => listed.sort_by_key(|(entry, ..)| {
+            (entry.deletion_date.is_none(), Reverse(entry.deletion_date))
+        }),
+        SortKey::Size => listed
+            .sort_by_key(|(.., file)| (file.size.bytes().is_none(), Reverse(file.size.bytes()))),
+        SortKey::Name => listed.sort_by(|(entry, ..), (other, ..)| entry.name.cmp(&other.name)),
+        SortKey::Path => listed.sort_by(|(entry, ..), (other, ..)| {
+            (entry.original_path.is_none(), &entry.original_path)
+                .cmp(&(other.original_path.is_none(), &other.original_path))
+        }),
+    }
+
+    if reverse {
+        listed.reverse();
+    }
+}
+
+/// The columns of the row of `entry`, numbered `id`, whose file is `file` and which has `links` hard links if known.
+/// With `colored`, its name is colored by its kind.
+pub fn row(
+    id: u64,
+    entry: &TrashEntry,
+    links: Option<u64>,
+    file: &EntryFile,
+    colored: bool,
+) -> Vec<String> {
+    let unknown = || "-".to_owned();
+
+    let mut name = display_path(entry.name.as_ref());
+    if let Some(color) = file.kind.and_then(EntryKind::color).filter(|_| colored) {
+        name = format!("\x1b[{color}m{name}\x1b[0m");
+    }
+
+    let mut size = match file.size {
+        EntrySize::Known(size) => human_size(size),
+        EntrySize::Computed(size) => format!("~{}", human_size(size)),
+        EntrySize::Unknown => "~".into(),
+    };
+    if let Some(original_size) = entry.original_size {
+        size.push_str(&format!(" (from {})", human_size(original_size)));
+    }
+
+    vec![
+        id.to_string(),
+        entry
+            .deletion_date
+            .map_or_else(unknown, |date| date.to_string()),
+        name,
+        match (&entry.raw_path, &entry.original_path) {
+            (None, _) => NO_INFO.to_owned(),
+            (Some(_), Some(path)) => display_path(path),
+            (Some(_), None) => unknown(),
+        },
+        links.map_or_else(unknown, |links| links.to_string()),
+        file.kind.map_or_else(unknown, |kind| kind.name().into()),
+        size,
+        file.target.as_deref().map_or_else(unknown, display_path),
+    ]
+}
+
+/// How many columns `column` takes up on a terminal, leaving out the escape sequences that color it. Anything else is
+/// shown as is, names having been made printable (see [`display_path`]).
+fn width(column: &str) -> usize {
+    let mut width = 0;
+    let mut chars = column.chars();
+    while let Some(char) = chars.next() {
+        if char == '\x1b' {
+            // Up to the `m` ending it
+            chars.by_ref().find(|&char| char == 'm');
+        } else {
+            width += 1;
+        }
+    }
+
+    width
+}
+
+/// `column` shortened to `max_width` characters by replacing its middle with `…`, if it's any longer
+fn truncate_middle(column: &str, max_width: usize) -> String {
+    let chars: Vec<char> = column.chars().collect();
+    if chars.len() <= max_width {
+        return column.to_owned();
+    }
+
+    let kept = max_width.saturating_sub(1);
+    let (head, tail) = (kept - kept / 2, kept / 2);
+    let mut truncated: String = chars[..head].iter().collect();
+    truncated.push('…');
+    truncated.extend(&chars[chars.len() - tail..]);
+    truncated
+}
+
+/// Renders `rows`, aligned into columns if `aligned` is set and tab-separated otherwise. Aligned rows are kept within
+/// `max_width` columns, if given, by shortening original paths (see [`truncate_middle`]).
+pub fn render(rows: &[Vec<String>], aligned: bool, max_width: Option<usize>) -> String {
+    let mut out = String::new();
+    if !aligned {
+        for row in rows {
+            out.push_str(&row.join("\t"));
+            out.push('\n');
+        }
+        return out;
+    }
+
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        widths.resize(widths.len().max(row.len()), 0);
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(self::width(column));
+        }
+    }
+
+    let table_width = widths.iter().sum::<usize>() + 2 * widths.len().saturating_sub(1);
+    let excess = max_width.map_or(0, |max_width| table_width.saturating_sub(max_width));
+    let mut path_width = None;
+    if let Some(width) = widths.get_mut(PATH_COLUMN).filter(|_| excess > 0) {
+        *width = width.saturating_sub(excess).max(MIN_PATH_WIDTH).min(*width);
+        path_width = Some(*width);
+    }
+
+    for row in rows {
+        let mut line = String::new();
+        for (index, column) in row.iter().enumerate() {
+            let truncated;
+            let column = match path_width {
+                Some(path_width) if index == PATH_COLUMN => {
+                    truncated = truncate_middle(column, path_width);
+                    &truncated
+                }
+                _ => column,
+            };
+            if index + 1 == row.len() {
+                line.push_str(column);
+            } else {
+                let padding = widths[index] - width(column);
+                line.push_str(column);
+                line.extend(std::iter::repeat_n(' ', padding + 2));
+            }
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, fs, os::unix::fs::symlink, path::PathBuf};
+
+    use super::{
+        render, row, sort, truncate_middle, ColorChoice, EntryFile, EntryKind, EntrySize, SortKey,
+    };
+    use crate::{
+        date::DateTime, directorysizes::read_directory_sizes, entries::TrashEntry, error::Result,
+        fs::RealFs, home_dir::Env, trash::Trash,
+    };
+
+    fn entry(name: &str) -> TrashEntry {
+        let original_path = PathBuf::from("/home/user").join(name);
+        TrashEntry {
+            name: name.into(),
+            raw_path: Some(original_path.clone()),
+            original_path: Some(original_path),
+            deletion_date: DateTime::parse("2024-03-01T10:00:00"),
+            original_size: None,
+        }
+    }
+
+    #[test]
+    fn describes_each_kind_of_entry() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        fs::write(&trash.directory_sizes, "")?;
+
+        fs::write(dir.path().join("notes.txt"), "dummy")?;
+        fs::create_dir(dir.path().join("build"))?;
+        fs::write(dir.path().join("build/app"), vec![0; 2048])?;
+        symlink("/etc/hosts", dir.path().join("hosts"))?;
+        for name in ["notes.txt", "build", "hosts"] {
+            trash.send_to_trash(&RealFs, &dir.path().join(name))?;
+        }
+        // Put there without going through `tt`, so `directorysizes` doesn't have it
+        fs::create_dir(trash.files.as_path().join("cache"))?;
+        fs::write(trash.files.as_path().join("cache/blob"), "12345678")?;
+
+        let directory_sizes = read_directory_sizes(&trash)?;
+        let of = |name: &str| EntryFile::of(&trash, OsStr::new(name), &directory_sizes);
+        assert_eq!(
+            of("notes.txt"),
+            EntryFile {
+                kind: Some(EntryKind::File),
+                size: EntrySize::Known(5),
+                target: None
+            }
+        );
+        assert_eq!(of("build").size, EntrySize::Known(2048));
+        assert_eq!(of("cache").size, EntrySize::Computed(8));
+        assert_eq!(of("hosts").target, Some(PathBuf::from("/etc/hosts")));
+        assert_eq!(of("missing").kind, None);
+
+        let rows: Vec<_> = [
+            ("notes.txt", Some(1)),
+            ("build", None),
+            ("cache", None),
+            ("hosts", Some(1)),
+            ("missing", None),
+        ]
+        .into_iter()
+        .zip(1..)
+        .map(|((name, links), id)| row(id, &entry(name), links, &of(name), false))
+        .collect();
+        assert_eq!(
+            render(&rows, false, None),
+            "1\t2024-03-01T10:00:00\tnotes.txt\t/home/user/notes.txt\t1\tfile\t5 bytes\t-\n\
+             2\t2024-03-01T10:00:00\tbuild\t/home/user/build\t-\tdirectory\t2.0 KiB\t-\n\
+             3\t2024-03-01T10:00:00\tcache\t/home/user/cache\t-\tdirectory\t~8 bytes\t-\n\
+             4\t2024-03-01T10:00:00\thosts\t/home/user/hosts\t1\tsymlink\t10 bytes\t/etc/hosts\n\
+             5\t2024-03-01T10:00:00\tmissing\t/home/user/missing\t-\t-\t~\t-\n"
+        );
+        let archive = TrashEntry {
+            original_size: Some(4096),
+            ..entry("notes.txt")
+        };
+        assert_eq!(
+            render(&[row(1, &archive, Some(1), &of("notes.txt"), false)], false, None),
+            "1\t2024-03-01T10:00:00\tnotes.txt\t/home/user/notes.txt\t1\tfile\t5 bytes (from 4.0 KiB)\t-\n"
+        );
+        assert_eq!(
+            render(&rows[..3], true, Some(200)),
+            "1  2024-03-01T10:00:00  notes.txt  /home/user/notes.txt  1  file       5 bytes   -\n\
+             2  2024-03-01T10:00:00  build      /home/user/build      -  directory  2.0 KiB   -\n\
+             3  2024-03-01T10:00:00  cache      /home/user/cache      -  directory  ~8 bytes  -\n"
+        );
+        // Too wide for the terminal, so paths give way, as far as they can
+        assert_eq!(
+            render(&rows[..3], true, Some(78)),
+            "1  2024-03-01T10:00:00  notes.txt  /home/us…tes.txt  1  file       5 bytes   -\n\
+             2  2024-03-01T10:00:00  build      /home/user/build  -  directory  2.0 KiB   -\n\
+             3  2024-03-01T10:00:00  cache      /home/user/cache  -  directory  ~8 bytes  -\n"
+        );
+        assert_eq!(
+            render(&rows[..3], true, Some(20)),
+            render(&rows[..3], true, Some(78))
+        );
+
+        // Colors don't throw alignment off
+        let colored: Vec<_> = ["notes.txt", "build", "hosts"]
+            .into_iter()
+            .map(|name| row(1, &entry(name), None, &of(name), true))
+            .collect();
+        assert_eq!(
+            render(&colored, true, None),
+            "1  2024-03-01T10:00:00  notes.txt  /home/user/notes.txt  -  file       5 bytes   -\n\
+             1  2024-03-01T10:00:00  \x1b[01;34mbuild\x1b[0m      /home/user/build      -  directory  2.0 KiB   -\n\
+             1  2024-03-01T10:00:00  \x1b[01;36mhosts\x1b[0m      /home/user/hosts      -  symlink    10 bytes  /etc/hosts\n"
+        );
+
+        // Put there by hand, without an info file
+        fs::write(trash.files.as_path().join("stray"), "dummy")?;
+        let stray = TrashEntry {
+            name: "stray".into(),
+            raw_path: None,
+            original_path: None,
+            deletion_date: None,
+            original_size: None,
+        };
+        assert_eq!(
+            render(&[row(1, &stray, Some(1), &of("stray"), false)], false, None),
+            "1\t-\tstray\t<no valid info file>\t1\tfile\t5 bytes\t-\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn truncates_in_the_middle() {
+        assert_eq!(
+            truncate_middle("/home/user/notes.txt", 20),
+            "/home/user/notes.txt"
+        );
+        assert_eq!(truncate_middle("/home/user/notes.txt", 11), "/home…s.txt");
+        assert_eq!(truncate_middle("/srv/données/été", 9), "/srv…/été");
+    }
+
+    #[test]
+    fn chooses_when_to_color() -> Result<()> {
+        let plain = Env::default();
+        let no_color: Env = [("NO_COLOR", "1")].into_iter().collect();
+        let empty_no_color: Env = [("NO_COLOR", "")].into_iter().collect();
+
+        let auto: ColorChoice = "auto".parse()?;
+        assert!(auto.enabled(true, &plain));
+        assert!(!auto.enabled(false, &plain));
+        assert!(!auto.enabled(true, &no_color));
+        assert!(auto.enabled(true, &empty_no_color));
+
+        let always: ColorChoice = "always".parse()?;
+        assert!(always.enabled(false, &no_color));
+        assert!(!"never".parse::<ColorChoice>()?.enabled(true, &plain));
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sorts_entries() {
+        let listed = |date: Option<&str>, name: &str, path: Option<&str>, size: Option<u64>| {
+            let entry = TrashEntry {
+                deletion_date: date.and_then(DateTime::parse),
+                original_path: path.map(PathBuf::from),
+                ..entry(name)
+            };
+            let file = EntryFile {
+                kind: Some(EntryKind::File),
+                size: size.map_or(EntrySize::Unknown, EntrySize::Known),
+                target: None,
+            };
+            (entry, (), file)
+        };
+        // As given by `entries`, by name
+        let entries = [
+            listed(Some("2024-03-02T09:00:00"), "a", Some("/srv/a"), Some(10)),
+            listed(None, "b", Some("/home/b"), None),
+            listed(Some("2024-03-01T10:00:00"), "c", None, Some(300)),
+            listed(Some("2024-03-02T09:00:00"), "d", Some("/home/d"), Some(10)),
+        ];
+        let sorted = |key, reverse| {
+            let mut sorted = entries.clone();
+            sort(&mut sorted, key, reverse);
+            sorted
+                .iter()
+                .map(|(entry, ..)| entry.name.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        // Ties keep their order
+        assert_eq!(sorted(SortKey::Date, false), ["a", "d", "c", "b"]);
+        assert_eq!(sorted(SortKey::Date, true), ["b", "c", "d", "a"]);
+        assert_eq!(sorted(SortKey::Size, false), ["c", "a", "d", "b"]);
+        assert_eq!(sorted(SortKey::Name, true), ["d", "c", "b", "a"]);
+        assert_eq!(sorted(SortKey::Path, false), ["b", "d", "a", "c"]);
+
+        assert_eq!("size".parse::<SortKey>().ok(), Some(SortKey::Size));
+        assert!("age".parse::<SortKey>().is_err());
+    }
+}
@@ -0,0 +1,306 @@
+//! Keeping operations on a whole trash from interleaving with trashing, through a lock file at the root of the trash.
+//!
+//! Trashing or restoring a file takes the lock shared, as any number of files may be trashed at once. Emptying, purging,
+//! fixing, deduplicating and migrating out of a trash, as well as the passes of the daemon, take it exclusive: they go
+//! through everything in the trash, and mustn't come across a file halfway through being trashed, its info file written
+//! but the file itself not yet moved.
+//!
+//! The lock is advisory (see `flock(2)`), so it only keeps `tt` from getting in its own way. Whoever can't take it
+//! retries for up to [`LOCK_TIMEOUT`], then gives up with [`Error::TrashBusy`].
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::ErrorKind,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use unixstring::UnixString;
+
+use crate::{
+    error::{Error, Result},
+    ffi::{try_lock_exclusive, try_lock_shared, Lstat},
+    trash::Trash,
+};
+
+/// The name of the lock file, at the root of every trash
+pub const LOCK_FILE_NAME: &str = "tt.lock";
+
+/// How long taking the lock may wait for whoever holds it
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait between attempts at taking the lock
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How a trash is locked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Along with anyone else locking it shared, as when trashing
+    Shared,
+    /// By no one else
+    Exclusive,
+}
+
+/// The lock of a trash, released when dropped or when the process dies
+#[derive(Debug)]
+pub struct TrashLock {
+    _file: File,
+}
+
+impl TrashLock {
+    /// Locks `trash` shared, waiting for up to [`LOCK_TIMEOUT`]
+    pub fn shared(trash: &Trash) -> Result<Self> {
+        Self::acquire(trash, LockMode::Shared, LOCK_TIMEOUT)
+    }
+
+    /// Locks `trash` shared, as done while an entry is moved into or out of it.
+    ///
+    /// Not being able to open the lock file means the trash can't be written to, which is better told by whatever
+    /// tries to write to it next, so that's `None` rather than an error.
+    pub fn for_entry(trash: &Trash) -> Result<Option<Self>> {
+        match Self::shared(trash) {
+            Err(Error::Io(_)) => Ok(None),
+            lock => lock.map(Some),
+        }
+    }
+
+    /// Locks `trash` exclusive, waiting for up to [`LOCK_TIMEOUT`]
+    pub fn exclusive(trash: &Trash) -> Result<Self> {
+        Self::acquire(trash, LockMode::Exclusive, LOCK_TIMEOUT)
+    }
+
+    /// Locks `trash` as told by `mode`, failing with [`Error::TrashBusy`] if that's not possible within `timeout`
+    pub fn acquire(trash: &Trash, mode: LockMode, timeout: Duration) -> Result<Self> {
+        let path = trash.lock_path();
+        let file = open(&path)?;
+
+        let started = Instant::now();
+        loop {
+            let taken = match mode {
+                LockMode::Shared => try_lock_shared(&file)?,
+                LockMode::Exclusive => try_lock_exclusive(&file)?,
+            };
+            if taken {
+                return Ok(Self { _file: file });
+            }
+
+            let waited = started.elapsed();
+            if waited >= timeout {
+                return Err(Error::TrashBusy(
+                    trash.root.as_path().to_owned(),
+                    holder(&path),
+                ));
+            }
+            thread::sleep(RETRY_INTERVAL.min(timeout - waited));
+        }
+    }
+}
+
+/// Opens the lock file at `path`, creating it if needed. Read access is enough to lock it, which is all that's left
+/// when someone else created it in a shared trash.
+///
+/// Anyone in the group of a shared trash could have put something else there, so it must be a regular file: a
+/// symlink isn't followed to whatever file it points to, and a FIFO isn't waited on. Either fails with
+/// [`Error::UnsafeLockFile`].
+fn open(path: &Path) -> Result<File> {
+    let flags = libc::O_NOFOLLOW | libc::O_NONBLOCK;
+    let opened = match OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .custom_flags(flags)
+        .open(path)
+    {
+        Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+            OpenOptions::new().read(true).custom_flags(flags).open(path)
+        }
+        file => file,
+    };
+    let unsafe_lock_file = |reason: &str| Error::UnsafeLockFile(path.to_owned(), reason.into());
+
+    let file = match opened {
+        Err(err) if err.raw_os_error() == Some(libc::ELOOP) => {
+            return Err(unsafe_lock_file("it is a symbolic link"))
+        }
+        // Directories can't be opened for writing, and FIFOs without a reader can't be without blocking
+        Err(err) if matches!(err.raw_os_error(), Some(libc::EISDIR | libc::ENXIO)) => {
+            return Err(unsafe_lock_file("it is not a regular file"))
+        }
+        file => file?,
+    };
+    if !file.metadata()?.is_file() {
+        return Err(unsafe_lock_file("it is not a regular file"));
+    }
+
+    Ok(file)
+}
+
+/// The PID of a process holding the lock file at `path`, if `/proc/locks` tells
+fn holder(path: &Path) -> Option<u32> {
+    let id = Lstat::lstat(UnixString::try_from(path.to_owned()).ok()?)
+        .ok()?
+        .file_id();
+    let locks = fs::read_to_string("/proc/locks").ok()?;
+
+    holder_in(&locks, id.device, id.inode)
+}
+
+/// The PID of a process holding a `flock` lock on the file `inode` of `device`, according to `locks`, the contents of
+/// `/proc/locks`
+fn holder_in(locks: &str, device: u64, inode: u64) -> Option<u32> {
+    let file = format!(
+        "{:02x}:{:02x}:{inode}",
+        libc::major(device),
+        libc::minor(device)
+    );
+
+    // Such as `1: FLOCK  ADVISORY  WRITE 1234 fe:00:1220996 0 EOF`, those waiting for it being `1: -> FLOCK ...`
+    locks.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[..] {
+            [_, "FLOCK", _, _, pid, id, ..] if id == file => pid.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread, time::Duration};
+
+    use super::{holder_in, LockMode, TrashLock};
+    use crate::{
+        error::{Error, Result},
+        fs::RealFs,
+        trash::Trash,
+    };
+
+    fn trash(dir: &std::path::Path) -> Result<Trash> {
+        let trash = Trash::from_root(dir.join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+
+        Ok(trash)
+    }
+
+    #[test]
+    fn trashing_waits_for_an_exclusive_lock() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash(dir.path())?;
+        let paths: Vec<_> = (0..4)
+            .map(|index| dir.path().join(format!("file-{index}")))
+            .collect();
+        for path in &paths {
+            fs::write(path, "dummy")?;
+        }
+
+        let lock = TrashLock::exclusive(&trash)?;
+        thread::scope(|scope| -> Result<()> {
+            let trashing: Vec<_> = paths
+                .iter()
+                .map(|path| scope.spawn(|| trash.send_to_trash(&RealFs, path)))
+                .collect();
+
+            // Nothing gets in while the lock is held...
+            thread::sleep(Duration::from_millis(300));
+            assert_eq!(fs::read_dir(&trash.files)?.count(), 0);
+            assert_eq!(fs::read_dir(&trash.info)?.count(), 0);
+            assert!(paths.iter().all(|path| path.exists()));
+
+            // ...and everything does once it's released
+            drop(lock);
+            for handle in trashing {
+                handle.join().expect("trashing doesn't panic")?;
+            }
+
+            Ok(())
+        })?;
+        assert_eq!(fs::read_dir(&trash.files)?.count(), 4);
+        assert!(paths.iter().all(|path| !path.exists()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn gives_up_on_a_busy_trash() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash(dir.path())?;
+
+        // As held while files are trashed, which doesn't keep others from trashing
+        let trashing = TrashLock::shared(&trash)?;
+        let also_trashing = TrashLock::acquire(&trash, LockMode::Shared, Duration::ZERO)?;
+
+        match TrashLock::acquire(&trash, LockMode::Exclusive, Duration::from_millis(100)) {
+            Err(Error::TrashBusy(root, holder)) => {
+                assert_eq!(root, trash.root.as_path());
+                if fs::metadata("/proc/locks").is_ok() {
+                    assert_eq!(holder, Some(std::process::id()));
+                }
+            }
+            other => panic!("expected the trash to be busy, got {other:?}"),
+        }
+
+        drop(trashing);
+        drop(also_trashing);
+        TrashLock::acquire(&trash, LockMode::Exclusive, Duration::ZERO)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_lock_files_that_arent_regular_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash(dir.path())?;
+        let refused = |err: Error, expected: &str| matches!(err, Error::UnsafeLockFile(path, reason) if path == trash.lock_path() && reason.contains(expected));
+
+        // Which would otherwise be created, or opened for writing, wherever it points to
+        let target = dir.path().join("target");
+        std::os::unix::fs::symlink(&target, trash.lock_path())?;
+        let file = dir.path().join("file");
+        fs::write(&file, "dummy")?;
+        assert!(refused(
+            TrashLock::exclusive(&trash).unwrap_err(),
+            "symbolic link"
+        ));
+        assert!(refused(
+            trash.send_to_trash(&RealFs, &file).unwrap_err(),
+            "symbolic link"
+        ));
+        assert!(!target.exists());
+        assert!(file.exists());
+
+        fs::remove_file(trash.lock_path())?;
+        fs::create_dir(trash.lock_path())?;
+        assert!(refused(
+            TrashLock::shared(&trash).unwrap_err(),
+            "not a regular file"
+        ));
+
+        fs::remove_dir(trash.lock_path())?;
+        let fifo = std::ffi::CString::new(trash.lock_path().into_os_string().into_encoded_bytes())
+            .expect("no NUL in a temporary path");
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) }, 0);
+        assert!(refused(
+            TrashLock::exclusive(&trash).unwrap_err(),
+            "not a regular file"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_the_holder_in_proc_locks() {
+        let locks = "\
+            1: POSIX  ADVISORY  WRITE 99 fe:00:1220996 0 EOF\n\
+            2: FLOCK  ADVISORY  WRITE 1234 08:01:42 0 EOF\n\
+            3: -> FLOCK  ADVISORY  WRITE 5678 fe:00:1220996 0 EOF\n\
+            3: FLOCK  ADVISORY  READ 4321 fe:00:1220996 0 EOF\n";
+        let device = libc::makedev(0xfe, 0);
+
+        assert_eq!(holder_in(locks, device, 1220996), Some(4321));
+        assert_eq!(holder_in(locks, libc::makedev(8, 1), 42), Some(1234));
+        assert_eq!(holder_in(locks, device, 7), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! Leveled logging of how `tt` goes about things, for finding out why a file went where it went without recompiling.
+//!
+//! Nothing is logged unless a level is set, with the `TT_LOG` environment variable or `--log-level` (which wins), in
+//! which case messages of that level and the more serious ones are printed on stderr, each on a line of its own
+//! starting with `tt: LEVEL:`, e.g.
+//!
+//! ```text
+//! tt: debug: /mnt/usb/photos is under the mount point /mnt/usb (vfat)
+//! tt: debug: trashing /mnt/usb/photos into the trash at /mnt/usb/.Trash-1000
+//! tt: debug: photos is taken in /mnt/usb/.Trash-1000/files, trying another name
+//! ```
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use crate::{
+    error::{Error, Result},
+    report::display_message,
+};
+
+/// The environment variable setting the level, when `--log-level` isn't given
+pub const LOG_VAR: &str = "TT_LOG";
+
+/// The level set, as a [`Level`] plus one, or 0 when nothing is logged
+static LEVEL: AtomicU8 = AtomicU8::new(0);
+
+/// How much a message matters, from the most serious to the least
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    /// Decisions taken, such as which trash a file goes to
+    Debug,
+    /// What these decisions were taken from, such as every mount point found
+    Trace,
+}
+
+impl Level {
+    /// The values `--log-level` and `TT_LOG` accept
+    pub const NAMES: &'static [&'static str] = &["error", "warn", "info", "debug", "trace"];
+
+    fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+}
+
+impl FromStr for Level {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" => Ok(Self::Debug),
+            "trace" => Ok(Self::Trace),
+            _ => Err(Error::Usage(format!(
+                "--log-level and {LOG_VAR} expect one of {}, got {text}",
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+/// Logs messages of `level` and the more serious ones from now on, or nothing if `None`
+pub fn set_level(level: Option<Level>) {
+    LEVEL.store(level.map_or(0, |level| level as u8 + 1), Ordering::Relaxed);
+}
+
+/// Whether messages of `level` are logged
+pub fn enabled(level: Level) -> bool {
+    (level as u8) < LEVEL.load(Ordering::Relaxed)
+}
+
+/// Logs `message` at `level`, if messages of that level are logged
+pub fn log(level: Level, message: impl fmt::Display) {
+    if enabled(level) {
+        eprintln!("tt: {}: {}", level.name(), display_message(&message));
+    }
+}
+
+/// Logs `message` at the [`Level::Debug`] level
+pub fn debug(message: impl fmt::Display) {
+    log(Level::Debug, message);
+}
+
+/// Logs `message` at the [`Level::Trace`] level
+pub fn trace(message: impl fmt::Display) {
+    log(Level::Trace, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Level;
+
+    #[test]
+    fn orders_levels_by_how_much_they_matter() {
+        let levels: Vec<Level> = Level::NAMES
+            .iter()
+            .map(|name| name.parse().unwrap())
+            .collect();
+        assert!(levels.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(levels
+            .iter()
+            .all(|level| level.name().parse().ok() == Some(*level)));
+        assert!("verbose".parse::<Level>().is_err());
+    }
+}
@@ -1,99 +1,1559 @@
+mod audit;
+mod cat;
+mod check;
+mod checksum;
+mod cli;
+mod completions;
+mod compress;
+mod config;
+mod context;
+mod crash;
+mod daemon;
+mod date;
+mod dedupe;
+mod diff;
 mod directorysizes;
+mod entries;
 mod error;
 mod ffi;
+mod file_uri;
 mod fs;
+mod glob;
 mod home_dir;
+mod hook;
+mod ids;
 mod info_file;
+mod json;
 mod light_fs;
+mod listing;
+mod lock;
+mod log;
+mod migrate;
+mod mount_table;
+mod open_files;
+mod picker;
+mod policy;
+mod prompt;
+mod protect;
+mod recovery;
+mod report;
+mod restore;
+mod resume;
+mod shell;
+mod shred;
+mod stats;
+mod summary;
 mod trash;
+mod tree;
+#[cfg(feature = "ui")]
+mod ui;
+mod usage;
+mod which;
 
 #[cfg(test)]
 mod tests;
 
 use std::{
+    collections::BTreeMap,
     env,
+    ffi::{OsStr, OsString},
+    io::{self, BufRead, IsTerminal, Write},
     path::{Path, PathBuf},
+    process::Stdio,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use lazy_static::lazy_static;
+use unixstring::UnixString;
 
 pub use error::{Error, Result};
-use trash::Trash;
-use unixstring::UnixString;
 
-use crate::ffi::real_user_id;
-use crate::ffi::MountPoint;
+use crate::cli::{Args, Interactive, Mode};
+use crate::config::Config;
+use crate::context::TrashContext;
+use crate::ffi::{Lstat, MountPoint};
+use crate::fs::RealFs;
+use crate::home_dir::Env;
+use crate::mount_table::MountTable;
+use crate::policy::Action;
+use crate::trash::Trash;
 
 lazy_static! {
     // TODO: add a set of trashes of other mount points
-    pub static ref HOME_DIR: UnixString = home_dir::home_dir().unwrap();
-    pub static ref HOME_TRASH_PATH: UnixString =
-        home_dir::home_trash_path(&*HOME_DIR).expect("failed to obtain user's home directory!");
     pub static ref MOUNT_POINTS: Vec<MountPoint> =
         ffi::probe_mount_points().expect("failed to probe mount points!");
-    pub static ref HOME_TRASH: Trash =
-        Trash::from_root(&*HOME_TRASH_PATH).expect("failed to probe mount points!");
 }
 
-fn find_mount_point_of_file(path: &Path) -> Result<&MountPoint> {
-    MOUNT_POINTS
-        .iter()
-        .find(|mount_point| mount_point.contains(path))
-        .ok_or(Error::FailedToObtainMountPoints)
-}
+/// The exit status when something asked for failed, be it only for some of the files it was about
+const FAILURE: i32 = 1;
+/// The exit status when the command line itself is wrong, as with `rm`
+const USAGE: i32 = 2;
 
 fn main() {
-    if let Err(err) = run() {
-        eprintln!("tt: error: {}", err);
-        std::process::exit(127);
+    let env = Env::current();
+
+    let status = match parse_args(&env) {
+        Ok(args) => {
+            // Taken from the parsed options, which may have come from TT_OPTS
+            let json = args.json;
+            run(env, args).unwrap_or_else(|err| {
+                let status = match err {
+                    Error::Usage(_) => USAGE,
+                    _ => FAILURE,
+                };
+                fail(&err, json, status)
+            })
+        }
+        Err(err) => {
+            // There are no options to go by, so the command line is looked through, for failing to parse it to be
+            // reported in JSON as well
+            let json = env::args_os().skip(1).any(|arg| arg == "--json");
+            fail(&err, json, USAGE)
+        }
+    };
+    std::process::exit(status);
+}
+
+/// Reports `err`, which stopped everything, returning `status`
+fn fail(err: &Error, json: bool, status: i32) -> i32 {
+    report::error(err);
+    if json {
+        json::print(&json::Fatal::from(err));
     }
+    status
 }
 
-fn run() -> Result<()> {
-    for file in env::args_os().skip(1) {
-        let file = PathBuf::from(file).canonicalize()?;
-        if file.starts_with("/home") {
-            // The file is located at home so we'll send it to the home trash
-            HOME_TRASH.send_to_trash(&file)?;
+/// Parses the options in the environment and then the command line
+fn parse_args(env: &Env) -> Result<Args> {
+    let default_options = match env.get(cli::OPTS_VAR) {
+        Some(options) => cli::default_options(options)?,
+        None => Vec::new(),
+    };
+    Args::parse(default_options.into_iter().chain(env::args_os().skip(1)))
+}
+
+/// Does what was asked through the command line, returning the exit status
+fn run(env: Env, args: Args) -> Result<i32> {
+    report::set_verbosity(args.quiet, args.verbose);
+    let log_level = match args.log_level {
+        Some(level) => Some(level),
+        None => env
+            .get(log::LOG_VAR)
+            .map(|level| level.to_string_lossy().parse())
+            .transpose()?,
+    };
+    log::set_level(log_level);
+    // Before the mount points are probed, so that probing them is timed too
+    let run_started = args.stats.then(stats::enable);
+    // Before anything that could fail, such as loading the configuration file
+    match args.mode {
+        Mode::Help => {
+            print!("{}", cli::help());
+            return Ok(0);
+        }
+        Mode::Version => {
+            println!("tt {}", env!("CARGO_PKG_VERSION"));
+            return Ok(0);
+        }
+        _ => {}
+    }
+    let home_dir = home_dir::home_dir_from(&env).ok_or(Error::HomeDirNotFound)?;
+    let config = Config::load(&env, home_dir.as_path())?;
+    let started = stats::start();
+    let mount_table = MountTable::new(MOUNT_POINTS.clone(), ffi::probe_mount_points);
+    stats::finish(stats::Phase::Probe, started);
+    let ctx = TrashContext::from_env(&env, mount_table)?
+        .verifying_trashes(!args.no_verify_trash)
+        .targeting(args.trash.as_deref().map(Path::new))?
+        .with_policies(config.mounts.clone(), args.policy)
+        .checksumming(args.checksum)
+        .deleted_at(args.deletion_date)
+        .preserving_context(!args.no_preserve_context)
+        .attempting_names(config.name_attempts)
+        .confirming_large(args.confirm_large.then_some(config.confirm_large))
+        .keeping_to_one_filesystem(args.one_file_system)
+        .forcing_copies(args.force_copy)
+        .updating_directory_sizes(!args.no_directorysizes)
+        .preserving_root(!args.no_preserve_root)
+        .compressing_above(if args.compress {
+            Some(0)
         } else {
-            trash_file_in_other_mount_point(file)?;
+            config.compress_above
+        });
+    let ctx = if config.audit_log {
+        ctx.auditing(audit::Syslog)
+    } else {
+        ctx
+    };
+
+    // How entries are restored, by the modes that restore them
+    let restore_options = args.restore_options();
+
+    match args.mode {
+        Mode::Trash => {
+            let mut operands = args.operands.clone();
+            if let Some(list) = &args.files_from {
+                let delimiter = if args.null { b'\0' } else { b'\n' };
+                operands.extend(if list == "-" {
+                    cli::read_file_list(io::stdin().lock(), delimiter)?
+                } else {
+                    cli::read_file_list(fs_err::File::open(list)?, delimiter)?
+                });
+            }
+
+            let mut trashed = Vec::new();
+            let mut trashes_used: Vec<Trash> = Vec::new();
+            // The names planned for each trash when dry running, which are taken for the files planned after them
+            let mut planned: BTreeMap<PathBuf, Vec<OsString>> = BTreeMap::new();
+            // A dry run asks nothing, planning as if everything was confirmed
+            let mut prompter = ((args.interactive != Interactive::Never || args.confirm_large)
+                && !args.dry_run)
+                .then(|| prompt::Prompter::on_terminal(args.yes));
+
+            if let Some(prompter) = prompter
+                .as_mut()
+                .filter(|_| args.interactive == Interactive::Once)
+            {
+                if !confirm_trashing(prompter, &operands)? {
+                    if args.json {
+                        let results = operands
+                            .iter()
+                            .map(|operand| json::TrashOutcome::skipped(operand))
+                            .collect();
+                        json::print(&json::Document::Trash {
+                            dry_run: args.dry_run,
+                            results,
+                        });
+                    } else {
+                        println!("tt: nothing was trashed");
+                    }
+                    return Ok(FAILURE);
+                }
+            }
+
+            let send = |argument: &OsString| -> Result<json::TrashOutcome> {
+                let path = file_uri::operand_path(argument)?;
+                // A symlink is trashed itself, as rm removes it, so that's what's told apart from directories
+                let file = fs::resolve_operand(&path)?;
+                fs::check_directory_operand(&file, args.dir, args.recursive)?;
+                ctx.preserve_root(&file)?;
+                if !args.override_protection {
+                    protect::check_protection(&config.protect, &file)?;
+                }
+                if let Some(prompter) = prompter
+                    .as_mut()
+                    .filter(|_| args.interactive == Interactive::Always)
+                {
+                    if !prompter.confirm(&format!("tt: trash {}?", report::display_path(&file)))? {
+                        return Ok(json::TrashOutcome::skipped(argument));
+                    }
+                }
+                let policy = ctx.policy_for(&file)?;
+                if policy.policy == Action::Delete && args.dry_run {
+                    if !args.print && !args.json {
+                        println!(
+                            "tt: would permanently delete {} instead of trashing it.",
+                            report::display_path(&file)
+                        );
+                    }
+                    return Ok(json::TrashOutcome::deleted(argument, &file));
+                }
+                if policy.policy == Action::Delete {
+                    fs::remove_recursively(&RealFs, &file)
+                        .map_err(|err| fs::explain_denial(&RealFs, &file, err))?;
+                    if !args.print && !args.json {
+                        println!(
+                            "tt: permanently deleted {} instead of trashing it.",
+                            report::display_path(&file)
+                        );
+                    }
+                    return Ok(json::TrashOutcome::deleted(argument, &file));
+                }
+
+                if args.dry_run {
+                    let (trash, created) = ctx.planned_trash_for(&file)?;
+                    log::debug(format_args!(
+                        "{} would be trashed into the {}",
+                        report::display_path(&file),
+                        trash_name(&ctx, &trash)
+                    ));
+                    let names = planned.entry(trash.root.as_path().to_owned()).or_default();
+                    let plan = ctx.plan(&trash, &file, names)?;
+                    if created && names.is_empty() && !args.print && !args.json {
+                        println!(
+                            "tt: would create the trash at {}",
+                            report::display_path(trash.root.as_path())
+                        );
+                    }
+                    names.push(plan.file_name.clone().into_os_string());
+
+                    let outcome = json::TrashOutcome::trashed(argument, &file, &trash, &plan);
+                    let report = report::Trashed {
+                        original_path: file,
+                        trashed_path: trash.files.as_path().join(&plan.file_name),
+                        size: plan.size,
+                    };
+                    if args.print {
+                        println!("{report}");
+                    } else if !args.json {
+                        let elsewhere = trash.root != ctx.home_trash().root;
+                        println!(
+                            "{}",
+                            report.verbose_line(
+                                plan.transfer,
+                                elsewhere.then(|| trash.root.as_path()),
+                                true
+                            )
+                        );
+                    }
+                    return Ok(outcome);
+                }
+
+                let trash = ctx.trash_for(&file)?;
+                log::debug(format_args!(
+                    "trashing {} into the {}",
+                    report::display_path(&file),
+                    trash_name(&ctx, &trash)
+                ));
+                let links = Lstat::lstat(UnixString::try_from(file.clone())?)?;
+                let mut confirm_large = |path: &Path, stats: fs::DirectoryStats| {
+                    let Some(prompter) = prompter.as_mut() else {
+                        return Ok(true);
+                    };
+                    let question = format!(
+                        "tt: about to trash {} files totalling {} from {}, continue?",
+                        usage::grouped(stats.entries),
+                        usage::human_size(stats.size),
+                        report::display_path(path)
+                    );
+                    match prompter.confirm(&question) {
+                        // Without anyone to ask, it's trashed as it would have been without --confirm-large
+                        Err(Error::ConfirmationRequired) => Ok(true),
+                        answer => answer,
+                    }
+                };
+                let sent = match ctx.send(&trash, &file, &mut confirm_large) {
+                    Err(Error::NotConfirmed(_)) => {
+                        return Ok(json::TrashOutcome::skipped(argument))
+                    }
+                    sent => sent?,
+                };
+
+                if !links.is_directory() && links.nlink() > 1 {
+                    let others = links.nlink() - 1;
+                    report::info(format_args!(
+                        "note: {} has {others} other link{}, trashing this name frees no space",
+                        report::display_path(&file),
+                        if others == 1 { "" } else { "s" }
+                    ));
+                }
+
+                // Trashing still succeeded, even if making room afterwards didn't
+                let purged = policy::enforce_limits(
+                    &RealFs,
+                    &trash,
+                    &policy,
+                    Some(sent.file_name.as_os_str()),
+                    SystemTime::now(),
+                );
+                report_purged(&trash, purged);
+
+                let outcome = json::TrashOutcome::trashed(argument, &file, &trash, &sent);
+                let report = report::Trashed {
+                    original_path: file,
+                    trashed_path: trash.files.as_path().join(&sent.file_name),
+                    size: sent.size,
+                };
+
+                if args.print {
+                    println!("{report}");
+                } else if args.verbose && !args.json {
+                    let elsewhere = trash.root != ctx.home_trash().root;
+                    println!(
+                        "{}",
+                        report.verbose_line(
+                            sent.transfer,
+                            elsewhere.then(|| trash.root.as_path()),
+                            false
+                        )
+                    );
+                } else if !args.json {
+                    println!(
+                        "tt: successfully sent {} to {}.",
+                        report::display_path(&report.original_path),
+                        report::display_path(trash.files.as_path())
+                    );
+                }
+                trashed.push(report);
+                if !trashes_used.iter().any(|used| used.root == trash.root) {
+                    trashes_used.push(trash);
+                }
+
+                Ok(outcome)
+            };
+
+            let results = summary::try_every(&operands, args.force, send);
+
+            // Whatever was trashed is worth telling the hook about, even if other files failed
+            let hook = args.hook.as_ref().or(config.hook.as_ref());
+            if let Some(hook) = hook.filter(|_| !trashed.is_empty()) {
+                // Nothing but the document may go to stdout
+                let stdout = if args.json {
+                    io::stderr().into()
+                } else {
+                    Stdio::inherit()
+                };
+                if let Err(err) = hook::run_hook(hook, &trashed, stdout) {
+                    report::warn(format_args!(
+                        "the hook failed: {}",
+                        report::display_message(&err)
+                    ));
+                }
+            }
+
+            if !args.quiet {
+                let stamp = usage::stamp_path(&home_dir::state_dir_from(&env, home_dir.as_path()));
+                warn_about_usage(
+                    &trashes_used,
+                    ctx.home_trash(),
+                    &config.usage_warning,
+                    &stamp,
+                );
+            }
+
+            if !args.quiet && !args.print && !args.json && operands.len() > 1 {
+                let summary = summary::Summary {
+                    dry_run: args.dry_run,
+                    ..summary::Summary::of(&results)
+                };
+                println!("{summary}");
+            }
+            // On stderr, so as not to get in the way of --json
+            if let Some(run_started) = run_started {
+                for line in stats::lines(&stats::collected(), run_started.elapsed()) {
+                    eprintln!("{line}");
+                }
+            }
+            if args.json {
+                let document = json::Document::Trash {
+                    dry_run: args.dry_run,
+                    results,
+                };
+                json::print(&document);
+                return Ok(if document.has_failures() { FAILURE } else { 0 });
+            }
+            if results
+                .iter()
+                .any(|outcome| matches!(outcome, json::TrashOutcome::Failed { .. }))
+            {
+                return Ok(FAILURE);
+            }
+        }
+        Mode::Check => {
+            let trash = ctx.target_trash();
+            // Held while fixing, so that files being trashed aren't taken for leftovers
+            let _lock = if args.fix {
+                ctx.verify(trash)?;
+                Some(lock::TrashLock::exclusive(trash)?)
+            } else {
+                None
+            };
+            let mut findings = check::check(trash)?;
+            if args.verify {
+                findings.extend(check::verify_checksums(trash)?);
+            }
+            let mut checked = Vec::new();
+            for finding in findings {
+                let fixed = args.fix
+                    && check::fix(
+                        &RealFs,
+                        trash,
+                        &finding,
+                        recovery::GRACE_PERIOD,
+                        SystemTime::now(),
+                    )?;
+
+                if args.json {
+                    checked.push(json::CheckedFinding::new(&finding, fixed));
+                } else if fixed {
+                    println!("{finding}\tremoved");
+                } else {
+                    println!("{finding}");
+                }
+            }
+
+            if args.json {
+                json::print(&json::Document::Check {
+                    trash: report::escape_path(trash.root.as_path()),
+                    findings: checked,
+                });
+            }
+        }
+        Mode::Orphans => {
+            // Looking must not touch, so there's no recovering here
+            let mut found = false;
+            for trash in queried_trashes(&ctx, args.all_trashes) {
+                let orphans = match check::orphans(&trash) {
+                    Ok(orphans) => orphans,
+                    Err(err) if args.all_trashes => {
+                        report::warn(format_args!(
+                            "skipping the {}: {}",
+                            trash_name(&ctx, &trash),
+                            report::display_message(&err)
+                        ));
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                };
+                for orphan in &orphans {
+                    println!("{orphan}");
+                }
+                found |= !orphans.is_empty();
+            }
+
+            // Lets scripts tell whether anything was found
+            return Ok(if found { 1 } else { 0 });
+        }
+        Mode::Restore(name) => {
+            ctx.verify(ctx.target_trash())?;
+            let trashes = ctx.known_trashes();
+            recover(&trashes);
+
+            // Names of entries take precedence over original paths
+            if let Some(trash) = trashes
+                .iter()
+                .find(|trash| restore::find_entry(trash, &name).is_ok())
+            {
+                let plan =
+                    restore_entry(trash, &name, args.restore_to.as_deref(), restore_options)?;
+                print_restored(&[plan], vec![], args.dry_run, args.json);
+                return Ok(0);
+            }
+
+            if glob::is_pattern(&name) {
+                if args.restore_to.is_some() {
+                    return Err(Error::Usage(
+                        "--restore-to restores a single entry, not a pattern".into(),
+                    ));
+                }
+                let mut found = Vec::new();
+                for trash in &trashes {
+                    found.extend(
+                        entries::find_matching(trash, &name, args.by_path)?
+                            .into_iter()
+                            .map(|entry| which::Found {
+                                trash,
+                                entry,
+                                size: None,
+                            }),
+                    );
+                }
+                if found.is_empty() {
+                    return if args.force {
+                        Ok(0)
+                    } else {
+                        Err(Error::NoMatchingEntry(name.into()))
+                    };
+                }
+
+                return Ok(restore_found(
+                    found,
+                    restore_options,
+                    args.dry_run,
+                    args.json,
+                ));
+            }
+
+            let path = fs::normalize_path(name.as_ref())?;
+            let generations = restore::generations(&trashes, &path)?;
+            if generations.is_empty() {
+                return Err(Error::EntryNotFound(name.into()));
+            }
+
+            let restore_to = args
+                .restore_to
+                .as_deref()
+                .map(|directory| fs::normalize_path(directory.as_ref()))
+                .transpose()?;
+            let plans = restore::restore_generations(
+                &RealFs,
+                &generations,
+                &path,
+                args.generations,
+                restore_to.as_deref(),
+                restore_options,
+            )?;
+            print_restored(&plans, vec![], args.dry_run, args.json);
+
+            if args.generations == restore::Generations::Newest && generations.len() > 1 {
+                report::info(format_args!(
+                    "older versions of {} remain in the trash, restore them with --generation N or --all:",
+                    report::display_path(&path)
+                ));
+                for (index, found) in generations.iter().enumerate().skip(1) {
+                    let deletion_date = found
+                        .entry
+                        .deletion_date
+                        .map_or_else(|| "at an unknown date".into(), |date| date.to_string());
+                    let size = found
+                        .size
+                        .map_or_else(|| "of unknown size".into(), usage::human_size);
+                    report::info_detail(format_args!(
+                        "{}: trashed {} as {}, {size}",
+                        index + 1,
+                        deletion_date,
+                        report::display_path(found.entry.name.as_ref())
+                    ));
+                }
+            }
+        }
+        Mode::RestoreLast => {
+            ctx.verify(ctx.target_trash())?;
+            let trashes = ctx.known_trashes();
+            recover(&trashes);
+
+            let (trash, entry) = restore::last_trashed(&trashes)?.ok_or(Error::NothingToRestore)?;
+            let plan = restore_entry(
+                trash,
+                &entry.name,
+                args.restore_to.as_deref(),
+                restore_options,
+            )?;
+            print_restored(&[plan], vec![], args.dry_run, args.json);
+        }
+        Mode::RestoreId(id) => {
+            let trash = ctx.target_trash();
+            ctx.verify(trash)?;
+            recover(std::slice::from_ref(trash));
+
+            let entries = entries::entries(trash)?;
+            let listing = ids::listing_path(&home_dir::state_dir_from(&env, home_dir.as_path()));
+            let entry = ids::find(&listing, trash, &entries, id)?;
+            let plan = restore_entry(
+                trash,
+                &entry.name,
+                args.restore_to.as_deref(),
+                restore_options,
+            )?;
+            print_restored(&[plan], vec![], args.dry_run, args.json);
+        }
+        Mode::RestoreUnder(path) => {
+            ctx.verify(ctx.target_trash())?;
+            let trashes = ctx.known_trashes();
+            recover(&trashes);
+
+            let path = fs::normalize_path(path.as_ref())?;
+            let mut found = which::which(&trashes, &path, true)?;
+            found.retain(|found| args.filter.matches(&found.entry));
+            if found.is_empty() {
+                return Err(Error::EntryNotFound(path));
+            }
+
+            return Ok(restore_found(
+                found,
+                restore_options,
+                args.dry_run,
+                args.json,
+            ));
+        }
+        Mode::Pick(command) => {
+            ctx.verify(ctx.target_trash())?;
+            let trashes = ctx.known_trashes();
+            recover(&trashes);
+
+            let mut candidates = Vec::new();
+            for trash in &trashes {
+                for entry in entries::entries(trash)? {
+                    if args.filter.matches(&entry) {
+                        candidates.push((trash, entry));
+                    }
+                }
+            }
+            // The most recently trashed first, as those are the likeliest to be restored
+            candidates.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.deletion_date));
+
+            let lines = candidates
+                .iter()
+                .enumerate()
+                .map(|(id, (_, entry))| picker::candidate_line(id, entry))
+                .collect();
+            let Some(picked) = picker::pick(&command, lines)? else {
+                if args.json {
+                    print_restored(&[], vec![], args.dry_run, true);
+                } else {
+                    println!("tt: nothing was restored");
+                }
+                return Ok(FAILURE);
+            };
+
+            let mut plans = Vec::new();
+            for id in picked {
+                let (trash, entry) = &candidates[id];
+                plans.push(restore::restore(
+                    &RealFs,
+                    trash,
+                    &entry.name,
+                    restore_options,
+                )?);
+            }
+            print_restored(&plans, vec![], args.dry_run, args.json);
+        }
+        Mode::InteractiveRestore => {
+            let trash = ctx.target_trash();
+            ctx.verify(trash)?;
+            recover(std::slice::from_ref(trash));
+
+            shell::run(
+                &RealFs,
+                trash,
+                restore_options,
+                args.yes,
+                io::stdin().lock(),
+                io::stdout().lock(),
+            )?;
+        }
+        Mode::Which(path) => {
+            let path = fs::normalize_path(path.as_ref())?;
+            let trashes = ctx.known_trashes();
+            recover(&trashes);
+            let found = which::which(&trashes, &path, args.recursive)?;
+
+            for found in &found {
+                println!("{found}");
+            }
+
+            // Lets scripts tell whether anything was found
+            return Ok(if found.is_empty() { 1 } else { 0 });
+        }
+        Mode::Cat(name) => {
+            // Looking must not touch, so there's no recovering here
+            let trashes = ctx.known_trashes();
+
+            if let Some((trash, name)) = trashes
+                .iter()
+                .find_map(|trash| Some((trash, restore::find_entry(trash, &name).ok()?)))
+            {
+                cat::cat(trash, &name, args.info, &mut io::stdout().lock())?;
+                return Ok(0);
+            }
+
+            let path = fs::normalize_path(name.as_ref())?;
+            let generations = restore::generations(&trashes, &path)?;
+            let version = match args.generations {
+                restore::Generations::Newest => 1,
+                restore::Generations::Version(version) => version,
+                restore::Generations::All => {
+                    return Err(Error::Usage("--cat shows a single version".into()))
+                }
+            };
+            let found = match generations.get(version - 1) {
+                Some(found) => found,
+                None if generations.is_empty() => return Err(Error::EntryNotFound(name.into())),
+                None => return Err(Error::VersionNotFound(path, version)),
+            };
+
+            if args.generations == restore::Generations::Newest && generations.len() > 1 {
+                report::info(format_args!(
+                    "{} was trashed {} times, showing the most recent version (pick another with --generation N)",
+                    report::display_path(&path),
+                    generations.len()
+                ));
+            }
+            cat::cat(
+                found.trash,
+                &found.entry.name,
+                args.info,
+                &mut io::stdout().lock(),
+            )?;
+        }
+        Mode::Diff(name) => {
+            let trashes = ctx.known_trashes();
+            recover(&trashes);
+
+            let compared = || -> Result<i32> {
+                let (trash, name) = trashes
+                    .iter()
+                    .find_map(|trash| Some((trash, restore::find_entry(trash, &name).ok()?)))
+                    .ok_or_else(|| Error::EntryNotFound(name.clone().into()))?;
+                let original = restore::original_path(trash, &name)?;
+                let trashed = trash.files.as_path().join(&name);
+
+                if let Some(tool) = &args.tool {
+                    return diff::run_tool(tool, &trashed, &original);
+                }
+
+                let differs = diff::diff(&trashed, &original, &mut io::stdout().lock())?;
+                Ok(i32::from(differs))
+            };
+
+            // Like `diff`, trouble is told apart from differences
+            return Ok(compared().unwrap_or_else(|err| {
+                report::error(&err);
+                2
+            }));
+        }
+        Mode::Migrate { from, to } => {
+            let from = ctx.find_trash(&from, false)?;
+            let to = ctx.find_trash(&to, true)?;
+            if from.root == to.root {
+                return Err(Error::Usage("--from and --to are the same trash".into()));
+            }
+            recover(&[from.clone(), to.clone()]);
+            // Entries are trashed into `to` as files would be
+            let _locks = (
+                lock::TrashLock::exclusive(&from)?,
+                lock::TrashLock::shared(&to)?,
+            );
+
+            let entries = entries::entries(&from)?;
+            for name in &args.operands {
+                if !entries.iter().any(|entry| entry.name == *name) {
+                    return Err(Error::EntryNotFound(name.into()));
+                }
+            }
+
+            for entry in entries {
+                let is_selected = args.operands.is_empty() || args.operands.contains(&entry.name);
+                if !is_selected || !args.filter.matches(&entry) {
+                    continue;
+                }
+
+                if entry.raw_path.is_none() {
+                    report::warn(format_args!(
+                        "not migrating {}: its info file is missing or invalid",
+                        report::display_path(entry.name.as_ref())
+                    ));
+                    continue;
+                }
+
+                let name =
+                    migrate::migrate(&RealFs, &from, &entry.name, &to, !args.no_preserve_context)?;
+                println!(
+                    "tt: migrated {} to {}",
+                    report::display_path(entry.name.as_ref()),
+                    report::display_path(&to.files.as_path().join(name))
+                );
+            }
+        }
+        Mode::List => {
+            let trashes = queried_trashes(&ctx, args.all_trashes);
+            recover(&trashes);
+
+            // Each trash along with its entries, those of the trashes that couldn't be read being left out
+            let mut listed_trashes = Vec::new();
+            for trash in &trashes {
+                match entries::entries(trash) {
+                    Ok(entries) => listed_trashes.push((trash, entries)),
+                    Err(err) if args.all_trashes => {
+                        report::warn(format_args!(
+                            "skipping the {}: {}",
+                            trash_name(&ctx, trash),
+                            report::display_message(&err)
+                        ));
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+
+            if args.tree {
+                for (index, (trash, entries)) in listed_trashes.into_iter().enumerate() {
+                    let directory_sizes = directorysizes::read_directory_sizes(trash)?;
+                    let listed: Vec<_> = entries
+                        .into_iter()
+                        .filter(|entry| args.filter.matches(entry))
+                        .map(|entry| {
+                            let size =
+                                entries::entry_size(trash, &entry.name, &directory_sizes).ok();
+                            (entry, size)
+                        })
+                        .collect();
+                    if args.all_trashes {
+                        if index > 0 {
+                            println!();
+                        }
+                        println!("{}:", trash_name(&ctx, trash));
+                    }
+                    print!("{}", tree::render(&listed));
+                }
+                return Ok(0);
+            }
+
+            let is_terminal = io::stdout().is_terminal();
+            let colored = args.color.enabled(is_terminal, &env);
+            let max_width = if args.no_truncate {
+                None
+            } else {
+                ffi::terminal_width()
+            };
+            let listing_path =
+                ids::listing_path(&home_dir::state_dir_from(&env, home_dir.as_path()));
+            let mut json_entries = Vec::new();
+            for (index, (trash, entries)) in listed_trashes.into_iter().enumerate() {
+                // Numbered before filtering, so that an entry has the same number whatever is listed
+                let numbers = ids::numbered(&entries);
+                let ids: Vec<_> = entries.iter().map(|entry| numbers[&*entry.name]).collect();
+                if let Err(err) = ids::record(&listing_path, trash, ids::Fingerprint::of(&entries))
+                {
+                    report::warn(format_args!(
+                        "the numbers of this listing can't be used with --restore-id: {}",
+                        report::display_message(&err)
+                    ));
+                }
+
+                // Not being able to tell is no reason not to list anything
+                let directory_sizes =
+                    directorysizes::read_directory_sizes(trash).unwrap_or_default();
+                let mut listed: Vec<_> = entries
+                    .into_iter()
+                    .zip(ids)
+                    .filter(|(entry, _)| args.filter.matches(entry))
+                    .map(|(entry, id)| {
+                        let links = entries::link_count(trash, &entry.name).ok().flatten();
+                        let file = listing::EntryFile::of(trash, &entry.name, &directory_sizes);
+                        (entry, (id, links), file)
+                    })
+                    .collect();
+                listing::sort(&mut listed, args.sort, args.reverse);
+
+                if args.json {
+                    json_entries.extend(listed.iter().map(|(entry, (id, links), file)| {
+                        json::ListedEntry::new(trash, *id, entry, *links, file)
+                    }));
+                    continue;
+                }
+
+                let rows: Vec<_> = listed
+                    .iter()
+                    .map(|(entry, (id, links), file)| {
+                        listing::row(*id, entry, *links, file, colored)
+                    })
+                    .collect();
+                if args.all_trashes {
+                    if index > 0 {
+                        println!();
+                    }
+                    println!("{}:", trash_name(&ctx, trash));
+                }
+                print!("{}", listing::render(&rows, is_terminal, max_width));
+            }
+
+            if args.json {
+                json::print(&json::Document::List {
+                    trash: report::escape_path(ctx.target_trash().root.as_path()),
+                    entries: json_entries,
+                });
+            }
+        }
+        #[cfg(feature = "ui")]
+        Mode::Ui => {
+            let trash = ctx.target_trash();
+            ctx.verify(trash)?;
+            recover(std::slice::from_ref(trash));
+            let options = restore::RestoreOptions {
+                dry_run: false,
+                ..restore_options
+            };
+            ui::browse(&RealFs, trash, options)?;
+        }
+        Mode::Empty => {
+            let trashes = match &args.mount {
+                Some(path) => match ctx.mount_trash(Path::new(path))? {
+                    Some(trash) => vec![trash],
+                    None => {
+                        let message = format!(
+                            "tt: there's no trash on the mount point of {}, so there's nothing to empty",
+                            report::display_path(path.as_ref())
+                        );
+                        // With --json, stdout only ever holds the document, and there's no trash to report on
+                        if args.json {
+                            eprintln!("{message}");
+                        } else {
+                            println!("{message}");
+                        }
+                        return Ok(0);
+                    }
+                },
+                None => queried_trashes(&ctx, args.all_trashes),
+            };
+            for trash in &trashes {
+                ctx.verify(trash)?;
+            }
+            recover(&trashes);
+
+            let mut selections = Vec::new();
+            for trash in &trashes {
+                match entries::entries(trash) {
+                    Ok(entries) => selections.push((trash, entries)),
+                    Err(err) if args.all_trashes => {
+                        report::warn(format_args!(
+                            "skipping the {}: {}",
+                            trash_name(&ctx, trash),
+                            report::display_message(&err)
+                        ));
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            for name in &args.operands {
+                let is_found = selections
+                    .iter()
+                    .any(|(_, entries)| entries.iter().any(|entry| entry.name == *name));
+                if !is_found {
+                    return Err(Error::EntryNotFound(name.into()));
+                }
+            }
+            let is_selected = |entry: &entries::TrashEntry| {
+                args.operands.is_empty() || args.operands.contains(&entry.name)
+            };
+            if args.filter.skips_undated() {
+                let undated = selections
+                    .iter()
+                    .flat_map(|(_, entries)| entries)
+                    .filter(|entry| is_selected(entry) && entry.deletion_date.is_none())
+                    .count();
+                if undated > 0 {
+                    report::warn(format_args!(
+                        "skipping {undated} {} without a valid deletion date, which --include-undated deletes as well",
+                        if undated == 1 { "entry" } else { "entries" }
+                    ));
+                }
+            }
+
+            // Each trash, with the entries to be deleted from it, those `--keep-last` keeps there and the stamps of
+            // the entries to be deleted, which tell whether they're still the ones confirmed once the trash is locked
+            let mut emptying = Vec::new();
+            for (trash, mut to_be_purged) in selections {
+                to_be_purged.retain(|entry| is_selected(entry) && args.filter.matches(entry));
+                let kept = match args.keep_last {
+                    Some(count) => {
+                        let (kept, rest) = entries::keep_newest(to_be_purged, count);
+                        to_be_purged = rest;
+                        kept
+                    }
+                    None => vec![],
+                };
+                let stamps: Vec<_> = to_be_purged
+                    .iter()
+                    .map(|entry| entries::Stamp::of(trash, entry))
+                    .collect();
+                emptying.push((trash, to_be_purged, kept, stamps));
+            }
+            // Trashes are only named when there may be several, or when it was looked for on a mount point
+            let in_trash = |preposition: &str, trash: &Trash| {
+                if args.all_trashes || args.mount.is_some() {
+                    format!(", {preposition} the {},", trash_name(&ctx, trash))
+                } else {
+                    String::new()
+                }
+            };
+            let purge = |trash: &Trash, entry: &entries::TrashEntry, stamp| {
+                entries::assert_unchanged(trash, entry, stamp)?;
+                if args.shred {
+                    entries::shred_and_purge(&RealFs, trash, &entry.name)
+                } else {
+                    entries::purge(&RealFs, trash, &entry.name)
+                }
+            };
+            // `--json` is only given along with a single trash
+            let emptied = |confirmed, deleted, failed| {
+                let (trash, _, kept, _) = &emptying[0];
+                json::Document::Empty {
+                    trash: report::escape_path(trash.root.as_path()),
+                    confirmed,
+                    deleted,
+                    kept: kept
+                        .iter()
+                        .map(|entry| report::escape_path(entry.name.as_ref()))
+                        .collect(),
+                    failed,
+                }
+            };
+
+            for (trash, _, kept, _) in &emptying {
+                if !kept.is_empty() {
+                    report::info(format_args!(
+                        "keeping{} the most recently trashed {}",
+                        in_trash("in", trash),
+                        entries::Summary::of(trash, kept)?
+                    ));
+                }
+            }
+
+            if emptying
+                .iter()
+                .all(|(_, to_be_purged, _, _)| to_be_purged.is_empty())
+            {
+                if args.json {
+                    json::print(&emptied(true, vec![], vec![]));
+                } else {
+                    println!("tt: nothing to delete");
+                }
+                return Ok(0);
+            }
+
+            for (trash, to_be_purged, _, _) in &emptying {
+                if to_be_purged.is_empty() {
+                    continue;
+                }
+                report::info(format_args!(
+                    "about to permanently delete{} {}",
+                    in_trash("from", trash),
+                    entries::Summary::of(trash, to_be_purged)?
+                ));
+
+                if !args.quiet {
+                    warn_about_holders(trash, to_be_purged);
+                }
+            }
+
+            // As rm -f, -f never asks
+            let confirmed = prompt::Prompter::on_terminal(args.yes || args.force)
+                .confirm("tt: delete them?")?;
+
+            if !confirmed {
+                if args.json {
+                    json::print(&emptied(false, vec![], vec![]));
+                } else {
+                    println!("tt: nothing was deleted");
+                }
+                return Ok(FAILURE);
+            }
+
+            if args.json {
+                let (trash, to_be_purged, _, stamps) = &emptying[0];
+                let _lock = lock::TrashLock::exclusive(trash)?;
+                let (mut deleted, mut failed) = (Vec::new(), Vec::new());
+                for (entry, stamp) in to_be_purged.iter().zip(stamps) {
+                    match purge(trash, entry, *stamp) {
+                        Ok(()) => deleted.push(report::escape_path(entry.name.as_ref())),
+                        Err(err) => {
+                            report::error_while(
+                                format_args!(
+                                    "failed to delete {}",
+                                    report::display_path(entry.name.as_ref())
+                                ),
+                                &err,
+                            );
+                            failed.push(json::EntryFailure::new(&entry.name, &err));
+                        }
+                    }
+                }
+
+                let document = emptied(true, deleted, failed);
+                json::print(&document);
+                return Ok(if document.has_failures() { FAILURE } else { 0 });
+            }
+
+            // An entry that can't be deleted doesn't keep the others from being deleted
+            let (mut deleted, mut failed) = (0, 0);
+            for (trash, to_be_purged, _, stamps) in &emptying {
+                if to_be_purged.is_empty() {
+                    continue;
+                }
+                let _lock = lock::TrashLock::exclusive(trash)?;
+                for (entry, stamp) in to_be_purged.iter().zip(stamps) {
+                    match purge(trash, entry, *stamp) {
+                        Ok(()) => deleted += 1,
+                        Err(err) => {
+                            report::error_while(
+                                format_args!(
+                                    "failed to delete {}",
+                                    report::display_path(entry.name.as_ref())
+                                ),
+                                &err,
+                            );
+                            failed += 1;
+                        }
+                    }
+                }
+            }
+
+            println!("tt: permanently deleted {deleted} entries");
+            if failed > 0 {
+                return Ok(FAILURE);
+            }
+        }
+        Mode::Purge(name) => {
+            let trash = ctx.target_trash();
+            ctx.verify(trash)?;
+            recover(std::slice::from_ref(trash));
+
+            // Entries are only deleted if they're still the ones confirmed (see `entries::assert_unchanged`)
+            let purge = |entry: &entries::TrashEntry, stamp| {
+                entries::assert_unchanged(trash, entry, stamp)?;
+                if args.shred {
+                    entries::shred_and_purge(&RealFs, trash, &entry.name)
+                } else {
+                    entries::purge(&RealFs, trash, &entry.name)
+                }
+            };
+
+            // Names of entries take precedence over patterns
+            if glob::is_pattern(&name) && restore::find_entry(trash, &name).is_err() {
+                let matching = entries::find_matching(trash, &name, args.by_path)?;
+                let stamps: Vec<_> = matching
+                    .iter()
+                    .map(|entry| entries::Stamp::of(trash, entry))
+                    .collect();
+                if matching.is_empty() {
+                    return if args.force {
+                        Ok(0)
+                    } else {
+                        Err(Error::NoMatchingEntry(name.into()))
+                    };
+                }
+
+                report::info(format_args!(
+                    "about to permanently delete {}",
+                    entries::Summary::of(trash, &matching)?
+                ));
+                // As with --empty, -f never asks
+                if !prompt::Prompter::on_terminal(args.yes || args.force)
+                    .confirm("tt: delete them?")?
+                {
+                    println!("tt: nothing was deleted");
+                    return Ok(FAILURE);
+                }
+
+                let _lock = lock::TrashLock::exclusive(trash)?;
+                let mut failed = 0;
+                for (entry, stamp) in matching.iter().zip(stamps) {
+                    let name = report::display_path(entry.name.as_ref());
+                    match purge(entry, stamp) {
+                        Ok(()) => println!("tt: permanently deleted {name}"),
+                        Err(err) => {
+                            report::error_while(format_args!("failed to delete {name}"), &err);
+                            failed += 1;
+                        }
+                    }
+                }
+                return Ok(if failed > 0 { FAILURE } else { 0 });
+            }
+
+            let entry = entries::find_by_prefix(trash, &name)?;
+            let stamp = entries::Stamp::of(trash, &entry);
+            let name = report::display_path(entry.name.as_ref());
+            let question = match &entry.original_path {
+                Some(path) => format!(
+                    "tt: permanently delete {name}, trashed from {}?",
+                    report::display_path(path)
+                ),
+                None => format!("tt: permanently delete {name}?"),
+            };
+            if !prompt::Prompter::on_terminal(args.yes || args.force).confirm(&question)? {
+                println!("tt: nothing was deleted");
+                return Ok(FAILURE);
+            }
+
+            let _lock = lock::TrashLock::exclusive(trash)?;
+            purge(&entry, stamp)?;
+            println!("tt: permanently deleted {name}");
+        }
+        Mode::Dedupe => {
+            let trash = ctx.target_trash();
+            ctx.verify(trash)?;
+            recover(std::slice::from_ref(trash));
+
+            let _lock = lock::TrashLock::exclusive(trash)?;
+            let deduplication = dedupe::dedupe(&RealFs, trash)?;
+            println!(
+                "tt: replaced {} duplicate file{} with hard links, saving {}",
+                deduplication.linked,
+                if deduplication.linked == 1 { "" } else { "s" },
+                usage::human_size(deduplication.saved)
+            );
+        }
+        Mode::Size => {
+            // Looking must not touch, so there's no recovering here
+            let trashes = queried_trashes(&ctx, args.all_trashes);
+            let mut total = entries::Summary::default();
+            for trash in &trashes {
+                let summary = match entries::entries(trash)
+                    .and_then(|entries| entries::Summary::of(trash, &entries))
+                {
+                    Ok(summary) => summary,
+                    Err(err) if args.all_trashes => {
+                        report::warn(format_args!(
+                            "skipping the {}: {}",
+                            trash_name(&ctx, trash),
+                            report::display_message(&err)
+                        ));
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                };
+                println!("{}: {}", trash_name(&ctx, trash), summary.describe_size());
+
+                total.count += summary.count;
+                total.total_size += summary.total_size;
+                total.original_size += summary.original_size;
+            }
+
+            if trashes.len() > 1 {
+                println!("total: {}", total.describe_size());
+            }
+        }
+        Mode::Daemon => {
+            let _lock = daemon::DaemonLock::acquire(&home_dir::state_dir_from(&env, &home_dir))?;
+            ffi::catch_termination()?;
+            let interval = args.interval.unwrap_or(daemon::DEFAULT_INTERVAL);
+
+            loop {
+                // Enumerated again on every pass, as filesystems come and go
+                let trashes = queried_trashes(&ctx, args.all_trashes);
+                recover(&trashes);
+                for (trash, purged) in daemon::pass(&ctx, &trashes, SystemTime::now()) {
+                    report_purged(trash, purged);
+                }
+
+                if args.once || !daemon::wait(daemon::jittered(interval, daemon::random_seed())) {
+                    break;
+                }
+            }
+        }
+        Mode::Help | Mode::Version => unreachable!("handled before anything else"),
+        Mode::Completions(shell) => {
+            print!("{}", completions::script(&shell)?);
+        }
+        Mode::Complete(kind) => {
+            let prefix = args.operands.first().map(|prefix| prefix.to_string_lossy());
+            for candidate in
+                completions::complete(ctx.target_trash(), &kind, prefix.as_deref().unwrap_or(""))?
+            {
+                println!("{candidate}");
+            }
         }
     }
 
-    Ok(())
+    Ok(0)
 }
 
-/// Tries to trash a file (given by `path` which is located in a non-home mount point)
-fn trash_file_in_other_mount_point(path: PathBuf) -> Result<()> {
-    // Try to find the mount point of this file
-    let mount_point = find_mount_point_of_file(&path)?;
-    let topdir = &mount_point.fs_path_prefix;
+/// Asks once whether to trash `operands`, for `-I`, when there are more than three of them or any directory
+fn confirm_trashing(
+    prompter: &mut prompt::Prompter<impl BufRead, impl Write>,
+    operands: &[OsString],
+) -> Result<bool> {
+    let count = operands.len();
+    let has_directories = operands.iter().any(|operand| {
+        file_uri::operand_path(operand)
+            .and_then(|path| Ok(path.symlink_metadata()?))
+            .is_ok_and(|metadata| metadata.is_dir())
+    });
+    if count <= 3 && !has_directories {
+        return Ok(true);
+    }
 
-    // Check if a valid trash already exists in this mount point
-    if let Ok(trash) = Trash::from_root_checked(topdir) {
-        trash.send_to_trash(&path)?;
-        return Ok(());
+    let arguments = if count == 1 { "argument" } else { "arguments" };
+    let directories = if has_directories {
+        ", directories included"
+    } else {
+        ""
     };
+    prompter.confirm(&format!("tt: trash {count} {arguments}{directories}?"))
+}
 
-    // If a $topdir/.Trash does not exist or has not passed the checks, check if `$topdir/.Trash-$uid` exists.
-    // If a $topdir/.Trash-$uid directory does not exist, the implementation must immediately create it, without any warnings or delays for the user.
-    // TODO: should we use the effective user ID here?
-    let uid = real_user_id();
+/// Restores the entry of `trash` named `name` where it was trashed from, or into the directory `restore_to` if given
+/// (see `--restore-to`)
+fn restore_entry(
+    trash: &Trash,
+    name: &OsStr,
+    restore_to: Option<&OsStr>,
+    options: restore::RestoreOptions,
+) -> Result<restore::RestorePlan> {
+    match restore_to {
+        Some(directory) => restore::restore_into(
+            &RealFs,
+            trash,
+            name,
+            &fs::normalize_path(directory.as_ref())?,
+            options,
+        ),
+        None => restore::restore(&RealFs, trash, name, options),
+    }
+}
+
+/// Tells what restoring took, or would take if `dry_run` is set, as steps or as a JSON document
+fn print_restored(
+    plans: &[restore::RestorePlan],
+    failed: Vec<json::EntryFailure>,
+    dry_run: bool,
+    json: bool,
+) {
+    if json {
+        json::print(&json::Document::Restore {
+            dry_run,
+            restored: plans.iter().map(json::Restored::from).collect(),
+            failed,
+        });
+        return;
+    }
+
+    for step in plans.iter().flat_map(|plan| plan.describe(dry_run)) {
+        println!("{step}");
+    }
+}
+
+/// Restores the entries `found` as a batch (see [`restore::order_batch`]), reporting what became of each of them,
+/// and returns the exit status: 1 if any couldn't be restored
+fn restore_found(
+    found: Vec<which::Found>,
+    options: restore::RestoreOptions,
+    dry_run: bool,
+    json: bool,
+) -> i32 {
+    let batch = restore::order_batch(found);
+    let (mut plans, mut failed) = (Vec::new(), Vec::new());
+    for (batched, result) in batch
+        .iter()
+        .zip(restore::restore_batch(&RealFs, &batch, options))
+    {
+        match result {
+            Ok(plan) => plans.push(plan),
+            Err(err) => {
+                report::error_while(
+                    format_args!(
+                        "failed to restore {}",
+                        report::display_path(batched.name.as_ref())
+                    ),
+                    &err,
+                );
+                failed.push(json::EntryFailure::new(&batched.name, &err));
+            }
+        }
+    }
 
-    let trash_uid_path = topdir.join(format!(".Trash-{}", uid));
+    let any_failed = !failed.is_empty();
+    print_restored(&plans, failed, dry_run, json);
+    if any_failed {
+        FAILURE
+    } else {
+        0
+    }
+}
 
-    let trash = if let Ok(trash) = Trash::from_root_checked(&trash_uid_path) {
-        trash
+/// The trashes `--list`, `--empty` and `--size` go over: with `all_trashes`, every trash of the current user, starting
+/// with the home trash, and otherwise the one targeted
+fn queried_trashes(ctx: &TrashContext, all_trashes: bool) -> Vec<Trash> {
+    if all_trashes {
+        ctx.known_trashes()
     } else {
-        let trash = Trash::from_root(&trash_uid_path)?;
-        fs_err::create_dir(&trash.info)?;
-        fs_err::create_dir(&trash.files)?;
-        fs_err::File::create(&trash.directory_sizes)?;
+        vec![ctx.target_trash().clone()]
+    }
+}
 
-        trash
-    };
+/// How `trash` is referred to in messages: `home trash` or `trash at ROOT`
+fn trash_name(ctx: &TrashContext, trash: &Trash) -> String {
+    if trash.root == ctx.home_trash().root {
+        "home trash".into()
+    } else {
+        format!("trash at {}", report::display_path(trash.root.as_path()))
+    }
+}
 
-    trash.send_to_trash(&path)?;
+/// Warns about the trashes in `trashes` (among which `home_trash` might be) that are over `limits`, unless a warning was already shown in the last day
+/// according to the file at `stamp`
+fn warn_about_usage(
+    trashes: &[Trash],
+    home_trash: &Trash,
+    limits: &usage::UsageLimits,
+    stamp: &Path,
+) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    if trashes.is_empty() || !usage::is_warning_due(stamp, now) {
+        return;
+    }
+
+    let mut warned = false;
+    for trash in trashes {
+        // Not being able to tell is no reason to bother anyone
+        if let Ok(Some(warning)) =
+            usage::check_usage(trash, trash.root == home_trash.root, limits, &MOUNT_POINTS)
+        {
+            report::warn(&warning);
+            warned = true;
+        }
+    }
+
+    if warned {
+        if let Err(err) = usage::record_warning(stamp, now) {
+            report::warn(format_args!(
+                "failed to record when the last warning was shown: {}",
+                report::display_message(&err)
+            ));
+        }
+    }
+}
+
+/// Warns about the processes holding `to_be_purged`, entries of `trash`, open, since deleting them won't free
+/// their space until these exit
+fn warn_about_holders(trash: &Trash, to_be_purged: &[entries::TrashEntry]) {
+    let files =
+        std::fs::canonicalize(&trash.files).unwrap_or_else(|_| trash.files.as_path().to_owned());
+
+    for holder in open_files::holders(&files, open_files::SCAN_TIME_LIMIT) {
+        let count = to_be_purged
+            .iter()
+            .filter(|entry| holder.entries.contains(&entry.name))
+            .count();
+        if count == 0 {
+            continue;
+        }
+
+        report::info(format_args!(
+            "note: {count} {} still open by pid {} ({}), space will be freed when it exits",
+            if count == 1 {
+                "entry is"
+            } else {
+                "entries are"
+            },
+            holder.pid,
+            holder.command
+        ));
+    }
+}
+
+/// Reports what was purged from `trash` to stay within the limits of its policy, or why that failed
+fn report_purged(trash: &Trash, purged: Result<Vec<OsString>>) {
+    match purged {
+        Ok(purged) => {
+            for name in purged {
+                report::info(format_args!(
+                    "purged {} from {} to stay within the limits of its policy",
+                    report::display_path(name.as_ref()),
+                    report::display_path(trash.root.as_path())
+                ));
+            }
+        }
+        Err(err) => report::warn(format_args!(
+            "failed to enforce the limits of {}: {}",
+            report::display_path(trash.root.as_path()),
+            report::display_message(&err)
+        )),
+    }
+}
 
-    Ok(())
+/// Cleans up what interrupted runs left in `trashes`, before enumerating them.
+///
+/// Failing to do so is not a reason to fail what was asked, so only warnings are shown.
+fn recover(trashes: &[Trash]) {
+    for trash in trashes {
+        match recovery::recover(&RealFs, trash, recovery::GRACE_PERIOD, SystemTime::now()) {
+            Ok(removed) => {
+                for finding in removed {
+                    report::info(format_args!(
+                        "removed leftover {} {}",
+                        finding.kind(),
+                        report::display_path(finding.path())
+                    ));
+                }
+            }
+            Err(err) => report::warn(format_args!(
+                "failed to clean up {}: {}",
+                report::display_path(trash.root.as_path()),
+                report::display_message(&err)
+            )),
+        }
+    }
 }
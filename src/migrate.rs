@@ -0,0 +1,270 @@
+//! Moving entries from a trash to another, e.g. out of a removable drive's trash before unplugging it.
+//!
+//! A migrated entry is trashed into its new trash the same way a file would be: its new info file is
+//! written first, under a name that's unique there, and the entry itself is moved after that. Only then
+//! is its old info file removed, so an entry is always whole in one of the two trashes.
+
+use std::{
+    ffi::{OsStr, OsString},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use unixstring::UnixString;
+
+use crate::{
+    directorysizes::{read_directory_sizes, remove_directory_size, update_directory_sizes},
+    entries::entry_size,
+    error::Result,
+    ffi::format_timestamp,
+    fs::{move_file, share_with_group, FileSystem},
+    info_file::{build_info_file_path, read_info_file, TrashInfo},
+    report::{display_path, warn},
+    trash::{NameAttempts, Trash},
+};
+
+/// Moves the entry of `from` named `name` into `to`, returning its name there.
+///
+/// Its original path and deletion date are kept, though the path is made relative or absolute
+/// as `to` wants it (see [`Trash::path_in_info_file`]). Names taken in `to` are made unique like
+/// they are when trashing. Entries whose info file can't be read are refused, as there'd be no
+/// telling where they came from.
+///
+/// If moving the entry fails, it's left whole in `from`. Failing to update `directorysizes`
+/// afterwards only warrants a warning, since it's a cache. Security contexts are only copied along to
+/// another filesystem when `preserve_context` is set.
+pub fn migrate(
+    fs: &impl FileSystem,
+    from: &Trash,
+    name: &OsStr,
+    to: &Trash,
+    preserve_context: bool,
+) -> Result<OsString> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
+    let source_path = from.files.as_path().join(name);
+    let source_info_path = build_info_file_path(name, from.info_path());
+    let source_info = read_info_file(&source_info_path)?;
+    let original_path = from.resolve_original_path(&source_info.path)?;
+
+    let info = TrashInfo {
+        path: to.path_in_info_file(&original_path),
+        deletion_date: match source_info.deletion_date {
+            Some(deletion_date) => Some(deletion_date),
+            None => Some(format_timestamp(now)?),
+        },
+        ..source_info
+    };
+
+    // Symlinks to directories are not followed
+    let unx: UnixString = source_path.clone().try_into()?;
+    // Archives have their size in `$trash/directorysizes` too
+    let directory_size = if fs.lstat(unx.as_c_str())?.is_directory() || info.compressed.is_some() {
+        Some(entry_size(from, name, &read_directory_sizes(from)?)?)
+    } else {
+        None
+    };
+
+    let (new_name, marker_path, info_file_path) =
+        to.reserve_name(fs, name, &info, &NameAttempts::default())?;
+    let destination = to.files.as_path().join(&new_name);
+    let shared_group = to.shared_group();
+
+    let moved = match shared_group {
+        Some(gid) => share_with_group(&info_file_path, gid),
+        None => Ok(()),
+    }
+    .and_then(|()| move_file(fs, &source_path, &destination, preserve_context));
+
+    if let Err(err) = moved {
+        // The entry is still whole in `from`
+        fs.remove_file(&info_file_path)?;
+        fs.remove_file(&marker_path)?;
+        return Err(err);
+    }
+
+    // The entry is whole in `to`, so what's left of it in `from` must go
+    fs.remove_file(&source_info_path)?;
+
+    if let Err(err) = fs.remove_file(&marker_path) {
+        warn(format_args!(
+            "failed to remove {}: {}",
+            display_path(&marker_path),
+            err
+        ));
+    }
+
+    if let Some(gid) = shared_group {
+        if let Err(err) = share_with_group(&destination, gid) {
+            warn(format_args!(
+                "failed to share {} with the trash's group: {}",
+                display_path(&destination),
+                err
+            ));
+        }
+    }
+
+    if let Some(directory_size) = directory_size {
+        let updated = update_directory_sizes(fs, to, directory_size, &new_name, now)
+            .and_then(|()| remove_directory_size(fs, from, name));
+
+        if let Err(err) = updated {
+            warn(format_args!(
+                "failed to move the size of {} to {}: {}",
+                display_path(new_name.as_ref()),
+                display_path(to.directory_sizes.as_path()),
+                err
+            ));
+        }
+    }
+
+    Ok(new_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        os::unix::fs::symlink,
+        path::Path,
+    };
+
+    use super::migrate;
+    use crate::{
+        directorysizes::read_directory_sizes,
+        entries::entries,
+        error::Result,
+        fs::RealFs,
+        info_file::read_info_file,
+        recovery::marker_path,
+        tests::{FaultyFs, Op},
+        trash::Trash,
+    };
+
+    fn dummy_trash(root: &Path) -> Result<Trash> {
+        let trash = Trash::from_root(root)?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        File::create(&trash.directory_sizes)?;
+
+        Ok(trash)
+    }
+
+    #[test]
+    fn migrates_entries_between_trashes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let topdir = dir.path().join("usb");
+        let usb_trash = dummy_trash(&topdir.join(".Trash-1000"))?;
+        let home_trash = dummy_trash(&dir.path().join("home/.local/share/Trash"))?;
+
+        // A file, a directory, a symlink and a file whose name is already taken in the home trash
+        fs::create_dir_all(topdir.join("photos/2024"))?;
+        fs::write(topdir.join("notes.txt"), "notes")?;
+        fs::write(topdir.join("photos/2024/beach.jpg"), vec![0; 100])?;
+        symlink("notes.txt", topdir.join("link"))?;
+        fs::write(topdir.join("todo"), "usb")?;
+        for name in ["notes.txt", "photos", "link", "todo"] {
+            usb_trash.send_to_trash(&RealFs, &topdir.join(name))?;
+        }
+        fs::write(dir.path().join("todo"), "home")?;
+        home_trash.send_to_trash(&RealFs, &dir.path().join("todo"))?;
+
+        let deletion_date =
+            read_info_file(&usb_trash.info_path().join("notes.txt.trashinfo"))?.deletion_date;
+        let mut migrated = Vec::new();
+        for entry in entries(&usb_trash)? {
+            migrated.push(migrate(
+                &RealFs,
+                &usb_trash,
+                &entry.name,
+                &home_trash,
+                true,
+            )?);
+        }
+        migrated.sort();
+
+        assert!(entries(&usb_trash)?.is_empty());
+        assert_eq!(fs::read_dir(usb_trash.info_path())?.count(), 0);
+        assert!(read_directory_sizes(&usb_trash)?.is_empty());
+
+        let home_entries = entries(&home_trash)?;
+        assert_eq!(home_entries.len(), 5);
+        assert!(migrated
+            .iter()
+            .any(|name| name != "todo" && name.to_string_lossy().starts_with("todo")));
+        assert_eq!(
+            fs::read_to_string(home_trash.files.as_path().join("todo"))?,
+            "home"
+        );
+
+        for entry in &home_entries {
+            // The home trash gets absolute paths
+            let raw_path = entry.raw_path.as_ref().unwrap();
+            assert!(raw_path.is_absolute());
+            assert!(!marker_path(&home_trash, &entry.name).exists());
+        }
+        let notes = home_entries
+            .iter()
+            .find(|entry| entry.name == "notes.txt")
+            .unwrap();
+        assert_eq!(
+            notes.original_path.as_deref(),
+            Some(&*topdir.join("notes.txt"))
+        );
+        assert_eq!(
+            read_info_file(&home_trash.info_path().join("notes.txt.trashinfo"))?.deletion_date,
+            deletion_date
+        );
+        assert_eq!(
+            fs::read_link(home_trash.files.as_path().join("link"))?,
+            Path::new("notes.txt")
+        );
+        assert_eq!(
+            fs::read(home_trash.files.as_path().join("photos/2024/beach.jpg"))?,
+            vec![0; 100]
+        );
+        let sizes = read_directory_sizes(&home_trash)?;
+        assert_eq!(sizes.len(), 1);
+        assert_eq!(
+            (sizes[0].name.to_str(), sizes[0].size),
+            (Some("photos"), 100)
+        );
+
+        // And back, where paths are relative to the drive again
+        migrate(&RealFs, &home_trash, "notes.txt".as_ref(), &usb_trash, true)?;
+        let info = read_info_file(&usb_trash.info_path().join("notes.txt.trashinfo"))?;
+        assert_eq!(info.path, Path::new("notes.txt"));
+        assert_eq!(info.deletion_date, deletion_date);
+
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_entries_whole_when_failing() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let from = dummy_trash(&dir.path().join("from"))?;
+        let to = dummy_trash(&dir.path().join("to"))?;
+
+        let dummy_path = dir.path().join("dummy");
+        fs::write(&dummy_path, "dummy")?;
+        from.send_to_trash(&RealFs, &dummy_path)?;
+
+        // As if `to` was on another device, and copying there failed
+        let faulty = FaultyFs::failing(Op::Rename, 1, libc::EXDEV).and(Op::Copy, 1, libc::EIO);
+        assert!(migrate(&faulty, &from, "dummy".as_ref(), &to, true).is_err());
+
+        assert_eq!(entries(&from)?.len(), 1);
+        assert!(from.info_path().join("dummy.trashinfo").exists());
+        assert!(entries(&to)?.is_empty());
+        assert_eq!(fs::read_dir(to.info_path())?.count(), 0);
+        assert_eq!(fs::read_dir(to.files.as_path())?.count(), 0);
+
+        // Nothing is in the way of trying again
+        assert_eq!(
+            migrate(&RealFs, &from, "dummy".as_ref(), &to, true)?,
+            "dummy"
+        );
+        assert!(entries(&from)?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+//! The mount table, as a handle that probes it again when it looks out of date.
+//!
+//! It's probed once at startup, but filesystems may be mounted while `tt` runs (during a long batch, say),
+//! in which case the files on them would be looked up in a table that doesn't know about them.
+
+use std::{
+    cell::{Cell, Ref, RefCell},
+    fmt,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::Result,
+    ffi::{innermost_mount_point, MountPoint},
+    stats::{self, Phase},
+};
+
+/// How long to wait before probing the mount table again, so that lookups that keep missing don't keep probing
+pub const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Probes the mount table
+type Probe = Box<dyn Fn() -> Result<Vec<MountPoint>>>;
+
+pub struct MountTable {
+    mount_points: RefCell<Vec<MountPoint>>,
+    probe: Probe,
+    /// When the table was last probed again, if ever
+    last_refresh: Cell<Option<Instant>>,
+}
+
+impl MountTable {
+    /// A table holding `mount_points`, which `probe` is called to probe again
+    pub fn new(
+        mount_points: Vec<MountPoint>,
+        probe: impl Fn() -> Result<Vec<MountPoint>> + 'static,
+    ) -> Self {
+        Self {
+            mount_points: RefCell::new(mount_points),
+            probe: Box::new(probe),
+            last_refresh: Cell::new(None),
+        }
+    }
+
+    /// The mount points, as last probed
+    pub fn mount_points(&self) -> Ref<'_, [MountPoint]> {
+        Ref::map(self.mount_points.borrow(), Vec::as_slice)
+    }
+
+    /// The mount point containing `path` (see [`innermost_mount_point`]).
+    ///
+    /// If none does, or if `is_current` says that the one found can't be the right one, the table is probed
+    /// again (see [`MIN_REFRESH_INTERVAL`]) and `path` looked up in the new table.
+    pub fn find(
+        &self,
+        path: &Path,
+        is_current: impl Fn(&MountPoint) -> bool,
+    ) -> Option<MountPoint> {
+        let found = innermost_mount_point(&self.mount_points(), path).cloned();
+        if found.as_ref().is_some_and(&is_current) || !self.refresh() {
+            return found;
+        }
+
+        innermost_mount_point(&self.mount_points(), path).cloned()
+    }
+
+    /// Probes the table again, unless it was done less than [`MIN_REFRESH_INTERVAL`] ago, returning whether it was.
+    ///
+    /// Failing to probe it keeps the table as it was.
+    fn refresh(&self) -> bool {
+        let now = Instant::now();
+        let is_due = self
+            .last_refresh
+            .get()
+            .is_none_or(|last| now.duration_since(last) >= MIN_REFRESH_INTERVAL);
+        if !is_due {
+            return false;
+        }
+        self.last_refresh.set(Some(now));
+
+        let started = stats::start();
+        let probed = (self.probe)();
+        stats::finish(Phase::Probe, started);
+        match probed {
+            Ok(mount_points) => {
+                *self.mount_points.borrow_mut() = mount_points;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// A table that never changes, probing it again giving the same mount points
+impl From<Vec<MountPoint>> for MountTable {
+    fn from(mount_points: Vec<MountPoint>) -> Self {
+        let probed = mount_points.clone();
+        Self::new(mount_points, move || Ok(probed.clone()))
+    }
+}
+
+impl fmt::Debug for MountTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MountTable")
+            .field("mount_points", &self.mount_points)
+            .field("last_refresh", &self.last_refresh)
+            .finish_non_exhaustive()
+    }
+}
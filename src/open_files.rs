@@ -0,0 +1,109 @@
+//! Finding the processes that hold trashed files open, whose space deleting them doesn't free until they close them.
+//!
+//! Done by going through `/proc/*/fd`, skipping what we're not allowed to read (other users' processes, mostly) or
+//! what's gone by the time we get to it, and giving up on the rest once [`SCAN_TIME_LIMIT`] is up.
+
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    fs,
+    path::{Component, Path},
+    time::{Duration, Instant},
+};
+
+/// How long scanning `/proc` may take, past which the processes not yet looked at are left out
+pub const SCAN_TIME_LIMIT: Duration = Duration::from_secs(2);
+
+/// A process holding entries of a trash open
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Holder {
+    pub pid: u32,
+    /// The name of its command, as in `/proc/PID/comm`
+    pub command: String,
+    /// The names of the entries it holds files of
+    pub entries: BTreeSet<OsString>,
+}
+
+/// The processes holding open files under `files`, the `files` directory of a trash, ordered by PID.
+///
+/// Stops looking once `time_limit` is up, so processes may be missed, but never made up.
+pub fn holders(files: &Path, time_limit: Duration) -> Vec<Holder> {
+    let started = Instant::now();
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return vec![];
+    };
+
+    let mut pids: Vec<u32> = processes
+        .filter_map(|process| process.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    pids.sort_unstable();
+
+    let mut holders = Vec::new();
+    for pid in pids {
+        if started.elapsed() > time_limit {
+            break;
+        }
+
+        let entries = held_entries(pid, files);
+        if entries.is_empty() {
+            continue;
+        }
+
+        let command = fs::read_to_string(format!("/proc/{pid}/comm"))
+            .map(|command| command.trim_end().to_owned())
+            .unwrap_or_default();
+        holders.push(Holder {
+            pid,
+            command,
+            entries,
+        });
+    }
+
+    holders
+}
+
+/// The names of the entries of `files` that the process `pid` has files of open, if we may know
+fn held_entries(pid: u32, files: &Path) -> BTreeSet<OsString> {
+    let Ok(descriptors) = fs::read_dir(format!("/proc/{pid}/fd")) else {
+        return BTreeSet::new();
+    };
+
+    descriptors
+        .filter_map(|descriptor| fs::read_link(descriptor.ok()?.path()).ok())
+        .filter_map(
+            |target| match target.strip_prefix(files).ok()?.components().next()? {
+                Component::Normal(name) => Some(name.to_owned()),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, fs::File};
+
+    use super::{holders, SCAN_TIME_LIMIT};
+    use crate::error::Result;
+
+    #[test]
+    fn finds_who_holds_entries_open() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let files = dir.path().canonicalize()?.join("files");
+        fs::create_dir_all(files.join("dir"))?;
+        fs::write(files.join("dir/inner"), "dummy")?;
+        fs::write(files.join("closed"), "dummy")?;
+
+        let _open = File::open(files.join("dir/inner"))?;
+
+        let holders = holders(&files, SCAN_TIME_LIMIT);
+        let us = holders
+            .iter()
+            .find(|holder| holder.pid == std::process::id())
+            .expect("this process holds an entry open");
+        assert_eq!(us.entries.iter().collect::<Vec<_>>(), ["dir"]);
+        assert!(!us.command.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+//! Picking the entries to restore with an external command, such as `fzf`, for `--picker`.
+//!
+//! The picker gets a line per entry on its stdin and prints the lines picked on its stdout. Lines are made of
+//! tab-separated fields: an id, the name of the entry, when it was trashed and where it was trashed from.
+//! Only the id is read back, so the other fields are escaped (see [`escape_path`]) to fit on a line without
+//! having to survive the round trip. Entries without a deletion date or original path have `-` instead.
+
+use std::{
+    collections::BTreeSet,
+    io::{ErrorKind, Write},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use crate::{
+    entries::TrashEntry,
+    error::{Error, Result},
+    report::escape_path,
+};
+
+/// The line standing for `entry`, identified by `id`
+pub fn candidate_line(id: usize, entry: &TrashEntry) -> String {
+    let deletion_date = entry
+        .deletion_date
+        .map_or_else(|| "-".into(), |date| date.to_string());
+    let original_path = entry
+        .original_path
+        .as_ref()
+        .map_or_else(|| "-".into(), |path| escape_path(path));
+
+    format!(
+        "{id}\t{}\t{deletion_date}\t{original_path}",
+        escape_path(Path::new(&entry.name))
+    )
+}
+
+/// Runs `command` through `sh -c` with `lines` on its stdin, returning the ids (see [`candidate_line`]) of the
+/// lines it printed, in order and without repeats.
+///
+/// A command exiting unsuccessfully, as pickers do when the user backs out, means nothing was picked: `None`.
+/// Fails with [`Error::InvalidPickerOutput`] if it prints a line it wasn't given.
+pub fn pick(command: &str, lines: Vec<String>) -> Result<Option<Vec<usize>>> {
+    let candidates = lines.len();
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    // Written from another thread, as the picker may print before it's done reading
+    let mut stdin = child.stdin.take().expect("the picker's stdin is piped");
+    let writer = thread::spawn(move || {
+        let written = lines.iter().try_for_each(|line| writeln!(stdin, "{line}"));
+        match written {
+            // Pickers don't have to read all of their input
+            Err(err) if err.kind() != ErrorKind::BrokenPipe => Err(err),
+            _ => Ok(()),
+        }
+    });
+
+    let output = child.wait_with_output()?;
+    writer
+        .join()
+        .expect("writing to the picker doesn't panic")?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let mut seen = BTreeSet::new();
+    let mut picked = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let id = line.split('\t').next().unwrap_or_default();
+        match id.parse::<usize>() {
+            Ok(id) if id < candidates => {
+                if seen.insert(id) {
+                    picked.push(id);
+                }
+            }
+            _ => return Err(Error::InvalidPickerOutput(line.to_owned())),
+        }
+    }
+
+    Ok(Some(picked))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    use super::{candidate_line, pick};
+    use crate::{date::DateTime, entries::TrashEntry, error::Result, Error};
+
+    fn entry(name: &[u8], original_path: Option<&str>) -> TrashEntry {
+        TrashEntry {
+            name: OsStr::from_bytes(name).to_owned(),
+            raw_path: original_path.map(Into::into),
+            original_path: original_path.map(Into::into),
+            deletion_date: DateTime::parse("2024-03-01T10:00:00"),
+            original_size: None,
+        }
+    }
+
+    fn lines() -> Vec<String> {
+        [
+            entry(b"notes.txt", Some("/home/user/notes.txt")),
+            entry(b"tab\there\nand\xff", Some("/tmp/tab\there\nand")),
+            entry(b"orphan", None),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(id, entry)| candidate_line(id, entry))
+        .collect()
+    }
+
+    #[test]
+    fn formats_candidate_lines() {
+        assert_eq!(
+            lines(),
+            [
+                "0\tnotes.txt\t2024-03-01T10:00:00\t/home/user/notes.txt",
+                "1\ttab%09here%0Aand%FF\t2024-03-01T10:00:00\t/tmp/tab%09here%0Aand",
+                "2\torphan\t2024-03-01T10:00:00\t-",
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_back_the_picked_lines() -> Result<()> {
+        assert_eq!(pick("head -1", lines())?, Some(vec![0]));
+        assert_eq!(pick("grep -F %09", lines())?, Some(vec![1]));
+        // In the order they were picked, without repeats
+        assert_eq!(
+            pick(r#"picked=$(tac); echo "$picked"; echo "$picked""#, lines())?,
+            Some(vec![2, 1, 0])
+        );
+        assert_eq!(pick("true", lines())?, Some(vec![]));
+
+        // Even when there's more than a pipe can hold before the picker is done reading
+        let many: Vec<_> = (0..100_000)
+            .map(|id| candidate_line(id, &entry(b"notes.txt", None)))
+            .collect();
+        assert_eq!(pick("cat", many)?.map(|picked| picked.len()), Some(100_000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn backing_out_picks_nothing() -> Result<()> {
+        assert_eq!(pick("head -1; exit 130", lines())?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_lines_it_did_not_give() {
+        for output in ["echo notes.txt", "echo 3", "echo -1"] {
+            assert!(
+                matches!(pick(output, lines()), Err(Error::InvalidPickerOutput(..))),
+                "{output}"
+            );
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//! Per-mount policies, set in the `mounts` section of the configuration file:
+//!
+//! ```toml
+//! [mounts."/mnt/scratch"]
+//! policy = "delete"
+//!
+//! [mounts.nfs4]
+//! retention = "30d"
+//! ```
+//!
+//! Sections are keyed by the path of a mount point or by a filesystem type. A file's policy is the one of its
+//! mount point (the innermost one), or the one of its filesystem's type, or the default: trashing, without limits.
+
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    path::Path,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    date::{parse_age, DateTime},
+    directorysizes::read_directory_sizes,
+    entries::{entries, entry_size, purge},
+    error::{Error, Result},
+    ffi::{innermost_mount_point, MountPoint},
+    fs::FileSystem,
+    trash::Trash,
+};
+
+/// What is done with the files of a mount point
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Send them to the trash
+    #[default]
+    Trash,
+    /// Delete them permanently, without going through the trash
+    Delete,
+    /// Leave them be, failing with an error
+    Refuse,
+}
+
+impl Action {
+    /// The values `--policy` accepts
+    pub const NAMES: &'static [&'static str] = &["trash", "delete", "refuse"];
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        match text {
+            "trash" => Ok(Self::Trash),
+            "delete" => Ok(Self::Delete),
+            "refuse" => Ok(Self::Refuse),
+            _ => Err(Error::Usage(format!(
+                "--policy expects one of {}, got {text}",
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+/// The policy of a mount point, as set in the configuration file
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MountPolicy {
+    pub policy: Action,
+    /// How many bytes its trash may take up: past that, its oldest entries are purged after trashing
+    pub quota: Option<u64>,
+    /// How long entries are kept in its trash: older ones are purged after trashing. Written as an age, e.g. `30d`.
+    #[serde(deserialize_with = "deserialize_age")]
+    pub retention: Option<Duration>,
+}
+
+fn deserialize_age<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Duration>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+
+    parse_age(text.trim())
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid age {text:?}, expected e.g. 30d")))
+}
+
+/// Finds the policy of `path`, given the `policies` of the configuration file and the mount table.
+///
+/// A section keyed by the path of the innermost mount point containing `path` takes precedence
+/// over one keyed by the type of its filesystem. Keys that start with `/` are paths.
+pub fn resolve(
+    policies: &BTreeMap<String, MountPolicy>,
+    mount_points: &[MountPoint],
+    path: &Path,
+) -> MountPolicy {
+    let Some(mount_point) = innermost_mount_point(mount_points, path) else {
+        return MountPolicy::default();
+    };
+
+    let by_path = policies
+        .iter()
+        .find(|(key, _)| key.starts_with('/') && Path::new(key) == mount_point.fs_path_prefix);
+    let by_fs_type = || policies.get(&mount_point.fs_type);
+
+    by_path
+        .map(|(_, policy)| policy)
+        .or_else(by_fs_type)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Purges the entries of `trash` that are past the retention or quota of `policy` as of `now`, oldest first,
+/// returning their names.
+///
+/// The entry named `just_trashed`, if any, is never purged, and neither are entries without a valid deletion date,
+/// since there's no telling how old they are. They still count towards the quota, though.
+pub fn enforce_limits(
+    fs: &impl FileSystem,
+    trash: &Trash,
+    policy: &MountPolicy,
+    just_trashed: Option<&OsStr>,
+    now: SystemTime,
+) -> Result<Vec<OsString>> {
+    if policy.quota.is_none() && policy.retention.is_none() {
+        return Ok(vec![]);
+    }
+
+    let entries = entries(trash)?;
+    let directory_sizes = read_directory_sizes(trash)?;
+    let mut total_size: u64 = entries
+        .iter()
+        .filter_map(|entry| entry_size(trash, &entry.name, &directory_sizes).ok())
+        .sum();
+
+    let mut purgeable: Vec<_> = entries
+        .into_iter()
+        .filter(|entry| Some(entry.name.as_os_str()) != just_trashed)
+        .filter_map(|entry| Some((entry.deletion_date?, entry.name)))
+        .collect();
+    purgeable.sort();
+
+    let cutoff = match policy.retention {
+        Some(retention) => {
+            let now = now.duration_since(UNIX_EPOCH)?;
+            Some(DateTime::from_timestamp(now.saturating_sub(retention))?)
+        }
+        None => None,
+    };
+
+    let mut purged = Vec::new();
+    for (deletion_date, name) in purgeable {
+        let is_expired = cutoff.is_some_and(|cutoff| deletion_date < cutoff);
+        let is_over_quota = policy.quota.is_some_and(|quota| total_size > quota);
+        if !is_expired && !is_over_quota {
+            break;
+        }
+
+        let size = entry_size(trash, &name, &directory_sizes).unwrap_or(0);
+        purge(fs, trash, &name)?;
+        total_size = total_size.saturating_sub(size);
+        purged.push(name);
+    }
+
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        fs,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{enforce_limits, resolve, Action, MountPolicy};
+    use crate::{config::Config, error::Result, ffi::MountPoint, fs::RealFs, trash::Trash};
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn mount_point(prefix: &str, fs_type: &str) -> MountPoint {
+        MountPoint {
+            fs_name: "dummy".into(),
+            fs_path_prefix: prefix.into(),
+            fs_type: fs_type.into(),
+            read_only: false,
+        }
+    }
+
+    #[test]
+    fn resolves_policies() -> Result<()> {
+        let mount_points = [
+            mount_point("/", "btrfs"),
+            mount_point("/home", "nfs4"),
+            mount_point("/mnt/scratch", "ext4"),
+            mount_point("/mnt/backup", "ext4"),
+            mount_point("/mnt/backup/nested", "xfs"),
+        ];
+        let config = Config::parse(
+            r#"
+            [mounts.nfs4]
+            retention = "30d"
+
+            [mounts.ext4]
+            policy = "delete"
+
+            [mounts."/mnt/backup/"]
+            quota = 1000
+
+            [mounts."/mnt/backup/nested"]
+            policy = "refuse"
+            "#,
+            "config.toml".as_ref(),
+        )?;
+
+        let trash_for_30_days = MountPolicy {
+            retention: Some(30 * DAY),
+            ..MountPolicy::default()
+        };
+        let trash_with_quota = MountPolicy {
+            quota: Some(1000),
+            ..MountPolicy::default()
+        };
+        let delete = MountPolicy {
+            policy: Action::Delete,
+            ..MountPolicy::default()
+        };
+        let refuse = MountPolicy {
+            policy: Action::Refuse,
+            ..MountPolicy::default()
+        };
+
+        for (path, expected) in [
+            ("/etc/fstab", &MountPolicy::default()),
+            ("/home/user/notes.txt", &trash_for_30_days),
+            ("/mnt/scratch/build", &delete),
+            // The mount point's path wins over its type
+            ("/mnt/backup/2024.tar", &trash_with_quota),
+            ("/mnt/backup", &trash_with_quota),
+            ("/mnt/backup/nested/file", &refuse),
+            ("/mnt/backups/file", &MountPolicy::default()),
+        ] {
+            assert_eq!(
+                &resolve(&config.mounts, &mount_points, path.as_ref()),
+                expected,
+                "{path}"
+            );
+        }
+
+        assert_eq!(
+            resolve(&BTreeMap::new(), &[], "/etc/fstab".as_ref()),
+            MountPolicy::default()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parses_policies() {
+        assert_eq!("refuse".parse::<Action>().ok(), Some(Action::Refuse));
+        assert!("nothing".parse::<Action>().is_err());
+
+        for invalid in [
+            "[mounts.ext4]\npolicy = \"shred\"",
+            "[mounts.ext4]\nretention = \"forever\"",
+            "[mounts.ext4]\nquota = -1",
+            "[mounts.ext4]\nqouta = 1",
+        ] {
+            assert!(
+                Config::parse(invalid, "config.toml".as_ref()).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    /// Puts an entry named `name`, `size` bytes long and trashed at `deletion_date`, in `trash`
+    fn add_entry(trash: &Trash, name: &str, size: usize, deletion_date: &str) -> Result<()> {
+        fs::write(trash.files.as_path().join(name), vec![0; size])?;
+        fs::write(
+            trash.info_path().join(format!("{name}.trashinfo")),
+            format!("[Trash Info]\nPath=/tmp/{name}\nDeletionDate={deletion_date}\n"),
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn enforces_retention_and_quota() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path())?;
+        fs::create_dir(&trash.files)?;
+        fs::create_dir(&trash.info)?;
+        fs::write(&trash.directory_sizes, "")?;
+
+        add_entry(&trash, "ancient", 10, "2001-01-01T00:00:00")?;
+        add_entry(&trash, "old", 10, "2020-01-01T00:00:00")?;
+        add_entry(&trash, "undated", 10, "someday")?;
+        add_entry(&trash, "recent", 10, "2999-01-01T00:00:00")?;
+        add_entry(&trash, "new", 100, "2999-01-01T00:00:01")?;
+
+        let now = SystemTime::now();
+        let unlimited = MountPolicy::default();
+        assert!(enforce_limits(&RealFs, &trash, &unlimited, Some("new".as_ref()), now)?.is_empty());
+
+        let retention = MountPolicy {
+            retention: Some(365 * DAY),
+            ..MountPolicy::default()
+        };
+        assert_eq!(
+            enforce_limits(&RealFs, &trash, &retention, Some("new".as_ref()), now)?,
+            ["ancient", "old"]
+        );
+        assert!(!trash.files.as_path().join("old").exists());
+        assert!(!trash.info_path().join("old.trashinfo").exists());
+
+        // Even when over quota, neither the new entry nor the undated one go
+        let quota = MountPolicy {
+            quota: Some(50),
+            ..MountPolicy::default()
+        };
+        assert_eq!(
+            enforce_limits(&RealFs, &trash, &quota, Some("new".as_ref()), now)?,
+            ["recent"]
+        );
+        for name in ["new", "undated"] {
+            assert!(trash.files.as_path().join(name).exists());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Asking for confirmation before doing something that can't be undone, for `--empty`, `-i` and `-I`.
+//!
+//! Questions are asked and answered on the controlling terminal, `/dev/tty`, rather than on stderr and
+//! stdin, so that they work whatever stdin is (a pipe feeding something else, say) and never end up mixed
+//! with what's printed to stdout.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+};
+
+use crate::error::{Error, Result};
+
+/// Where questions get their answers
+#[derive(Debug)]
+pub struct Prompter<R, W> {
+    /// Whether every question is answered with yes beforehand (e.g. `--yes`)
+    yes: bool,
+    /// Where answers are read from and questions written to, if there's anyone to answer
+    terminal: Option<(R, W)>,
+}
+
+impl Prompter<BufReader<File>, File> {
+    /// Asks on the controlling terminal, unless `yes` answers every question beforehand.
+    ///
+    /// Without a controlling terminal, questions fail with [`Error::ConfirmationRequired`] (see [`Prompter::confirm`]).
+    pub fn on_terminal(yes: bool) -> Self {
+        let open = || -> std::io::Result<_> {
+            let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+            Ok((BufReader::new(tty.try_clone()?), tty))
+        };
+
+        // Not opened when it won't be needed
+        let terminal = if yes { None } else { open().ok() };
+
+        Self::new(yes, terminal)
+    }
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    /// Asks on `terminal`, an input and an output, unless `yes` answers every question beforehand
+    pub fn new(yes: bool, terminal: Option<(R, W)>) -> Self {
+        Self { yes, terminal }
+    }
+
+    /// Asks `question`, returning whether it was answered with yes.
+    ///
+    /// When there's nobody to answer, nothing is assumed and [`Error::ConfirmationRequired`] is returned.
+    pub fn confirm(&mut self, question: &str) -> Result<bool> {
+        if self.yes {
+            return Ok(true);
+        }
+
+        let Some((input, output)) = &mut self.terminal else {
+            return Err(Error::ConfirmationRequired);
+        };
+
+        write!(output, "{question} [y/N] ")?;
+        output.flush()?;
+
+        let mut answer = String::new();
+        input.read_line(&mut answer)?;
+
+        Ok(matches!(
+            answer.trim().to_ascii_lowercase().as_str(),
+            "y" | "yes"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Prompter;
+    use crate::Error;
+
+    fn answer(input: &str, yes: bool, interactive: bool) -> crate::Result<bool> {
+        let terminal = interactive.then(|| (input.as_bytes(), Vec::new()));
+        Prompter::new(yes, terminal).confirm("Go ahead?")
+    }
+
+    #[test]
+    fn refuses_when_nobody_can_answer() {
+        assert!(matches!(
+            answer("y\n", false, false),
+            Err(Error::ConfirmationRequired)
+        ));
+    }
+
+    #[test]
+    fn proceeds_with_yes() {
+        // Nothing is read when the question was answered beforehand
+        assert!(answer("", true, false).unwrap());
+        assert!(answer("n\n", true, true).unwrap());
+    }
+
+    #[test]
+    fn reads_the_answer() {
+        let mut prompter = Prompter::new(false, Some(("y\n".as_bytes(), Vec::new())));
+        assert!(prompter.confirm("Go ahead?").unwrap());
+        assert_eq!(prompter.terminal.unwrap().1, b"Go ahead? [y/N] ");
+
+        assert!(answer("YES\n", false, true).unwrap());
+        assert!(!answer("n\n", false, true).unwrap());
+        assert!(!answer("\n", false, true).unwrap());
+        assert!(!answer("", false, true).unwrap());
+    }
+
+    #[test]
+    fn reads_one_answer_per_question() {
+        let mut prompter = Prompter::new(false, Some(("y\nn\nyes\n".as_bytes(), Vec::new())));
+        let answers: Vec<_> = ["a?", "b?", "c?", "d?"]
+            .into_iter()
+            .map(|question| prompter.confirm(question).unwrap())
+            .collect();
+
+        assert_eq!(answers, [true, false, true, false]);
+        assert_eq!(
+            prompter.terminal.unwrap().1,
+            b"a? [y/N] b? [y/N] c? [y/N] d? [y/N] "
+        );
+    }
+}
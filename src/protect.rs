@@ -0,0 +1,150 @@
+//! Protection rules: glob patterns, set with `protect` in the configuration file, matching paths `tt` refuses to trash.
+//!
+//! Patterns are matched against the bytes of canonical paths, component by component, as described in
+//! [`crate::glob`]: a `**` component matches any number of components, including none, so `~/.gnupg/**` matches
+//! `~/.gnupg` too, and patterns that don't start with `/` can match at any depth.
+//!
+//! Rules are only checked against the paths given to `tt`, not against the files inside a directory being trashed.
+//! However, trashing a directory that contains the fixed part of an absolute rule is refused as well:
+//! with `~/work/contracts/**`, neither `~/work/contracts` nor `~/work` can be trashed.
+
+use std::path::Path;
+
+use crate::{
+    error::{Error, Result},
+    glob::{components, matches, matches_path},
+};
+
+/// Checks that `path`, which must be canonical, is not protected by any of `rules`.
+///
+/// The error names the first rule that protects it, counting from 1.
+pub fn check_protection(rules: &[impl AsRef<Path>], path: &Path) -> Result<()> {
+    let protected_by = rules.iter().position(|rule| protects(rule.as_ref(), path));
+
+    match protected_by {
+        Some(index) => Err(Error::Protected(
+            path.to_owned(),
+            index + 1,
+            rules[index].as_ref().to_owned(),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Whether `rule` protects `path`, either by matching it or by matching something under it
+pub fn protects(rule: &Path, path: &Path) -> bool {
+    // Relative rules match at any depth
+    if !rule.is_absolute() {
+        return matches_path(rule, path);
+    }
+
+    let rule = components(rule);
+    let path = components(path);
+
+    // The components of the rule before its first wildcard
+    let fixed_part = rule
+        .iter()
+        .take_while(|component| !component.iter().any(|byte| b"*?".contains(byte)));
+    let contains_fixed_part = path.len() <= fixed_part.clone().count()
+        && fixed_part
+            .zip(&path)
+            .all(|(fixed, component)| fixed == component);
+
+    contains_fixed_part || matches(&rule, &path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{check_protection, protects};
+    use crate::{config::Config, error::Result, Error};
+
+    #[test]
+    fn matches_rules() {
+        for (rule, path, expected) in [
+            ("/home/user/.gnupg/**", "/home/user/.gnupg", true),
+            (
+                "/home/user/.gnupg/**",
+                "/home/user/.gnupg/pubring.kbx",
+                true,
+            ),
+            (
+                "/home/user/.gnupg/**",
+                "/home/user/.gnupg/private-keys-v1.d/key",
+                true,
+            ),
+            ("/home/user/.gnupg/**", "/home/user/.gnupg-backup", false),
+            ("/home/user/.gnupg/**", "/home/user/notes.txt", false),
+            // Containing the fixed part of a rule
+            ("/home/user/.gnupg/**", "/home/user", true),
+            ("/home/user/.gnupg/**", "/", true),
+            ("/home/user/work/contracts", "/home/user/work", true),
+            ("/home/user/work/contracts", "/home/user/workshop", false),
+            ("/home/*/secret", "/home", true),
+            ("/home/*/secret", "/home/user", false),
+            ("/home/*/secret", "/home/user/secret", true),
+            // Relative rules match at any depth
+            ("**/*.kdbx", "/home/user/passwords.kdbx", true),
+            ("**/*.kdbx", "/home/user/vault/old.kdbx", true),
+            ("**/*.kdbx", "/home/user/passwords.kdbx.bak", false),
+            ("*.kdbx", "/home/user/passwords.kdbx", true),
+            ("*.gnupg*", "/home/user/.gnupg", true),
+            ("*.gnupg*", "/home/user/.gnupg/pubring.kbx", false),
+            ("vault/**", "/home/user/vault/old.kdbx", true),
+            ("vault/**", "/home/user/vault", true),
+            ("vault/**", "/home/user/vaults", false),
+            // `*` doesn't cross components, `?` matches a single byte
+            ("/home/*/notes.txt", "/home/user/notes.txt", true),
+            ("/home/*/notes.txt", "/home/user/docs/notes.txt", false),
+            ("/home/**/notes.txt", "/home/user/docs/notes.txt", true),
+            ("/tmp/file?.txt", "/tmp/file1.txt", true),
+            ("/tmp/file?.txt", "/tmp/file10.txt", false),
+            ("/tmp/file?.txt", "/tmp/file.txt", false),
+        ] {
+            assert_eq!(
+                protects(rule.as_ref(), path.as_ref()),
+                expected,
+                "{rule} on {path}"
+            );
+        }
+    }
+
+    #[test]
+    fn matches_bytes_that_are_not_utf8() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let path = Path::new(OsStr::from_bytes(b"/tmp/caf\xe9"));
+        assert!(protects("/tmp/caf?".as_ref(), path));
+        assert!(protects("/tmp/*".as_ref(), path));
+        assert!(!protects("/tmp/cafe".as_ref(), path));
+    }
+
+    #[test]
+    fn refuses_protected_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let home = dir.path().canonicalize()?;
+        let protected = home.join(".gnupg/pubring.kbx");
+        fs::create_dir(home.join(".gnupg"))?;
+        fs::write(&protected, "keys")?;
+        fs::write(home.join("notes.txt"), "notes")?;
+
+        let config = Config::parse(
+            r#"protect = ["**/*.kdbx", "~/.gnupg/**"]"#,
+            "config.toml".as_ref(),
+        )?
+        .expand_tildes(&home);
+
+        let err = check_protection(&config.protect, &protected).unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::Protected(path, 2, rule) if *path == protected && *rule == home.join(".gnupg/**")
+        ));
+        assert!(err.to_string().contains("protected by rule 2"));
+
+        check_protection(&config.protect, &home.join("notes.txt"))?;
+        assert!(check_protection(&config.protect, &home).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+//! Cleans up after runs of `tt` that died between writing an info file and moving the file it describes.
+//!
+//! The spec wants the info file written first, so such a run leaves `$trash/info/foo.trashinfo`
+//! behind without `$trash/files/foo`. While trashing `foo`, `tt` keeps a marker holding its PID next
+//! to the info file, which tells a trashing in progress apart from an interrupted one.
+
+use std::{
+    ffi::{OsStr, OsString},
+    io::{ErrorKind, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    check::{fix, temp_files, trashed_file_name, Finding},
+    error::Result,
+    ffi::process_exists,
+    fs::{temp_file_owner, FileSystem},
+    resume::source_of,
+    trash::Trash,
+};
+
+/// How old a dangling info file can be and still be assumed to be left by `tt`.
+///
+/// Older ones might be another tool's business, so they're only reported by `--check`.
+pub const GRACE_PERIOD: Duration = Duration::from_secs(15 * 60);
+
+/// The extension of markers, which must not end in `.trashinfo`
+const MARKER_EXTENSION: &str = ".tt-pending";
+
+/// The path of the marker telling that the file named `file_name` is being sent to `trash`
+pub fn marker_path(trash: &Trash, file_name: &OsStr) -> PathBuf {
+    let mut marker_name = OsString::from(file_name);
+    marker_name.push(".trashinfo");
+    marker_name.push(MARKER_EXTENSION);
+
+    trash.info_path().join(marker_name)
+}
+
+/// Marks the file named `file_name` as being sent to `trash` by this process.
+///
+/// Fails with [`AlreadyExists`](ErrorKind::AlreadyExists) if it's already marked.
+pub fn mark_pending(fs: &impl FileSystem, trash: &Trash, file_name: &OsStr) -> Result<PathBuf> {
+    let path = marker_path(trash, file_name);
+
+    let mut marker = fs.create_new_file(&path)?;
+    write!(marker, "{}", std::process::id())?;
+
+    Ok(path)
+}
+
+/// Whether the marker at `path` belongs to a process that is still running.
+///
+/// A marker without a PID might be getting written right now, so it's trusted until it's older than `grace_period`.
+fn is_live_marker(path: &Path, grace_period: Duration, now: SystemTime) -> bool {
+    let Ok(contents) = fs_err::read_to_string(path) else {
+        return false;
+    };
+
+    match contents.trim().parse::<i32>() {
+        Ok(pid) => pid > 0 && process_exists(pid),
+        Err(_) => age(path, now).is_ok_and(|age| age <= grace_period),
+    }
+}
+
+/// How long ago the file at `path` was last modified (without following symlinks). Files from the future are treated as brand new.
+pub fn age(path: &Path, now: SystemTime) -> Result<Duration> {
+    let modified = fs_err::symlink_metadata(path)?.modified()?;
+
+    Ok(now.duration_since(modified).unwrap_or_default())
+}
+
+/// Whether what `finding` is about was left behind by a process that's gone, rather than being the
+/// work of a `tt` or another tool that's still running.
+///
+/// Our temporary files tell which process made them. Anything else must be older than `min_age` as of `now`,
+/// and orphaned info files must not be marked as pending by a live process.
+pub fn is_leftover(trash: &Trash, finding: &Finding, min_age: Duration, now: SystemTime) -> bool {
+    let path = finding.path();
+    let is_old = || age(path, now).is_ok_and(|age| age >= min_age);
+
+    match finding {
+        Finding::LeftoverTemp(_) => match path.file_name().and_then(temp_file_owner) {
+            Some(pid) => !process_exists(pid),
+            None => is_old(),
+        },
+        Finding::OrphanedInfoFile(_) => {
+            let is_pending = path
+                .file_name()
+                .and_then(|name| trashed_file_name(name.to_owned()))
+                .is_some_and(|name| is_live_marker(&marker_path(trash, &name), min_age, now));
+
+            !is_pending && is_old()
+        }
+        Finding::MissingInfoFile(_) => is_old(),
+        // Lines are only added once their directory is in place, so there's nothing to wait for
+        Finding::StaleDirectorySize(_) => true,
+        // The entry is whole, so it's not for us to remove
+        Finding::ChecksumMismatch(_) => false,
+        // Unless what it was copying is gone, it may still be resumed
+        Finding::InterruptedCopy(_) => {
+            source_of(path).is_none_or(|source| source.symlink_metadata().is_err()) && is_old()
+        }
+    }
+}
+
+/// Removes what interrupted runs of `tt` left behind in `trash`, returning what was removed.
+///
+/// These are the info files without a file in `$trash/files`, without a live marker, and that are younger
+/// than `grace_period` as of `now`, along with our temporary files that are [leftovers](is_leftover).
+/// Markers left by dead processes are removed as well.
+pub fn recover(
+    fs: &impl FileSystem,
+    trash: &Trash,
+    grace_period: Duration,
+    now: SystemTime,
+) -> Result<Vec<Finding>> {
+    let mut removed = Vec::new();
+
+    for temp_file in temp_files(trash)? {
+        let finding = Finding::LeftoverTemp(temp_file);
+        if fix(fs, trash, &finding, grace_period, now)? {
+            removed.push(finding);
+        }
+    }
+
+    let info_files = match fs_err::read_dir(trash.info_path()) {
+        Ok(info_files) => info_files,
+        // Nothing was trashed yet
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(removed),
+        Err(err) => return Err(err.into()),
+    };
+
+    for info_file in info_files {
+        let info_file = info_file?;
+        let info_file_name = info_file.file_name();
+
+        if info_file_name
+            .as_bytes()
+            .ends_with(MARKER_EXTENSION.as_bytes())
+        {
+            // Markers of dead processes don't protect anything
+            if !is_live_marker(&info_file.path(), grace_period, now) {
+                remove_if_exists(fs, &info_file.path())?;
+            }
+            continue;
+        }
+
+        let Some(file_name) = trashed_file_name(info_file_name) else {
+            continue;
+        };
+
+        if trash
+            .files
+            .as_path()
+            .join(&file_name)
+            .symlink_metadata()
+            .is_ok()
+        {
+            continue;
+        }
+
+        if is_live_marker(&marker_path(trash, &file_name), grace_period, now) {
+            // Still being trashed
+            continue;
+        }
+
+        if !age(&info_file.path(), now).is_ok_and(|age| age <= grace_period) {
+            continue;
+        }
+
+        remove_if_exists(fs, &info_file.path())?;
+        removed.push(Finding::OrphanedInfoFile(info_file.path()));
+    }
+
+    removed.sort();
+
+    Ok(removed)
+}
+
+fn remove_if_exists(fs: &impl FileSystem, path: &Path) -> Result<()> {
+    match fs.remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        path::Path,
+        process::Command,
+        time::SystemTime,
+    };
+
+    use super::{marker_path, recover, GRACE_PERIOD};
+    use crate::{
+        check::Finding,
+        crash::{self, CrashPoint},
+        error::Result,
+        fs::RealFs,
+        trash::Trash,
+    };
+
+    fn dummy_trash(dir: &Path) -> Result<Trash> {
+        let trash = Trash::from_root(dir.join("trash"))?;
+        fs::create_dir(&trash.root)?;
+        fs::create_dir(&trash.files)?;
+        fs::create_dir(&trash.info)?;
+        File::create(&trash.directory_sizes)?;
+
+        Ok(trash)
+    }
+
+    /// The PID of a process that already exited
+    fn dead_pid() -> Result<u32> {
+        let mut child = Command::new("true").spawn()?;
+        child.wait()?;
+
+        Ok(child.id())
+    }
+
+    #[test]
+    fn recovers_from_crash_after_info_write() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+        let dummy_path = dir.path().join("dummy");
+        fs::write(&dummy_path, "dummy")?;
+
+        {
+            let _crash = crash::arm(CrashPoint::AfterInfoWrite);
+            assert!(trash.send_to_trash(&RealFs, &dummy_path).is_err());
+        }
+
+        let info_file_path = trash.info_path().join("dummy.trashinfo");
+        let marker = marker_path(&trash, "dummy".as_ref());
+        let now = SystemTime::now();
+
+        // The process that wrote it is still around, so it might still be trashing
+        assert!(recover(&RealFs, &trash, GRACE_PERIOD, now)?.is_empty());
+        assert!(info_file_path.exists());
+
+        // Now it's gone
+        fs::write(&marker, dead_pid()?.to_string())?;
+        assert_eq!(
+            recover(&RealFs, &trash, GRACE_PERIOD, now)?,
+            [Finding::OrphanedInfoFile(info_file_path.clone())]
+        );
+        assert_eq!(fs::read_dir(trash.info_path())?.count(), 0);
+
+        // The name is free again
+        trash.send_to_trash(&RealFs, &dummy_path)?;
+        assert!(trash.files.as_path().join("dummy").exists());
+        assert!(info_file_path.exists());
+        assert!(!marker.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_old_and_complete_info_files_alone() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+
+        let old = trash.info_path().join("old.trashinfo");
+        File::create(&old)?.set_modified(SystemTime::now() - 2 * GRACE_PERIOD)?;
+        let young = trash.info_path().join("young.trashinfo");
+        File::create(&young)?;
+        let complete = trash.info_path().join("complete.trashinfo");
+        File::create(&complete)?;
+        File::create(trash.files.as_path().join("complete"))?;
+        // A marker without a PID yet, which might be getting written
+        File::create(marker_path(&trash, "writing".as_ref()))?;
+        let writing = trash.info_path().join("writing.trashinfo");
+        File::create(&writing)?;
+
+        let now = SystemTime::now();
+        assert_eq!(
+            recover(&RealFs, &trash, GRACE_PERIOD, now)?,
+            [Finding::OrphanedInfoFile(young)]
+        );
+        assert!(old.exists() && complete.exists() && writing.exists());
+
+        // A marker that never got its PID is stale, but its info file is then too old to be removed
+        let later = now + 2 * GRACE_PERIOD;
+        assert!(recover(&RealFs, &trash, GRACE_PERIOD, later)?.is_empty());
+        assert!(!marker_path(&trash, "writing".as_ref()).exists());
+        assert!(writing.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn never_clobbers_reserved_names() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = dummy_trash(dir.path())?;
+
+        // Another tool's dangling info file, and a trashing in progress
+        let foreign = trash.info_path().join("dummy.trashinfo");
+        fs::write(&foreign, "foreign")?;
+        fs::write(
+            marker_path(&trash, "pending".as_ref()),
+            std::process::id().to_string(),
+        )?;
+
+        for name in ["dummy", "pending"] {
+            let path = dir.path().join(name);
+            fs::write(&path, name)?;
+
+            let file_name = trash.send_to_trash(&RealFs, &path)?;
+            assert_ne!(file_name, Path::new(name));
+            assert!(trash.files.as_path().join(&file_name).exists());
+            assert!(!marker_path(&trash, file_name.as_os_str()).exists());
+        }
+
+        assert_eq!(fs::read_to_string(&foreign)?, "foreign");
+        assert!(!trash.files.as_path().join("dummy").exists());
+
+        Ok(())
+    }
+}
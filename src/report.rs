@@ -0,0 +1,272 @@
+//! The machine-readable report of what was trashed, printed by `--print` and given to the hook.
+//!
+//! Every trashed file gets a line with its original path and its path in the trash, separated by a tab.
+//!
+//! Also here are the ways paths and messages are rendered for people rather than for other programs, such as the lines
+//! `-v` prints.
+
+use std::{
+    fmt::{self, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{error::Error, fs::Transfer};
+
+/// Whether warnings are left out, as with `-q`
+static QUIET: AtomicBool = AtomicBool::new(false);
+/// Whether errors come with what's known about them besides their message, as with `-v`
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// A file that was sent to the trash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trashed {
+    /// Where the file was trashed from
+    pub original_path: PathBuf,
+    /// Where the file now is, in `$trash/files`
+    pub trashed_path: PathBuf,
+    /// The size of the file (of its contents, for directories), in bytes, unless it's a directory that wasn't measured
+    /// (see [`crate::trash::TrashOptions::update_directory_sizes`])
+    pub size: Option<u64>,
+}
+
+/// Escapes `path` so that it fits in a single field of a line.
+///
+/// Control characters (including tabs and newlines), `%` and bytes that aren't valid UTF-8
+/// are percent-encoded, everything else is kept as is.
+pub fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            if ch.is_control() || ch == '%' {
+                let mut buf = [0; 4];
+                for byte in ch.encode_utf8(&mut buf).bytes() {
+                    let _ = write!(escaped, "%{byte:02X}");
+                }
+            } else {
+                escaped.push(ch);
+            }
+        }
+        for byte in chunk.invalid() {
+            let _ = write!(escaped, "%{byte:02X}");
+        }
+    }
+
+    escaped
+}
+
+/// Renders `path` for a terminal, where it mustn't be able to move the cursor, clear the screen or break a line.
+///
+/// Control characters and bytes that aren't valid UTF-8 are written as `\xNN`, everything else is kept as is. Unlike
+/// [`escape_path`], this is meant for people rather than other programs: nothing reads it back.
+pub fn display_path(path: &Path) -> String {
+    let mut displayed = String::new();
+
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            if ch.is_control() {
+                let mut buf = [0; 4];
+                for byte in ch.encode_utf8(&mut buf).bytes() {
+                    let _ = write!(displayed, "\\x{byte:02X}");
+                }
+            } else {
+                displayed.push(ch);
+            }
+        }
+        for byte in chunk.invalid() {
+            let _ = write!(displayed, "\\x{byte:02X}");
+        }
+    }
+
+    displayed
+}
+
+/// Renders `message` for a terminal as [`display_path`] does, for messages that may hold paths of their own, such as
+/// those of I/O errors
+pub fn display_message(message: &impl fmt::Display) -> String {
+    display_path(Path::new(&message.to_string()))
+}
+
+/// Sets how much goes to stderr besides errors, from `-q` and `-v`
+pub fn set_verbosity(quiet: bool, verbose: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Prints `message`, something worth knowing about what's being done besides what was asked, unless `-q` was given
+pub fn info(message: impl fmt::Display) {
+    if !QUIET.load(Ordering::Relaxed) {
+        eprintln!("tt: {}", display_message(&message));
+    }
+}
+
+/// Prints `line` indented, as one of the lines going with what [`info`] just printed, unless `-q` was given
+pub fn info_detail(line: impl fmt::Display) {
+    if !QUIET.load(Ordering::Relaxed) {
+        eprintln!("  {}", display_message(&line));
+    }
+}
+
+/// Prints `message` as a warning, something that went wrong without stopping what was asked, unless `-q` was given
+pub fn warn(message: impl fmt::Display) {
+    if !QUIET.load(Ordering::Relaxed) {
+        eprintln!("tt: warning: {}", display_message(&message));
+    }
+}
+
+/// Prints `err` as an error, in a line of its own. With `-v`, what else is known about it follows, such as where a
+/// file that couldn't be moved into the trash was going.
+pub fn error(err: &Error) {
+    eprintln!("tt: error: {}", display_message(err));
+    error_details(err);
+}
+
+/// Prints `err` as [`error`] does, after what failed because of it, such as `failed to delete NAME`
+pub fn error_while(failed: impl fmt::Display, err: &Error) {
+    eprintln!(
+        "tt: error: {}: {}",
+        display_message(&failed),
+        display_message(err)
+    );
+    error_details(err);
+}
+
+/// What else is known about `err`, printed with `-v`
+fn error_details(err: &Error) {
+    if VERBOSE.load(Ordering::Relaxed) {
+        if let Error::MoveFailed(_, destination, _) = err {
+            eprintln!("tt: it was being moved to {}", display_path(destination));
+        }
+    }
+}
+
+impl Trashed {
+    /// The line `-v` prints for this file, which got to the trash through `transfer`. `trash_root` is the root of the
+    /// trash it went to, unless that's the home trash. With `dry_run`, it's what `--dry-run` prints for a file that
+    /// would go there. Directories that weren't measured are said to be left out of `directorysizes`.
+    pub fn verbose_line(
+        &self,
+        transfer: Transfer,
+        trash_root: Option<&Path>,
+        dry_run: bool,
+    ) -> String {
+        let mut trash = match trash_root {
+            Some(root) => format!("trash at {}", display_path(root)),
+            None => "home trash".into(),
+        };
+        if self.size.is_none() {
+            trash += ", not recorded in directorysizes";
+        }
+
+        format!(
+            "tt: {} {} -> {} ({}, {trash})",
+            if dry_run { "would trash" } else { "trashed" },
+            display_path(&self.original_path),
+            display_path(&self.trashed_path),
+            transfer.description()
+        )
+    }
+}
+
+impl fmt::Display for Trashed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}",
+            escape_path(&self.original_path),
+            escape_path(&self.trashed_path)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+    use super::{display_message, display_path, escape_path, Trashed};
+    use crate::fs::Transfer;
+
+    #[test]
+    fn escapes_paths() {
+        for (path, escaped) in [
+            (&b"/home/user/notes.txt"[..], "/home/user/notes.txt"),
+            (b"/home/user/caf\xc3\xa9 menu", "/home/user/caf\u{e9} menu"),
+            (b"/tmp/tab\there", "/tmp/tab%09here"),
+            (b"/tmp/new\nline", "/tmp/new%0Aline"),
+            (b"/tmp/100%", "/tmp/100%25"),
+            (b"/tmp/latin1-\xe9", "/tmp/latin1-%E9"),
+        ] {
+            assert_eq!(escape_path(Path::new(OsStr::from_bytes(path))), escaped);
+        }
+    }
+
+    #[test]
+    fn displays_paths_for_terminals() {
+        for (path, displayed) in [
+            (&b"/home/user/notes.txt"[..], "/home/user/notes.txt"),
+            (b"/home/user/caf\xc3\xa9 100%", "/home/user/caf\u{e9} 100%"),
+            (b"/tmp/new\nline", r"/tmp/new\x0Aline"),
+            (b"/tmp/\x1b[2Jclear", r"/tmp/\x1B[2Jclear"),
+            (b"/tmp/\xc2\x9bcsi", r"/tmp/\xC2\x9Bcsi"),
+            (b"/tmp/latin1-\xe9", r"/tmp/latin1-\xE9"),
+        ] {
+            assert_eq!(display_path(Path::new(OsStr::from_bytes(path))), displayed);
+        }
+
+        // Already displayed paths in a message are left as they are
+        let message = format!(
+            "failed to open {}: \x1b]0;title\x07",
+            display_path(Path::new("a\tb"))
+        );
+        assert_eq!(
+            display_message(&message),
+            r"failed to open a\x09b: \x1B]0;title\x07"
+        );
+    }
+
+    #[test]
+    fn reports_original_and_trashed_paths() {
+        let trashed = Trashed {
+            original_path: "/home/user/a b".into(),
+            trashed_path: "/home/user/.local/share/Trash/files/a b".into(),
+            size: Some(0),
+        };
+
+        assert_eq!(
+            trashed.to_string(),
+            "/home/user/a b\t/home/user/.local/share/Trash/files/a b"
+        );
+        assert_eq!(
+            trashed.verbose_line(Transfer::Renamed, None, false),
+            "tt: trashed /home/user/a b -> /home/user/.local/share/Trash/files/a b (renamed, home trash)"
+        );
+
+        let trashed = Trashed {
+            original_path: "/mnt/usb/new\nline".into(),
+            trashed_path: "/mnt/usb/.Trash-1000/files/new\nline.2".into(),
+            size: Some(0),
+        };
+        assert_eq!(
+            trashed.verbose_line(
+                Transfer::Copied,
+                Some(Path::new("/mnt/usb/.Trash-1000")),
+                true
+            ),
+            "tt: would trash /mnt/usb/new\\x0Aline -> /mnt/usb/.Trash-1000/files/new\\x0Aline.2 \
+             (copied then removed, trash at /mnt/usb/.Trash-1000)"
+        );
+
+        let trashed = Trashed {
+            original_path: "/home/user/build".into(),
+            trashed_path: "/home/user/.local/share/Trash/files/build".into(),
+            size: None,
+        };
+        assert_eq!(
+            trashed.verbose_line(Transfer::Renamed, None, false),
+            "tt: trashed /home/user/build -> /home/user/.local/share/Trash/files/build \
+             (renamed, home trash, not recorded in directorysizes)"
+        );
+    }
+}
@@ -0,0 +1,1316 @@
+//! Restoring trashed files to their original location
+
+use std::{
+    collections::BTreeSet,
+    ffi::{OsStr, OsString},
+    fs::{OpenOptions, Permissions},
+    io::Read,
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::{MetadataExt, OpenOptionsExt, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+};
+
+use percent_encoding::percent_decode;
+use unixstring::UnixString;
+
+use crate::{
+    checksum::{self, Verification},
+    compress::{self, decompress},
+    dedupe::{linked_from_elsewhere, unshare},
+    directorysizes::remove_directory_size,
+    entries::{entries, TrashEntry},
+    error::{Error, Result},
+    ffi,
+    fs::{explain_denial, move_file, FileSystem, PRIVILEGE_BITS},
+    info_file::{build_info_file_path, parse_info_file, read_info_file, TrashInfo},
+    lock::TrashLock,
+    report::{display_path, warn},
+    trash::Trash,
+    which::{which, Found},
+};
+
+/// Finds the entry of `trash` named `name`, returning its name in `$trash/files`.
+///
+/// Names that don't match any entry are percent-decoded and looked up again, since that's
+/// how shell completion hands over names that can't be typed.
+pub fn find_entry(trash: &Trash, name: &OsStr) -> Result<OsString> {
+    // Names are a single path component, anything else can't be in `$trash/files`
+    let exists = |name: &OsStr| {
+        let is_file_name =
+            !name.is_empty() && !name.as_bytes().contains(&b'/') && name != "." && name != "..";
+        is_file_name && trash.files.as_path().join(name).symlink_metadata().is_ok()
+    };
+
+    if exists(name) {
+        return Ok(name.to_owned());
+    }
+
+    let decoded = OsString::from_vec(percent_decode(name.as_bytes()).collect());
+    if exists(&decoded) {
+        return Ok(decoded);
+    }
+
+    Err(Error::EntryNotFound(name.into()))
+}
+
+/// How entries are restored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestoreOptions {
+    /// Whether nothing is changed, though every check is still made
+    pub dry_run: bool,
+    /// Whether entries are checked against the checksum recorded when trashing them (see [`crate::checksum`]),
+    /// leaving the ones that changed in the trash
+    pub verify: bool,
+    /// Whether security contexts are copied along when entries are copied to another filesystem (see [`crate::fs::copy_xattrs`])
+    pub preserve_context: bool,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            verify: false,
+            preserve_context: true,
+        }
+    }
+}
+
+/// Restores the entry of `trash` named `name` to its original location.
+///
+/// Restoring never overwrites: if something already exists in the original location, the entry
+/// is left in the trash.
+///
+/// If `options.dry_run` is set, nothing is changed but every check is still made. Either way, returns what was (or would be) done.
+pub fn restore(
+    fs: &impl FileSystem,
+    trash: &Trash,
+    name: &OsStr,
+    options: RestoreOptions,
+) -> Result<RestorePlan> {
+    let name = find_entry(trash, name)?;
+    let destination = original_path(trash, &name)?;
+
+    restore_to(fs, trash, &name, destination, options)
+}
+
+/// Restores the entry of `trash` named `name` into `directory`, under the name it was trashed from, for `--restore-to`.
+///
+/// Otherwise, this is what [`restore`] does: the entry is left in the trash if that name is taken in `directory`.
+pub fn restore_into(
+    fs: &impl FileSystem,
+    trash: &Trash,
+    name: &OsStr,
+    directory: &Path,
+    options: RestoreOptions,
+) -> Result<RestorePlan> {
+    let name = find_entry(trash, name)?;
+    // Entries without a readable info file keep their name in the trash
+    let original_path = original_path(trash, &name).unwrap_or_else(|_| PathBuf::from(&name));
+    let destination = destination_in(directory, &original_path)?;
+
+    restore_to(fs, trash, &name, destination, options)
+}
+
+/// Where a file trashed from `original_path` goes when restored into `directory`, which must exist
+fn destination_in(directory: &Path, original_path: &Path) -> Result<PathBuf> {
+    let file_name = original_path
+        .file_name()
+        .ok_or_else(|| Error::FailedToObtainFileName(original_path.to_owned()))?;
+    // Followed if it's a symlink, as `~/Desktop` may well be
+    if !directory.is_dir() {
+        return Err(Error::RestoreDirectoryNotFound(directory.to_owned()));
+    }
+
+    Ok(directory.join(file_name))
+}
+
+/// The entry of `trashes` trashed last, for `--restore-last`, along with its trash. Entries without a deletion date
+/// are never the last.
+///
+/// Deletion dates have a resolution of one second, so entries trashed in the same second are told apart by when their
+/// info files were written, and then by name, the lexicographically greatest being the last.
+pub fn last_trashed(trashes: &[Trash]) -> Result<Option<(&Trash, TrashEntry)>> {
+    let written = |trash: &Trash, entry: &TrashEntry| {
+        UnixString::try_from(build_info_file_path(&entry.name, trash.info_path()))
+            .ok()
+            .and_then(|unx| ffi::Lstat::lstat(unx).ok())
+            .map(|lstat| (lstat.modified(), lstat.modified_nsec()))
+    };
+
+    let mut dated = Vec::new();
+    for trash in trashes {
+        for entry in entries(trash)? {
+            if entry.deletion_date.is_some() {
+                dated.push((trash, entry));
+            }
+        }
+    }
+
+    Ok(dated.into_iter().max_by_key(|(trash, entry)| {
+        (
+            entry.deletion_date,
+            written(trash, entry),
+            entry.name.clone(),
+        )
+    }))
+}
+
+/// Where the entry of `trash` named `name` (exactly as in `$trash/files`) was trashed from, according to its info file
+pub fn original_path(trash: &Trash, name: &OsStr) -> Result<PathBuf> {
+    let info = read_info_file(&build_info_file_path(name, trash.info_path()))?;
+
+    trash.resolve_original_path(&info.path)
+}
+
+/// Which of the trashed versions of a path to restore
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Generations {
+    /// The most recently trashed one
+    #[default]
+    Newest,
+    /// The `n`th most recently trashed one, starting at 1
+    Version(usize),
+    /// All of them: the newest one to the original path and the older ones next to it
+    All,
+}
+
+/// The trashed versions of `path` (which must be normalized) found in `trashes`, newest first.
+///
+/// Versions are ordered by their deletion date. Versions trashed at the same time (dates have a
+/// resolution of one second) are ordered by their names in the trash, the lexicographically
+/// greatest name being considered the newest. Versions without a deletion date are the oldest.
+pub fn generations<'a>(trashes: &'a [Trash], path: &Path) -> Result<Vec<Found<'a>>> {
+    let mut generations = which(trashes, path, false)?;
+    generations.reverse();
+
+    Ok(generations)
+}
+
+/// Restores the trashed versions of `path` chosen by `chosen`, as [`restore`] does, or as [`restore_into`] does if
+/// `directory` is given.
+pub fn restore_generations(
+    fs: &impl FileSystem,
+    generations: &[Found],
+    path: &Path,
+    chosen: Generations,
+    directory: Option<&Path>,
+    options: RestoreOptions,
+) -> Result<Vec<RestorePlan>> {
+    let version_not_found = |version| Error::VersionNotFound(path.to_owned(), version);
+    let destination = match directory {
+        Some(directory) => destination_in(directory, path)?,
+        None => path.to_owned(),
+    };
+
+    match chosen {
+        Generations::Newest => restore_generations(
+            fs,
+            generations,
+            path,
+            Generations::Version(1),
+            directory,
+            options,
+        ),
+        Generations::Version(version) => {
+            let found = version
+                .checked_sub(1)
+                .and_then(|index| generations.get(index))
+                .ok_or_else(|| version_not_found(version))?;
+
+            Ok(vec![restore_to(
+                fs,
+                found.trash,
+                &found.entry.name,
+                destination,
+                options,
+            )?])
+        }
+        Generations::All => {
+            let (newest, older) = generations
+                .split_first()
+                .ok_or_else(|| version_not_found(1))?;
+
+            let mut restored = vec![restore_to(
+                fs,
+                newest.trash,
+                &newest.entry.name,
+                destination.clone(),
+                options,
+            )?];
+
+            for found in older {
+                // On dry runs, the paths of the previous versions weren't actually taken
+                let taken: Vec<_> = restored.iter().map(|plan| &plan.destination).collect();
+                let destination = available_path(&destination, &taken);
+                restored.push(restore_to(
+                    fs,
+                    found.trash,
+                    &found.entry.name,
+                    destination,
+                    options,
+                )?);
+            }
+
+            Ok(restored)
+        }
+    }
+}
+
+/// The first of `path.1`, `path.2`, ... that doesn't exist and isn't `taken`, for restoring a file next to another version of it
+fn available_path(path: &Path, taken: &[&PathBuf]) -> PathBuf {
+    (1..)
+        .map(|n| {
+            let mut candidate = path.as_os_str().to_owned();
+            candidate.push(format!(".{n}"));
+            PathBuf::from(candidate)
+        })
+        .find(|candidate| candidate.symlink_metadata().is_err() && !taken.contains(&candidate))
+        .expect("ran out of numbers")
+}
+
+/// An entry restored along with others (see [`order_batch`])
+#[derive(Debug, Clone)]
+pub struct Batched<'a> {
+    pub trash: &'a Trash,
+    /// Its name in `$trash/files`
+    pub name: OsString,
+    /// Where it's restored to, its original path
+    pub destination: PathBuf,
+    /// Where in the batch the entry restored to the closest ancestor of `destination` is, if one is,
+    /// such as a directory that was trashed after some of its files were. It's restored first.
+    pub parent: Option<usize>,
+}
+
+/// Orders the entries `found` (see [`which`]) so that they can be restored one after the other: parents first, so
+/// that a trashed directory is back in place before the files trashed from it separately are put back in it.
+///
+/// Entries are otherwise ordered by path, and entries trashed from the same path newest first, so the newest is the one
+/// restored there.
+pub fn order_batch(found: Vec<Found<'_>>) -> Vec<Batched<'_>> {
+    let mut found: Vec<_> = found
+        .into_iter()
+        .filter_map(|found| Some((found.entry.original_path.clone()?, found)))
+        .collect();
+    found.sort_by(|(path, found), (other_path, other)| {
+        let depth = |path: &Path| path.components().count();
+        depth(path)
+            .cmp(&depth(other_path))
+            .then_with(|| path.cmp(other_path))
+            .then_with(|| other.entry.deletion_date.cmp(&found.entry.deletion_date))
+            .then_with(|| other.entry.name.cmp(&found.entry.name))
+    });
+
+    let mut batch: Vec<Batched> = Vec::with_capacity(found.len());
+    for (destination, found) in found {
+        // The deepest of the ancestors restored before, the first (and newest) of them if there are several
+        let mut parent: Option<usize> = None;
+        for (index, earlier) in batch.iter().enumerate() {
+            let is_ancestor =
+                destination != earlier.destination && destination.starts_with(&earlier.destination);
+            let is_closer = parent.is_none_or(|parent| {
+                earlier.destination.components().count()
+                    > batch[parent].destination.components().count()
+            });
+            if is_ancestor && is_closer {
+                parent = Some(index);
+            }
+        }
+
+        batch.push(Batched {
+            trash: found.trash,
+            name: found.entry.name,
+            destination,
+            parent,
+        });
+    }
+
+    batch
+}
+
+/// Restores the entries of `batch`, in order (see [`order_batch`]), returning what became of each.
+///
+/// A failure doesn't stop the others from being restored, except for the entries that were to go inside it: these are
+/// left in the trash with [`Error::ParentNotRestored`], since restoring them would make the directory they go in
+/// anew, in the way of the trashed one. That's unless the directory is already there.
+pub fn restore_batch(
+    fs: &impl FileSystem,
+    batch: &[Batched],
+    options: RestoreOptions,
+) -> Vec<Result<RestorePlan>> {
+    let mut results: Vec<Result<RestorePlan>> = Vec::with_capacity(batch.len());
+    // What the entries restored so far made, which a dry run only pretends to
+    let mut made: BTreeSet<PathBuf> = BTreeSet::new();
+
+    for batched in batch {
+        let failed_parent = batched.parent.filter(|&parent| {
+            matches!(&results[parent], Err(err) if !matches!(err, Error::RestoreDestinationExists(..)))
+        });
+
+        let result = match failed_parent {
+            Some(parent) => Err(Error::ParentNotRestored(
+                batched.destination.clone(),
+                batch[parent].destination.clone(),
+            )),
+            None => restore_to(
+                fs,
+                batched.trash,
+                &batched.name,
+                batched.destination.clone(),
+                options,
+            ),
+        };
+        let result = result.map(|mut plan| {
+            plan.missing_parents.retain(|parent| !made.contains(parent));
+            made.extend(plan.missing_parents.iter().cloned());
+            made.insert(plan.destination.clone());
+            plan
+        });
+        results.push(result);
+    }
+
+    results
+}
+
+/// What restoring an entry takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestorePlan {
+    /// The entry, in `$trash/files`
+    pub source: PathBuf,
+    /// Where the entry is restored to
+    pub destination: PathBuf,
+    /// The ancestors of `destination` that don't exist and have to be created, outermost first
+    pub missing_parents: Vec<PathBuf>,
+    /// Whether the entry is in a different filesystem than `destination`, in which case it's copied
+    pub crosses_devices: bool,
+    /// Whether the entry is an archive of a directory (see [`crate::compress`]), which is unpacked
+    pub unpacks: bool,
+}
+
+impl RestorePlan {
+    /// Checks whether the entry of `trash` named `name` can be restored to `destination`,
+    /// figuring out what that would take.
+    fn new(
+        fs: &impl FileSystem,
+        trash: &Trash,
+        name: &OsStr,
+        destination: PathBuf,
+        verify: bool,
+    ) -> Result<Self> {
+        let source = trash.files.as_path().join(name);
+
+        if destination.symlink_metadata().is_ok() {
+            return Err(Error::RestoreDestinationExists(destination));
+        }
+
+        // The closest ancestor of the destination that exists, which is where something will be created
+        let mut missing_parents = Vec::new();
+        let mut existing_parent = destination.parent();
+        while let Some(parent) = existing_parent {
+            if parent.symlink_metadata().is_ok() {
+                break;
+            }
+            missing_parents.push(parent.to_owned());
+            existing_parent = parent.parent();
+        }
+        missing_parents.reverse();
+
+        let existing_parent: UnixString = existing_parent
+            .ok_or_else(|| Error::FailedToObtainFileName(destination.clone()))?
+            .to_owned()
+            .try_into()?;
+
+        // The entry is removed from `$trash/files` and created in the existing parent
+        for directory in [&existing_parent, &trash.files] {
+            if !ffi::is_writable_directory(directory) {
+                return Err(Error::NotWritable(directory.as_path().to_owned()));
+            }
+        }
+
+        if verify {
+            match checksum::verify(trash, name)? {
+                Verification::Matches => {}
+                Verification::Mismatch => return Err(Error::ChecksumMismatch(source)),
+                Verification::Unrecorded => warn(format_args!(
+                    "no checksum was recorded for {}, so it can't be verified",
+                    display_path(&source)
+                )),
+            }
+        }
+
+        let compressed = read_info_file(&build_info_file_path(name, trash.info_path()))
+            .ok()
+            .and_then(|info| info.compressed);
+        let unpacks = match compressed {
+            Some(format) if format == compress::FORMAT => true,
+            Some(format) => return Err(Error::UnsupportedCompression(source, format)),
+            None => false,
+        };
+
+        let source_unx: UnixString = source.clone().try_into()?;
+        // Unpacking writes the directory anew, wherever it is
+        let crosses_devices = !unpacks
+            && fs.lstat(source_unx.as_c_str())?.device()
+                != fs.lstat(existing_parent.as_c_str())?.device();
+
+        Ok(Self {
+            source,
+            destination,
+            missing_parents,
+            crosses_devices,
+            unpacks,
+        })
+    }
+
+    /// Describes each step of this plan, as done if `dry_run` is not set or as would be done otherwise.
+    pub fn describe(&self, dry_run: bool) -> Vec<String> {
+        let (create, restore, copy, unpack) = if dry_run {
+            (
+                "would create",
+                "would restore",
+                "would be copied",
+                "would be unpacked",
+            )
+        } else {
+            ("created", "restored", "copied", "unpacked")
+        };
+
+        let mut steps: Vec<_> = self
+            .missing_parents
+            .iter()
+            .map(|parent| format!("tt: {create} directory {}", display_path(parent)))
+            .collect();
+
+        let mut restored = format!(
+            "tt: {restore} {} to {}",
+            display_path(&self.source),
+            display_path(&self.destination)
+        );
+        if self.crosses_devices {
+            restored.push_str(&format!(" ({copy} across filesystems)"));
+        }
+        if self.unpacks {
+            restored.push_str(&format!(" ({unpack} from its archive)"));
+        }
+        steps.push(restored);
+
+        steps
+    }
+}
+
+/// Moves the entry of `trash` named `name` (as it's found in `$trash/files`) to `destination`,
+/// creating its missing parent directories and removing its info file and its line in `$trash/directorysizes`.
+/// The owner and mode recorded in its info file are put back, as far as we're allowed to and as long as the info
+/// file can be trusted with them (see [`reapply_metadata`]).
+///
+/// Fails if something already exists in `destination`, or if the entry doesn't match its checksum when
+/// `options.verify` is set. If `options.dry_run` is set, only checks whether the entry could be restored.
+pub fn restore_to(
+    fs: &impl FileSystem,
+    trash: &Trash,
+    name: &OsStr,
+    destination: PathBuf,
+    options: RestoreOptions,
+) -> Result<RestorePlan> {
+    // Held until the entry is wholly out of the trash, so that it isn't emptied from under us
+    let _lock = if options.dry_run {
+        None
+    } else {
+        TrashLock::for_entry(trash)?
+    };
+    let plan = RestorePlan::new(fs, trash, name, destination, options.verify)?;
+
+    if options.dry_run {
+        return Ok(plan);
+    }
+
+    let info_file_path = build_info_file_path(name, trash.info_path());
+    let info = read_owned_info_file(&info_file_path);
+
+    let unx: UnixString = plan.source.clone().try_into()?;
+    let is_directory = fs.lstat(unx.as_c_str())?.is_directory();
+    // Files that `--dedupe` linked other entries to, which must not change along with the restored ones
+    let shared = linked_from_elsewhere(trash, name)?;
+
+    for parent in &plan.missing_parents {
+        fs.create_dir(parent)?;
+    }
+
+    if plan.unpacks {
+        let keep_owners = info
+            .as_ref()
+            .is_some_and(|(info, info_file_owner)| is_trusted(info, *info_file_owner));
+        decompress(fs, &plan.source, &plan.destination, keep_owners)?;
+        // The directory is whole at its destination, so the archive can go
+        fs.remove_file(&plan.source)?;
+    } else {
+        move_file(
+            fs,
+            &plan.source,
+            &plan.destination,
+            options.preserve_context,
+        )
+        .map_err(|err| explain_denial(fs, &plan.source, err))?;
+        unshare(fs, &plan.destination, &shared)?;
+    }
+
+    if let Some((info, info_file_owner)) = &info {
+        reapply_metadata(&plan.destination, info, *info_file_owner);
+    }
+
+    // The file was already restored, so there's no going back if these fail
+    fs.remove_file(&info_file_path)?;
+
+    // Archives have their size in `$trash/directorysizes` too
+    if is_directory || plan.unpacks {
+        if let Err(err) = remove_directory_size(fs, trash, name) {
+            warn(format_args!(
+                "failed to update {}: {}",
+                display_path(trash.directory_sizes.as_path()),
+                err
+            ));
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Reads the info file at `path` (not following a symlink there) along with the user owning it, both taken from
+/// the same open file so that it can't be swapped in between.
+fn read_owned_info_file(path: &Path) -> Option<(TrashInfo, u32)> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+        .ok()?;
+    let owner = file.metadata().ok()?.uid();
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).ok()?;
+
+    Some((parse_info_file(&contents)?, owner))
+}
+
+/// Whether the owner and mode recorded in `info`, whose info file is owned by `info_file_owner`, can be given
+/// back to what's restored.
+///
+/// Info files of shared trashes can be written by anyone in their group, so what they record is only trusted if
+/// the info file is owned by the user restoring it or by the owner it records.
+fn is_trusted(info: &TrashInfo, info_file_owner: u32) -> bool {
+    info_file_owner == ffi::effective_user_id()
+        || info.owner.is_some_and(|(uid, _)| uid == info_file_owner)
+}
+
+/// Gives the file at `path` back the owner and mode recorded in `info`, whose info file is owned by
+/// `info_file_owner`, if it can be trusted with them (see [`is_trusted`]). The setuid, setgid and sticky bits are
+/// never put back (see [`PRIVILEGE_BITS`]).
+///
+/// Only root may give files away, and only their owner may change their mode, so failing to
+/// is not worth mentioning: the file was restored either way.
+fn reapply_metadata(path: &Path, info: &TrashInfo, info_file_owner: u32) {
+    if !is_trusted(info, info_file_owner) {
+        return;
+    }
+    let Ok(unx) = UnixString::try_from(path.to_owned()) else {
+        return;
+    };
+
+    // Changing the owner clears the setuid and setgid bits, so it goes first
+    if let Some((uid, gid)) = info.owner {
+        let _ = ffi::lchown(&unx, Some(uid), Some(gid));
+    }
+
+    // set_permissions follows symlinks, whose own mode is meaningless anyway
+    let is_symlink = ffi::Lstat::lstat(&unx).map_or(true, |stat| stat.is_symlink());
+    if let Some(mode) = info.mode.filter(|_| !is_symlink) {
+        let mode = mode & !PRIVILEGE_BITS;
+        let _ = fs_err::set_permissions(path, Permissions::from_mode(mode));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        io::Write,
+        path::{Path, PathBuf},
+        thread,
+        time::Duration,
+    };
+
+    use super::{
+        generations, last_trashed, order_batch, reapply_metadata, restore, restore_batch,
+        restore_generations, restore_into, Generations, RestoreOptions,
+    };
+    use crate::{
+        error::Result,
+        fs::RealFs,
+        info_file::{build_info_file_path, parse_info_file},
+        lock::TrashLock,
+        tests::{dummy_bytes, FaultyFs, Op},
+        trash::{Trash, TrashOptions},
+        which::which,
+        Error,
+    };
+
+    const DRY_RUN: RestoreOptions = RestoreOptions {
+        dry_run: true,
+        verify: false,
+        preserve_context: true,
+    };
+
+    fn trash_in(dir: &tempfile::TempDir) -> Result<Trash> {
+        let trash = Trash::from_root(dir.path().join("trash"))?;
+        fs::create_dir(&trash.root)?;
+        fs::create_dir(&trash.files)?;
+        fs::create_dir(&trash.info)?;
+        File::create(&trash.directory_sizes)?;
+
+        Ok(trash)
+    }
+
+    #[test]
+    fn restores_trashed_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+
+        let dummy_path = dir.path().join("dummy");
+        let contents = dummy_bytes();
+        File::create(&dummy_path)?.write_all(&contents)?;
+
+        let dummy_dir = dir.path().join("dummy-dir");
+        fs::create_dir(&dummy_dir)?;
+        File::create(dummy_dir.join("inner"))?.write_all(&dummy_bytes())?;
+
+        for to_be_trashed in [&dummy_path, &dummy_dir] {
+            let name = trash.send_to_trash(&RealFs, to_be_trashed)?;
+            assert!(!to_be_trashed.exists());
+
+            assert_eq!(
+                restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default())?.destination,
+                *to_be_trashed
+            );
+        }
+
+        assert_eq!(fs::read(&dummy_path)?, contents);
+        assert!(dummy_dir.join("inner").exists());
+
+        // Nothing is left behind in the trash
+        assert_eq!(fs::read_dir(&trash.files)?.count(), 0);
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 0);
+        assert!(fs::read_to_string(&trash.directory_sizes)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_to_overwrite_on_restore() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+        let name = trash.send_to_trash(&RealFs, &dummy_path)?;
+
+        File::create(&dummy_path)?.write_all(b"newer")?;
+
+        assert!(matches!(
+            restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default()),
+            Err(Error::RestoreDestinationExists(_))
+        ));
+        assert_eq!(fs::read(&dummy_path)?, b"newer");
+        assert!(trash.files.as_path().join(&name).exists());
+
+        for missing in ["missing", "", ".", "..", "../trash/files"] {
+            assert!(matches!(
+                restore(&RealFs, &trash, missing.as_ref(), RestoreOptions::default()),
+                Err(Error::EntryNotFound(_))
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn restores_into_another_directory() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+        let desktop = dir.path().join("desktop");
+        fs::create_dir(&desktop)?;
+
+        // The second one is named notes.txt.2 in the trash, but keeps its own name
+        let notes = dir.path().join("notes.txt");
+        let mut names = Vec::new();
+        for contents in ["first", "second"] {
+            fs::write(&notes, contents)?;
+            names.push(trash.send_to_trash(&RealFs, &notes)?);
+        }
+        assert_ne!(names[1].as_os_str(), "notes.txt");
+
+        let restored = restore_into(
+            &RealFs,
+            &trash,
+            names[1].as_os_str(),
+            &desktop,
+            RestoreOptions::default(),
+        )?;
+        assert_eq!(restored.destination, desktop.join("notes.txt"));
+        assert_eq!(fs::read_to_string(desktop.join("notes.txt"))?, "second");
+        assert!(!notes.exists());
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 1);
+
+        // The name is taken there now, and nothing is created for a directory that doesn't exist
+        for (directory, expected) in [
+            (&desktop, "restore-destination-exists"),
+            (&dir.path().join("missing"), "restore-directory-not-found"),
+            (&desktop.join("notes.txt"), "restore-directory-not-found"),
+        ] {
+            match restore_into(
+                &RealFs,
+                &trash,
+                names[0].as_os_str(),
+                directory,
+                RestoreOptions::default(),
+            ) {
+                Err(err) => assert_eq!(err.kind(), expected),
+                Ok(plan) => panic!("restored to {}", plan.destination.display()),
+            }
+        }
+        assert!(!dir.path().join("missing").exists());
+        assert!(trash.files.as_path().join(&names[0]).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn finds_the_last_trashed_entry() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+        let other = Trash::from_root(dir.path().join("other"))?;
+        for path in [&other.files, &other.info] {
+            fs::create_dir_all(path)?;
+        }
+        let trashes = [trash.clone(), other.clone()];
+        assert!(last_trashed(&trashes)?.is_none());
+
+        let trash_at = |trash: &Trash, name: &str, secs| -> Result<PathBuf> {
+            let path = dir.path().join(name);
+            File::create(&path)?.write_all(&dummy_bytes())?;
+            let options = TrashOptions {
+                deletion_date: Some(Duration::from_secs(secs)),
+                ..TrashOptions::default()
+            };
+            trash.send_to_trash_with(&RealFs, &path, &options)
+        };
+        trash_at(&trash, "old", 1_000)?;
+        let tie_a = trash_at(&trash, "tie-a", 2_000)?;
+        let tie_b = trash_at(&other, "tie-b", 2_000)?;
+        // Put there by hand, so it has no deletion date at all
+        File::create(trash.files.as_path().join("stray"))?;
+
+        // Trashed in the same second, but `tie-a`'s info file was written later
+        let written = |name: &PathBuf, secs| -> Result<()> {
+            let trash = if *name == tie_a { &trash } else { &other };
+            File::options()
+                .write(true)
+                .open(build_info_file_path(name.as_os_str(), trash.info_path()))?
+                .set_modified(std::time::UNIX_EPOCH + Duration::from_secs(secs))?;
+            Ok(())
+        };
+        written(&tie_a, 5_000)?;
+        written(&tie_b, 4_000)?;
+
+        let (found, entry) = last_trashed(&trashes)?.expect("there are dated entries");
+        assert_eq!(found.root, trash.root);
+        assert_eq!(entry.name, tie_a);
+        restore(&RealFs, found, &entry.name, RestoreOptions::default())?;
+        assert!(dir.path().join("tie-a").exists());
+
+        // And then the one that was trashed right before
+        let (found, entry) = last_trashed(&trashes)?.expect("there are dated entries");
+        assert_eq!(found.root, other.root);
+        assert_eq!(entry.name, tie_b);
+
+        Ok(())
+    }
+
+    #[test]
+    fn waits_for_the_trash_to_be_unlocked() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+        let name = trash.send_to_trash(&RealFs, &dummy_path)?;
+
+        // As if the trash were being emptied
+        let lock = TrashLock::exclusive(&trash)?;
+        thread::scope(|scope| -> Result<()> {
+            let restoring = scope
+                .spawn(|| restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default()));
+
+            thread::sleep(Duration::from_millis(200));
+            assert!(!dummy_path.exists());
+            drop(lock);
+            restoring.join().expect("restoring doesn't panic")?;
+
+            Ok(())
+        })?;
+        assert!(dummy_path.exists());
+        assert!(!build_info_file_path(name.as_os_str(), trash.info_path()).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn restores_owner_and_mode() -> Result<()> {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+        fs::set_permissions(&dummy_path, fs::Permissions::from_mode(0o640))?;
+        let metadata = fs::metadata(&dummy_path)?;
+
+        let name = trash.send_to_trash(&RealFs, &dummy_path)?;
+        let info_file =
+            fs::read_to_string(build_info_file_path(name.as_os_str(), trash.info_path()))?;
+        assert!(info_file.contains("\nX-TT-Mode=0640\n"));
+        assert!(info_file.contains(&format!(
+            "\nX-TT-Owner={}:{}\n",
+            metadata.uid(),
+            metadata.gid()
+        )));
+
+        // As if the mode was lost while copying it to the trash
+        let trashed = trash.files.as_path().join(&name);
+        fs::set_permissions(&trashed, fs::Permissions::from_mode(0o600))?;
+
+        restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default())?;
+        let restored = fs::metadata(&dummy_path)?;
+        assert_eq!(restored.permissions().mode() & 0o7777, 0o640);
+        assert_eq!(
+            (restored.uid(), restored.gid()),
+            (metadata.uid(), metadata.gid())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reapplies_only_trusted_metadata_without_privileges() -> Result<()> {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+        let name = trash.send_to_trash(&RealFs, &dummy_path)?;
+
+        // As if someone in the group of a shared trash made it setuid
+        let info_file_path = build_info_file_path(name.as_os_str(), trash.info_path());
+        let info_file = fs::read_to_string(&info_file_path)?;
+        let mode_line = info_file
+            .lines()
+            .find(|line| line.starts_with("X-TT-Mode="))
+            .expect("the mode is recorded");
+        fs::write(
+            &info_file_path,
+            info_file.replace(mode_line, "X-TT-Mode=4755"),
+        )?;
+
+        restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default())?;
+        let mode = fs::metadata(&dummy_path)?.permissions().mode();
+        assert_eq!(mode & 0o7777, 0o755);
+
+        // An info file written by someone else than whoever restores it and the owner it records
+        fs::set_permissions(&dummy_path, fs::Permissions::from_mode(0o600))?;
+        let uid = fs::metadata(&dummy_path)?.uid();
+        let info = parse_info_file(
+            format!("[Trash Info]\nPath=/dummy\nX-TT-Owner={uid}:0\nX-TT-Mode=0666\n").as_bytes(),
+        )
+        .expect("the info file is valid");
+        reapply_metadata(&dummy_path, &info, uid + 1);
+        let mode = fs::metadata(&dummy_path)?.permissions().mode();
+        assert_eq!(mode & 0o7777, 0o600);
+        // ... unlike one the recorded owner wrote
+        reapply_metadata(&dummy_path, &info, uid);
+        let mode = fs::metadata(&dummy_path)?.permissions().mode();
+        assert_eq!(mode & 0o7777, 0o666);
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_to_restore_entries_that_changed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+        let verify = RestoreOptions {
+            verify: true,
+            ..RestoreOptions::default()
+        };
+
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+        let name = trash.send_to_trash_with(
+            &RealFs,
+            &dummy_path,
+            &TrashOptions {
+                checksum: true,
+                ..TrashOptions::default()
+            },
+        )?;
+
+        // Bit rot, or tampering
+        let trashed = trash.files.as_path().join(&name);
+        let mut contents = fs::read(&trashed)?;
+        contents[0] ^= 1;
+        fs::write(&trashed, &contents)?;
+
+        for options in [
+            verify,
+            RestoreOptions {
+                dry_run: true,
+                ..verify
+            },
+        ] {
+            assert!(matches!(
+                restore(&RealFs, &trash, name.as_os_str(), options),
+                Err(Error::ChecksumMismatch(path)) if path == trashed
+            ));
+        }
+        assert!(trashed.exists());
+        assert!(!dummy_path.exists());
+
+        // Without --verify, it's restored as it is
+        restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default())?;
+        assert_eq!(fs::read(&dummy_path)?, contents);
+
+        Ok(())
+    }
+
+    #[test]
+    fn restores_generations_of_a_path() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+        let trashes = [trash.clone()];
+        let config = dir.path().join("config.toml");
+
+        let trash_generations = || -> Result<Vec<_>> {
+            let mut names = Vec::new();
+            for (contents, deletion_date) in [
+                ("first", "2024-01-01T00:00:00"),
+                ("second", "2024-02-01T00:00:00"),
+                ("third", "2024-02-01T00:00:00"),
+            ] {
+                fs::write(&config, contents)?;
+                let name = trash.send_to_trash(&RealFs, &config)?;
+
+                let info_file = build_info_file_path(name.as_os_str(), trash.info_path());
+                fs::write(
+                    info_file,
+                    format!(
+                        "[Trash Info]\nPath={}\nDeletionDate={deletion_date}\n",
+                        config.display()
+                    ),
+                )?;
+                names.push((name, contents));
+            }
+
+            // The second and third generations were trashed at the same time,
+            // so the one with the greatest name is considered the newest
+            let first = names.remove(0);
+            names.sort_by(|a, b| b.0.cmp(&a.0));
+            names.push(first);
+            Ok(names)
+        };
+
+        let newest_first = trash_generations()?;
+        let found = generations(&trashes, &config)?;
+        let found_names: Vec<_> = found.iter().map(|found| &found.entry.name).collect();
+        let expected_names: Vec<_> = newest_first
+            .iter()
+            .map(|(name, _)| name.as_os_str())
+            .collect();
+        assert_eq!(found_names, expected_names);
+
+        assert!(matches!(
+            restore_generations(
+                &RealFs,
+                &found,
+                &config,
+                Generations::Version(4),
+                None,
+                RestoreOptions::default()
+            ),
+            Err(Error::VersionNotFound(_, 4))
+        ));
+
+        restore_generations(
+            &RealFs,
+            &found,
+            &config,
+            Generations::Version(3),
+            None,
+            RestoreOptions::default(),
+        )?;
+        assert_eq!(fs::read_to_string(&config)?, "first");
+        fs::remove_file(&config)?;
+
+        let found = generations(&trashes, &config)?;
+        restore_generations(
+            &RealFs,
+            &found,
+            &config,
+            Generations::Newest,
+            None,
+            RestoreOptions::default(),
+        )?;
+        assert_eq!(fs::read_to_string(&config)?, newest_first[0].1);
+        fs::remove_file(&config)?;
+
+        // Only one generation is left, trash two more
+        let found = generations(&trashes, &config)?;
+        restore_generations(
+            &RealFs,
+            &found,
+            &config,
+            Generations::All,
+            None,
+            RestoreOptions::default(),
+        )?;
+        fs::remove_file(&config)?;
+        let newest_first = trash_generations()?;
+
+        let found = generations(&trashes, &config)?;
+        let restored: Vec<_> = restore_generations(
+            &RealFs,
+            &found,
+            &config,
+            Generations::All,
+            None,
+            RestoreOptions::default(),
+        )?
+        .into_iter()
+        .map(|plan| plan.destination)
+        .collect();
+        assert_eq!(
+            restored,
+            [
+                config.clone(),
+                dir.path().join("config.toml.1"),
+                dir.path().join("config.toml.2")
+            ]
+        );
+        for (path, (_, contents)) in restored.iter().zip(&newest_first) {
+            assert_eq!(fs::read_to_string(path)?, *contents);
+        }
+        assert_eq!(fs::read_dir(&trash.files)?.count(), 0);
+
+        Ok(())
+    }
+
+    /// Everything in `dir`, recursively, along with the contents of regular files
+    fn snapshot(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut snapshot: Vec<_> = walkdir::WalkDir::new(dir)
+            .into_iter()
+            .map(|entry| {
+                let path = entry.unwrap().into_path();
+                let contents = fs::read(&path).unwrap_or_default();
+                (path, contents)
+            })
+            .collect();
+        snapshot.sort();
+        snapshot
+    }
+
+    #[test]
+    fn dry_run_changes_nothing() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+
+        let dummy_dir = dir.path().join("dummy-dir");
+        fs::create_dir(&dummy_dir)?;
+        File::create(dummy_dir.join("inner"))?.write_all(&dummy_bytes())?;
+        let name = trash.send_to_trash(&RealFs, &dummy_dir)?;
+
+        let before = snapshot(dir.path());
+        let plan = restore(&RealFs, &trash, name.as_os_str(), DRY_RUN)?;
+        assert_eq!(snapshot(dir.path()), before);
+
+        assert_eq!(plan.destination, dummy_dir);
+        assert!(plan.missing_parents.is_empty());
+        assert!(!plan.crosses_devices);
+        assert_eq!(
+            plan.describe(true),
+            [format!(
+                "tt: would restore {} to {}",
+                trash.files.as_path().join(&name).display(),
+                dummy_dir.display()
+            )]
+        );
+
+        // The plan is what an actual restore does
+        assert_eq!(
+            restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default())?,
+            plan
+        );
+        assert!(dummy_dir.join("inner").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run_reports_collisions() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+
+        let dummy_path = dir.path().join("dummy");
+        fs::write(&dummy_path, "dummy")?;
+        let name = trash.send_to_trash(&RealFs, &dummy_path)?;
+        fs::write(&dummy_path, "newer")?;
+
+        let before = snapshot(dir.path());
+        assert!(matches!(
+            restore(&RealFs, &trash, name.as_os_str(), DRY_RUN),
+            Err(Error::RestoreDestinationExists(_))
+        ));
+        assert_eq!(snapshot(dir.path()), before);
+
+        Ok(())
+    }
+
+    #[test]
+    fn creates_missing_parents() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+
+        let parent = dir.path().join("parent");
+        let dummy_path = parent.join("nested/dummy");
+        fs::create_dir_all(dummy_path.parent().unwrap())?;
+        fs::write(&dummy_path, "dummy")?;
+        let name = trash.send_to_trash(&RealFs, &dummy_path)?;
+        fs::remove_dir_all(&parent)?;
+
+        let before = snapshot(dir.path());
+        let plan = restore(&RealFs, &trash, name.as_os_str(), DRY_RUN)?;
+        assert_eq!(snapshot(dir.path()), before);
+        assert_eq!(
+            plan.missing_parents,
+            [parent.clone(), parent.join("nested")]
+        );
+        assert_eq!(
+            plan.describe(true)[..2],
+            [
+                format!("tt: would create directory {}", parent.display()),
+                format!(
+                    "tt: would create directory {}",
+                    parent.join("nested").display()
+                ),
+            ]
+        );
+
+        restore(&RealFs, &trash, name.as_os_str(), RestoreOptions::default())?;
+        assert_eq!(fs::read_to_string(&dummy_path)?, "dummy");
+
+        Ok(())
+    }
+
+    /// Trashes `proj/src/a` and `proj/src/b` file by file, then `proj/src` itself, holding `c`, and `proj/notes`
+    fn trash_project(dir: &tempfile::TempDir, trash: &Trash) -> Result<PathBuf> {
+        let project = dir.path().join("proj");
+        fs::create_dir_all(project.join("src"))?;
+        for name in ["src/a", "src/b", "src/c", "notes"] {
+            fs::write(project.join(name), name)?;
+        }
+        for trashed in ["src/a", "src/b", "src", "notes"] {
+            trash.send_to_trash(&RealFs, &project.join(trashed))?;
+        }
+
+        Ok(project)
+    }
+
+    #[test]
+    fn orders_batches_parents_first() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+        let project = trash_project(&dir, &trash)?;
+        let trashes = [trash];
+
+        let batch = order_batch(which(&trashes, &project, true)?);
+        let destinations: Vec<_> = batch
+            .iter()
+            .map(|batched| batched.destination.strip_prefix(&project).unwrap())
+            .collect();
+        assert_eq!(
+            destinations,
+            [
+                Path::new("notes"),
+                "src".as_ref(),
+                "src/a".as_ref(),
+                "src/b".as_ref()
+            ]
+        );
+        let parents: Vec<_> = batch.iter().map(|batched| batched.parent).collect();
+        assert_eq!(parents, [None, None, Some(1), Some(1)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn restores_batches_in_place() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+        let project = trash_project(&dir, &trash)?;
+        // In the way of one of them
+        fs::create_dir(&project).ok();
+        fs::write(project.join("notes"), "newer")?;
+        let trashes = [trash];
+
+        let batch = order_batch(which(&trashes, &project, true)?);
+        let results = restore_batch(&RealFs, &batch, RestoreOptions::default());
+
+        assert!(matches!(
+            results[0],
+            Err(Error::RestoreDestinationExists(_))
+        ));
+        assert!(results[1..].iter().all(Result::is_ok));
+        for name in ["src/a", "src/b", "src/c"] {
+            assert_eq!(fs::read_to_string(project.join(name))?, name);
+        }
+        assert_eq!(fs::read_to_string(project.join("notes"))?, "newer");
+
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_children_of_failed_parents_in_the_trash() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_in(&dir)?;
+        let project = trash_project(&dir, &trash)?;
+        let trashes = [trash];
+
+        // Moving the trashed directory back fails
+        let fs = FaultyFs::failing(Op::Rename, 2, libc::EACCES);
+        let batch = order_batch(which(&trashes, &project, true)?);
+        let results = restore_batch(&fs, &batch, RestoreOptions::default());
+
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        for result in &results[2..] {
+            assert!(matches!(
+                result,
+                Err(Error::ParentNotRestored(_, parent)) if *parent == project.join("src")
+            ));
+        }
+        assert!(!project.join("src").exists());
+        for name in ["src", "a", "b"] {
+            assert!(trashes[0].files.as_path().join(name).exists());
+        }
+
+        Ok(())
+    }
+}
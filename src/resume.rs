@@ -0,0 +1,355 @@
+//! Copying directories into a trash on another filesystem in a way that survives being interrupted.
+//!
+//! Copying a huge directory can take long enough to get interrupted, be it by Ctrl-C or a reboot. So that trashing it
+//! again doesn't start over, it's copied into `$trash/copies/KEY.d`, `KEY` being derived from its path, and every file
+//! copied is recorded in the manifest `$trash/copies/KEY` along with the size and modification time it had. Trashing the
+//! same directory into the same trash again picks up where the copy was left: the files it recorded are skipped,
+//! unless they changed since. Once complete, the copy is renamed to its place in `$trash/files`, and the manifest is only
+//! removed after the directory itself is.
+//!
+//! A manifest is made of a `source PATH` line followed by a `SIZE MTIME MTIME_NSEC PATH` line per file copied, paths
+//! being percent-encoded and relative to the directory for files. It's locked while its copy is under way, so that two
+//! runs never copy into the same place.
+
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    io::{Read, Write},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::PermissionsExt,
+    },
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{Error, Result},
+    ffi::{try_lock_exclusive, DirFd, Lstat},
+    fs::{copy_xattrs, copy_xattrs_at, remove_recursively, rename_copy, FileSystem},
+    stats,
+};
+use percent_encoding::{percent_decode, percent_encode, NON_ALPHANUMERIC};
+use sha2::{Digest, Sha256};
+
+/// The name of the directory of a trash that copies under way are made in
+pub const COPIES_DIR: &str = "copies";
+
+/// What's recorded of a copied file, which must still hold for it to be skipped: its size and modification time
+type Stamp = (u64, i64, i64);
+
+/// The manifest of the copy of `from` made in the copies directory `copies`
+pub fn manifest_path(copies: &Path, from: &Path) -> PathBuf {
+    let hash = Sha256::digest(from.as_os_str().as_bytes());
+    let key: String = hash[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    copies.join(key)
+}
+
+/// Where the copy recorded by the manifest at `manifest` is made
+pub fn staging_path(manifest: &Path) -> PathBuf {
+    let mut staging = manifest.as_os_str().to_owned();
+    staging.push(".d");
+
+    staging.into()
+}
+
+/// Whether `file_name`, in a copies directory, is the name of a manifest rather than of a copy
+pub fn is_manifest_name(file_name: &OsStr) -> bool {
+    !file_name.as_bytes().ends_with(b".d")
+}
+
+/// The directory whose copy the manifest at `manifest` records, if it can be read
+pub fn source_of(manifest: &Path) -> Option<PathBuf> {
+    let text = fs_err::read(manifest).ok()?;
+
+    parse(&text).map(|(source, _)| source)
+}
+
+/// Copies the directory `from` into `to` through the copies directory `copies`, resuming an interrupted copy of it.
+///
+/// Returns the path of the manifest, which the caller removes once `from` is removed. Should copying fail, the copy
+/// so far and its manifest are left for the next attempt, and `to` isn't touched.
+pub fn copy(
+    fs: &impl FileSystem,
+    copies: &Path,
+    from: &Path,
+    to: &Path,
+    preserve_context: bool,
+) -> Result<PathBuf> {
+    if copies.symlink_metadata().is_err() {
+        fs.create_dir(copies)?;
+    }
+
+    let manifest_path = manifest_path(copies, from);
+    let staging = staging_path(&manifest_path);
+    let mut manifest = fs_err::OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(&manifest_path)?;
+    if !try_lock_exclusive(manifest.file())? {
+        return Err(Error::CopyInProgress(from.to_owned()));
+    }
+
+    let mut text = Vec::new();
+    manifest.read_to_end(&mut text)?;
+    // What the manifest records only holds as long as the copy is there
+    let copied = match parse(&text) {
+        Some((source, copied)) if source == from && staging.is_dir() => copied,
+        _ => {
+            if staging.symlink_metadata().is_ok() {
+                remove_recursively(fs, &staging)?;
+            }
+            // Appended to from the start again
+            manifest.file().set_len(0)?;
+            writeln!(manifest, "source {}", encode(from.as_os_str()))?;
+            HashMap::new()
+        }
+    };
+
+    if !staging.is_dir() {
+        fs.create_dir(&staging)?;
+    }
+    let mut resumption = Resumption {
+        fs,
+        copied,
+        manifest,
+        preserve_context,
+    };
+    let copy = DirFd::open(&staging)?;
+    resumption.copy_contents(&DirFd::open(from)?, &copy, Path::new(""))?;
+    copy_xattrs(from, &staging, preserve_context)?;
+    copy.set_mode(fs_err::symlink_metadata(from)?.permissions().mode())?;
+
+    rename_copy(fs, from, &staging, to)?;
+
+    Ok(manifest_path)
+}
+
+/// A copy under way
+struct Resumption<'a, F> {
+    fs: &'a F,
+    /// What the manifest recorded as copied when the copy was resumed
+    copied: HashMap<OsString, Stamp>,
+    manifest: fs_err::File,
+    preserve_context: bool,
+}
+
+impl<F: FileSystem> Resumption<'_, F> {
+    /// Copies what the directory `from` holds into the directory `to`, skipping the files recorded as copied already.
+    ///
+    /// `relative` is the path of `from` relative to the directory copied, which is only ever a key of the manifest:
+    /// files are reached relative to the directory they're in, so that trees deeper than `PATH_MAX` are copied too.
+    /// `to` is left writable, for its caller to give its own mode once done.
+    fn copy_contents(&mut self, from: &DirFd, to: &DirFd, relative: &Path) -> Result<()> {
+        // Whatever mode an earlier attempt left it with, it's filled in before getting its own
+        to.set_mode(0o700)?;
+
+        for name in from.entries()? {
+            let relative = relative.join(OsStr::from_bytes(name.to_bytes()));
+            let stat = Lstat::lstat_at(from, &name)?;
+            let existing = Lstat::lstat_at(to, &name).ok();
+
+            if stat.is_directory() {
+                if !existing.is_some_and(|existing| existing.is_directory()) {
+                    self.fs.create_dir_at(to, &name)?;
+                }
+                let copy = to.open_at(&name)?;
+                self.copy_contents(&from.open_at(&name)?, &copy, &relative)?;
+                copy_xattrs_at(from, to, &name, self.preserve_context)?;
+                copy.set_mode(stat.mode())?;
+            } else if stat.is_symlink() {
+                if existing.is_some() {
+                    self.fs.remove_file_at(to, &name)?;
+                }
+                self.fs.symlink_at(&from.read_link_at(&name)?, to, &name)?;
+                copy_xattrs_at(from, to, &name, self.preserve_context)?;
+            } else {
+                let stamp = (stat.size(), stat.modified() as i64, stat.modified_nsec());
+                let is_copied = self.copied.get(relative.as_os_str()) == Some(&stamp)
+                    && existing
+                        .as_ref()
+                        .is_some_and(|copy| copy.size() == stat.size());
+                if is_copied {
+                    continue;
+                }
+
+                // Which it may be, but not recorded, or not whole
+                if existing.is_some() {
+                    self.fs.remove_file_at(to, &name)?;
+                }
+                stats::copied(self.fs.copy_file_at(from, &name, to, &name)?);
+                copy_xattrs_at(from, to, &name, self.preserve_context)?;
+                writeln!(
+                    self.manifest,
+                    "{} {} {} {}",
+                    stamp.0,
+                    stamp.1,
+                    stamp.2,
+                    encode(relative.as_os_str())
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Removes the manifest at `manifest` along with the copy it records, giving up on resuming it
+pub fn discard(fs: &impl FileSystem, manifest: &Path) -> Result<()> {
+    let staging = staging_path(manifest);
+    if staging.symlink_metadata().is_ok() {
+        remove_recursively(fs, &staging)?;
+    }
+    fs.remove_file(manifest)?;
+
+    Ok(())
+}
+
+/// The manifests in the copies directory `copies`, sorted
+pub fn manifests(copies: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs_err::read_dir(copies) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut manifests = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if is_manifest_name(&entry.file_name()) {
+            manifests.push(entry.path());
+        }
+    }
+    manifests.sort();
+
+    Ok(manifests)
+}
+
+fn encode(path: &OsStr) -> String {
+    percent_encode(path.as_bytes(), NON_ALPHANUMERIC).to_string()
+}
+
+fn decode(field: &[u8]) -> OsString {
+    OsString::from_vec(percent_decode(field).collect())
+}
+
+/// The source and the files recorded by the manifest `text`, skipping lines that can't be read, such as one cut short
+fn parse(text: &[u8]) -> Option<(PathBuf, HashMap<OsString, Stamp>)> {
+    let mut lines = text.split(|&byte| byte == b'\n');
+    let source = decode(lines.next()?.strip_prefix(b"source ")?);
+
+    let copied = lines
+        .filter_map(|line| {
+            let mut fields = line.split(|&byte| byte == b' ');
+            let mut number = || std::str::from_utf8(fields.next()?).ok();
+            let stamp = (
+                number()?.parse().ok()?,
+                number()?.parse().ok()?,
+                number()?.parse().ok()?,
+            );
+            let path = fields.next()?;
+
+            Some((decode(path), stamp))
+        })
+        .collect();
+
+    Some((source.into(), copied))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::symlink, path::Path};
+
+    use super::{copy, manifest_path, source_of, staging_path};
+    use crate::{
+        error::Result,
+        fs::RealFs,
+        tests::{FaultyFs, Op},
+    };
+
+    #[test]
+    fn resumes_an_interrupted_copy() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("dataset");
+        fs::create_dir_all(source.join("b"))?;
+        for (name, contents) in [("a", "1"), ("b/c", "22"), ("b/d", "333"), ("e", "4444")] {
+            fs::write(source.join(name), contents)?;
+        }
+        symlink("b/c", source.join("link"))?;
+        let copies = dir.path().join("Trash/copies");
+        fs::create_dir_all(copies.parent().unwrap())?;
+        let destination = dir.path().join("Trash/dataset");
+
+        // Interrupted on its third file
+        let interrupted = FaultyFs::failing(Op::Copy, 3, libc::EIO);
+        assert!(copy(&interrupted, &copies, &source, &destination, true).is_err());
+        let manifest = manifest_path(&copies, &source);
+        assert_eq!(source_of(&manifest), Some(source.clone()));
+        assert!(staging_path(&manifest).join("b/c").exists());
+        assert!(!destination.exists());
+
+        // One of the files copied changed since
+        fs::write(source.join("a"), "changed")?;
+
+        let resumed = FaultyFs::default();
+        assert_eq!(
+            copy(&resumed, &copies, &source, &destination, true)?,
+            manifest
+        );
+        // `a` again, `b/d` and `e`, but not `b/c`
+        assert_eq!(resumed.calls(Op::Copy), 3);
+        for (name, contents) in [
+            ("a", "changed"),
+            ("b/c", "22"),
+            ("b/d", "333"),
+            ("e", "4444"),
+        ] {
+            assert_eq!(fs::read_to_string(destination.join(name))?, contents);
+        }
+        assert_eq!(fs::read_link(destination.join("link"))?, Path::new("b/c"));
+        assert!(!staging_path(&manifest).exists());
+        // Left to the caller, once the source is gone
+        assert!(manifest.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn starts_over_when_the_copy_is_gone() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("dataset");
+        fs::create_dir(&source)?;
+        fs::write(source.join("a"), "1")?;
+        let copies = dir.path().join("copies");
+
+        assert!(copy(
+            &FaultyFs::failing(Op::Rename, 1, libc::EIO),
+            &copies,
+            &source,
+            &dir.path().join("first"),
+            true
+        )
+        .is_err());
+        fs::remove_dir_all(staging_path(&manifest_path(&copies, &source)))?;
+
+        let fs = FaultyFs::default();
+        copy(&fs, &copies, &source, &dir.path().join("second"), true)?;
+        assert_eq!(fs.calls(Op::Copy), 1);
+        assert_eq!(fs::read_to_string(dir.path().join("second/a"))?, "1");
+
+        // Nothing in common with another directory's copy
+        let other = dir.path().join("other");
+        fs::create_dir(&other)?;
+        assert_ne!(
+            manifest_path(&copies, &source),
+            manifest_path(&copies, &other)
+        );
+        copy(&RealFs, &copies, &other, &dir.path().join("third"), true)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,457 @@
+//! A small shell for digging through a large trash, for `--interactive-restore`.
+//!
+//! It starts by showing a page of the most recently trashed entries, then reads one command per line from stdin:
+//!
+//! ```text
+//! tt> list *.pdf
+//! tt> info 12
+//! tt> restore 12
+//! tt> purge 3
+//! tt> quit
+//! ```
+//!
+//! Entries go by the numbers `--list` shows for them (see [`crate::ids`]). Restoring and purging go through
+//! [`restore`] and [`purge`], as `--restore` and `--purge` do, and each is over before the next command is read, so
+//! that leaving at any point, with `quit` or Ctrl-D, leaves the trash as consistent as these do.
+
+use std::{
+    ffi::{OsStr, OsString},
+    io::{BufRead, Write},
+    os::unix::ffi::{OsStrExt, OsStringExt},
+};
+
+use crate::{
+    cat::cat,
+    entries::{entries, purge, TrashEntry},
+    error::{Error, Result},
+    fs::FileSystem,
+    glob::{is_pattern, matches_name, matches_path},
+    ids::{numbered, Fingerprint},
+    lock::TrashLock,
+    prompt::Prompter,
+    report::{display_message, display_path},
+    restore::{restore, RestoreOptions},
+    trash::Trash,
+};
+
+/// How many entries are shown at once
+const PAGE_SIZE: usize = 20;
+
+const PROMPT: &str = "tt> ";
+
+const HELP: &str = "\
+list [PATTERN]  show the most recently trashed entries, or those whose name or original path matches PATTERN
+restore N       restore the entry numbered N to where it was trashed from
+purge N         permanently delete the entry numbered N, after asking
+info N          show the info file of the entry numbered N
+quit            leave, as Ctrl-D does";
+
+/// A line read from the user
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    List(Option<OsString>),
+    Restore(u64),
+    Purge(u64),
+    Info(u64),
+    Help,
+    Quit,
+}
+
+impl Command {
+    /// The command on `line`, if it isn't blank
+    fn parse(line: &[u8]) -> Result<Option<Self>> {
+        let line = line.trim_ascii();
+        let (word, rest) = match line.iter().position(u8::is_ascii_whitespace) {
+            Some(end) => (&line[..end], line[end..].trim_ascii()),
+            None => (line, &b""[..]),
+        };
+        let number = |command: &str| {
+            std::str::from_utf8(rest)
+                .ok()
+                .and_then(|number| number.parse().ok())
+                .ok_or_else(|| {
+                    Error::Usage(format!(
+                        "{command} expects the number of an entry, as shown by list"
+                    ))
+                })
+        };
+
+        let command = match word {
+            b"" => return Ok(None),
+            b"list" | b"ls" if rest.is_empty() => Self::List(None),
+            b"list" | b"ls" => Self::List(Some(OsString::from_vec(rest.to_vec()))),
+            b"restore" => Self::Restore(number("restore")?),
+            b"purge" => Self::Purge(number("purge")?),
+            b"info" => Self::Info(number("info")?),
+            b"help" | b"?" => Self::Help,
+            b"quit" | b"exit" | b"q" => Self::Quit,
+            _ => {
+                return Err(Error::Usage(format!(
+                    "unknown command {}, see help",
+                    String::from_utf8_lossy(word)
+                )))
+            }
+        };
+
+        Ok(Some(command))
+    }
+}
+
+/// Whether `entry` is listed with `pattern`, a glob matched against its name or its original path. A pattern without
+/// wildcards matches anything containing it.
+fn matches(pattern: &OsStr, entry: &TrashEntry) -> bool {
+    let pattern = if is_pattern(pattern) {
+        pattern.to_owned()
+    } else {
+        OsString::from_vec([b"*", pattern.as_bytes(), b"*"].concat())
+    };
+
+    matches_name(&pattern, &entry.name)
+        || entry
+            .original_path
+            .as_deref()
+            .is_some_and(|path| matches_path(pattern.as_ref(), path))
+}
+
+/// Reads commands from `input` until `quit` or the end of it, working on `trash` and writing to `output`.
+///
+/// Entries are restored with `restore_options`, and purged without asking if `yes` is set. A command that fails
+/// is reported and the next one read.
+pub fn run(
+    fs: &impl FileSystem,
+    trash: &Trash,
+    restore_options: RestoreOptions,
+    yes: bool,
+    input: impl BufRead,
+    output: impl Write,
+) -> Result<()> {
+    let mut shell = Shell {
+        fs,
+        trash,
+        restore_options,
+        yes,
+        input,
+        output,
+        pattern: None,
+        listed: None,
+    };
+
+    shell.list()?;
+    writeln!(
+        shell.output,
+        "tt: type help for the commands, quit to leave"
+    )?;
+    shell.run()
+}
+
+struct Shell<'a, F, R, W> {
+    fs: &'a F,
+    trash: &'a Trash,
+    restore_options: RestoreOptions,
+    yes: bool,
+    input: R,
+    output: W,
+    /// What entries were last listed with
+    pattern: Option<OsString>,
+    /// What the trash looked like when it was last listed, which the numbers shown depend on
+    listed: Option<Fingerprint>,
+}
+
+impl<F: FileSystem, R: BufRead, W: Write> Shell<'_, F, R, W> {
+    fn run(&mut self) -> Result<()> {
+        let mut line = Vec::new();
+        loop {
+            write!(self.output, "{PROMPT}")?;
+            self.output.flush()?;
+
+            line.clear();
+            if self.input.read_until(b'\n', &mut line)? == 0 {
+                // Ctrl-D leaves the cursor after the prompt
+                writeln!(self.output)?;
+                return Ok(());
+            }
+
+            let result = Command::parse(&line).and_then(|command| match command {
+                Some(Command::Quit) => Ok(false),
+                Some(command) => self.execute(command).map(|()| true),
+                None => Ok(true),
+            });
+            match result {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(err) => writeln!(self.output, "tt: error: {}", display_message(&err))?,
+            }
+        }
+    }
+
+    fn execute(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::List(pattern) => {
+                self.pattern = pattern;
+                self.list()
+            }
+            Command::Restore(id) => {
+                let Some(name) = self.numbered(id)? else {
+                    return Ok(());
+                };
+                let plan = restore(self.fs, self.trash, &name, self.restore_options)?;
+                for step in plan.describe(self.restore_options.dry_run) {
+                    writeln!(self.output, "{step}")?;
+                }
+                self.list()
+            }
+            Command::Purge(id) => {
+                let Some(name) = self.numbered(id)? else {
+                    return Ok(());
+                };
+                let question = format!("tt: permanently delete {}?", display_path(name.as_ref()));
+                if !Prompter::new(self.yes, Some((&mut self.input, &mut self.output)))
+                    .confirm(&question)?
+                {
+                    writeln!(self.output, "tt: nothing was deleted")?;
+                    return Ok(());
+                }
+
+                {
+                    let _lock = TrashLock::exclusive(self.trash)?;
+                    purge(self.fs, self.trash, &name)?;
+                }
+                writeln!(
+                    self.output,
+                    "tt: permanently deleted {}",
+                    display_path(name.as_ref())
+                )?;
+                self.list()
+            }
+            Command::Info(id) => match self.numbered(id)? {
+                Some(name) => cat(self.trash, &name, true, &mut self.output),
+                None => Ok(()),
+            },
+            Command::Help => Ok(writeln!(self.output, "{HELP}")?),
+            Command::Quit => unreachable!("handled by the loop"),
+        }
+    }
+
+    /// Shows a page of the most recently trashed entries matching the last pattern
+    fn list(&mut self) -> Result<()> {
+        let entries = entries(self.trash)?;
+        self.listed = Some(Fingerprint::of(&entries));
+
+        let mut shown: Vec<_> = numbered(&entries)
+            .into_iter()
+            .filter(|(name, _)| {
+                let entry = entries
+                    .iter()
+                    .find(|entry| entry.name == *name)
+                    .expect("numbered entries are among them");
+                self.pattern
+                    .as_ref()
+                    .is_none_or(|pattern| matches(pattern, entry))
+            })
+            .collect();
+        shown.sort_by_key(|(_, id)| std::cmp::Reverse(*id));
+
+        if shown.is_empty() {
+            match &self.pattern {
+                Some(pattern) => writeln!(
+                    self.output,
+                    "tt: no entry matches {}",
+                    display_path(pattern.as_ref())
+                )?,
+                None => writeln!(self.output, "tt: the trash is empty")?,
+            }
+            return Ok(());
+        }
+
+        for (name, id) in shown.iter().take(PAGE_SIZE) {
+            let entry = entries
+                .iter()
+                .find(|entry| entry.name == *name)
+                .expect("numbered entries are among them");
+            let deletion_date = entry
+                .deletion_date
+                .map_or_else(|| "-".repeat(19), |date| date.to_string());
+            let original_path = entry
+                .original_path
+                .as_deref()
+                .map_or_else(|| "<no valid info file>".into(), display_path);
+            writeln!(
+                self.output,
+                "{id:>5}  {deletion_date}  {}  {original_path}",
+                display_path(name.as_ref())
+            )?;
+        }
+        if let Some(more) = shown.len().checked_sub(PAGE_SIZE).filter(|&more| more > 0) {
+            writeln!(
+                self.output,
+                "tt: and {more} older, narrow them down with list PATTERN"
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The name of the entry numbered `id`, or `None` if the trash changed since it was listed, in which case it's
+    /// listed again as the number may have been shown for another entry
+    fn numbered(&mut self, id: u64) -> Result<Option<OsString>> {
+        let entries = entries(self.trash)?;
+        if self.listed != Some(Fingerprint::of(&entries)) {
+            writeln!(
+                self.output,
+                "tt: the trash changed since it was listed, so {id} may not be the entry it was:"
+            )?;
+            self.list()?;
+            return Ok(None);
+        }
+
+        let name = numbered(&entries)
+            .into_iter()
+            .find_map(|(name, numbered)| (numbered == id).then(|| name.to_owned()))
+            .ok_or(Error::IdNotFound(id))?;
+
+        Ok(Some(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{run, Command, Shell};
+    use crate::{
+        error::{Error, Result},
+        fs::RealFs,
+        restore::RestoreOptions,
+        trash::Trash,
+    };
+
+    const OPTIONS: RestoreOptions = RestoreOptions {
+        dry_run: false,
+        verify: false,
+        preserve_context: false,
+    };
+
+    fn trash_with(dir: &Path, files: &[(&str, &str)]) -> Result<Trash> {
+        let trash = Trash::from_root(dir.join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        for (name, date) in files {
+            fs::write(trash.files.as_path().join(name), name)?;
+            fs::write(
+                trash.info.as_path().join(format!("{name}.trashinfo")),
+                format!(
+                    "[Trash Info]\nPath={}\nDeletionDate={date}\n",
+                    dir.join(name).display()
+                ),
+            )?;
+        }
+
+        Ok(trash)
+    }
+
+    #[test]
+    fn parses_commands() -> Result<()> {
+        assert_eq!(Command::parse(b"  \n")?, None);
+        assert_eq!(Command::parse(b"list\n")?, Some(Command::List(None)));
+        assert_eq!(
+            Command::parse(b"list  *.pdf \n")?,
+            Some(Command::List(Some("*.pdf".into())))
+        );
+        assert_eq!(Command::parse(b"restore 12")?, Some(Command::Restore(12)));
+        assert_eq!(Command::parse(b"quit\n")?, Some(Command::Quit));
+        for line in [&b"restore twelve"[..], b"purge", b"frobnicate 1"] {
+            assert!(matches!(Command::parse(line), Err(Error::Usage(_))));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn restores_and_purges_by_number() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_with(
+            dir.path(),
+            &[
+                ("old.txt", "2024-01-01T00:00:00"),
+                ("report.pdf", "2024-02-01T00:00:00"),
+                ("new.txt", "2024-03-01T00:00:00"),
+            ],
+        )?;
+
+        let input = "list report\ninfo 2\nrestore 2\npurge 1\nn\npurge 1\ny\n";
+        let mut output = Vec::new();
+        run(
+            &RealFs,
+            &trash,
+            OPTIONS,
+            false,
+            input.as_bytes(),
+            &mut output,
+        )?;
+        let output = String::from_utf8(output).unwrap();
+
+        let dir = dir.path().display();
+        let page = format!(
+            "    3  2024-03-01T00:00:00  new.txt  {dir}/new.txt\n    2  2024-02-01T00:00:00  report.pdf  \
+             {dir}/report.pdf\n    1  2024-01-01T00:00:00  old.txt  {dir}/old.txt\n"
+        );
+        assert!(output.starts_with(&page));
+        assert!(output.contains(&format!(
+            "tt>     2  2024-02-01T00:00:00  report.pdf  {dir}/report.pdf\ntt> [Trash Info]\n"
+        )));
+        assert!(output.contains(&format!(
+            "tt: restored {}/files/report.pdf to {dir}/report.pdf\n",
+            trash.root.as_path().display()
+        )));
+        assert!(output.contains("tt: permanently delete old.txt? [y/N] tt: nothing was deleted\n"));
+        assert!(output.ends_with(
+            "tt: permanently delete old.txt? [y/N] tt: permanently deleted old.txt\n\
+             tt: no entry matches report\ntt> \n"
+        ));
+
+        assert!(Path::new(&format!("{dir}/report.pdf")).exists());
+        let left: Vec<_> = fs::read_dir(&trash.files)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<std::io::Result<_>>()?;
+        assert_eq!(left, ["new.txt"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn lists_again_rather_than_take_a_stale_number() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = trash_with(
+            dir.path(),
+            &[("a", "2024-01-01T00:00:00"), ("b", "2024-02-01T00:00:00")],
+        )?;
+        let mut shell = Shell {
+            fs: &RealFs,
+            trash: &trash,
+            restore_options: OPTIONS,
+            yes: true,
+            input: &b""[..],
+            output: Vec::new(),
+            pattern: None,
+            listed: None,
+        };
+
+        shell.list()?;
+        assert_eq!(shell.numbered(2)?, Some("b".into()));
+        assert!(matches!(shell.numbered(3), Err(Error::IdNotFound(3))));
+
+        // Once a is gone, b is numbered 1
+        fs::remove_file(trash.files.as_path().join("a"))?;
+        fs::remove_file(trash.info.as_path().join("a.trashinfo"))?;
+        assert_eq!(shell.numbered(1)?, None);
+        let output = String::from_utf8(shell.output.clone()).unwrap();
+        assert!(output.ends_with(&format!(
+            "tt: the trash changed since it was listed, so 1 may not be the entry it was:\n\
+             \x20   1  2024-02-01T00:00:00  b  {}/b\n",
+            dir.path().display()
+        )));
+        assert_eq!(shell.numbered(1)?, Some("b".into()));
+
+        Ok(())
+    }
+}
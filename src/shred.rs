@@ -0,0 +1,88 @@
+//! Overwriting the contents of files before they're deleted, for `--empty --shred`.
+//!
+//! Every regular file is overwritten once with zeros, in place, and synced to disk. Symlinks and special files are
+//! left as they are, having no contents of their own. This only makes the data unrecoverable on filesystems that write
+//! in place: copy-on-write filesystems (btrfs, ZFS), SSDs (which remap blocks as they wear) and snapshots or backups may
+//! all still hold the old contents.
+
+use std::{
+    collections::BTreeSet, fs::OpenOptions, io::Write, os::unix::fs::OpenOptionsExt, path::Path,
+};
+
+use unixstring::UnixString;
+use walkdir::WalkDir;
+
+use crate::{
+    error::Result,
+    ffi::{FileId, Lstat},
+};
+
+/// How much is written at once, so that large files are never held in memory
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Overwrites the contents of `path` with zeros, or those of every regular file under it if it's a directory, but
+/// those of `spared`, which other entries of the trash still hold (see [`crate::dedupe::linked_from_elsewhere`]).
+/// Symlinks are not followed.
+pub fn shred(path: &Path, spared: &BTreeSet<FileId>) -> Result<()> {
+    for entry in WalkDir::new(path) {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let unx: UnixString = entry.path().to_owned().try_into()?;
+        let stat = Lstat::lstat(&unx)?;
+
+        if stat.is_regular_file() && !spared.contains(&stat.file_id()) {
+            overwrite(entry.path(), stat.size())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Overwrites the first `size` bytes of the regular file at `path` with zeros, then syncs it
+fn overwrite(path: &Path, size: u64) -> Result<()> {
+    // Should it have been replaced by a symlink since, it's left alone
+    let mut file = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+
+    let zeros = vec![0; CHUNK_SIZE];
+    let mut left = size;
+    while left > 0 {
+        let chunk = left.min(CHUNK_SIZE as u64) as usize;
+        file.write_all(&zeros[..chunk])?;
+        left -= chunk as u64;
+    }
+
+    file.sync_all()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, fs, os::unix::fs::symlink};
+
+    use super::{shred, CHUNK_SIZE};
+    use crate::error::Result;
+
+    #[test]
+    fn overwrites_every_regular_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("secrets");
+        fs::create_dir_all(root.join("nested"))?;
+        let big = vec![0xAB; 2 * CHUNK_SIZE + 3];
+        fs::write(root.join("big"), &big)?;
+        fs::write(root.join("nested/small"), "password")?;
+        fs::write(dir.path().join("outside"), "untouched")?;
+        symlink(dir.path().join("outside"), root.join("link"))?;
+
+        shred(&root, &BTreeSet::new())?;
+
+        assert_eq!(fs::read(root.join("big"))?, vec![0; big.len()]);
+        assert_eq!(fs::read(root.join("nested/small"))?, [0; 8]);
+        // Neither is what symlinks point to
+        assert_eq!(fs::read(dir.path().join("outside"))?, b"untouched");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! Statistics about a run, printed at its end with `-vv` or `--stats`: how files got to the trash, how many bytes
+//! had to be copied rather than renamed, and how long probing mount points and each phase of trashing took, e.g.
+//!
+//! ```text
+//! tt: stats: 1,204 renamed, 37 copied, 0 compressed
+//! tt: stats: 19 GiB copied
+//! tt: stats: probing mount points took 1.2 ms
+//! tt: stats: resolving took 812.5 ms, writing info files 90.3 ms, moving 41.2 s, updating directorysizes 4.1 ms
+//! tt: stats: 42.8 s in all
+//! ```
+//!
+//! Nothing is collected unless [`enable`] was called: until then, timers don't even read the clock.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    fs::Transfer,
+    usage::{grouped, human_size},
+};
+
+/// Whether statistics are collected, as with `--stats`
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// What was collected so far
+static STATS: Mutex<Stats> = Mutex::new(Stats::new());
+
+/// A stretch of a run that's timed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Probing the mount table, at startup or again later (see [`crate::mount_table`])
+    Probe,
+    /// Looking at a file before trashing it: measuring directories, checksumming files, asking about them
+    Resolve,
+    /// Reserving a name in the trash by writing the info file of a file
+    InfoWrite,
+    /// Renaming, copying or compressing a file into the trash
+    Move,
+    /// Recording the size of a directory in `directorysizes`
+    DirectorySizes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub renamed: u64,
+    /// Copied, be it because the file was on another filesystem or because of `--force-copy`
+    pub copied: u64,
+    pub compressed: u64,
+    /// How many bytes of regular files were copied, be it across filesystems or because of `--force-copy`
+    pub bytes_copied: u64,
+    /// How long each [`Phase`] took, in their order
+    pub phases: [Duration; 5],
+}
+
+impl Stats {
+    const fn new() -> Self {
+        Self {
+            renamed: 0,
+            copied: 0,
+            compressed: 0,
+            bytes_copied: 0,
+            phases: [Duration::ZERO; 5],
+        }
+    }
+}
+
+/// Starts collecting statistics, and timing the run as a whole
+pub fn enable() -> Instant {
+    ENABLED.store(true, Ordering::Relaxed);
+    Instant::now()
+}
+
+/// Adds to the statistics collected, if they are
+fn collect(update: impl FnOnce(&mut Stats)) {
+    if ENABLED.load(Ordering::Relaxed) {
+        update(
+            &mut STATS
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+    }
+}
+
+/// When a phase starts, if statistics are collected, to be given to [`finish`] once it's over
+pub fn start() -> Option<Instant> {
+    ENABLED.load(Ordering::Relaxed).then(Instant::now)
+}
+
+/// Adds the time since `started` (see [`start`]) to how long `phase` took
+pub fn finish(phase: Phase, started: Option<Instant>) {
+    if let Some(started) = started {
+        collect(|stats| stats.phases[phase as usize] += started.elapsed());
+    }
+}
+
+/// Counts a file that got to the trash through `transfer`
+pub fn count(transfer: Transfer) {
+    collect(|stats| match transfer {
+        Transfer::Renamed => stats.renamed += 1,
+        Transfer::Copied | Transfer::ForcedCopy => stats.copied += 1,
+        Transfer::Compressed => stats.compressed += 1,
+    });
+}
+
+/// Counts `bytes` as copied rather than renamed
+pub fn copied(bytes: u64) {
+    collect(|stats| stats.bytes_copied += bytes);
+}
+
+/// What was collected so far
+pub fn collected() -> Stats {
+    *STATS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// `duration`, as people read it
+fn human_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+    if seconds >= 1.0 {
+        format!("{seconds:.1} s")
+    } else {
+        format!("{:.1} ms", seconds * 1000.0)
+    }
+}
+
+/// The lines `--stats` prints for `stats`, collected over a run that took `elapsed`
+pub fn lines(stats: &Stats, elapsed: Duration) -> Vec<String> {
+    let [probe, resolve, info_write, moving, directory_sizes] = stats.phases;
+
+    vec![
+        format!(
+            "tt: stats: {} renamed, {} copied, {} compressed",
+            grouped(stats.renamed),
+            grouped(stats.copied),
+            grouped(stats.compressed)
+        ),
+        format!(
+            "tt: stats: {} copied",
+            human_size(stats.bytes_copied)
+        ),
+        format!(
+            "tt: stats: probing mount points took {}",
+            human_duration(probe)
+        ),
+        format!(
+            "tt: stats: resolving took {}, writing info files {}, moving {}, updating directorysizes {}",
+            human_duration(resolve),
+            human_duration(info_write),
+            human_duration(moving),
+            human_duration(directory_sizes)
+        ),
+        format!("tt: stats: {} in all", human_duration(elapsed)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{lines, Phase, Stats};
+
+    #[test]
+    fn tells_what_was_collected() {
+        let mut stats = Stats {
+            renamed: 1204,
+            copied: 37,
+            bytes_copied: 3 << 20,
+            ..Stats::new()
+        };
+        stats.phases[Phase::Probe as usize] = Duration::from_micros(1200);
+        stats.phases[Phase::Move as usize] = Duration::from_millis(41_200);
+
+        assert_eq!(
+            lines(&stats, Duration::from_millis(42_800)),
+            [
+                "tt: stats: 1,204 renamed, 37 copied, 0 compressed",
+                "tt: stats: 3.0 MiB copied",
+                "tt: stats: probing mount points took 1.2 ms",
+                "tt: stats: resolving took 0.0 ms, writing info files 0.0 ms, moving 41.2 s, \
+                 updating directorysizes 0.0 ms",
+                "tt: stats: 42.8 s in all",
+            ]
+        );
+    }
+}
@@ -0,0 +1,349 @@
+//! The line closing a run that trashed several files, e.g.
+//! `tt: trashed 1,204 files and 37 directories (19 GiB), 3 skipped, 1 failed`.
+//!
+//! It's made from the outcomes collected for every operand, the very ones `--json` reports, so the two never disagree.
+//! Sizes are the ones found while trashing, which for directories are the ones recorded in `directorysizes`.
+
+use std::{ffi::OsString, fmt, path::PathBuf};
+
+use crate::{
+    error::{Error, Result},
+    json::TrashOutcome,
+    report,
+    usage::{grouped, human_size},
+};
+
+/// Tries `send` on every operand in turn, going on after those that fail as `rm` does, and returns what became of
+/// them. Failures are reported as they happen, naming the operand when the error doesn't; operands that don't exist
+/// are left out altogether with `force`.
+pub fn try_every(
+    operands: &[OsString],
+    force: bool,
+    mut send: impl FnMut(&OsString) -> Result<TrashOutcome>,
+) -> Vec<TrashOutcome> {
+    let mut outcomes = Vec::new();
+    for argument in operands {
+        match send(argument) {
+            Ok(outcome) => outcomes.push(outcome),
+            Err(Error::OperandNotFound(_)) if force => {}
+            Err(err) => {
+                let err = match err {
+                    Error::Io(err) => Error::TrashFailed(PathBuf::from(argument), err),
+                    err => err,
+                };
+                report::error(&err);
+                outcomes.push(TrashOutcome::failed(argument, &err));
+            }
+        }
+    }
+
+    outcomes
+}
+
+/// What became of the operands of a run, counted
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    /// Files trashed, symlinks and special files included
+    pub files: u64,
+    pub directories: u64,
+    /// How many bytes what was trashed takes up
+    pub bytes: u64,
+    /// Directories trashed without being measured, which `bytes` leaves out
+    pub unmeasured: u64,
+    pub skipped: u64,
+    /// Permanently deleted instead, as per the policy of their mount point
+    pub deleted: u64,
+    pub failed: u64,
+    /// Whether this is what would have become of them, with `--dry-run`
+    pub dry_run: bool,
+}
+
+impl Summary {
+    /// Counts `outcomes`
+    pub fn of(outcomes: &[TrashOutcome]) -> Self {
+        let mut summary = Self::default();
+        for outcome in outcomes {
+            match outcome {
+                TrashOutcome::Trashed {
+                    bytes, directory, ..
+                } => {
+                    if *directory {
+                        summary.directories += 1;
+                    } else {
+                        summary.files += 1;
+                    }
+                    match bytes {
+                        Some(bytes) => summary.bytes += bytes,
+                        None => summary.unmeasured += 1,
+                    }
+                }
+                TrashOutcome::Skipped { .. } => summary.skipped += 1,
+                TrashOutcome::Deleted { .. } => summary.deleted += 1,
+                TrashOutcome::Failed { .. } => summary.failed += 1,
+            }
+        }
+
+        summary
+    }
+}
+
+/// `count` followed by `singular` or `plural`, depending on it
+fn counted(count: u64, singular: &str, plural: &str) -> String {
+    let noun = if count == 1 { singular } else { plural };
+
+    format!("{} {noun}", grouped(count))
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let trashed = match (self.files, self.directories) {
+            (0, 0) => "nothing".to_owned(),
+            (files, 0) => counted(files, "file", "files"),
+            (0, directories) => counted(directories, "directory", "directories"),
+            (files, directories) => format!(
+                "{} and {}",
+                counted(files, "file", "files"),
+                counted(directories, "directory", "directories")
+            ),
+        };
+        let verb = if self.dry_run {
+            "would trash"
+        } else {
+            "trashed"
+        };
+        write!(f, "tt: {verb} {trashed}")?;
+        if self.unmeasured > 0 && self.unmeasured == self.files + self.directories {
+            write!(f, " (not measured)")?;
+        } else if self.unmeasured > 0 {
+            write!(f, " (at least {})", human_size(self.bytes))?;
+        } else if self.files + self.directories > 0 {
+            write!(f, " ({})", human_size(self.bytes))?;
+        }
+
+        for (count, what) in [
+            (self.deleted, "deleted instead"),
+            (self.skipped, "skipped"),
+            (self.failed, "failed"),
+        ] {
+            if count > 0 {
+                write!(f, ", {} {what}", grouped(count))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsString, fs, io};
+
+    use super::{try_every, Summary};
+    use crate::{
+        directorysizes::read_directory_sizes,
+        error::{Error, Result},
+        fs::{resolve_operand, RealFs},
+        json::TrashOutcome,
+        trash::{LargeThresholds, Trash, TrashOptions},
+    };
+
+    #[test]
+    fn counts_a_mixed_batch() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        fs::write(&trash.directory_sizes, "")?;
+
+        fs::write(dir.path().join("a"), "dummy")?;
+        fs::write(dir.path().join("b"), "abc")?;
+        fs::create_dir(dir.path().join("small"))?;
+        fs::write(dir.path().join("small/c"), "1234567")?;
+        fs::create_dir(dir.path().join("large"))?;
+        for name in ["d", "e"] {
+            fs::write(dir.path().join("large").join(name), "1")?;
+        }
+
+        // Directories of more than one entry are asked about, and turned down
+        let options = TrashOptions {
+            confirm_large: Some(LargeThresholds {
+                entries: 1,
+                size: u64::MAX,
+            }),
+            ..TrashOptions::default()
+        };
+        let outcomes: Vec<_> = ["a", "small", "missing", "large", "b"]
+            .into_iter()
+            .map(|name| {
+                let path = dir.path().join(name);
+                match trash.send(&RealFs, &path, &options, &mut |_, _| Ok(false)) {
+                    Ok(sent) => TrashOutcome::trashed(name.as_ref(), &path, &trash, &sent),
+                    Err(Error::NotConfirmed(_)) => TrashOutcome::skipped(name.as_ref()),
+                    Err(err) => TrashOutcome::failed(name.as_ref(), &err),
+                }
+            })
+            .collect();
+
+        assert_eq!(
+            Summary::of(&outcomes),
+            Summary {
+                files: 2,
+                directories: 1,
+                bytes: 15,
+                unmeasured: 0,
+                skipped: 1,
+                deleted: 0,
+                failed: 1,
+                dry_run: false,
+            }
+        );
+        // The size of the directory is the one recorded
+        assert_eq!(read_directory_sizes(&trash)?[0].size, 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn goes_past_failing_operands() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        fs::write(dir.path().join("a"), "dummy")?;
+        fs::write(dir.path().join("c"), "dummy")?;
+        std::os::unix::fs::symlink(dir.path().join("gone"), dir.path().join("dangling"))?;
+
+        let operands: Vec<OsString> = ["a", "missing", "unreadable", "dangling", "c"]
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+        let send = |argument: &OsString| {
+            let path = dir.path().join(argument);
+            if argument == "unreadable" {
+                return Err(io::Error::from_raw_os_error(libc::EACCES).into());
+            }
+            let path = resolve_operand(&path)?;
+            let sent = trash.send(&RealFs, &path, &TrashOptions::default(), &mut |_, _| {
+                Ok(true)
+            })?;
+            Ok(TrashOutcome::trashed(argument, &path, &trash, &sent))
+        };
+        let statuses = |outcomes: &[TrashOutcome]| -> Vec<_> {
+            outcomes
+                .iter()
+                .map(|outcome| {
+                    let outcome = serde_json::to_value(outcome).unwrap();
+                    match &outcome["error"] {
+                        serde_json::Value::Null => {
+                            format!("trashed {}", outcome["argument"].as_str().unwrap())
+                        }
+                        error => format!(
+                            "{} {}: {}",
+                            error["kind"].as_str().unwrap(),
+                            outcome["argument"].as_str().unwrap(),
+                            error["message"].as_str().unwrap()
+                        ),
+                    }
+                })
+                .collect()
+        };
+
+        // The operands after those that failed are trashed all the same, and every failure names its operand
+        let outcomes = try_every(&operands, false, send);
+        assert_eq!(
+            statuses(&outcomes),
+            [
+                "trashed a".to_owned(),
+                format!(
+                    "operand-not-found missing: {}",
+                    Error::OperandNotFound(dir.path().join("missing"))
+                ),
+                format!(
+                    "trash-failed unreadable: Failed to trash unreadable: {}",
+                    io::Error::from_raw_os_error(libc::EACCES)
+                ),
+                "trashed dangling".to_owned(),
+                "trashed c".to_owned(),
+            ]
+        );
+        assert_eq!(Summary::of(&outcomes).failed, 2);
+        assert_eq!(fs::read_dir(&trash.files)?.count(), 3);
+
+        // Missing operands aren't failures with -f, while dangling symlinks are trashed as any other file
+        fs::write(dir.path().join("a"), "dummy")?;
+        fs::write(dir.path().join("c"), "dummy")?;
+        std::os::unix::fs::symlink(dir.path().join("gone"), dir.path().join("dangling"))?;
+        let outcomes = try_every(&operands, true, send);
+        assert_eq!(statuses(&outcomes).len(), 4);
+        assert_eq!(Summary::of(&outcomes).failed, 1);
+        assert!(dir.path().join("dangling").symlink_metadata().is_err());
+        assert_eq!(fs::read_dir(&trash.files)?.count(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn says_what_became_of_every_operand() {
+        let summary = Summary {
+            files: 1204,
+            directories: 37,
+            bytes: 20_000_000_000,
+            unmeasured: 0,
+            skipped: 3,
+            deleted: 0,
+            failed: 1,
+            dry_run: false,
+        };
+        assert_eq!(
+            summary.to_string(),
+            "tt: trashed 1,204 files and 37 directories (19 GiB), 3 skipped, 1 failed"
+        );
+
+        let summary = Summary {
+            directories: 1,
+            bytes: 10,
+            deleted: 2,
+            ..Summary::default()
+        };
+        assert_eq!(
+            summary.to_string(),
+            "tt: trashed 1 directory (10 bytes), 2 deleted instead"
+        );
+
+        let summary = Summary {
+            files: 2,
+            directories: 2,
+            bytes: 10,
+            unmeasured: 1,
+            ..Summary::default()
+        };
+        assert_eq!(
+            summary.to_string(),
+            "tt: trashed 2 files and 2 directories (at least 10 bytes)"
+        );
+
+        let summary = Summary {
+            directories: 2,
+            unmeasured: 2,
+            ..Summary::default()
+        };
+        assert_eq!(
+            summary.to_string(),
+            "tt: trashed 2 directories (not measured)"
+        );
+
+        let summary = Summary {
+            skipped: 2,
+            ..Summary::default()
+        };
+        assert_eq!(summary.to_string(), "tt: trashed nothing, 2 skipped");
+
+        let summary = Summary {
+            files: 3,
+            bytes: 12,
+            dry_run: true,
+            ..Summary::default()
+        };
+        assert_eq!(summary.to_string(), "tt: would trash 3 files (12 bytes)");
+    }
+}
@@ -1,8 +1,21 @@
-use std::{fs::File, io::Write};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::{CStr, OsStr},
+    fs::File,
+    io,
+    io::Write,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
 
 use rand::{prelude::SmallRng, RngCore, SeedableRng};
 
-use crate::{home_dir::home_dir, trash::Trash};
+use crate::{
+    ffi::{DirFd, InodeFlags, Lstat},
+    fs::{FileSystem, RealFs},
+    trash::Trash,
+};
 
 pub fn dummy_bytes() -> Vec<u8> {
     let mut rng = SmallRng::from_entropy();
@@ -16,8 +29,7 @@ pub fn dummy_bytes() -> Vec<u8> {
 /// TODO: check for info file
 /// TODO: add test for directorysizes
 fn sends_file_to_trash() -> crate::Result<()> {
-    let home_dir = home_dir().unwrap();
-    let dir = tempfile::tempdir_in(&home_dir).unwrap();
+    let dir = tempfile::tempdir().unwrap();
     let dir_path = dir.path();
     let trash = Trash::from_root(dir_path)?;
 
@@ -29,16 +41,209 @@ fn sends_file_to_trash() -> crate::Result<()> {
     let mut dummy = File::create(&*dummy_path).unwrap();
     dummy.write_all(&dummy_bytes()).unwrap();
 
-    trash.send_to_trash(&dummy_path)?;
+    trash.send_to_trash(&RealFs, &dummy_path)?;
 
     // This path should no longer exist!
     assert!(!dummy_path.exists());
 
     // The file should now be in the trash
     let new_path = trash.files.as_path().join("dummy");
-    
+
     // The new file (now in the trash) should now exist
     assert!(new_path.exists());
 
     Ok(())
 }
+
+/// The operations of a [`FileSystem`], as far as [`FaultyFs`] is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Rename,
+    Copy,
+    CreateDir,
+    /// Both `create_file` and `create_new_file`
+    CreateFile,
+    Symlink,
+    /// Both `remove_file` and `remove_dir_all`
+    Remove,
+    Lstat,
+}
+
+/// A [`FileSystem`] that fails chosen operations, performing every other one on the actual filesystem.
+#[derive(Debug, Default)]
+pub struct FaultyFs {
+    /// The operation, which call to it should fail (starting at 1), and the errno it fails with
+    faults: Vec<(Op, usize, i32)>,
+    /// Attributes reported for some paths, as if set with `chattr`
+    inode_flags: HashMap<PathBuf, InodeFlags>,
+    /// Devices reported for some paths, as if another filesystem were mounted there
+    devices: HashMap<PathBuf, u64>,
+    /// The operation, which call to it the swap happens before, the path swapped and the target of the symlink
+    swaps: Vec<(Op, usize, PathBuf, PathBuf)>,
+    calls: RefCell<HashMap<Op, usize>>,
+}
+
+impl FaultyFs {
+    /// Makes the `nth` call to `op` fail with `errno`
+    pub fn failing(op: Op, nth: usize, errno: i32) -> Self {
+        Self::default().and(op, nth, errno)
+    }
+
+    /// Also makes the `nth` call to `op` fail with `errno`
+    pub fn and(mut self, op: Op, nth: usize, errno: i32) -> Self {
+        self.faults.push((op, nth, errno));
+        self
+    }
+
+    /// Replaces whatever is at `path` with a symlink to `target` right before the `nth` call to `op`,
+    /// as someone racing `tt` would. What was at `path` is moved to `path.swapped`.
+    pub fn swapping(mut self, op: Op, nth: usize, path: &Path, target: &Path) -> Self {
+        self.swaps
+            .push((op, nth, path.to_owned(), target.to_owned()));
+        self
+    }
+
+    /// Reports `flags` as the attributes of the file at `path`, without touching it
+    pub fn flagging(mut self, path: &Path, flags: InodeFlags) -> Self {
+        self.inode_flags.insert(path.to_owned(), flags);
+        self
+    }
+
+    /// Reports `device` as the device of the file at `path`, without touching it
+    pub fn relocating(mut self, path: &Path, device: u64) -> Self {
+        self.devices.insert(path.to_owned(), device);
+        self
+    }
+
+    /// `lstat`, the attributes of the file at `path`, on the device it's reported to be on
+    fn relocated(&self, path: &Path, lstat: Lstat) -> Lstat {
+        match self.devices.get(path) {
+            Some(&device) => lstat.on_device(device),
+            None => lstat,
+        }
+    }
+
+    /// How many times `op` was called so far
+    pub fn calls(&self, op: Op) -> usize {
+        self.calls.borrow().get(&op).copied().unwrap_or_default()
+    }
+
+    fn check(&self, op: Op) -> io::Result<()> {
+        let mut calls = self.calls.borrow_mut();
+        let call = calls.entry(op).or_default();
+        *call += 1;
+
+        for (_, _, path, target) in self
+            .swaps
+            .iter()
+            .filter(|(swap_op, nth, ..)| *swap_op == op && nth == call)
+        {
+            // Moved aside rather than removed, so that its inode can't be reused by the symlink
+            let mut aside = path.clone().into_os_string();
+            aside.push(".swapped");
+            std::fs::rename(path, aside)?;
+            std::os::unix::fs::symlink(target, path)?;
+        }
+
+        match self
+            .faults
+            .iter()
+            .find(|(fault_op, nth, _)| *fault_op == op && nth == call)
+        {
+            Some((_, _, errno)) => Err(io::Error::from_raw_os_error(*errno)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FileSystem for FaultyFs {
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.check(Op::Rename)?;
+        RealFs.rename(from, to)
+    }
+
+    fn copy_file(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        self.check(Op::Copy)?;
+        RealFs.copy_file(from, to)
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.check(Op::CreateDir)?;
+        RealFs.create_dir(path)
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<fs_err::File> {
+        self.check(Op::CreateFile)?;
+        RealFs.create_file(path)
+    }
+
+    fn create_new_file(&self, path: &Path) -> io::Result<fs_err::File> {
+        self.check(Op::CreateFile)?;
+        RealFs.create_new_file(path)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        self.check(Op::Symlink)?;
+        RealFs.symlink(target, link)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.check(Op::Remove)?;
+        RealFs.remove_file(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.check(Op::Remove)?;
+        RealFs.remove_dir_all(path)
+    }
+
+    fn lstat(&self, path: &CStr) -> crate::Result<Lstat> {
+        self.check(Op::Lstat)?;
+        let lstat = RealFs.lstat(path)?;
+        Ok(self.relocated(Path::new(OsStr::from_bytes(path.to_bytes())), lstat))
+    }
+
+    fn lstat_at(&self, dir: &DirFd, name: &CStr) -> crate::Result<Lstat> {
+        self.check(Op::Lstat)?;
+        let lstat = RealFs.lstat_at(dir, name)?;
+        Ok(self.relocated(&dir.path().join(OsStr::from_bytes(name.to_bytes())), lstat))
+    }
+
+    fn rename_at(&self, dir: &DirFd, name: &CStr, to: &Path) -> io::Result<()> {
+        self.check(Op::Rename)?;
+        RealFs.rename_at(dir, name, to)
+    }
+
+    fn create_dir_at(&self, dir: &DirFd, name: &CStr) -> io::Result<()> {
+        self.check(Op::CreateDir)?;
+        RealFs.create_dir_at(dir, name)
+    }
+
+    fn copy_file_at(
+        &self,
+        from_dir: &DirFd,
+        from_name: &CStr,
+        to_dir: &DirFd,
+        to_name: &CStr,
+    ) -> io::Result<u64> {
+        self.check(Op::Copy)?;
+        RealFs.copy_file_at(from_dir, from_name, to_dir, to_name)
+    }
+
+    fn symlink_at(&self, target: &CStr, dir: &DirFd, name: &CStr) -> io::Result<()> {
+        self.check(Op::Symlink)?;
+        RealFs.symlink_at(target, dir, name)
+    }
+
+    fn remove_file_at(&self, dir: &DirFd, name: &CStr) -> io::Result<()> {
+        self.check(Op::Remove)?;
+        RealFs.remove_file_at(dir, name)
+    }
+
+    fn inode_flags(&self, path: &Path) -> crate::Result<InodeFlags> {
+        match self.inode_flags.get(path) {
+            Some(flags) => Ok(*flags),
+            None => RealFs.inode_flags(path),
+        }
+    }
+}
@@ -1,22 +1,157 @@
 use std::{
-    path::{Path, PathBuf},
-    time::{SystemTime, UNIX_EPOCH},
+    ffi::{OsStr, OsString},
+    io::ErrorKind,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use fs_err as fs;
+use serde::Deserialize;
 use unixstring::UnixString;
 
 use crate::{
-    directorysizes::update_directory_sizes,
+    checksum::checksum,
+    compress,
+    crash::{crash_point, CrashPoint},
+    directorysizes::{read_directory_sizes, update_directory_sizes},
+    entries::{entries, entry_size},
     error::{Error, Result},
-    fs::{build_unique_file_name, directory_size},
-    info_file::write_info_file,
+    ffi::{is_group_member, real_user_id, DirFd, FileId, Lstat},
+    fs::{
+        build_unique_file_name, copy_and_remove_at, directory_stats, explain_denial, move_file_at,
+        normalize_lexically, numbered_file_name, remove_recursively, share_with_group,
+        DirectoryStats, FileSystem, Transfer,
+    },
+    info_file::{build_info_file_path, write_info_file, TrashInfo},
     light_fs::path_exists,
+    lock::{self, TrashLock},
+    log,
+    recovery::{mark_pending, marker_path},
+    report::{display_path, warn},
+    resume,
+    stats::{self, Phase},
 };
 
-#[derive(Debug)]
+/// How files are trashed, beyond what the specification requires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrashOptions {
+    /// Whether a checksum of regular files is recorded in their info file (see [`crate::checksum`])
+    pub checksum: bool,
+    /// The size, in bytes, from which directories are compressed into an archive (see [`crate::compress`])
+    pub compress_above: Option<u64>,
+    /// When files are recorded as trashed (a [`Duration`] since UNIX_EPOCH) instead of now, e.g. when importing them
+    pub deletion_date: Option<Duration>,
+    /// Whether security contexts are copied along when files are copied to another filesystem (see [`crate::fs::copy_xattrs`])
+    pub preserve_context: bool,
+    /// How many names are tried when the name of a file is taken in the trash
+    pub name_attempts: NameAttempts,
+    /// Past which directories are only trashed once confirmed (see [`Trash::send`])
+    pub confirm_large: Option<LargeThresholds>,
+    /// Whether files are copied into the trash, then removed, even when they could be renamed there, so that what's
+    /// in the trash never shares an inode with the links to it left outside
+    pub force_copy: bool,
+    /// Whether the sizes of trashed directories are recorded in `directorysizes`. When they aren't, directories are
+    /// only measured if compressing or confirming them depends on their size.
+    pub update_directory_sizes: bool,
+}
+
+impl TrashOptions {
+    /// Whether the size of trashed directories is needed
+    fn measures_directories(&self) -> bool {
+        self.update_directory_sizes || self.compress_above.is_some() || self.confirm_large.is_some()
+    }
+
+    /// Whether a directory of `directory_size` bytes (`None` for anything else) is compressed when trashed
+    fn compresses(&self, directory_size: Option<u64>) -> bool {
+        directory_size.is_some_and(|size| {
+            self.compress_above
+                .is_some_and(|threshold| size >= threshold)
+        })
+    }
+}
+
+impl Default for TrashOptions {
+    fn default() -> Self {
+        Self {
+            checksum: false,
+            compress_above: None,
+            deletion_date: None,
+            preserve_context: true,
+            name_attempts: NameAttempts::default(),
+            confirm_large: None,
+            force_copy: false,
+            update_directory_sizes: true,
+        }
+    }
+}
+
+/// When a directory is large enough that trashing it takes a while, as set in the `confirm_large` section of the
+/// configuration file. Exceeding either limit is enough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LargeThresholds {
+    /// How many files, directories and symlinks it may hold
+    pub entries: u64,
+    /// How many bytes its files may take up
+    pub size: u64,
+}
+
+impl Default for LargeThresholds {
+    fn default() -> Self {
+        Self {
+            entries: 100_000,
+            size: 10 << 30,
+        }
+    }
+}
+
+impl LargeThresholds {
+    /// Whether a directory holding `stats` is over these thresholds
+    pub fn are_exceeded(&self, stats: &DirectoryStats) -> bool {
+        stats.entries > self.entries || stats.size > self.size
+    }
+}
+
+/// How many variations of a name taken in a trash are tried before giving up, as set in the `name_attempts`
+/// section of the configuration file (see [`Trash::reserve_name`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NameAttempts {
+    /// How many numbered names (`NAME.1`, `NAME.2`, ...) are tried
+    pub numbered: u32,
+    /// How many names with a random suffix are tried after those
+    pub random: u32,
+}
+
+impl Default for NameAttempts {
+    fn default() -> Self {
+        Self {
+            numbered: 100,
+            random: 5,
+        }
+    }
+}
+
+impl NameAttempts {
+    /// The `attempt`th name tried for `file_name`, starting at 0 with `file_name` itself, if any is left
+    fn candidate(&self, file_name: &OsStr, attempt: u32) -> Option<OsString> {
+        if attempt == 0 {
+            Some(file_name.to_owned())
+        } else if attempt <= self.numbered {
+            Some(numbered_file_name(file_name, attempt))
+        } else if attempt - self.numbered <= self.random {
+            Some(build_unique_file_name(file_name, ""))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 /// A trash directory contains three subdirectories, named `info`, `directorysizes` and `files`.
 pub struct Trash {
+    /// The directory containing `files`, `info` and `directorysizes`.
+    pub root: UnixString,
     /// The $trash/files directory contains the files and directories that were trashed. When a file or directory is trashed, it must be moved into this directory.
     pub files: UnixString,
     /// The $trash/directorysizes directory is a cache of the sizes of the directories that were trashed
@@ -25,30 +160,71 @@ pub struct Trash {
     /// The $trash/info directory contains an “information file” for every file and directory in $trash/files.
     /// This file must have exactly the same name as the file or directory in $trash/files, plus the extension “.trashinfo”
     pub info: UnixString,
+    /// Where this trash was found, if it's the home trash and that's a symlink to `root` (see [`Trash::home`])
+    home_link: Option<PathBuf>,
 }
 
 impl Trash {
-    /// Builds a trash directory rooted at `root`.
+    /// Builds a trash directory rooted at `root`, which must be absolute. Its `.` and `..` components and trailing
+    /// slashes are resolved lexically (see [`normalize_lexically`]), so that a trash has a single root whichever way
+    /// it's spelled.
     ///
     /// Does not check if the directories of this trash directory exist.
     pub fn from_root(root: impl AsRef<Path>) -> Result<Self> {
         let root = root.as_ref();
+        if !root.is_absolute() {
+            return Err(Error::RelativeTrashRoot(root.to_owned()));
+        }
+        let root = &normalize_lexically(root);
 
         let files = root.join("files").try_into()?;
         let directory_sizes = root.join("directorysizes").try_into()?;
         let info = root.join("info").try_into()?;
 
         Ok(Self {
+            root: root.to_owned().try_into()?,
             files,
             directory_sizes,
             info,
+            home_link: None,
         })
     }
 
-    /// Builds a trash directory rooted at `root` checking if the directories of this trash directory exist.
+    /// Builds the home trash, found at `path`.
+    ///
+    /// Should `path` be a symlink, say to a bigger disk, the trash is rooted where it leads, so that devices are
+    /// compared and the trash is checked for safety there. It's still the home trash, though: its info files hold
+    /// absolute paths, or paths relative to the directory of `path`.
+    pub fn home(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let is_symlink = path
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.is_symlink());
+        // A dangling one is left as is, for the safety checks to refuse
+        let resolved = is_symlink
+            .then(|| std::fs::canonicalize(path).ok())
+            .flatten();
+
+        match resolved {
+            Some(resolved) => Ok(Self {
+                home_link: Some(path.to_owned()),
+                ..Self::from_root(resolved)?
+            }),
+            None => Self::from_root(path),
+        }
+    }
+
+    /// Where this trash was found, if it's the home trash and that's a symlink to its root (see [`Trash::home`])
+    pub fn home_link(&self) -> Option<&Path> {
+        self.home_link.as_deref()
+    }
+
+    /// Builds a trash directory rooted at `root` checking if the directories of this trash directory exist
+    /// and are safe to use (see [`Trash::assert_safe`]).
     pub fn from_root_checked(root: impl AsRef<Path>) -> Result<Self> {
         let trash = Self::from_root(root)?;
         trash.assert_exists()?;
+        trash.assert_safe()?;
         Ok(trash)
     }
 
@@ -57,28 +233,391 @@ impl Trash {
         self.info.as_path()
     }
 
+    /// The directory of this trash that directories from other filesystems are copied in (see [`crate::resume`])
+    pub fn copies_path(&self) -> PathBuf {
+        self.root.as_path().join(resume::COPIES_DIR)
+    }
+
+    /// The lock file of this trash (see [`crate::lock`])
+    pub fn lock_path(&self) -> PathBuf {
+        self.root.as_path().join(lock::LOCK_FILE_NAME)
+    }
+
+    /// The directory that relative `Path` values in this trash's info files are relative to.
+    ///
+    /// According to the spec, that's the directory the trash resides in: `$XDG_DATA_HOME` for the home trash
+    /// and `$topdir` for `$topdir/.Trash-$uid`. For `$topdir/.Trash/$uid`, that's `$topdir` as well.
+    pub fn base_dir(&self) -> Option<&Path> {
+        if let Some(link) = &self.home_link {
+            return link.parent();
+        }
+        let parent = self.root.as_path().parent()?;
+
+        if parent.file_name() == Some(OsStr::new(".Trash")) {
+            parent.parent()
+        } else {
+            Some(parent)
+        }
+    }
+
+    /// Resolves the `Path` value of an info file of this trash into the absolute path of the trashed file.
+    ///
+    /// Absolute values are taken as they are, while relative values are relative to [`Trash::base_dir`].
+    /// Values with `..` components are refused, as the spec forbids them.
+    pub fn resolve_original_path(&self, path_in_info_file: &Path) -> Result<PathBuf> {
+        let invalid = || Error::InvalidOriginalPath(path_in_info_file.to_owned());
+
+        let has_parent_dir = path_in_info_file
+            .components()
+            .any(|component| component == Component::ParentDir);
+        if has_parent_dir || path_in_info_file.as_os_str().is_empty() {
+            return Err(invalid());
+        }
+
+        if path_in_info_file.is_absolute() {
+            return Ok(path_in_info_file.to_owned());
+        }
+
+        Ok(self.base_dir().ok_or_else(invalid)?.join(path_in_info_file))
+    }
+
+    /// Whether this is the trash of a mount point (`$topdir/.Trash-$uid` or `$topdir/.Trash/$uid`),
+    /// as opposed to the home trash.
+    pub fn is_topdir_trash(&self) -> bool {
+        if self.home_link.is_some() {
+            return false;
+        }
+        let root = self.root.as_path();
+        let is_user_trash = root
+            .file_name()
+            .is_some_and(|name| name.as_bytes().starts_with(b".Trash-"));
+        let is_admin_trash = root.parent().and_then(Path::file_name) == Some(OsStr::new(".Trash"));
+
+        is_user_trash || is_admin_trash
+    }
+
+    /// The `Path` value an info file of this trash should have for a file trashed from `original_path`,
+    /// which is absolute. This is the opposite of [`Trash::resolve_original_path`].
+    ///
+    /// The spec wants the trashes of mount points to hold paths relative to their [base directory](Trash::base_dir),
+    /// so these get relative paths when possible. Otherwise, the path is kept absolute.
+    pub fn path_in_info_file(&self, original_path: &Path) -> PathBuf {
+        let relative = self
+            .base_dir()
+            .filter(|_| self.is_topdir_trash())
+            .and_then(|base_dir| original_path.strip_prefix(base_dir).ok())
+            .filter(|relative| !relative.as_os_str().is_empty());
+
+        relative.unwrap_or(original_path).to_owned()
+    }
+
+    /// How many bytes the entries of this trash take up, in total.
+    ///
+    /// The sizes of directories are taken from `$trash/directorysizes` when it has them, so this only
+    /// walks the directories it doesn't know about. Entries whose size can't be determined are not counted.
+    pub fn total_size(&self) -> Result<u64> {
+        let directory_sizes = read_directory_sizes(self)?;
+
+        Ok(entries(self)?
+            .iter()
+            .filter_map(|entry| entry_size(self, &entry.name, &directory_sizes).ok())
+            .sum())
+    }
+
+    /// If this trash directory is shared, returns the group it's shared with.
+    ///
+    /// A trash is considered shared when its root has the setgid bit set, which is how
+    /// an administrator would set up a `$topdir/.Trash` meant to be used by a whole group.
+    /// Entries sent to a shared trash are made readable and writable by its group.
+    pub fn shared_group(&self) -> Option<u32> {
+        let lstat = Lstat::lstat(&self.root).ok()?;
+        let is_setgid = lstat.mode() & libc::S_ISGID != 0;
+
+        is_setgid.then(|| lstat.owner_group_id())
+    }
+
+    /// Checks that the root, `files` and `info` directories of this trash can't be tampered with by other users.
+    ///
+    /// Each of them that exists must be an actual directory (not a symlink), owned by the current user and not
+    /// writable by everyone unless it's sticky. Otherwise, another user could have pre-created the trash to
+    /// capture or tamper with the files we trash. The root of a shared `$topdir/.Trash/$uid` an administrator
+    /// set up (see [`Trash::shared_group`]) may be owned by someone else, but its `files` and `info` may not.
+    pub fn assert_safe(&self) -> Result<()> {
+        self.assert_safe_for(real_user_id())
+    }
+
+    fn assert_safe_for(&self, uid: u32) -> Result<()> {
+        let is_admin_provided = self.is_admin_provided_for(uid);
+
+        for dir in [&self.root, &self.files, &self.info] {
+            // Missing directories are created by us, if ever
+            let Ok(lstat) = Lstat::lstat(dir) else {
+                continue;
+            };
+            let unsafe_trash =
+                |reason: String| Error::UnsafeTrash(dir.as_path().to_owned(), reason);
+
+            if lstat.is_symlink() {
+                return Err(unsafe_trash("it is a symbolic link".into()));
+            }
+            if !lstat.is_directory() {
+                return Err(unsafe_trash("it is not a directory".into()));
+            }
+            let is_exempt = is_admin_provided && dir == &self.root;
+            if !is_exempt && lstat.owner_user_id() != uid {
+                return Err(unsafe_trash(format!(
+                    "it is owned by user {} instead of user {}",
+                    lstat.owner_user_id(),
+                    uid
+                )));
+            }
+            if lstat.mode() & libc::S_IWOTH != 0 && lstat.mode() & libc::S_ISVTX == 0 {
+                return Err(unsafe_trash(
+                    "it is writable by everyone but not sticky".into(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether this is a shared `$topdir/.Trash/$uid` for `uid`: its root is setgid with a group the
+    /// current user is in, and lies in a sticky `.Trash` that isn't a symlink, as the spec asks of it.
+    ///
+    /// A `.Trash-$uid` never is, since anyone may have created it.
+    fn is_admin_provided_for(&self, uid: u32) -> bool {
+        let Some(gid) = self.shared_group() else {
+            return false;
+        };
+        let root = self.root.as_path();
+        let Some(parent) = root.parent() else {
+            return false;
+        };
+        let is_named_for_uid = root.file_name() == Some(OsStr::new(&uid.to_string()));
+        let is_in_dot_trash = parent.file_name() == Some(OsStr::new(".Trash"));
+        let is_sticky_directory = std::fs::symlink_metadata(parent)
+            .is_ok_and(|metadata| metadata.is_dir() && metadata.mode() & libc::S_ISVTX != 0);
+
+        is_named_for_uid && is_in_dot_trash && is_sticky_directory && is_group_member(gid)
+    }
+
     /// Checks that the directories of this trash exist.
     ///
     /// Doesn't check for `$trash/directorysizes` since it was added in a later version of the spec
     /// so it might have been created.
-    fn assert_exists(&self) -> Result<()> {
+    pub fn assert_exists(&self) -> Result<()> {
         if !path_exists(&self.info) || !path_exists(&self.files) {
-            let root = self
-                .files
-                .as_path()
-                .parent()
-                .expect("catastrophe: trash root ends with a root or prefix");
-            return Err(Error::TrashDirDoesNotExist(root.to_owned()));
+            return Err(Error::TrashDirDoesNotExist(self.root.as_path().to_owned()));
         }
 
         Ok(())
     }
 
+    /// Reserves `file_name` in this trash for the file `info` describes, by marking it as pending and writing its info file.
+    ///
+    /// Fails with [`AlreadyExists`](ErrorKind::AlreadyExists) if the name is already taken, leaving nothing behind.
+    fn reserve(
+        &self,
+        fs: &impl FileSystem,
+        file_name: &OsStr,
+        info: &TrashInfo,
+    ) -> Result<(PathBuf, PathBuf)> {
+        let marker_path = mark_pending(fs, self, file_name)?;
+
+        // Writes the info file for the file being trashed in `$trash/info`.
+        // This must be done before moving the file itself, as per the spec.
+        match write_info_file(fs, file_name, self, info) {
+            Ok(info_file_path) => Ok((marker_path, info_file_path)),
+            Err(err) => {
+                fs.remove_file(&marker_path)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Reserves a name in `$trash/files` for the file `info` describes, starting with `file_name`
+    /// and trying variations of it until one is free: `attempts` tells how many numbered ones
+    /// (`NAME.1`, `NAME.2`, ...) and then how many with a random suffix.
+    ///
+    /// According to the trash-spec 1.0, a file in the trash must not be overwritten by a newer file with the same
+    /// filename. The name is reserved by marking it as pending and then creating its info file, both of which fail
+    /// if another process got there first.
+    ///
+    /// Returns the reserved name, the path of its marker (which is to be removed once the file is in place)
+    /// and the path of its info file. Fails with [`Error::NamesExhausted`] once every name was found taken,
+    /// and with [`Error::CannotReserveName`] as soon as reserving one fails for another reason, such as
+    /// `$trash/info` not being writable.
+    pub fn reserve_name(
+        &self,
+        fs: &impl FileSystem,
+        file_name: &OsStr,
+        info: &TrashInfo,
+        attempts: &NameAttempts,
+    ) -> Result<(OsString, PathBuf, PathBuf)> {
+        let mut last_error = None;
+
+        let mut attempt = 0;
+        while let Some(candidate) = attempts.candidate(file_name, attempt) {
+            attempt += 1;
+
+            let candidate_path = self.files.as_path().join(&candidate);
+            // Dangling symlinks count as taken too
+            if candidate_path.symlink_metadata().is_ok() {
+                log::debug(format_args!(
+                    "{} is taken in {}, trying another name",
+                    display_path(candidate.as_ref()),
+                    display_path(self.files.as_path())
+                ));
+                last_error = Some((
+                    candidate_path,
+                    std::io::Error::from_raw_os_error(libc::EEXIST),
+                ));
+                continue;
+            }
+
+            match self.reserve(fs, &candidate, info) {
+                Ok((marker_path, info_file_path)) => {
+                    return Ok((candidate, marker_path, info_file_path))
+                }
+                Err(Error::Io(err)) if err.kind() == ErrorKind::AlreadyExists => {
+                    log::debug(format_args!(
+                        "{} was just taken in {}, trying another name",
+                        display_path(candidate.as_ref()),
+                        display_path(self.files.as_path())
+                    ));
+                    last_error = Some((candidate_path, err));
+                }
+                Err(Error::Io(err)) => {
+                    return Err(Error::CannotReserveName(
+                        self.info.as_path().to_owned(),
+                        err,
+                    ))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let (last_candidate, err) = last_error.expect("the name itself is always tried");
+        Err(Error::NamesExhausted(
+            file_name.into(),
+            attempt,
+            last_candidate,
+            err,
+        ))
+    }
+
+    /// Sends `to_be_removed` to this trash as [`Trash::send_to_trash_with`] does, with the default options
+    #[cfg(test)]
+    pub fn send_to_trash(&self, fs: &impl FileSystem, to_be_removed: &Path) -> Result<PathBuf> {
+        self.send_to_trash_with(fs, to_be_removed, &TrashOptions::default())
+    }
+
+    /// Sends `to_be_removed` to this trash as [`Trash::send`] does, only returning its name in `$trash/files`
+    #[cfg(test)]
+    pub fn send_to_trash_with(
+        &self,
+        fs: &impl FileSystem,
+        to_be_removed: &Path,
+        options: &TrashOptions,
+    ) -> Result<PathBuf> {
+        self.send(fs, to_be_removed, options, &mut |_, _| Ok(true))
+            .map(|sent| sent.file_name)
+    }
+
+    /// Finds out what [`Trash::send`] would do with `to_be_removed`, without writing anything: the name it would get in
+    /// `$trash/files`, how it would get there and how much it takes up. This trash may not exist yet, in which case
+    /// it's taken as empty.
+    ///
+    /// The name is the first one [`Trash::reserve_name`] would try that isn't taken now, nor in `planned`, the names
+    /// given to the files planned to go to this trash before this one. Directories are taken as confirmed, whatever
+    /// their size.
+    pub fn plan(
+        &self,
+        to_be_removed: &Path,
+        options: &TrashOptions,
+        planned: &[OsString],
+    ) -> Result<Sent> {
+        let file_name = to_be_removed
+            .file_name()
+            .ok_or_else(|| Error::FailedToObtainFileName(to_be_removed.into()))?;
+        let stat = Lstat::lstat(UnixString::try_from(to_be_removed.to_owned())?)?;
+        let directory_size = if stat.is_directory() && options.measures_directories() {
+            Some(directory_stats(UnixString::try_from(to_be_removed.to_owned())?)?.size)
+        } else {
+            None
+        };
+
+        let compresses = options.compresses(directory_size);
+        let file_name = if compresses {
+            compress::archive_name(file_name)
+        } else {
+            file_name.to_owned()
+        };
+
+        let is_taken = |candidate: &OsStr| {
+            planned.iter().any(|name| name == candidate)
+                || [
+                    self.files.as_path().join(candidate),
+                    build_info_file_path(candidate, self.info.as_path()),
+                    marker_path(self, candidate),
+                ]
+                .iter()
+                // Dangling symlinks count as taken too
+                .any(|path| path.symlink_metadata().is_ok())
+        };
+        let mut attempt = 0;
+        let mut last_candidate = None;
+        let file_name = loop {
+            let Some(candidate) = options.name_attempts.candidate(&file_name, attempt) else {
+                let last_candidate = last_candidate.expect("the name itself is always tried");
+                return Err(Error::NamesExhausted(
+                    file_name.into(),
+                    attempt,
+                    self.files.as_path().join(last_candidate),
+                    std::io::Error::from_raw_os_error(libc::EEXIST),
+                ));
+            };
+            attempt += 1;
+            if !is_taken(&candidate) {
+                break candidate;
+            }
+            last_candidate = Some(candidate);
+        };
+
+        // Renamed if it's on the filesystem of `$trash/files`, or of where that would be created
+        let files_device = self
+            .files
+            .as_path()
+            .ancestors()
+            .find_map(|ancestor| ancestor.symlink_metadata().ok())
+            .map(|metadata| metadata.dev());
+        let transfer = if compresses {
+            Transfer::Compressed
+        } else if options.force_copy {
+            Transfer::ForcedCopy
+        } else if files_device == Some(stat.device()) {
+            Transfer::Renamed
+        } else {
+            Transfer::Copied
+        };
+
+        Ok(Sent {
+            file_name: file_name.into(),
+            transfer,
+            size: if stat.is_directory() {
+                directory_size
+            } else {
+                Some(stat.size())
+            },
+            is_directory: stat.is_directory(),
+        })
+    }
+
     /// Sends the file given by `path` to the given trash structure
     ///
     ///
     /// In case of success, returns the name of the trashed file
-    /// exactly as sent to `TRASH/files`.
+    /// exactly as sent to `TRASH/files`, along with how it got there.
     ///
     /// # Note:
     ///
@@ -88,60 +627,233 @@ impl Trash {
     ///   When trashing a file or directory, the implementation
     ///   MUST create the corresponding file in $trash/info first
     ///```
-    /// Our implementation respects this by calling `build_info_file` before `move_file`
-    pub fn send_to_trash(&self, to_be_removed: &Path) -> Result<PathBuf> {
-        // How much time has passed since Jan 1st 1970?
-        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
-
-        // If we're trashing a directory, we must calculate its size
-        let directory_size = if to_be_removed.is_dir() {
-            let unx = to_be_removed.to_owned().try_into()?;
-            Some(directory_size(unx)?)
-        } else {
-            None
+    /// Our implementation respects this by writing the info file before calling `move_file_at`.
+    /// While the file is being trashed, it's also marked as pending (see [`crate::recovery`]), which
+    /// lets dangling info files left by interrupted runs be told apart from trashings in progress.
+    ///
+    /// All filesystem operations are performed through `fs`.
+    ///
+    /// If moving the file fails, its info file is removed and the file is left where it was.
+    ///
+    /// The file is looked at and moved relative to its parent directory, which is opened once, and it must still be
+    /// the file first seen (see [`FileId`]) right before it's moved. Otherwise, this fails with [`Error::SourceChanged`]
+    /// rather than trash whatever it was swapped for, which matters when running with elevated privileges.
+    ///
+    /// `$trash/directorysizes` is only a cache, so failing to update it is not considered
+    /// a failure to trash the file: a warning is shown and the file stays in the trash.
+    ///
+    /// What's done beyond what the specification requires, such as recording a checksum, is told by `options`.
+    /// Directories over its [`TrashOptions::confirm_large`] thresholds are only trashed if `confirm`, given the
+    /// directory and what it holds (as found while computing its size), says so. It's asked before anything is
+    /// written to the trash, and the directory is left alone with [`Error::NotConfirmed`] if it says no.
+    pub fn send(
+        &self,
+        fs: &impl FileSystem,
+        to_be_removed: &Path,
+        options: &TrashOptions,
+        confirm: &mut dyn FnMut(&Path, DirectoryStats) -> Result<bool>,
+    ) -> Result<Sent> {
+        // How much time has passed since Jan 1st 1970? Unless told otherwise, that's when the file is trashed
+        let now = match options.deletion_date {
+            Some(deletion_date) => deletion_date,
+            None => SystemTime::now().duration_since(UNIX_EPOCH)?,
         };
 
+        let mut started = stats::start();
+        let unx: UnixString = to_be_removed.to_owned().try_into()?;
+
         // The name of the file to be removed
         let file_name = to_be_removed
             .file_name()
             .ok_or_else(|| Error::FailedToObtainFileName(to_be_removed.into()))?;
+        let parent = to_be_removed
+            .parent()
+            .ok_or_else(|| Error::FailedToObtainFileName(to_be_removed.into()))?;
 
-        // Where the file will be sent to once trashed
-        let file_in_trash = self.files.as_path().join(&file_name);
+        // The file is operated on relative to its parent directory, opened once, so that it can't be swapped
+        // for another by replacing a directory above it. Swapping the file itself is caught by checking that it's
+        // still the one first seen right before it's moved.
+        let parent = DirFd::open(parent)?;
+        let name = UnixString::try_from(file_name.to_owned())?;
+        let unchanged = |file_id: FileId| -> Result<()> {
+            if fs.lstat_at(&parent, name.as_c_str())?.file_id() == file_id {
+                Ok(())
+            } else {
+                Err(Error::SourceChanged(to_be_removed.to_owned()))
+            }
+        };
 
-        // According to the trash-spec 1.0 states that, a file in the trash
-        // must not be overwritten by a newer file with the same filename.
-        //
-        // For this reason, we'll make a new unique filename for the file we're deleting if this
-        // occurs
-        let file_name = if file_in_trash.exists() {
-            build_unique_file_name(&file_name, &self.files.as_path())
+        // If we're trashing a directory, we must calculate its size.
+        // Symlinks to directories are not followed.
+        let stat = fs.lstat_at(&parent, name.as_c_str())?;
+        let file_id = stat.file_id();
+        let directory_stats = if stat.is_directory() && options.measures_directories() {
+            Some(directory_stats(unx)?)
         } else {
-            file_name.to_owned()
+            None
+        };
+        let directory_size = directory_stats.map(|stats| stats.size);
+
+        if let (Some(directory_stats), Some(thresholds)) = (directory_stats, options.confirm_large)
+        {
+            if thresholds.are_exceeded(&directory_stats) {
+                // Waiting for an answer is no part of trashing
+                stats::finish(Phase::Resolve, started);
+                if !confirm(to_be_removed, directory_stats)? {
+                    return Err(Error::NotConfirmed(to_be_removed.to_owned()));
+                }
+                started = stats::start();
+            }
+        }
+
+        // Makes a new unique filename for the file we're deleting if its name is already taken
+        let mut info = TrashInfo::new(to_be_removed, now)?.with_metadata(&stat);
+        if options.checksum {
+            if stat.is_regular_file() {
+                // An extra pass over the file, as it's read before being moved
+                info.checksum = Some(checksum(to_be_removed)?);
+            } else {
+                warn(format_args!(
+                    "{} is not a regular file, so no checksum is recorded for it",
+                    display_path(to_be_removed)
+                ));
+            }
+        }
+
+        let compresses = options.compresses(directory_size);
+        let archive_name;
+        let file_name = if compresses {
+            info.compressed = Some(compress::FORMAT.to_owned());
+            info.original_size = directory_size;
+            archive_name = compress::archive_name(file_name);
+            &archive_name
+        } else {
+            file_name
         };
 
+        stats::finish(Phase::Resolve, started);
+        let started = stats::start();
+
+        // Held until the file is wholly in the trash
+        let _lock = TrashLock::for_entry(self)?;
+
+        let (file_name, marker_path, info_file_path) =
+            self.reserve_name(fs, file_name, &info, &options.name_attempts)?;
+
         // The path of the trashed file in `$trash/files`
         let trash_file_path = self.files.as_path().join(&file_name);
 
-        // Writes the info file for the file being trashed in `$trash/info`.
-        // This must be done before deleting the original file, as per the spec.
-        let info_file_path = write_info_file(&to_be_removed, &file_name, self, now)?;
-
-        // Send the file being trashed... to the trash
-        if let Err(err) = crate::fs::move_file(to_be_removed, &*trash_file_path) {
-            // Remove the info file if moving the file fails
-            fs::remove_file(info_file_path)?;
-            eprintln!(
-                "failed to move {} to {}",
-                to_be_removed.display(),
-                trash_file_path.display()
-            );
-            return Err(err);
+        // The group of this trash, if it's shared
+        let shared_group = self.shared_group();
+
+        crash_point(CrashPoint::AfterInfoWrite)?;
+
+        if let Some(gid) = shared_group {
+            if let Err(err) = share_with_group(&info_file_path, gid) {
+                fs.remove_file(&info_file_path)?;
+                fs.remove_file(&marker_path)?;
+                return Err(err);
+            }
+        }
+
+        stats::finish(Phase::InfoWrite, started);
+        let started = stats::start();
+
+        // Send the file being trashed... to the trash, or an archive of it
+        let moved = unchanged(file_id).and_then(|()| {
+            if compresses {
+                let archive_size = compress::compress(fs, to_be_removed, &trash_file_path)?;
+                // Archiving reads the directory through its path, so it must not have changed meanwhile either
+                if let Err(err) = unchanged(file_id) {
+                    fs.remove_file(&trash_file_path)?;
+                    return Err(err);
+                }
+                Ok((Transfer::Compressed, Some(archive_size)))
+            } else if options.force_copy {
+                copy_and_remove_at(
+                    fs,
+                    &parent,
+                    name.as_c_str(),
+                    file_id,
+                    &trash_file_path,
+                    options.preserve_context,
+                    Some(&self.copies_path()),
+                )
+                .map(|()| (Transfer::ForcedCopy, None))
+            } else {
+                move_file_at(
+                    fs,
+                    &parent,
+                    name.as_c_str(),
+                    file_id,
+                    &trash_file_path,
+                    options.preserve_context,
+                    Some(&self.copies_path()),
+                )
+                .map(|transfer| (transfer, None))
+            }
+        });
+        let (transfer, archive_size) = match moved {
+            Ok(moved) => moved,
+            Err(err) => {
+                // Remove the info file if moving the file fails
+                fs.remove_file(&info_file_path)?;
+                fs.remove_file(&marker_path)?;
+                // Errors that don't say which files they're about are told where the file was going
+                return Err(match explain_denial(fs, to_be_removed, err) {
+                    Error::Io(err) => {
+                        Error::MoveFailed(to_be_removed.to_owned(), trash_file_path, err)
+                    }
+                    err => err,
+                });
+            }
+        };
+
+        if archive_size.is_some() {
+            // The archive is whole, so the directory is trashed even if some of it is left behind
+            if let Err(err) = remove_recursively(fs, to_be_removed) {
+                warn(format_args!(
+                    "failed to remove {} after archiving it into the trash: {}",
+                    display_path(to_be_removed),
+                    err
+                ));
+            }
+        }
+
+        stats::finish(Phase::Move, started);
+        stats::count(transfer);
+        crash_point(CrashPoint::AfterMove)?;
+
+        // The file is fully trashed, so there's nothing left to recover from
+        if let Err(err) = fs.remove_file(&marker_path) {
+            warn(format_args!(
+                "failed to remove {}: {}",
+                display_path(&marker_path),
+                err
+            ));
         }
 
-        // If we just trashed a directory, update `$trash/directorysizes`.
-        if let Some(directory_size) = directory_size {
-            update_directory_sizes(
+        if let Some(gid) = shared_group {
+            // The file was already trashed at this point, so we won't fail because of this
+            if let Err(err) = share_with_group(&trash_file_path, gid) {
+                warn(format_args!(
+                    "failed to share {} with the trash's group: {}",
+                    display_path(&trash_file_path),
+                    err
+                ));
+            }
+        }
+
+        // If we just trashed a directory, update `$trash/directorysizes`. Archives take up their own size.
+        if let Some(directory_size) = archive_size
+            .or(directory_size)
+            .filter(|_| options.update_directory_sizes)
+        {
+            crash_point(CrashPoint::BeforeDirectorySizes)?;
+
+            let started = stats::start();
+            let updated = update_directory_sizes(
+                fs,
                 // The trash the directory was sent to
                 self,
                 // The size of this directory, in bytes
@@ -150,28 +862,72 @@ impl Trash {
                 &file_name,
                 // When this directory was trashed
                 now,
-            )?;
-        }
+            );
+            stats::finish(Phase::DirectorySizes, started);
 
-        println!(
-            "tt: successfully sent {} to {}.",
-            to_be_removed.display(),
-            self.files.as_path().display()
-        );
+            if let Err(err) = updated {
+                warn(format_args!(
+                    "failed to update {}: {}",
+                    display_path(self.directory_sizes.as_path()),
+                    err
+                ));
+            }
+        }
 
-        Ok(file_name.into())
+        Ok(Sent {
+            file_name: file_name.into(),
+            transfer,
+            size: if stat.is_directory() {
+                directory_size
+            } else {
+                Some(stat.size())
+            },
+            is_directory: stat.is_directory(),
+        })
     }
 }
 
+/// What became of a file sent to a trash (see [`Trash::send`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sent {
+    /// Its name in `$trash/files`
+    pub file_name: PathBuf,
+    pub transfer: Transfer,
+    /// How many bytes it took up before being trashed, unless it's a directory that wasn't measured (see
+    /// [`TrashOptions::update_directory_sizes`])
+    pub size: Option<u64>,
+    pub is_directory: bool,
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Trash;
-    use crate::error::Result;
+    use std::{
+        fs::{self, File, Permissions},
+        io::Write,
+        os::unix::fs::{MetadataExt, PermissionsExt},
+        path::Path,
+        time::Duration,
+    };
+
+    use chrono::TimeZone;
+
+    use super::{LargeThresholds, NameAttempts, Trash, TrashOptions};
+    use crate::{
+        context::TrashContext,
+        directorysizes::read_directory_sizes,
+        error::{Error, Result},
+        ffi::InodeFlags,
+        fs::{DirectoryStats, RealFs, Transfer},
+        info_file::build_info_file_path,
+        tests::{dummy_bytes, FaultyFs, Op},
+    };
 
     #[test]
     fn trash_from_root_has_correct_paths() -> Result<()> {
         let trash = Trash::from_root("/home/vrmiguel/.Trash")?;
 
+        assert_eq!(trash.root, "/home/vrmiguel/.Trash");
+
         assert_eq!(trash.files, "/home/vrmiguel/.Trash/files");
 
         assert_eq!(
@@ -183,4 +939,708 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn normalizes_trash_roots() -> Result<()> {
+        for root in [
+            "/home/user/.Trash/",
+            "/home/user/./.Trash//",
+            "/home/user/docs/../.Trash",
+            "/home/../home/user/.Trash/.",
+        ] {
+            let trash = Trash::from_root(root)?;
+            assert_eq!(trash.root, "/home/user/.Trash", "{root}");
+            assert_eq!(trash.files, "/home/user/.Trash/files", "{root}");
+        }
+
+        for root in ["relative/.Trash", ".Trash", "./.Trash", ""] {
+            assert!(
+                matches!(Trash::from_root(root), Err(Error::RelativeTrashRoot(path)) if path == Path::new(root)),
+                "{root}"
+            );
+        }
+
+        // Nothing's left to take the parent of, but that's no reason to panic
+        let trash = Trash::from_root("/../")?;
+        assert_eq!(trash.root, "/");
+        assert_eq!(trash.info, "/info");
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("missing/..").join("trash");
+        assert!(matches!(
+            Trash::from_root(&root)?.assert_exists(),
+            Err(Error::TrashDirDoesNotExist(path)) if path == dir.path().join("trash")
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_original_paths() -> Result<()> {
+        let home_trash = Trash::from_root("/home/dummy/.local/share/Trash")?;
+        let topdir_trash = Trash::from_root("/mnt/data/.Trash-1000")?;
+        let shared_topdir_trash = Trash::from_root("/mnt/data/.Trash/1000")?;
+
+        assert_eq!(
+            home_trash.resolve_original_path("docs/file".as_ref())?,
+            Path::new("/home/dummy/.local/share/docs/file")
+        );
+        for trash in [&topdir_trash, &shared_topdir_trash] {
+            assert_eq!(
+                trash.resolve_original_path("docs/file".as_ref())?,
+                Path::new("/mnt/data/docs/file")
+            );
+            assert_eq!(
+                trash.resolve_original_path("/home/dummy/file".as_ref())?,
+                Path::new("/home/dummy/file")
+            );
+        }
+
+        // Which is the opposite of picking the value of `Path`
+        assert_eq!(
+            home_trash.path_in_info_file("/home/dummy/.local/share/docs/file".as_ref()),
+            Path::new("/home/dummy/.local/share/docs/file")
+        );
+        for trash in [&topdir_trash, &shared_topdir_trash] {
+            assert!(trash.is_topdir_trash());
+            assert_eq!(
+                trash.path_in_info_file("/mnt/data/docs/file".as_ref()),
+                Path::new("docs/file")
+            );
+            assert_eq!(
+                trash.path_in_info_file("/mnt/other/file".as_ref()),
+                Path::new("/mnt/other/file")
+            );
+        }
+        assert!(!home_trash.is_topdir_trash());
+
+        for invalid in ["", "../file", "docs/../../file", "/home/../etc/passwd"] {
+            assert!(matches!(
+                home_trash.resolve_original_path(invalid.as_ref()),
+                Err(Error::InvalidOriginalPath(_))
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Builds a trash in a temporary directory, with its root having the given permission bits
+    fn trash_with_mode(mode: u32) -> Result<(tempfile::TempDir, Trash)> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("trash"))?;
+
+        fs::create_dir(&trash.root)?;
+        fs::set_permissions(&trash.root, Permissions::from_mode(mode))?;
+        fs::create_dir(&trash.files)?;
+        fs::create_dir(&trash.info)?;
+        File::create(&trash.directory_sizes)?;
+
+        Ok((dir, trash))
+    }
+
+    #[test]
+    fn confirms_large_directories_before_writing_anything() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+
+        // Just over the threshold, with its 4 files and a directory
+        let dataset = dir.path().join("dataset");
+        fs::create_dir_all(dataset.join("nested"))?;
+        for file in ["a", "b", "nested/c", "nested/d"] {
+            fs::write(dataset.join(file), "dummy")?;
+        }
+        let options = TrashOptions {
+            confirm_large: Some(LargeThresholds {
+                entries: 4,
+                size: 1 << 20,
+            }),
+            ..TrashOptions::default()
+        };
+
+        let mut asked = Vec::new();
+        let mut decline = |path: &Path, stats: DirectoryStats| {
+            asked.push((path.to_owned(), stats));
+            Ok(false)
+        };
+        assert!(matches!(
+            trash.send(&RealFs, &dataset, &options, &mut decline),
+            Err(Error::NotConfirmed(path)) if path == dataset
+        ));
+        assert_eq!(
+            asked,
+            [(
+                dataset.clone(),
+                DirectoryStats {
+                    size: 20,
+                    entries: 5
+                }
+            )]
+        );
+        assert!(dataset.join("nested/d").exists());
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 0);
+
+        // Not asked under the thresholds
+        let small = dir.path().join("small");
+        fs::create_dir(&small)?;
+        fs::write(small.join("a"), "dummy")?;
+        let mut unreachable =
+            |_: &Path, _| -> Result<bool> { panic!("asked about a small directory") };
+        trash.send(&RealFs, &small, &options, &mut unreachable)?;
+
+        trash.send(&RealFs, &dataset, &options, &mut |_, _| Ok(true))?;
+        assert!(!dataset.exists());
+        assert!(trash.files.as_path().join("dataset/nested/d").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn forces_copies_when_told_to() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+
+        let original = dir.path().join("original");
+        let sibling = dir.path().join("sibling");
+        fs::write(&original, "dummy")?;
+        fs::hard_link(&original, &sibling)?;
+        let options = TrashOptions {
+            force_copy: true,
+            ..TrashOptions::default()
+        };
+
+        assert_eq!(
+            trash.plan(&original, &options, &[])?.transfer,
+            Transfer::ForcedCopy
+        );
+        let sent = trash.send(&RealFs, &original, &options, &mut |_, _| Ok(true))?;
+        assert_eq!(sent.transfer, Transfer::ForcedCopy);
+        assert!(!original.exists());
+
+        // The copy in the trash is a file of its own, which the sibling no longer shares an inode with
+        let trashed = fs::metadata(trash.files.as_path().join(&sent.file_name))?;
+        let sibling = fs::metadata(&sibling)?;
+        assert_ne!(trashed.ino(), sibling.ino());
+        assert_eq!(sibling.nlink(), 1);
+        assert_eq!(
+            fs::read(trash.files.as_path().join(&sent.file_name))?,
+            b"dummy"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_whole_copies_in_the_trash_when_the_original_cant_be_removed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+
+        let original = dir.path().join("original");
+        fs::write(&original, "dummy")?;
+        let options = TrashOptions {
+            force_copy: true,
+            ..TrashOptions::default()
+        };
+
+        let fs = FaultyFs::failing(Op::Remove, 1, libc::EPERM);
+        let sent = trash.send(&fs, &original, &options, &mut |_, _| Ok(true))?;
+
+        // The original is left behind, but the copy can still be restored
+        assert!(original.exists());
+        assert_eq!(
+            fs::read(trash.files.as_path().join(&sent.file_name))?,
+            b"dummy"
+        );
+        assert!(build_info_file_path(sent.file_name.as_os_str(), trash.info.as_path()).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_directorysizes_alone_when_told_to() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join("Trash"))?;
+        fs::create_dir_all(&trash.files)?;
+        fs::create_dir_all(&trash.info)?;
+        fs::write(&trash.directory_sizes, "")?;
+
+        let build = dir.path().join("build");
+        fs::create_dir(&build)?;
+        fs::write(build.join("a"), "dummy")?;
+        let options = TrashOptions {
+            update_directory_sizes: false,
+            ..TrashOptions::default()
+        };
+
+        assert_eq!(trash.plan(&build, &options, &[])?.size, None);
+        let sent = trash.send(&RealFs, &build, &options, &mut |_, _| Ok(true))?;
+        assert_eq!(sent.size, None);
+        assert!(trash.files.as_path().join("build/a").exists());
+        assert!(fs::read_to_string(&trash.directory_sizes)?.is_empty());
+
+        // Measured all the same when compressing depends on it
+        let small = dir.path().join("small");
+        fs::create_dir(&small)?;
+        fs::write(small.join("a"), "dummy")?;
+        let options = TrashOptions {
+            compress_above: Some(1 << 20),
+            ..options
+        };
+        let sent = trash.send(&RealFs, &small, &options, &mut |_, _| Ok(true))?;
+        assert_eq!(sent.size, Some(5));
+        assert!(fs::read_to_string(&trash.directory_sizes)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_unsafe_trashes() -> Result<()> {
+        let is_unsafe = |result: Result<()>, expected: &str| matches!(result, Err(Error::UnsafeTrash(_, reason)) if reason.contains(expected));
+
+        let (_dir, trash) = trash_with_mode(0o700)?;
+        trash.assert_safe()?;
+        let uid = fs::metadata(&trash.root)?.uid();
+        assert!(is_unsafe(trash.assert_safe_for(uid + 1), "owned by user"));
+
+        let (_dir, trash) = trash_with_mode(0o777)?;
+        assert!(is_unsafe(trash.assert_safe(), "writable by everyone"));
+        let (_dir, trash) = trash_with_mode(0o1777)?;
+        trash.assert_safe()?;
+
+        // Shared trashes still can't be wide open
+        let (_dir, trash) = trash_with_mode(0o2775)?;
+        trash.assert_safe()?;
+        fs::set_permissions(&trash.files, Permissions::from_mode(0o777))?;
+        assert!(is_unsafe(trash.assert_safe(), "writable by everyone"));
+
+        // Anyone could have created a setgid `.Trash-$uid`, so it must be ours
+        let dir = tempfile::tempdir()?;
+        let trash = Trash::from_root(dir.path().join(format!(".Trash-{}", uid + 1)))?;
+        for path in [&trash.root, &trash.files, &trash.info] {
+            fs::create_dir(path)?;
+        }
+        fs::set_permissions(&trash.root, Permissions::from_mode(0o2775))?;
+        assert!(matches!(
+            trash.assert_safe_for(uid + 1),
+            Err(Error::UnsafeTrash(path, reason)) if path == trash.root.as_path() && reason.contains("owned by user")
+        ));
+
+        // The root of an administrator's `$topdir/.Trash/$uid` may belong to someone else, but not what's in it
+        let dot_trash = dir.path().join(".Trash");
+        fs::create_dir(&dot_trash)?;
+        fs::set_permissions(&dot_trash, Permissions::from_mode(0o1777))?;
+        let trash = Trash::from_root(dot_trash.join((uid + 1).to_string()))?;
+        for path in [&trash.root, &trash.files, &trash.info] {
+            fs::create_dir(path)?;
+        }
+        fs::set_permissions(&trash.root, Permissions::from_mode(0o2775))?;
+        assert!(matches!(
+            trash.assert_safe_for(uid + 1),
+            Err(Error::UnsafeTrash(path, reason)) if path == trash.files.as_path() && reason.contains("owned by user")
+        ));
+
+        // ... as long as `.Trash` is sticky
+        fs::set_permissions(&dot_trash, Permissions::from_mode(0o777))?;
+        assert!(matches!(
+            trash.assert_safe_for(uid + 1),
+            Err(Error::UnsafeTrash(path, _)) if path == trash.root.as_path()
+        ));
+
+        // A symlink to a trash someone else controls
+        let (dir, target) = trash_with_mode(0o700)?;
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(&target.root, &link)?;
+        assert!(is_unsafe(
+            Trash::from_root(&link)?.assert_safe(),
+            "symbolic link"
+        ));
+        assert!(matches!(
+            Trash::from_root_checked(&link),
+            Err(Error::UnsafeTrash(..))
+        ));
+
+        let (_dir, trash) = trash_with_mode(0o700)?;
+        fs::remove_dir(&trash.info)?;
+        File::create(&trash.info)?;
+        assert!(is_unsafe(trash.assert_safe(), "not a directory"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn setgid_trash_is_shared() -> Result<()> {
+        let (_dir, trash) = trash_with_mode(0o2775)?;
+        let gid = fs::metadata(&trash.root)?.gid();
+
+        assert_eq!(trash.shared_group(), Some(gid));
+
+        let (_dir, trash) = trash_with_mode(0o775)?;
+        assert_eq!(trash.shared_group(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn entries_sent_to_shared_trash_are_group_writable() -> Result<()> {
+        let (dir, trash) = trash_with_mode(0o2775)?;
+        let gid = fs::metadata(&trash.root)?.gid();
+
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+        fs::set_permissions(&dummy_path, Permissions::from_mode(0o600))?;
+
+        let dummy_dir = dir.path().join("dummy-dir");
+        fs::create_dir(&dummy_dir)?;
+        fs::set_permissions(&dummy_dir, Permissions::from_mode(0o700))?;
+        File::create(dummy_dir.join("inner"))?.write_all(&dummy_bytes())?;
+
+        for to_be_trashed in [&dummy_path, &dummy_dir] {
+            let file_name = trash.send_to_trash(&RealFs, to_be_trashed)?;
+            let mut info_file_name = file_name.clone().into_os_string();
+            info_file_name.push(".trashinfo");
+
+            let info_file = fs::metadata(trash.info_path().join(info_file_name))?;
+            assert_eq!(info_file.mode() & 0o060, 0o060);
+            assert_eq!(info_file.gid(), gid);
+
+            let trashed = fs::metadata(trash.files.as_path().join(&file_name))?;
+            assert_eq!(trashed.gid(), gid);
+            if trashed.is_dir() {
+                assert_eq!(trashed.mode() & 0o070, 0o070);
+            } else {
+                assert_eq!(trashed.mode() & 0o060, 0o060);
+            }
+        }
+
+        let inner = fs::metadata(trash.files.as_path().join("dummy-dir/inner"))?;
+        assert_eq!(inner.mode() & 0o060, 0o060);
+
+        Ok(())
+    }
+
+    #[test]
+    fn entries_sent_to_private_trash_keep_their_permissions() -> Result<()> {
+        let (dir, trash) = trash_with_mode(0o700)?;
+
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+        fs::set_permissions(&dummy_path, Permissions::from_mode(0o600))?;
+
+        let file_name = trash.send_to_trash(&RealFs, &dummy_path)?;
+
+        let trashed = fs::metadata(trash.files.as_path().join(file_name))?;
+        assert_eq!(trashed.mode() & 0o777, 0o600);
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_move_removes_info_file() -> Result<()> {
+        let (dir, trash) = trash_with_mode(0o700)?;
+        let ctx = TrashContext::new(&trash.root, vec![])?.with_fs(FaultyFs::failing(
+            Op::Rename,
+            1,
+            libc::EACCES,
+        ));
+
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+
+        assert!(ctx.send_to_trash(ctx.home_trash(), &dummy_path).is_err());
+
+        // The file was not trashed and no info file was left behind
+        assert!(dummy_path.exists());
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 0);
+        assert_eq!(fs::read_dir(&trash.files)?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_directorysizes_update_keeps_directory_trashed() -> Result<()> {
+        let (dir, trash) = trash_with_mode(0o700)?;
+        // The first rename moves the directory, the second one replaces `directorysizes`
+        let ctx = TrashContext::new(&trash.root, vec![])?.with_fs(FaultyFs::failing(
+            Op::Rename,
+            2,
+            libc::EIO,
+        ));
+
+        let dummy_dir = dir.path().join("dummy-dir");
+        fs::create_dir(&dummy_dir)?;
+        File::create(dummy_dir.join("inner"))?.write_all(&dummy_bytes())?;
+
+        let file_name = ctx.send_to_trash(ctx.home_trash(), &dummy_dir)?;
+
+        // `directorysizes` is only a cache: the directory stays in the trash
+        assert!(!dummy_dir.exists());
+        assert!(trash.files.as_path().join(&file_name).is_dir());
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 1);
+        assert!(fs::read_to_string(&trash.directory_sizes)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn numbers_names_taken_in_the_trash() -> Result<()> {
+        let (dir, trash) = trash_with_mode(0o700)?;
+        let ctx = TrashContext::new(&trash.root, vec![])?;
+
+        let dummy_path = dir.path().join("dummy");
+        for expected in ["dummy", "dummy.1", "dummy.2"] {
+            fs::write(&dummy_path, expected)?;
+            assert_eq!(
+                ctx.send_to_trash(ctx.home_trash(), &dummy_path)?,
+                Path::new(expected)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn gives_up_once_every_name_is_taken() -> Result<()> {
+        let (dir, trash) = trash_with_mode(0o700)?;
+        let dummy_path = dir.path().join("dummy");
+        fs::write(&dummy_path, "dummy")?;
+
+        // Taken in `files`, or reserved by an info file
+        for name in ["dummy", "dummy.1", "dummy.2"] {
+            fs::write(trash.files.as_path().join(name), "taken")?;
+        }
+        fs::write(trash.info.as_path().join("dummy.3.trashinfo"), "")?;
+
+        let attempts = NameAttempts {
+            numbered: 3,
+            random: 0,
+        };
+        let ctx = TrashContext::new(&trash.root, vec![])?.attempting_names(attempts);
+        match ctx.send_to_trash(ctx.home_trash(), &dummy_path) {
+            Err(Error::NamesExhausted(name, tried, last_candidate, err)) => {
+                assert_eq!(name, Path::new("dummy"));
+                assert_eq!(tried, 4);
+                assert_eq!(last_candidate, trash.files.as_path().join("dummy.3"));
+                assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+            }
+            other => panic!("expected running out of names, got {other:?}"),
+        }
+        // Nothing was left behind
+        assert!(dummy_path.exists());
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 1);
+
+        // Random names are tried last
+        let ctx = ctx.attempting_names(NameAttempts {
+            random: 1,
+            ..attempts
+        });
+        let file_name = ctx.send_to_trash(ctx.home_trash(), &dummy_path)?;
+        assert!(file_name.to_string_lossy().len() > "dummy.3".len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn tells_unwritable_info_directories_apart_from_collisions() -> Result<()> {
+        let (dir, trash) = trash_with_mode(0o700)?;
+        let dummy_path = dir.path().join("dummy");
+        fs::write(&dummy_path, "dummy")?;
+
+        // Creating the pending marker is the first thing reserving a name does
+        let ctx = TrashContext::new(&trash.root, vec![])?.with_fs(FaultyFs::failing(
+            Op::CreateFile,
+            1,
+            libc::EACCES,
+        ));
+        match ctx.send_to_trash(ctx.home_trash(), &dummy_path) {
+            Err(Error::CannotReserveName(info_dir, err)) => {
+                assert_eq!(info_dir, trash.info.as_path());
+                assert_eq!(err.raw_os_error(), Some(libc::EACCES));
+            }
+            other => panic!("expected failing to reserve a name, got {other:?}"),
+        }
+        assert!(dummy_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn records_the_given_deletion_date() -> Result<()> {
+        let (dir, trash) = trash_with_mode(0o700)?;
+        // 2004-08-31T22:32:08Z
+        let deletion_date = Duration::from_secs(1_093_991_528);
+        let ctx = TrashContext::new(&trash.root, vec![])?.deleted_at(Some(deletion_date));
+        let local = chrono::Local
+            .timestamp_opt(1_093_991_528, 0)
+            .unwrap()
+            .format("%Y-%m-%dT%T")
+            .to_string();
+
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+        fs::set_permissions(&dummy_path, Permissions::from_mode(0o640))?;
+        let owner = fs::metadata(&dummy_path)?;
+        ctx.send_to_trash(ctx.home_trash(), &dummy_path)?;
+
+        assert_eq!(
+            fs::read_to_string(trash.info_path().join("dummy.trashinfo"))?,
+            format!(
+                "[Trash Info]\nPath={}\nDeletionDate={local}\nX-TT-Mode=0640\nX-TT-Owner={}:{}\n",
+                dummy_path.display(),
+                owner.uid(),
+                owner.gid()
+            )
+        );
+
+        // And so does `directorysizes`, for directories
+        let dummy_dir = dir.path().join("dummy-dir");
+        fs::create_dir(&dummy_dir)?;
+        File::create(dummy_dir.join("inner"))?.write_all(&dummy_bytes())?;
+        ctx.send_to_trash(ctx.home_trash(), &dummy_dir)?;
+        let sizes = read_directory_sizes(&trash)?;
+        assert_eq!(sizes.len(), 1);
+        assert_eq!(sizes[0].mtime, 1_093_991_528);
+
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_files_swapped_while_trashing_alone() -> Result<()> {
+        let (dir, trash) = trash_with_mode(0o700)?;
+        // What an attacker would like trashed in place of their own file
+        let precious = dir.path().join("precious");
+        fs::create_dir(&precious)?;
+        fs::write(precious.join("passwd"), "root")?;
+
+        let dummy_path = dir.path().join("dummy");
+        // Swapped once planned, when the info file is being written
+        fs::create_dir(&dummy_path)?;
+        let ctx = TrashContext::new(&trash.root, vec![])?.with_fs(FaultyFs::default().swapping(
+            Op::CreateFile,
+            1,
+            &dummy_path,
+            &precious,
+        ));
+        assert!(matches!(
+            ctx.send_to_trash(ctx.home_trash(), &dummy_path),
+            Err(Error::SourceChanged(path)) if path == dummy_path
+        ));
+        assert_eq!(fs::read_link(&dummy_path)?, precious);
+        assert_eq!(fs::read_to_string(precious.join("passwd"))?, "root");
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 0);
+        assert_eq!(fs::read_dir(&trash.files)?.count(), 0);
+
+        // Swapped while being copied to another filesystem, in which case the copy is thrown away
+        fs::remove_file(&dummy_path)?;
+        fs::remove_dir(dir.path().join("dummy.swapped"))?;
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+        let ctx = TrashContext::new(&trash.root, vec![])?.with_fs(
+            FaultyFs::failing(Op::Rename, 1, libc::EXDEV).swapping(
+                Op::Copy,
+                1,
+                &dummy_path,
+                &precious.join("passwd"),
+            ),
+        );
+        assert!(matches!(
+            ctx.send_to_trash(ctx.home_trash(), &dummy_path),
+            Err(Error::SourceChanged(path)) if path == dummy_path
+        ));
+        assert_eq!(fs::read_to_string(precious.join("passwd"))?, "root");
+        assert!(dummy_path.symlink_metadata()?.is_symlink());
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 0);
+        assert_eq!(fs::read_dir(&trash.files)?.count(), 0);
+
+        // Without swaps, copying to another filesystem goes through just the same
+        fs::remove_file(&dummy_path)?;
+        let contents = dummy_bytes();
+        fs::write(&dummy_path, &contents)?;
+        let ctx = TrashContext::new(&trash.root, vec![])?.with_fs(FaultyFs::failing(
+            Op::Rename,
+            1,
+            libc::EXDEV,
+        ));
+        let name = ctx.send_to_trash(ctx.home_trash(), &dummy_path)?;
+        assert!(!dummy_path.exists());
+        assert_eq!(fs::read(trash.files.as_path().join(name))?, contents);
+
+        Ok(())
+    }
+
+    #[test]
+    fn explains_failures_caused_by_attributes() -> Result<()> {
+        let (dir, trash) = trash_with_mode(0o700)?;
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+        let denied = || FaultyFs::failing(Op::Rename, 1, libc::EPERM);
+
+        let ctx = TrashContext::new(&trash.root, vec![])?
+            .with_fs(denied().flagging(&dummy_path, InodeFlags::IMMUTABLE));
+        let err = ctx
+            .send_to_trash(ctx.home_trash(), &dummy_path)
+            .unwrap_err();
+        assert!(matches!(&err, Error::Immutable(path) if *path == dummy_path));
+        assert!(err.to_string().contains("run 'chattr -i'"));
+
+        // The directory it's in is to blame just the same
+        let ctx = TrashContext::new(&trash.root, vec![])?
+            .with_fs(denied().flagging(dir.path(), InodeFlags::APPEND_ONLY));
+        assert!(matches!(
+            ctx.send_to_trash(ctx.home_trash(), &dummy_path),
+            Err(Error::AppendOnly(path)) if path == dir.path()
+        ));
+
+        // Otherwise, the error is kept as is
+        let ctx = TrashContext::new(&trash.root, vec![])?.with_fs(denied());
+        let err = ctx
+            .send_to_trash(ctx.home_trash(), &dummy_path)
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::MoveFailed(path, _, err) if *path == dummy_path && err.raw_os_error() == Some(libc::EPERM)
+        ));
+        assert!(err.to_string().starts_with(&format!(
+            "Failed to move {} into the trash: ",
+            dummy_path.display()
+        )));
+        let ctx = TrashContext::new(&trash.root, vec![])?.with_fs(
+            FaultyFs::failing(Op::Rename, 1, libc::EIO)
+                .flagging(&dummy_path, InodeFlags::IMMUTABLE),
+        );
+        assert!(matches!(
+            ctx.send_to_trash(ctx.home_trash(), &dummy_path),
+            Err(Error::MoveFailed(_, _, err)) if err.raw_os_error() == Some(libc::EIO)
+        ));
+
+        assert!(dummy_path.exists());
+        assert_eq!(fs::read_dir(&trash.info)?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs root and a filesystem with attributes, such as ext4"]
+    fn explains_failures_caused_by_actual_attributes() -> Result<()> {
+        let (dir, trash) = trash_with_mode(0o700)?;
+        let dummy_path = dir.path().join("dummy");
+        File::create(&dummy_path)?.write_all(&dummy_bytes())?;
+
+        let chattr = |flag| {
+            std::process::Command::new("chattr")
+                .arg(flag)
+                .arg(&dummy_path)
+                .status()
+        };
+        assert!(chattr("+i")?.success());
+        let result = trash.send_to_trash(&RealFs, &dummy_path);
+        assert!(chattr("-i")?.success());
+
+        assert!(matches!(result, Err(Error::Immutable(path)) if path == dummy_path));
+        assert!(dummy_path.exists());
+
+        Ok(())
+    }
 }
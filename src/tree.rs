@@ -0,0 +1,248 @@
+//! Rendering the entries of a trash as a tree of the directories they were trashed from, for `--list --tree`.
+//!
+//! Directories are made of the components of the entries' original paths, compared as bytes, so that paths that aren't
+//! valid UTF-8 are grouped like any other. A directory holding nothing but another directory is shown on the same line,
+//! as `project/src` is below. Entries whose original path is unknown go under a last `<unknown>` node.
+//!
+//! ```text
+//! /home/user
+//! ├── notes.txt  2024-03-01T10:00:00  5 bytes
+//! └── project/src
+//!     ├── lib.rs (as lib.rs.2)  2024-03-02T09:00:00  1.2 KiB
+//!     └── main.rs  -  -
+//! <unknown>
+//! └── orphan  -  6 bytes
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    fmt::Write,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{date::DateTime, entries::TrashEntry, report::escape_path, usage::human_size};
+
+/// An entry, as a leaf of the tree
+#[derive(Debug)]
+struct Leaf {
+    /// The last component of its original path, or its name in the trash if that's unknown
+    file_name: OsString,
+    /// Its name in `$trash/files`
+    name: OsString,
+    deletion_date: Option<DateTime>,
+    size: Option<u64>,
+}
+
+impl Leaf {
+    fn label(&self) -> String {
+        let mut label = escape_path(Path::new(&self.file_name));
+        if self.name != self.file_name {
+            let _ = write!(label, " (as {})", escape_path(Path::new(&self.name)));
+        }
+
+        let deletion_date = self
+            .deletion_date
+            .map_or_else(|| "-".into(), |date| date.to_string());
+        let size = self.size.map_or_else(|| "-".into(), human_size);
+
+        format!("{label}  {deletion_date}  {size}")
+    }
+}
+
+/// A directory entries were trashed from
+#[derive(Debug, Default)]
+struct Directory {
+    directories: BTreeMap<OsString, Directory>,
+    leaves: Vec<Leaf>,
+}
+
+impl Directory {
+    /// Follows the chain of directories holding nothing but another directory from this one, whose path is `path`,
+    /// returning the path of the last one and the last one itself
+    fn collapse(&self, mut path: PathBuf) -> (PathBuf, &Directory) {
+        let mut directory = self;
+        while directory.leaves.is_empty() && directory.directories.len() == 1 {
+            let (name, only) = directory
+                .directories
+                .iter()
+                .next()
+                .expect("there's one directory");
+            path.push(name);
+            directory = only;
+        }
+
+        (path, directory)
+    }
+
+    /// Renders what this directory holds, each line starting with `prefix`
+    fn render_children(&self, prefix: &str, out: &mut String) {
+        // Directories and leaves mixed, by name
+        let mut children: Vec<(&OsStr, Result<&Directory, &Leaf>)> = self
+            .directories
+            .iter()
+            .map(|(name, directory)| (name.as_os_str(), Ok(directory)))
+            .chain(
+                self.leaves
+                    .iter()
+                    .map(|leaf| (leaf.file_name.as_os_str(), Err(leaf))),
+            )
+            .collect();
+        children.sort_by(|(name, child), (other_name, other)| {
+            name.cmp(other_name)
+                .then_with(|| child.is_err().cmp(&other.is_err()))
+                .then_with(|| match (child, other) {
+                    (Err(leaf), Err(other)) => leaf.name.cmp(&other.name),
+                    _ => std::cmp::Ordering::Equal,
+                })
+        });
+
+        let count = children.len();
+        for (index, (name, child)) in children.into_iter().enumerate() {
+            let is_last = index + 1 == count;
+            let (branch, indent) = if is_last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+
+            match child {
+                Ok(directory) => {
+                    let (path, directory) = directory.collapse(PathBuf::from(name));
+                    let _ = writeln!(out, "{prefix}{branch}{}", escape_path(&path));
+                    directory.render_children(&format!("{prefix}{indent}"), out);
+                }
+                Err(leaf) => {
+                    let _ = writeln!(out, "{prefix}{branch}{}", leaf.label());
+                }
+            }
+        }
+    }
+}
+
+/// Renders `entries`, each along with its size if known, as a tree of where they were trashed from
+pub fn render(entries: &[(TrashEntry, Option<u64>)]) -> String {
+    let mut root = Directory::default();
+    let mut unknown = Directory::default();
+
+    for (entry, size) in entries {
+        let leaf = |file_name: &OsStr| Leaf {
+            file_name: file_name.to_owned(),
+            name: entry.name.clone(),
+            deletion_date: entry.deletion_date,
+            size: *size,
+        };
+
+        let Some(original_path) = &entry.original_path else {
+            unknown.leaves.push(leaf(&entry.name));
+            continue;
+        };
+        let (Some(parent), Some(file_name)) = (original_path.parent(), original_path.file_name())
+        else {
+            // Such as `/`, which can't be trashed anyway
+            unknown.leaves.push(leaf(&entry.name));
+            continue;
+        };
+
+        let mut directory = &mut root;
+        for component in parent.components() {
+            if let Component::Normal(name) = component {
+                directory = directory.directories.entry(name.to_owned()).or_default();
+            }
+        }
+        directory.leaves.push(leaf(file_name));
+    }
+
+    let mut out = String::new();
+    if !root.leaves.is_empty() || !root.directories.is_empty() {
+        let (path, directory) = root.collapse(PathBuf::from("/"));
+        let _ = writeln!(out, "{}", escape_path(&path));
+        directory.render_children("", &mut out);
+    }
+    if !unknown.leaves.is_empty() {
+        out.push_str("<unknown>\n");
+        unknown.render_children("", &mut out);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::PathBuf};
+
+    use super::render;
+    use crate::{date::DateTime, entries::TrashEntry};
+
+    fn entry(
+        name: &[u8],
+        original_path: Option<&[u8]>,
+        size: Option<u64>,
+    ) -> (TrashEntry, Option<u64>) {
+        let original_path = original_path.map(|path| PathBuf::from(OsStr::from_bytes(path)));
+        let entry = TrashEntry {
+            name: OsStr::from_bytes(name).to_owned(),
+            raw_path: original_path.clone(),
+            original_path,
+            deletion_date: DateTime::parse("2024-03-01T10:00:00"),
+            original_size: None,
+        };
+
+        (entry, size)
+    }
+
+    #[test]
+    fn renders_nested_origins() {
+        let entries = [
+            entry(b"notes.txt", Some(b"/home/user/notes.txt"), Some(5)),
+            entry(b"main.rs", Some(b"/home/user/project/src/main.rs"), None),
+            entry(
+                b"lib.rs.2",
+                Some(b"/home/user/project/src/lib.rs"),
+                Some(1200),
+            ),
+            entry(
+                b"lib.rs",
+                Some(b"/home/user/project/src/lib.rs"),
+                Some(1100),
+            ),
+            entry(b"build", Some(b"/home/user/project/build"), Some(4_000_000)),
+            entry(b"caf\xe9", Some(b"/home/user/caf\xe9"), Some(1)),
+            entry(b"orphan", None, Some(6)),
+        ];
+
+        assert_eq!(
+            render(&entries),
+            "/home/user\n\
+             ├── caf%E9  2024-03-01T10:00:00  1 bytes\n\
+             ├── notes.txt  2024-03-01T10:00:00  5 bytes\n\
+             └── project\n\
+             \x20   ├── build  2024-03-01T10:00:00  3.8 MiB\n\
+             \x20   └── src\n\
+             \x20       ├── lib.rs  2024-03-01T10:00:00  1.1 KiB\n\
+             \x20       ├── lib.rs (as lib.rs.2)  2024-03-01T10:00:00  1.2 KiB\n\
+             \x20       └── main.rs  2024-03-01T10:00:00  -\n\
+             <unknown>\n\
+             └── orphan  2024-03-01T10:00:00  6 bytes\n"
+        );
+    }
+
+    #[test]
+    fn collapses_chains_of_directories() {
+        let entries = [
+            entry(b"a", Some(b"/srv/data/2024/03/a"), Some(1)),
+            entry(b"b", Some(b"/srv/data/2024/04/deep/er/b"), Some(2)),
+        ];
+
+        assert_eq!(
+            render(&entries),
+            "/srv/data/2024\n\
+             ├── 03\n\
+             │   └── a  2024-03-01T10:00:00  1 bytes\n\
+             └── 04/deep/er\n\
+             \x20   └── b  2024-03-01T10:00:00  2 bytes\n"
+        );
+
+        assert_eq!(render(&[]), "");
+    }
+}
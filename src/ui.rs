@@ -0,0 +1,339 @@
+//! Browsing the trash interactively, for `--ui`, which is only built with the `ui` feature.
+//!
+//! Entries are listed with their name, original path, size and deletion date, and can be restored,
+//! permanently deleted or have their info file shown. Typing filters them by name or original path.
+//! Everything done to the trash goes through [`restore`] and [`purge`]: this module only draws the list
+//! (see [`model`]) and reads keys.
+//!
+//! Info files are read in batches between keystrokes and sizes are only computed for the entries on screen,
+//! so that trashes holding tens of thousands of entries can be browsed right away.
+
+mod model;
+
+use std::{
+    ffi::OsString,
+    io::{self, IsTerminal, Write},
+    time::Duration,
+};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+    ExecutableCommand, QueueableCommand,
+};
+
+use self::model::ListModel;
+use crate::{
+    cat::cat,
+    directorysizes::{read_directory_sizes, DirectorySize},
+    entries::{entry_size, lazy_entries, purge, Entries},
+    error::{Error, Result},
+    fs::FileSystem,
+    report::display_path,
+    restore::{restore, RestoreOptions},
+    trash::Trash,
+    usage::human_size,
+};
+
+/// How many info files are read between two looks at the keyboard
+const BATCH_SIZE: usize = 500;
+
+/// The rows taken by the header, the filter, the column titles and the status line
+const CHROME_HEIGHT: usize = 4;
+
+/// The width of the deletion date and size columns, including the space after them
+const DATE_WIDTH: usize = 20;
+const SIZE_WIDTH: usize = 11;
+
+/// What the keys being pressed are for
+enum Screen {
+    List,
+    /// Waiting for the deletion of the named entry to be confirmed
+    ConfirmPurge(OsString),
+    /// Showing the info file of an entry
+    Info(String),
+}
+
+/// Puts the terminal back the way it was, however browsing ends
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        let guard = Self;
+        io::stdout().execute(EnterAlternateScreen)?.execute(Hide)?;
+
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = io::stdout()
+            .execute(Show)
+            .and_then(|out| out.execute(LeaveAlternateScreen));
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Browses `trash` until the user quits, restoring entries with `restore_options`.
+///
+/// Fails with [`Error::NotATerminal`] when stdout isn't a terminal.
+pub fn browse(fs: &impl FileSystem, trash: &Trash, restore_options: RestoreOptions) -> Result<()> {
+    if !io::stdout().is_terminal() {
+        return Err(Error::NotATerminal);
+    }
+
+    let mut browser = Browser {
+        fs,
+        trash,
+        restore_options,
+        model: ListModel::default(),
+        pending: lazy_entries(trash)?,
+        directory_sizes: read_directory_sizes(trash)?,
+        screen: Screen::List,
+        status: String::new(),
+    };
+
+    let _guard = TerminalGuard::enter()?;
+    browser.run()
+}
+
+struct Browser<'a, F> {
+    fs: &'a F,
+    trash: &'a Trash,
+    restore_options: RestoreOptions,
+    model: ListModel,
+    /// The entries whose info files weren't read yet
+    pending: Entries<'a>,
+    directory_sizes: Vec<DirectorySize>,
+    screen: Screen,
+    /// What the last action did
+    status: String,
+}
+
+impl<F: FileSystem> Browser<'_, F> {
+    fn run(&mut self) -> Result<()> {
+        loop {
+            self.model.extend(self.pending.by_ref().take(BATCH_SIZE));
+            self.draw()?;
+
+            // Don't wait for keys while there's still loading to do
+            let timeout = if self.pending.len() > 0 {
+                Duration::ZERO
+            } else {
+                Duration::from_secs(60)
+            };
+            if !event::poll(timeout)? {
+                continue;
+            }
+
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Release && !self.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Acts on `key`, returning whether to keep browsing
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+
+        match std::mem::replace(&mut self.screen, Screen::List) {
+            Screen::Info(_) => {}
+            Screen::ConfirmPurge(name) => {
+                if matches!(key.code, KeyCode::Char('y' | 'Y')) {
+                    self.status = match purge(self.fs, self.trash, &name) {
+                        Ok(()) => {
+                            self.model.remove(&name);
+                            format!("deleted {}", display_path(name.as_ref()))
+                        }
+                        Err(err) => format!("error: {err}"),
+                    };
+                } else {
+                    self.status = "nothing was deleted".into();
+                }
+            }
+            Screen::List => return self.handle_list_key(key),
+        }
+
+        true
+    }
+
+    fn handle_list_key(&mut self, key: KeyEvent) -> bool {
+        let page = self.list_height() as isize;
+        let selected = self.model.selected().map(|entry| entry.name.clone());
+
+        match key.code {
+            KeyCode::Esc if self.model.filter().is_empty() => return false,
+            KeyCode::Esc => self.model.clear_filter(),
+            KeyCode::Up => self.model.move_selection(-1),
+            KeyCode::Down => self.model.move_selection(1),
+            KeyCode::PageUp => self.model.move_selection(-page),
+            KeyCode::PageDown => self.model.move_selection(page),
+            KeyCode::Home => self.model.select_first(),
+            KeyCode::End => self.model.select_last(),
+            KeyCode::Backspace => self.model.pop_filter(),
+            KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.model.cycle_sort()
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.model.push_filter(c)
+            }
+            KeyCode::Enter => {
+                if let Some(name) = selected {
+                    self.status = match restore(self.fs, self.trash, &name, self.restore_options) {
+                        Ok(plan) => {
+                            self.model.remove(&name);
+                            format!("restored {}", display_path(&plan.destination))
+                        }
+                        Err(err) => format!("error: {err}"),
+                    };
+                }
+            }
+            KeyCode::Delete => {
+                if let Some(name) = selected {
+                    self.screen = Screen::ConfirmPurge(name);
+                }
+            }
+            KeyCode::Tab => {
+                if let Some(name) = selected {
+                    let mut info = Vec::new();
+                    match cat(self.trash, &name, true, &mut info) {
+                        Ok(()) => self.screen = Screen::Info(String::from_utf8_lossy(&info).into()),
+                        Err(err) => self.status = format!("error: {err}"),
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    /// How many entries fit on screen
+    fn list_height(&self) -> usize {
+        let (_, height) = terminal::size().unwrap_or((80, 24));
+
+        usize::from(height).saturating_sub(CHROME_HEIGHT)
+    }
+
+    fn draw(&mut self) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (usize::from(width), usize::from(height));
+        let mut out = io::stdout().lock();
+
+        out.queue(Clear(ClearType::All))?;
+
+        if let Screen::Info(info) = &self.screen {
+            for (row, line) in info.lines().take(height.saturating_sub(1)).enumerate() {
+                out.queue(MoveTo(0, row as u16))?
+                    .queue(Print(truncate(line, width)))?;
+            }
+            out.queue(MoveTo(0, height.saturating_sub(1) as u16))?
+                .queue(Print(truncate("Press any key to go back", width)))?;
+            out.flush()?;
+
+            return Ok(());
+        }
+
+        let loading = match self.pending.len() {
+            0 => String::new(),
+            left => format!(", {left} more loading"),
+        };
+        let header = format!(
+            "{}: {} of {} entries{loading}, sorted by {}",
+            display_path(self.trash.root.as_path()),
+            self.model.len(),
+            self.model.total(),
+            self.model.sort_key().label(),
+        );
+        out.queue(MoveTo(0, 0))?
+            .queue(Print(truncate(&header, width)))?;
+        out.queue(MoveTo(0, 1))?.queue(Print(truncate(
+            &format!("Filter: {}", self.model.filter()),
+            width,
+        )))?;
+
+        let name_width = width.saturating_sub(DATE_WIDTH + SIZE_WIDTH) / 3;
+        let titles = format!(
+            "{:DATE_WIDTH$}{:>size$} {:name_width$} ORIGINAL PATH",
+            "DELETED",
+            "SIZE",
+            "NAME",
+            size = SIZE_WIDTH - 1,
+        );
+        out.queue(MoveTo(0, 2))?
+            .queue(SetAttribute(Attribute::Bold))?
+            .queue(Print(truncate(&titles, width)))?
+            .queue(SetAttribute(Attribute::Reset))?;
+
+        let list_height = height.saturating_sub(CHROME_HEIGHT);
+        self.model.scroll_to_selection(list_height);
+        self.compute_sizes(list_height);
+
+        for (row, (entry, is_selected)) in self.model.rows(list_height).into_iter().enumerate() {
+            let size = self.model.size(&entry.name).flatten();
+            let deletion_date = entry
+                .deletion_date
+                .map_or_else(|| "-".into(), |date| date.to_string());
+            let size = size.map_or_else(|| "-".into(), human_size);
+            let original_path = entry
+                .original_path
+                .as_ref()
+                .map_or_else(|| "-".into(), |path| display_path(path));
+            let line = format!(
+                "{deletion_date:DATE_WIDTH$}{size:>width$} {:name_width$} {original_path}",
+                truncate(&display_path(entry.name.as_ref()), name_width),
+                width = SIZE_WIDTH - 1,
+            );
+
+            out.queue(MoveTo(0, (row + 3) as u16))?;
+            if is_selected {
+                out.queue(SetAttribute(Attribute::Reverse))?;
+            }
+            out.queue(Print(truncate(&line, width)))?
+                .queue(SetAttribute(Attribute::Reset))?;
+        }
+
+        let status = match &self.screen {
+            Screen::ConfirmPurge(name) => {
+                format!("Permanently delete {}? (y/n)", display_path(name.as_ref()))
+            }
+            _ if !self.status.is_empty() => self.status.clone(),
+            _ => "Enter restore, Delete delete, Tab info file, Ctrl-S sort, Esc quit".into(),
+        };
+        out.queue(MoveTo(0, height.saturating_sub(1) as u16))?
+            .queue(Print(truncate(&status, width)))?;
+
+        out.flush()?;
+
+        Ok(())
+    }
+
+    /// Computes the sizes of the entries on screen that aren't known yet
+    fn compute_sizes(&mut self, list_height: usize) {
+        let missing: Vec<_> = self
+            .model
+            .rows(list_height)
+            .into_iter()
+            .map(|(entry, _)| entry.name.clone())
+            .filter(|name| self.model.size(name).is_none())
+            .collect();
+
+        for name in missing {
+            let size = entry_size(self.trash, &name, &self.directory_sizes).ok();
+            self.model.set_size(name, size);
+        }
+    }
+}
+
+/// The first `width` characters of `text`
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
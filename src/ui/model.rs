@@ -0,0 +1,368 @@
+//! What the trash browser shows, apart from how it's drawn: the entries loaded so far, which of them
+//! match the filter being typed, in which order, and which one is selected.
+
+use std::{cmp::Ordering, collections::HashMap, ffi::OsString};
+
+use crate::entries::TrashEntry;
+
+/// What the entries are sorted by
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// The most recently trashed first, the ones without a valid deletion date last
+    #[default]
+    DeletionDate,
+    Name,
+    /// Entries whose original path is unknown last
+    OriginalPath,
+}
+
+impl SortKey {
+    /// The key sorted by after this one, cycling through them all
+    pub fn next(self) -> Self {
+        match self {
+            SortKey::DeletionDate => SortKey::Name,
+            SortKey::Name => SortKey::OriginalPath,
+            SortKey::OriginalPath => SortKey::DeletionDate,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::DeletionDate => "deletion date",
+            SortKey::Name => "name",
+            SortKey::OriginalPath => "original path",
+        }
+    }
+
+    fn compare(self, a: &TrashEntry, b: &TrashEntry) -> Ordering {
+        /// Orders missing values after present ones, which are ordered by `compare`
+        fn missing_last<T>(
+            a: Option<T>,
+            b: Option<T>,
+            compare: impl Fn(T, T) -> Ordering,
+        ) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => compare(a, b),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            }
+        }
+
+        let by_key = match self {
+            SortKey::DeletionDate => {
+                missing_last(a.deletion_date, b.deletion_date, |a, b| b.cmp(&a))
+            }
+            SortKey::Name => Ordering::Equal,
+            SortKey::OriginalPath => {
+                missing_last(a.original_path.as_ref(), b.original_path.as_ref(), Ord::cmp)
+            }
+        };
+
+        by_key.then_with(|| a.name.cmp(&b.name))
+    }
+}
+
+/// The entries of a trash as the browser lists them
+#[derive(Debug, Default)]
+pub struct ListModel {
+    entries: Vec<TrashEntry>,
+    /// The sizes of the entries computed so far, by name, `None` meaning it couldn't be
+    sizes: HashMap<OsString, Option<u64>>,
+    filter: String,
+    sort: SortKey,
+    /// Indices in `entries` of the entries matching `filter`, in order
+    visible: Vec<usize>,
+    /// Index in `visible` of the selected entry
+    selected: usize,
+    /// Index in `visible` of the first entry on screen
+    offset: usize,
+}
+
+impl ListModel {
+    /// Adds `entries` to the list, keeping the same entry selected
+    pub fn extend(&mut self, entries: impl IntoIterator<Item = TrashEntry>) {
+        self.entries.extend(entries);
+        self.refresh();
+    }
+
+    /// Removes the entry named `name`, once it's been restored or deleted
+    pub fn remove(&mut self, name: &OsString) {
+        self.entries.retain(|entry| &entry.name != name);
+        self.sizes.remove(name);
+        self.refresh();
+    }
+
+    /// How many entries were loaded, matching the filter or not
+    pub fn total(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// How many entries match the filter
+    pub fn len(&self) -> usize {
+        self.visible.len()
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    pub fn push_filter(&mut self, c: char) {
+        self.filter.push(c);
+        self.refresh();
+    }
+
+    pub fn pop_filter(&mut self) {
+        self.filter.pop();
+        self.refresh();
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.filter.clear();
+        self.refresh();
+    }
+
+    pub fn sort_key(&self) -> SortKey {
+        self.sort
+    }
+
+    pub fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.refresh();
+    }
+
+    /// The selected entry, if any entry matches the filter
+    pub fn selected(&self) -> Option<&TrashEntry> {
+        self.visible.get(self.selected).map(|&i| &self.entries[i])
+    }
+
+    /// Moves the selection by `delta` entries, stopping at either end of the list
+    pub fn move_selection(&mut self, delta: isize) {
+        let last = self.visible.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    pub fn select_first(&mut self) {
+        self.selected = 0;
+    }
+
+    pub fn select_last(&mut self) {
+        self.selected = self.visible.len().saturating_sub(1);
+    }
+
+    /// Scrolls just enough for the selected entry to be among the first `height` rows shown
+    pub fn scroll_to_selection(&mut self, height: usize) {
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if height > 0 && self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+    }
+
+    /// The entries to show in `height` rows, along with whether each is the selected one
+    pub fn rows(&self, height: usize) -> Vec<(&TrashEntry, bool)> {
+        self.visible
+            .iter()
+            .enumerate()
+            .skip(self.offset)
+            .take(height)
+            .map(|(position, &i)| (&self.entries[i], position == self.selected))
+            .collect()
+    }
+
+    /// The size of the entry named `name`, if it's known yet: `Some(None)` means it couldn't be computed
+    pub fn size(&self, name: &OsString) -> Option<Option<u64>> {
+        self.sizes.get(name).copied()
+    }
+
+    pub fn set_size(&mut self, name: OsString, size: Option<u64>) {
+        self.sizes.insert(name, size);
+    }
+
+    /// Whether `entry` matches `filter`, which is lowercase, by name or original path, ignoring case
+    fn matches(entry: &TrashEntry, filter: &str) -> bool {
+        let name = entry.name.to_string_lossy().to_lowercase();
+        let original_path = entry
+            .original_path
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_lowercase());
+
+        name.contains(filter) || original_path.is_some_and(|path| path.contains(filter))
+    }
+
+    /// Recomputes which entries are visible and in which order, keeping the same entry selected if it still is,
+    /// and the same position otherwise
+    fn refresh(&mut self) {
+        let selected_name = self.selected().map(|entry| entry.name.clone());
+
+        let filter = self.filter.to_lowercase();
+        let mut visible: Vec<usize> = (0..self.entries.len())
+            .filter(|&i| Self::matches(&self.entries[i], &filter))
+            .collect();
+        visible.sort_by(|&a, &b| self.sort.compare(&self.entries[a], &self.entries[b]));
+        self.visible = visible;
+
+        self.selected = selected_name
+            .and_then(|name| {
+                self.visible
+                    .iter()
+                    .position(|&i| self.entries[i].name == name)
+            })
+            .unwrap_or(self.selected.min(self.visible.len().saturating_sub(1)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{ListModel, SortKey};
+    use crate::{date::DateTime, entries::TrashEntry};
+
+    fn entry(name: &str, original_path: Option<&str>, deletion_date: Option<&str>) -> TrashEntry {
+        TrashEntry {
+            name: name.into(),
+            raw_path: original_path.map(PathBuf::from),
+            original_path: original_path.map(PathBuf::from),
+            deletion_date: deletion_date.and_then(DateTime::parse),
+            original_size: None,
+        }
+    }
+
+    fn model() -> ListModel {
+        let mut model = ListModel::default();
+        model.extend([
+            entry(
+                "b.txt",
+                Some("/home/user/b.txt"),
+                Some("2024-03-01T10:00:00"),
+            ),
+            entry("undated", Some("/tmp/undated"), None),
+            entry(
+                "Notes",
+                Some("/home/user/Notes"),
+                Some("2024-05-01T10:00:00"),
+            ),
+        ]);
+        model.extend([
+            entry("a.txt", Some("/srv/a.txt"), Some("2024-01-01T10:00:00")),
+            entry("orphan", None, None),
+        ]);
+        model
+    }
+
+    /// The names of the entries shown, the selected one marked with `>`
+    fn snapshot(model: &mut ListModel) -> Vec<String> {
+        model.scroll_to_selection(usize::MAX);
+        model
+            .rows(usize::MAX)
+            .into_iter()
+            .map(|(entry, selected)| {
+                let marker = if selected { ">" } else { " " };
+                format!("{marker}{}", entry.name.to_string_lossy())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sorts_entries() {
+        let mut model = model();
+        assert_eq!(model.sort_key(), SortKey::DeletionDate);
+        assert_eq!(
+            snapshot(&mut model),
+            [">Notes", " b.txt", " a.txt", " orphan", " undated"]
+        );
+
+        model.cycle_sort();
+        assert_eq!(
+            snapshot(&mut model),
+            [">Notes", " a.txt", " b.txt", " orphan", " undated"]
+        );
+
+        model.cycle_sort();
+        assert_eq!(model.sort_key(), SortKey::OriginalPath);
+        assert_eq!(
+            snapshot(&mut model),
+            [">Notes", " b.txt", " a.txt", " undated", " orphan"]
+        );
+
+        model.cycle_sort();
+        assert_eq!(model.sort_key(), SortKey::DeletionDate);
+    }
+
+    #[test]
+    fn filters_entries() {
+        let mut model = model();
+
+        // By name or original path, ignoring case
+        for c in "NOTES".chars() {
+            model.push_filter(c);
+        }
+        assert_eq!(snapshot(&mut model), [">Notes"]);
+
+        model.clear_filter();
+        for c in "/home".chars() {
+            model.push_filter(c);
+        }
+        assert_eq!(snapshot(&mut model), [">Notes", " b.txt"]);
+
+        model.push_filter('x');
+        assert!(snapshot(&mut model).is_empty());
+        assert!(model.selected().is_none());
+
+        model.pop_filter();
+        assert_eq!(model.filter(), "/home");
+        assert_eq!(model.len(), 2);
+        assert_eq!(model.total(), 5);
+    }
+
+    #[test]
+    fn keeps_the_selection() {
+        let mut model = model();
+        model.move_selection(2);
+        assert_eq!(model.selected().unwrap().name, "a.txt");
+
+        // Through sorting, filtering and loading more entries
+        model.cycle_sort();
+        assert_eq!(model.selected().unwrap().name, "a.txt");
+        model.push_filter('t');
+        assert_eq!(model.selected().unwrap().name, "a.txt");
+        model.extend([entry("0.txt", None, None)]);
+        assert_eq!(
+            snapshot(&mut model),
+            [" 0.txt", " Notes", ">a.txt", " b.txt", " undated"]
+        );
+
+        // The selection moves on to what follows once its entry is gone
+        model.remove(&"a.txt".into());
+        assert_eq!(
+            snapshot(&mut model),
+            [" 0.txt", " Notes", ">b.txt", " undated"]
+        );
+
+        // Without going past either end
+        model.move_selection(-10);
+        assert_eq!(model.selected().unwrap().name, "0.txt");
+        model.move_selection(10);
+        assert_eq!(model.selected().unwrap().name, "undated");
+    }
+
+    #[test]
+    fn scrolls_to_the_selection() {
+        let mut model = model();
+        let names = |model: &mut ListModel, height| -> Vec<String> {
+            model.scroll_to_selection(height);
+            model
+                .rows(height)
+                .into_iter()
+                .map(|(entry, _)| entry.name.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        assert_eq!(names(&mut model, 2), ["Notes", "b.txt"]);
+        model.select_last();
+        assert_eq!(names(&mut model, 2), ["orphan", "undated"]);
+        model.move_selection(-2);
+        assert_eq!(names(&mut model, 2), ["a.txt", "orphan"]);
+        model.select_first();
+        assert_eq!(names(&mut model, 2), ["Notes", "b.txt"]);
+    }
+}
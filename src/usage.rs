@@ -0,0 +1,274 @@
+//! Warning when a trash takes up too much of its filesystem, since it's easy to forget about it until the disk is full.
+//!
+//! After trashing, the trashes files were sent to are checked against the `usage_warning` section of the
+//! configuration file: by default, a trash is too big once it takes up more than 10% of its filesystem.
+//! Warnings are shown at most once a day, which is kept track of in `$XDG_STATE_HOME/tt/last-usage-warning`,
+//! and not at all with `--quiet`.
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    error::Result,
+    ffi::{filesystem_capacity, innermost_mount_point, MountPoint},
+    report::display_path,
+    trash::Trash,
+};
+
+/// How long warnings are kept quiet for once one is shown
+pub const WARNING_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The name of the file, in the state directory, holding when the last warning was shown
+pub const STAMP_FILE_NAME: &str = "last-usage-warning";
+
+/// When a trash is too big, as set in the `usage_warning` section of the configuration file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UsageLimits {
+    /// The percentage of its filesystem's capacity a trash may take up, 0 meaning any
+    pub percent: u8,
+    /// How many bytes a trash may take up
+    pub size: Option<u64>,
+}
+
+impl Default for UsageLimits {
+    fn default() -> Self {
+        Self {
+            percent: 10,
+            size: None,
+        }
+    }
+}
+
+impl UsageLimits {
+    /// Whether a trash of `trash_size` bytes, on a filesystem of `capacity` bytes, is over these limits
+    pub fn are_exceeded(&self, trash_size: u64, capacity: u64) -> bool {
+        // Widened so that neither side can overflow
+        let over_percent = self.percent > 0
+            && u128::from(trash_size) * 100 > u128::from(capacity) * u128::from(self.percent);
+        let over_size = self.size.is_some_and(|size| trash_size > size);
+
+        over_percent || over_size
+    }
+}
+
+/// The percentage of `capacity` that `size` makes up, rounded down
+pub fn percentage(size: u64, capacity: u64) -> u64 {
+    if capacity == 0 {
+        return 100;
+    }
+
+    (u128::from(size) * 100 / u128::from(capacity)) as u64
+}
+
+/// `bytes` in the largest binary unit that keeps it at 1 or more, e.g. `22 GiB` or `1.5 MiB`
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["bytes", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} bytes")
+    } else if size < 10.0 {
+        format!("{size:.1} {}", UNITS[unit])
+    } else {
+        format!("{size:.0} {}", UNITS[unit])
+    }
+}
+
+/// `count` with its digits grouped by thousands, e.g. `2,134,221`
+pub fn grouped(count: u64) -> String {
+    let digits = count.to_string();
+    let mut grouped = String::with_capacity(digits.len() * 4 / 3);
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+
+    grouped
+}
+
+/// The file holding when the last warning was shown, given the state directory (see [`crate::home_dir::state_dir_from`])
+pub fn stamp_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(STAMP_FILE_NAME)
+}
+
+/// Whether a warning may be shown as of `now` (a [`Duration`] starting in UNIX_EPOCH), that is,
+/// whether none was in the [`WARNING_INTERVAL`] before it, according to the file at `stamp`.
+///
+/// A missing or unreadable file means no warning was ever shown. So does a time in the future,
+/// which a clock that was set back would leave behind.
+pub fn is_warning_due(stamp: &Path, now: Duration) -> bool {
+    let last_warning = fs_err::read_to_string(stamp)
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
+        .map(Duration::from_secs);
+
+    match last_warning {
+        Some(last_warning) if last_warning <= now => now - last_warning >= WARNING_INTERVAL,
+        _ => true,
+    }
+}
+
+/// Records that a warning was shown at `now` in the file at `stamp`, creating its directory if needed
+pub fn record_warning(stamp: &Path, now: Duration) -> Result<()> {
+    match fs_err::write(stamp, now.as_secs().to_string()) {
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            if let Some(dir) = stamp.parent() {
+                fs_err::create_dir_all(dir)?;
+            }
+            fs_err::write(stamp, now.as_secs().to_string())?;
+        }
+        result => result?,
+    }
+
+    Ok(())
+}
+
+/// Checks whether `trash` is over `limits`, returning the warning to show if so.
+///
+/// The warning names the trash (`is_home` telling whether it's the home trash) and the mount point
+/// (in `mount_points`) its filesystem is mounted on.
+pub fn check_usage(
+    trash: &Trash,
+    is_home: bool,
+    limits: &UsageLimits,
+    mount_points: &[MountPoint],
+) -> Result<Option<String>> {
+    let capacity = filesystem_capacity(&trash.root)?;
+    let trash_size = trash.total_size()?;
+    if !limits.are_exceeded(trash_size, capacity) {
+        return Ok(None);
+    }
+
+    let name = if is_home {
+        "the home trash".to_owned()
+    } else {
+        format!("the trash at {}", display_path(trash.root.as_path()))
+    };
+    let mount_point = innermost_mount_point(mount_points, trash.root.as_path()).map_or_else(
+        || "its filesystem".to_owned(),
+        |mount_point| display_path(&mount_point.fs_path_prefix),
+    );
+
+    Ok(Some(format!(
+        "{name} is using {} ({}% of {mount_point}), consider emptying it with 'tt --empty --before 30d'",
+        human_size(trash_size),
+        percentage(trash_size, capacity),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use super::{
+        grouped, human_size, is_warning_due, percentage, record_warning, stamp_path, UsageLimits,
+        WARNING_INTERVAL,
+    };
+    use crate::{config::Config, error::Result};
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    #[test]
+    fn checks_limits() {
+        let default = UsageLimits::default();
+        assert!(!default.are_exceeded(10 * GIB, 100 * GIB));
+        assert!(default.are_exceeded(10 * GIB + 1, 100 * GIB));
+        assert!(!default.are_exceeded(0, 0));
+        assert!(default.are_exceeded(1, 0));
+        // No overflow, even with absurd sizes
+        assert!(!default.are_exceeded(u64::MAX / 10, u64::MAX));
+        assert!(default.are_exceeded(u64::MAX, u64::MAX));
+
+        let by_size = UsageLimits {
+            percent: 0,
+            size: Some(20 * GIB),
+        };
+        assert!(!by_size.are_exceeded(20 * GIB, 21 * GIB));
+        assert!(by_size.are_exceeded(20 * GIB + 1, 1000 * GIB));
+
+        // Either limit is enough
+        let both = UsageLimits {
+            percent: 50,
+            size: Some(20 * GIB),
+        };
+        assert!(both.are_exceeded(30 * GIB, 1000 * GIB));
+        assert!(both.are_exceeded(6 * GIB, 10 * GIB));
+        assert!(!both.are_exceeded(4 * GIB, 10 * GIB));
+
+        assert_eq!(percentage(22 * GIB, 200 * GIB), 11);
+        assert_eq!(percentage(1, 3), 33);
+        assert_eq!(percentage(1, 0), 100);
+    }
+
+    #[test]
+    fn parses_limits() -> Result<()> {
+        assert_eq!(
+            Config::parse("", "config.toml".as_ref())?.usage_warning,
+            UsageLimits::default()
+        );
+        assert_eq!(
+            Config::parse("[usage_warning]\nsize = 1_000_000", "config.toml".as_ref())?
+                .usage_warning,
+            UsageLimits {
+                percent: 10,
+                size: Some(1_000_000)
+            }
+        );
+        assert!(Config::parse("[usage_warning]\npercent = 300", "config.toml".as_ref()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(human_size(0), "0 bytes");
+        assert_eq!(human_size(1023), "1023 bytes");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(22 * GIB + GIB / 3), "22 GiB");
+        assert_eq!(human_size(u64::MAX), "16 EiB");
+
+        assert_eq!(grouped(0), "0");
+        assert_eq!(grouped(999), "999");
+        assert_eq!(grouped(1000), "1,000");
+        assert_eq!(grouped(2_134_221), "2,134,221");
+    }
+
+    #[test]
+    fn warns_at_most_once_a_day() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        // Its directory doesn't exist yet
+        let stamp = stamp_path(&dir.path().join("state/tt"));
+        let now = Duration::from_secs(1_700_000_000);
+
+        assert!(is_warning_due(&stamp, now));
+        record_warning(&stamp, now)?;
+        assert!(!is_warning_due(&stamp, now));
+        assert!(!is_warning_due(&stamp, now + WARNING_INTERVAL / 2));
+        assert!(is_warning_due(&stamp, now + WARNING_INTERVAL));
+
+        // The clock was set back
+        assert!(is_warning_due(&stamp, now - Duration::from_secs(1)));
+
+        record_warning(&stamp, now + WARNING_INTERVAL)?;
+        assert!(!is_warning_due(&stamp, now + WARNING_INTERVAL));
+
+        fs::write(&stamp, "garbage")?;
+        assert!(is_warning_due(&stamp, now));
+
+        Ok(())
+    }
+}
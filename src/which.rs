@@ -0,0 +1,145 @@
+//! Finding the trashed versions of a path, for `tt --which`
+
+use std::{fmt, path::Path};
+
+use crate::{
+    completions::candidate,
+    directorysizes::read_directory_sizes,
+    entries::{entries, entry_size, TrashEntry},
+    error::Result,
+    report::display_path,
+    trash::Trash,
+};
+
+/// A trashed version of the path given to `tt --which`
+#[derive(Debug)]
+pub struct Found<'a> {
+    pub trash: &'a Trash,
+    pub entry: TrashEntry,
+    /// The size of the entry in bytes, if it could be determined
+    pub size: Option<u64>,
+}
+
+/// Searches `trashes` for entries trashed from `path`, which must be normalized (see [`normalize_path`](crate::fs::normalize_path)).
+///
+/// If `recursive` is set, entries trashed from anywhere under `path` also match.
+/// Entries are sorted by deletion date, oldest first.
+pub fn which<'a>(trashes: &'a [Trash], path: &Path, recursive: bool) -> Result<Vec<Found<'a>>> {
+    let mut found = Vec::new();
+
+    for trash in trashes {
+        let directory_sizes = read_directory_sizes(trash)?;
+
+        for entry in entries(trash)? {
+            let matches = entry.original_path.as_deref().is_some_and(|original_path| {
+                original_path == path || (recursive && original_path.starts_with(path))
+            });
+
+            if matches {
+                let size = entry_size(trash, &entry.name, &directory_sizes).ok();
+                found.push(Found { trash, entry, size });
+            }
+        }
+    }
+
+    found.sort_by(|a, b| {
+        (a.entry.deletion_date, &a.entry.name).cmp(&(b.entry.deletion_date, &b.entry.name))
+    });
+
+    Ok(found)
+}
+
+/// Quotes `text` so that a POSIX shell reads it as a single word
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+impl fmt::Display for Found<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unknown = || "unknown".to_owned();
+
+        if let Some(original_path) = &self.entry.original_path {
+            writeln!(f, "{}", display_path(original_path))?;
+        }
+        writeln!(f, "  trash:   {}", display_path(self.trash.root.as_path()))?;
+        writeln!(
+            f,
+            "  trashed: {}",
+            self.entry
+                .deletion_date
+                .map_or_else(unknown, |date| date.to_string())
+        )?;
+        let mut size = self
+            .size
+            .map_or_else(unknown, |size| format!("{size} bytes"));
+        if let Some(original_size) = self.entry.original_size {
+            size.push_str(&format!(", compressed from {original_size} bytes"));
+        }
+        writeln!(f, "  size:    {size}")?;
+        write!(
+            f,
+            "  restore: tt --restore {}",
+            shell_quote(&candidate(&self.entry.name))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{shell_quote, which};
+    use crate::{error::Result, fs::RealFs, trash::Trash};
+
+    #[test]
+    fn quotes_for_the_shell() {
+        assert_eq!(shell_quote("plain"), "'plain'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn finds_trashed_versions_of_a_path() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir_path = dir.path().canonicalize()?;
+
+        let trashes = [
+            Trash::from_root(dir_path.join("first"))?,
+            Trash::from_root(dir_path.join("second"))?,
+        ];
+        for trash in &trashes {
+            fs::create_dir(&trash.root)?;
+            fs::create_dir(&trash.files)?;
+            fs::create_dir(&trash.info)?;
+            fs::write(&trash.directory_sizes, "")?;
+        }
+
+        let notes = dir_path.join("notes");
+        let todo = notes.join("todo.txt");
+        fs::create_dir(&notes)?;
+
+        // Two generations of todo.txt, in different trashes
+        for trash in &trashes {
+            fs::write(&todo, "todo")?;
+            trash.send_to_trash(&RealFs, &todo)?;
+        }
+        fs::write(notes.join("other.txt"), "other")?;
+        trashes[0].send_to_trash(&RealFs, &notes.join("other.txt"))?;
+
+        let found = which(&trashes, &todo, false)?;
+        assert_eq!(found.len(), 2);
+        assert!(found
+            .iter()
+            .all(|found| found.entry.original_path.as_deref() == Some(&*todo)));
+        assert!(found.iter().all(|found| found.size == Some(4)));
+
+        assert!(which(&trashes, &notes, false)?.is_empty());
+        assert_eq!(which(&trashes, &notes, true)?.len(), 3);
+        assert!(which(&trashes, &dir_path.join("note"), true)?.is_empty());
+
+        let described = found[0].to_string();
+        assert!(described.starts_with(&format!("{}\n", todo.display())));
+        assert!(described.ends_with("restore: tt --restore 'todo.txt'"));
+
+        Ok(())
+    }
+}